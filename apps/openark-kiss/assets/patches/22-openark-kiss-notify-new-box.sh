@@ -24,4 +24,14 @@ cat <<EOF >/usr/local/bin/notify-new-box.sh
 EOF
 chmod 550 /usr/local/bin/notify-new-box.sh
 
+# Install the enrollment token, if available
+# NOTE: Otherwise, the notifier fetches it on boot
+mkdir -p /etc/kiss
+if curl --fail --silent --output /etc/kiss/enrollment-token \
+    "http://assets.{{ .Release.Namespace }}.svc.{{ include "helm.clusterDomainName" $ }}/auth/enrollment-token"; then
+    chmod 400 /etc/kiss/enrollment-token
+else
+    rm -f /etc/kiss/enrollment-token
+fi
+
 {{- end }}
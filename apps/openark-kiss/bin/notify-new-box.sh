@@ -10,6 +10,18 @@ set -e -o pipefail
 ADDRESS="$(ip route get 1.1.1.1 | grep -oP 'src \K\d+(\.\d+){3}' | head -1)"
 UUID="$(cat /sys/class/dmi/id/product_uuid)"
 
+# Load the enrollment token, installed into the boot image
+TOKEN_FILE="${KISS_ENROLLMENT_TOKEN_FILE:-/etc/kiss/enrollment-token}"
+if [ ! -s "${TOKEN_FILE}" ]; then
+    # Fetch it from the assets server, e.g. on the PXE-booted images
+    mkdir -p "$(dirname "${TOKEN_FILE}")"
+    curl --fail --silent --retry 5 --retry-delay 5 \
+        --output "${TOKEN_FILE}" \
+        "http://assets.{{ .Release.Namespace }}.svc.{{ include "helm.clusterDomainName" $ }}/auth/enrollment-token"
+    chmod 400 "${TOKEN_FILE}"
+fi
+
 # Submit to KISS Cluster
 exec curl --retry 5 --retry-delay 5 \
+    --header @<(echo "Authorization: Bearer $(cat "${TOKEN_FILE}")") \
     "http://apiserver.{{ .Release.Namespace }}.svc.{{ include "helm.clusterDomainName" $ }}/new?address=${ADDRESS}&uuid=${UUID}"
@@ -356,6 +356,13 @@ initContainers:
 {{- end }}
 {{- end }}
 
+{{- /********************************
+    Idle Reporter
+*************************************/}}
+{{- if .Values.features.hostDisplay }}
+  - {{- include "podTemplate.idle" $ | nindent 4 }}
+{{- end }}
+
 {{- end }}
 
 {{- /*
@@ -153,6 +153,14 @@
     hostPath: null
 {{- end }}
 
+{{- /********************************/}}
+{{- if .Values.features.hostDisplay }}
+  - name: runtime-idle
+    hostPath:
+      path: /run/openark-vine/idle
+      type: DirectoryOrCreate
+{{- end }}
+
 {{- /********************************/}}
   - name: runtime-udev
 {{- if .Values.features.hostUdev }}
@@ -0,0 +1,53 @@
+{{- define "podTemplate.idle" -}}
+name: idle
+image: "{{ .Values.greeter.image.repo }}:{{ .Values.greeter.image.tag | default .Chart.AppVersion }}"
+imagePullPolicy: {{ .Values.greeter.image.pullPolicy | quote }}
+command:
+  - /usr/bin/env
+  - bash
+args:
+  - -c
+  - |
+    # Report the idle time of the display to the node's session handler
+    set -e -o pipefail
+    IDLE_FILE="/run/openark-vine/idle/${POD_NAME}"
+    while :; do
+        if idle="$(xprintidle)"; then
+            echo "${idle}" >"${IDLE_FILE}.tmp"
+            mv "${IDLE_FILE}.tmp" "${IDLE_FILE}"
+        fi
+        sleep "${IDLE_REPORT_INTERVAL_SECONDS}"
+    done
+env:
+  - name: DISPLAY
+    value: ":0"
+  - name: IDLE_REPORT_INTERVAL_SECONDS
+    value: "15"
+  - name: POD_NAME
+    valueFrom:
+      fieldRef:
+        apiVersion: v1
+        fieldPath: metadata.name
+resources:
+  requests:
+    cpu: 5m
+    memory: 20Mi
+  limits:
+    cpu: 20m
+    memory: 50Mi
+restartPolicy: Always
+securityContext:
+  runAsNonRoot: false
+  runAsUser: 0
+volumeMounts:
+
+{{- /********************************/}}
+  - name: runtime-idle
+    mountPath: /run/openark-vine/idle
+
+{{- /********************************/}}
+  - name: tmp-x11
+    mountPath: /tmp/.X11-unix
+    readOnly: true
+
+{{- end }}
@@ -1,4 +1,5 @@
 mod session;
+mod spectrum;
 
 use anyhow::Result;
 use clap::{Parser, Subcommand};
@@ -28,12 +29,17 @@ impl Args {
 enum Command {
     #[command(flatten)]
     Session(self::session::Args),
+
+    /// Manage the spectrum pools
+    #[command(subcommand)]
+    Spectrum(self::spectrum::Args),
 }
 
 impl Command {
     async fn exec(self) -> Result<()> {
         match self {
             Self::Session(args) => args.exec().await,
+            Self::Spectrum(args) => args.exec().await,
         }
     }
 }
@@ -1,7 +1,8 @@
-use anyhow::Result;
+use anyhow::{Result, bail};
 use clap::Parser;
+use futures::StreamExt;
 use kube::Client;
-use openark_vine_session_api::exec::ExecArgs;
+use openark_vine_session_api::exec::{ExecArgs, ExecEvent, ExecOutput, ExecReport, ExecStream};
 
 /// Execute a command into multiple vine sessions.
 #[derive(Parser)]
@@ -17,7 +18,26 @@ impl Args {
         let kube = Client::try_default().await?;
 
         let session = ::openark_vine_session_exec::exec(kube, &args).await?;
-        session.join().await;
+        let mut events = Box::pin(session.stream());
+
+        // Print the outputs as soon as possible, prefixed by the pod names
+        let mut report = ExecReport::default();
+        while let Some(event) = events.next().await {
+            if let ExecEvent::Output(ExecOutput { pod, stream, line }) = &event {
+                match stream {
+                    ExecStream::Stdout => println!("[{pod}] {line}"),
+                    ExecStream::Stderr => eprintln!("[{pod}] {line}"),
+                }
+            }
+            report.push(event);
+        }
+
+        super::print_summary(&report);
+
+        let num_failed = report.num_failed();
+        if num_failed > 0 {
+            bail!("Failed at {num_failed} sessions")
+        }
         Ok(())
     }
 }
@@ -0,0 +1,405 @@
+use std::{
+    ffi::{OsStr, OsString},
+    path::{Component, Path, PathBuf},
+    process::Stdio,
+    time::Duration,
+};
+
+use anyhow::{Result, anyhow, bail};
+use clap::Parser;
+use futures::{StreamExt, stream};
+use k8s_openapi::api::core::v1::Pod;
+use kube::{
+    Api, Client, ResourceExt,
+    api::{AttachParams, AttachedProcess},
+};
+use openark_vine_session_api::exec::{ExecReport, ExecResult, ExecState};
+use openark_vine_session_exec::{CONTAINER, SessionPods};
+use tokio::{
+    fs,
+    io::{self, AsyncWriteExt},
+    process::Command,
+    time::timeout,
+};
+
+/// Copy files and directories into or out of multiple vine sessions.
+///
+/// The remote paths are prefixed by `:`, e.g. `openark session cp ./assignment :/home/user/Desktop`
+/// pushes a local file into the directory of every session,
+/// and `openark session cp :/home/user/Desktop/answer ./answers` collects the files into
+/// `./answers/<pod>/answer`.
+#[derive(Parser)]
+pub(crate) struct Args {
+    /// Source path, where the remote one is prefixed by `:`
+    #[arg(value_name = "SRC")]
+    source: String,
+
+    /// Destination directory, where the remote one is prefixed by `:`
+    #[arg(value_name = "DEST")]
+    destination: String,
+
+    /// Target session pod label selector
+    #[arg(long)]
+    label_selector: Option<String>,
+
+    /// Target session namespace
+    #[arg(short = 'n', long, default_value = "vine-session")]
+    namespace: String,
+
+    /// The maximum number of sessions to be copied at once
+    #[arg(short = 'j', long, default_value_t = 16)]
+    parallel: usize,
+
+    /// Timeout of copying each session, as seconds
+    #[arg(long)]
+    timeout_seconds: Option<u64>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Direction {
+    /// Copy a local path into a remote directory
+    Push {
+        source: PathBuf,
+        destination: String,
+    },
+    /// Copy a remote path into the local per-session directories
+    Pull {
+        source: String,
+        destination: PathBuf,
+    },
+}
+
+impl Direction {
+    fn parse(source: &str, destination: &str) -> Result<Self> {
+        match (source.strip_prefix(':'), destination.strip_prefix(':')) {
+            (None, Some(destination)) => Ok(Self::Push {
+                source: source.into(),
+                destination: destination.into(),
+            }),
+            (Some(source), None) => Ok(Self::Pull {
+                source: source.into(),
+                destination: destination.into(),
+            }),
+            (Some(_), Some(_)) | (None, None) => {
+                bail!("Either of the source or the destination should be remote, prefixed by `:`")
+            }
+        }
+    }
+}
+
+impl Args {
+    pub(super) async fn exec(self) -> Result<()> {
+        let Self {
+            source,
+            destination,
+            label_selector,
+            namespace,
+            parallel,
+            timeout_seconds,
+        } = self;
+
+        let direction = Direction::parse(&source, &destination)?;
+        let timeout = timeout_seconds.map(Duration::from_secs);
+
+        let kube = Client::try_default().await?;
+        let SessionPods { api, pods, skipped } =
+            ::openark_vine_session_exec::select(kube, label_selector.as_deref(), Some(&namespace))
+                .await?;
+
+        let mut report = ExecReport::from(skipped);
+        let results: Vec<_> = stream::iter(&pods)
+            .map(|pod| copy(&api, pod, &direction, timeout))
+            .buffer_unordered(parallel.max(1))
+            .collect()
+            .await;
+        report.results.extend(results);
+
+        super::print_summary(&report);
+
+        let num_failed = report.num_failed();
+        if num_failed > 0 {
+            bail!("Failed at {num_failed} sessions")
+        }
+        Ok(())
+    }
+}
+
+async fn copy(
+    api: &Api<Pod>,
+    pod: &Pod,
+    direction: &Direction,
+    timeout_duration: Option<Duration>,
+) -> ExecResult {
+    let name = pod.name_any();
+    let node = pod.spec.as_ref().and_then(|spec| spec.node_name.clone());
+
+    let task = async {
+        match direction {
+            Direction::Push {
+                source,
+                destination,
+            } => push(api, &name, source, destination).await,
+            // Nodes may be rebound to other sessions, so name the directories after the pods
+            Direction::Pull {
+                source,
+                destination,
+            } => pull(api, &name, source, &destination.join(&name)).await,
+        }
+    };
+    let result = match timeout_duration {
+        Some(duration) => match timeout(duration, task).await {
+            Ok(result) => result,
+            Err(_) => Err(anyhow!("Timed out after {}s", duration.as_secs())),
+        },
+        None => task.await,
+    };
+
+    let (state, message) = match result {
+        Ok(()) => (ExecState::Succeeded, None),
+        Err(error) => (ExecState::Failed, Some(error.to_string())),
+    };
+    ExecResult {
+        pod: name,
+        node,
+        state,
+        exit_code: None,
+        message,
+        stdout: String::default(),
+        stderr: String::default(),
+        truncated: false,
+    }
+}
+
+async fn push(api: &Api<Pod>, pod: &str, source: &Path, destination: &str) -> Result<()> {
+    let (parent, file_name) = split_path(source)?;
+
+    // Archive the local files
+    let mut local = Command::new("tar")
+        .arg("-cf")
+        .arg("-")
+        .arg("-C")
+        .arg(parent)
+        .arg(file_name)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .kill_on_drop(true)
+        .spawn()?;
+
+    // Extract them into the remote directory
+    let command = [
+        "sh",
+        "-c",
+        r#"mkdir -p -- "$0" && exec tar -xf - -C "$0""#,
+        destination,
+    ];
+    let ap = AttachParams {
+        container: Some(CONTAINER.into()),
+        stdin: true,
+        stdout: false,
+        stderr: true,
+        ..Default::default()
+    };
+    let mut remote = api.exec(pod, command, &ap).await?;
+
+    let mut reader = local
+        .stdout
+        .take()
+        .ok_or_else(|| anyhow!("Missing local tar output"))?;
+    let mut writer = remote
+        .stdin()
+        .ok_or_else(|| anyhow!("Missing remote tar input"))?;
+    io::copy(&mut reader, &mut writer).await?;
+    // Close the remote input, so that tar does not wait for more
+    writer.shutdown().await?;
+    drop(writer);
+
+    let status = local.wait().await?;
+    if !status.success() {
+        bail!("Failed to archive {}: {status}", source.display())
+    }
+    join(remote).await
+}
+
+async fn pull(api: &Api<Pod>, pod: &str, source: &str, destination: &Path) -> Result<()> {
+    let (parent, file_name) = split_path(Path::new(source))?;
+
+    // Archive the remote files
+    let command = [
+        "tar".into(),
+        "-cf".into(),
+        "-".into(),
+        "-C".into(),
+        parent.to_string_lossy().into_owned(),
+        file_name.to_string_lossy().into_owned(),
+    ];
+    let ap = AttachParams {
+        container: Some(CONTAINER.into()),
+        stdin: false,
+        stdout: true,
+        stderr: true,
+        ..Default::default()
+    };
+    let mut remote = api.exec(pod, command, &ap).await?;
+
+    // Download the archive next to the local per-session directory
+    fs::create_dir_all(destination).await?;
+    let archive: PathBuf = {
+        let mut path = OsString::from(destination);
+        path.push(".tar");
+        path.into()
+    };
+    let mut reader = remote
+        .stdout()
+        .ok_or_else(|| anyhow!("Missing remote tar output"))?;
+    let mut writer = fs::File::create(&archive).await?;
+    let result = async {
+        io::copy(&mut reader, &mut writer).await?;
+        writer.flush().await?;
+        join(remote).await?;
+        extract(&archive, destination).await
+    }
+    .await;
+    fs::remove_file(&archive).await?;
+    result
+}
+
+/// Extract a local archive into the directory, rejecting the members escaping it.
+async fn extract(archive: &Path, destination: &Path) -> Result<()> {
+    let output = Command::new("tar")
+        .arg("-tf")
+        .arg(archive)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .output()
+        .await?;
+    if !output.status.success() {
+        bail!("Failed to list {}: {}", archive.display(), output.status)
+    }
+    for member in String::from_utf8_lossy(&output.stdout).lines() {
+        validate_member(member)?;
+    }
+
+    // Do not restore the remote owners and permissions
+    let status = Command::new("tar")
+        .arg("-xf")
+        .arg(archive)
+        .arg("--no-same-owner")
+        .arg("--no-same-permissions")
+        .arg("-C")
+        .arg(destination)
+        .stdin(Stdio::null())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .status()
+        .await?;
+    if !status.success() {
+        bail!("Failed to extract into {}: {status}", destination.display())
+    }
+    Ok(())
+}
+
+/// Reject the archive members which are absolute or refer to their parents.
+fn validate_member(member: &str) -> Result<()> {
+    let is_valid = Path::new(member)
+        .components()
+        .all(|component| matches!(component, Component::CurDir | Component::Normal(_)));
+    if is_valid {
+        Ok(())
+    } else {
+        bail!("Invalid archive member: {member}")
+    }
+}
+
+/// Split a path into the parent directory and the file name, as `tar -C` takes them.
+fn split_path(path: &Path) -> Result<(&Path, &OsStr)> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("Invalid path: {}", path.display()))?;
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        Some(_) | None => Path::new("."),
+    };
+    Ok((parent, file_name))
+}
+
+/// Wait for the remote process, collecting its error messages on failure.
+async fn join(mut process: AttachedProcess) -> Result<()> {
+    let stderr = process.stderr();
+    let status = process.take_status();
+
+    let mut message = Vec::default();
+    if let Some(mut stderr) = stderr {
+        io::copy(&mut stderr, &mut message).await?;
+    }
+    let status = match status {
+        Some(status) => status.await,
+        None => None,
+    };
+    process.join().await?;
+
+    match status {
+        Some(status) if status.status.as_deref() == Some("Success") => Ok(()),
+        Some(status) => {
+            let message = String::from_utf8_lossy(&message);
+            let message = message.trim();
+            if message.is_empty() {
+                bail!("{}", status.message.unwrap_or_default())
+            } else {
+                bail!("{message}")
+            }
+        }
+        None => bail!("Missing exit status"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_direction() {
+        assert_eq!(
+            Direction::parse("./assignment", ":/home/user/Desktop").unwrap(),
+            Direction::Push {
+                source: "./assignment".into(),
+                destination: "/home/user/Desktop".into(),
+            },
+        );
+        assert_eq!(
+            Direction::parse(":/home/user/Desktop/answer", "./answers").unwrap(),
+            Direction::Pull {
+                source: "/home/user/Desktop/answer".into(),
+                destination: "./answers".into(),
+            },
+        );
+        assert!(Direction::parse(":/a", ":/b").is_err());
+        assert!(Direction::parse("./a", "./b").is_err());
+    }
+
+    #[test]
+    fn split_paths() {
+        fn split(path: &str) -> (&Path, &OsStr) {
+            split_path(Path::new(path)).unwrap()
+        }
+
+        assert_eq!(split("answer"), (Path::new("."), OsStr::new("answer")));
+        assert_eq!(split("./answer"), (Path::new("."), OsStr::new("answer")));
+        assert_eq!(
+            split("/home/user/answer/"),
+            (Path::new("/home/user"), OsStr::new("answer")),
+        );
+        assert!(split_path(Path::new("/")).is_err());
+        assert!(split_path(Path::new("..")).is_err());
+    }
+
+    #[test]
+    fn reject_escaping_members() {
+        assert!(validate_member("answer").is_ok());
+        assert!(validate_member("./answer/main.py").is_ok());
+        assert!(validate_member("/etc/passwd").is_err());
+        assert!(validate_member("../answer").is_err());
+        assert!(validate_member("answer/../../etc").is_err());
+    }
+}
@@ -1,17 +1,72 @@
 mod batch;
+mod cp;
+mod status;
 
 use anyhow::Result;
 use clap::Subcommand;
+use openark_vine_session_api::exec::{ExecReport, ExecResult};
 
 #[derive(Subcommand)]
 pub(crate) enum Args {
     Batch(self::batch::Args),
+    Cp(self::cp::Args),
+    Status(self::status::Args),
 }
 
 impl Args {
     pub(super) async fn exec(self) -> Result<()> {
         match self {
             Self::Batch(args) => args.exec().await,
+            Self::Cp(args) => args.exec().await,
+            Self::Status(args) => args.exec().await,
         }
     }
 }
+
+/// Print the results of the sessions as a table.
+fn print_summary(report: &ExecReport) {
+    let mut results: Vec<_> = report.results.iter().collect();
+    results.sort_by(|a, b| a.pod.cmp(&b.pod));
+
+    let rows: Vec<[String; 5]> = results
+        .into_iter()
+        .map(|result| {
+            let ExecResult {
+                pod,
+                node,
+                state,
+                exit_code,
+                message,
+                ..
+            } = result;
+            [
+                pod.clone(),
+                node.clone().unwrap_or_else(|| "-".into()),
+                state.to_string(),
+                exit_code
+                    .map(|code| code.to_string())
+                    .unwrap_or_else(|| "-".into()),
+                message.clone().unwrap_or_default(),
+            ]
+        })
+        .collect();
+
+    let header = ["POD", "NODE", "STATE", "EXIT", "MESSAGE"].map(String::from);
+    let mut widths = header.each_ref().map(String::len);
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    println!();
+    for row in ::std::iter::once(&header).chain(&rows) {
+        let line = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{cell:width$}"))
+            .collect::<Vec<_>>()
+            .join("  ");
+        println!("{}", line.trim_end());
+    }
+}
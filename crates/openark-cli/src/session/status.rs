@@ -0,0 +1,89 @@
+use anyhow::Result;
+use clap::Parser;
+use k8s_openapi::api::core::v1::Pod;
+use kube::{Api, Client, ResourceExt, api::ListParams};
+use openark_vine_session_api::session::{SessionEvent, SessionStatus};
+
+/// Show the lifecycle states of vine sessions, and why they are not running.
+#[derive(Parser)]
+pub(crate) struct Args {
+    /// Target session pod label selector
+    #[arg(long)]
+    label_selector: Option<String>,
+
+    /// Target session namespace
+    #[arg(short = 'n', long, default_value = "vine-session")]
+    namespace: String,
+
+    /// Whether to show the events of each session
+    #[arg(short, long)]
+    events: bool,
+}
+
+impl Args {
+    pub(super) async fn exec(self) -> Result<()> {
+        let Self {
+            label_selector,
+            namespace,
+            events,
+        } = self;
+
+        let kube = Client::try_default().await?;
+        let api = Api::<Pod>::namespaced(kube, &namespace);
+        let lp = ListParams {
+            label_selector,
+            ..Default::default()
+        };
+        let mut pods = api.list(&lp).await?.items;
+        pods.sort_by_key(|pod| pod.name_any());
+
+        if pods.is_empty() {
+            println!("No sessions");
+            return Ok(());
+        }
+
+        for pod in &pods {
+            let SessionStatus {
+                level: _,
+                state,
+                reason,
+                message,
+            } = SessionStatus::from_pod(pod);
+
+            let mut line = format!(
+                "{name} ({node}): {state}",
+                name = pod.name_any(),
+                node = pod
+                    .spec
+                    .as_ref()
+                    .and_then(|spec| spec.node_name.as_deref())
+                    .unwrap_or("<none>"),
+                state = state.map(|state| state.to_string()).unwrap_or_default(),
+            );
+            if let Some(reason) = reason {
+                line.push_str(&format!(" [{reason}]"));
+            }
+            if let Some(message) = message {
+                line.push_str(&format!(" {message}"));
+            }
+            println!("{line}");
+
+            if events {
+                for SessionEvent {
+                    level,
+                    reason,
+                    message,
+                    timestamp,
+                } in SessionEvent::from_pod(pod)
+                {
+                    let timestamp = timestamp
+                        .map(|timestamp| timestamp.to_string())
+                        .unwrap_or_else(|| "-".into());
+                    let message = message.unwrap_or_default();
+                    println!("  {timestamp} {level} {reason}: {message}");
+                }
+            }
+        }
+        Ok(())
+    }
+}
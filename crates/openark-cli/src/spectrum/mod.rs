@@ -0,0 +1,17 @@
+mod simulate;
+
+use anyhow::Result;
+use clap::Subcommand;
+
+#[derive(Subcommand)]
+pub(crate) enum Args {
+    Simulate(self::simulate::Args),
+}
+
+impl Args {
+    pub(super) async fn exec(self) -> Result<()> {
+        match self {
+            Self::Simulate(args) => args.exec().await,
+        }
+    }
+}
@@ -0,0 +1,424 @@
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Result, bail};
+use clap::{Parser, ValueEnum};
+use openark_spectrum_api::{
+    pool::{PoolCrd, PoolSolver},
+    pool_claim::PoolClaimCrd,
+    schema::{CommitState, PoolResource},
+};
+use openark_spectrum_scheduler::{
+    item::{Item, Resource, ScheduledItem, Topology, WeightedItems},
+    schedule,
+};
+use ordered_float::OrderedFloat;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+/// Simulate the scheduling of a pool, and print how the bindings would change.
+#[derive(Parser)]
+pub(crate) struct Args {
+    /// A JSON snapshot of the pool, its claims and resources
+    #[arg(value_name = "PATH")]
+    snapshot: PathBuf,
+
+    /// A JSON list of the claims replacing those of the same names, e.g. with new resource settings
+    #[arg(long, value_name = "PATH")]
+    claims: Option<PathBuf>,
+
+    /// The format of the changes to be printed
+    #[arg(long, short, value_name = "FORMAT", value_enum, default_value_t)]
+    output: OutputFormat,
+
+    /// Override the solver of the pool [possible values: milp, greedy]
+    #[arg(long, value_name = "NAME", value_parser = parse_solver)]
+    solver: Option<PoolSolver>,
+}
+
+/// A format of the simulated changes.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// A JSON list of the changes per claim
+    Json,
+    /// A human-readable list of the changes per claim
+    #[default]
+    Table,
+}
+
+impl Args {
+    pub(super) async fn exec(self) -> Result<()> {
+        let Self {
+            snapshot,
+            claims,
+            output,
+            solver,
+        } = self;
+
+        let snapshot = load_snapshot(&snapshot, claims.as_deref()).await?;
+        let changes = simulate(&snapshot, solver)?;
+        match output {
+            OutputFormat::Json => println!("{}", ::serde_json::to_string_pretty(&changes)?),
+            OutputFormat::Table => print!("{}", format_table(&changes)),
+        }
+        Ok(())
+    }
+}
+
+/// Loads the snapshot, replacing its claims with the new ones if given.
+async fn load_snapshot(path: &Path, claims: Option<&Path>) -> Result<Snapshot> {
+    let mut snapshot: Snapshot = read_json(path).await?;
+
+    // Apply the new claims
+    if let Some(path) = claims {
+        let claims: Vec<PoolClaimCrd> = read_json(path).await?;
+        for claim in claims {
+            match snapshot
+                .claims
+                .iter_mut()
+                .find(|last| last.metadata.name == claim.metadata.name)
+            {
+                Some(last) => *last = claim,
+                None => snapshot.claims.push(claim),
+            }
+        }
+    }
+    Ok(snapshot)
+}
+
+/// Schedules the pool, and returns how the bindings of each claim would change.
+fn simulate(snapshot: &Snapshot, solver: Option<PoolSolver>) -> Result<Vec<Change>> {
+    let Snapshot {
+        pool,
+        claims,
+        resources,
+    } = snapshot;
+
+    // Collect the claims of the pool
+    let pool_name = pool.metadata.name.as_deref().unwrap_or_default();
+    let claims: Vec<_> = claims
+        .iter()
+        .filter(|claim| claim.spec.pool_name == pool_name)
+        .filter_map(|claim| Some((claim.metadata.name.as_deref()?, claim)))
+        .collect();
+
+    let items = claims
+        .iter()
+        .map(|&(name, claim)| Item {
+            claim: Cow::Borrowed(claim),
+            resource: Resource::from(&claim.spec.resources),
+            item: name,
+        })
+        .collect();
+    let bound = resources
+        .iter()
+        .map(|resource| PoolResource {
+            claim: resource
+                .claim
+                .as_deref()
+                .and_then(|name| claims.iter().position(|&(claim, _)| claim == name)),
+            state: resource.state,
+        })
+        .collect();
+    let weighted = WeightedItems {
+        items: resources
+            .iter()
+            .map(|resource| resource.name.as_str())
+            .collect(),
+        weights: resources
+            .iter()
+            .map(|resource| resource.weight.map(OrderedFloat))
+            .collect(),
+        topologies: resources
+            .iter()
+            .map(|SnapshotResource { topology, .. }| Topology {
+                node: topology.node.clone(),
+                rack: topology.rack.clone(),
+                zone: topology.zone.clone(),
+            })
+            .collect(),
+    };
+
+    let solver = solver.unwrap_or(pool.spec.solver);
+    let scheduled = schedule(items, bound, weighted, solver)?;
+
+    // Collect the last bindings
+    let mut last: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::default();
+    for resource in resources {
+        if let Some(claim) = resource.claim.as_deref() {
+            last.entry(claim)
+                .or_default()
+                .insert(resource.name.as_str());
+        }
+    }
+
+    Ok(diff(&last, &scheduled))
+}
+
+/// A snapshot of a pool to be simulated.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Snapshot {
+    pool: PoolCrd,
+    #[serde(default)]
+    claims: Vec<PoolClaimCrd>,
+    #[serde(default)]
+    resources: Vec<SnapshotResource>,
+}
+
+/// A resource of the pool, with its weight and the current binding.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SnapshotResource {
+    /// The address of the endpoint
+    name: String,
+    #[serde(default)]
+    weight: Option<f64>,
+    /// The name of the claim which the resource is bound to
+    #[serde(default)]
+    claim: Option<String>,
+    #[serde(default)]
+    state: CommitState,
+    #[serde(default)]
+    topology: SnapshotTopology,
+}
+
+#[derive(Default, Deserialize)]
+struct SnapshotTopology {
+    #[serde(default)]
+    node: Option<String>,
+    #[serde(default)]
+    rack: Option<String>,
+    #[serde(default)]
+    zone: Option<String>,
+}
+
+async fn read_json<T>(path: &Path) -> Result<T>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    let data = match fs::read(path).await {
+        Ok(data) => data,
+        Err(error) => bail!("failed to read {}: {error}", path.display()),
+    };
+    match ::serde_json::from_slice(&data) {
+        Ok(value) => Ok(value),
+        Err(error) => bail!("failed to parse {}: {error}", path.display()),
+    }
+}
+
+fn parse_solver(name: &str) -> Result<PoolSolver, String> {
+    match name.to_lowercase().as_str() {
+        "milp" => Ok(PoolSolver::Milp),
+        "greedy" => Ok(PoolSolver::Greedy),
+        _ => Err(format!("unknown solver: {name}")),
+    }
+}
+
+/// The simulated changes of the bindings of a claim.
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Change {
+    claim: String,
+    /// The number of the resources bound before the scheduling
+    last: usize,
+    /// The number of the resources bound after the scheduling
+    next: usize,
+    /// The total weight of the bound resources
+    load: f64,
+    /// Whether the minimum weight could not be met
+    unsatisfiable: bool,
+    added: Vec<String>,
+    removed: Vec<String>,
+}
+
+fn diff(
+    last: &BTreeMap<&str, BTreeSet<&str>>,
+    scheduled: &[ScheduledItem<&str, &str>],
+) -> Vec<Change> {
+    scheduled
+        .iter()
+        .map(|item| {
+            let next: BTreeSet<_> = item.resources.iter().copied().collect();
+            let last = last.get(item.item).cloned().unwrap_or_default();
+            Change {
+                claim: item.item.into(),
+                last: last.len(),
+                next: next.len(),
+                load: item.load,
+                unsatisfiable: item.unsatisfiable,
+                added: next.difference(&last).map(|&name| name.into()).collect(),
+                removed: last.difference(&next).map(|&name| name.into()).collect(),
+            }
+        })
+        .collect()
+}
+
+fn format_table(changes: &[Change]) -> String {
+    let mut buf = String::new();
+    let mut changed = false;
+    for change in changes {
+        buf.push_str(&format!(
+            "{name}: {last} -> {next} resources (load: {load})",
+            name = change.claim,
+            last = change.last,
+            next = change.next,
+            load = change.load,
+        ));
+        if change.unsatisfiable {
+            buf.push_str(" [unsatisfiable]");
+        }
+        buf.push('\n');
+
+        for resource in &change.added {
+            buf.push_str(&format!("  + {resource}\n"));
+        }
+        for resource in &change.removed {
+            buf.push_str(&format!("  - {resource}\n"));
+        }
+        changed |= !change.added.is_empty() || !change.removed.is_empty();
+    }
+
+    if !changed {
+        buf.push_str("No changes\n");
+    }
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use serde_json::json;
+
+    use super::*;
+
+    fn fixture() -> ::serde_json::Value {
+        json!({
+            "pool": {
+                "apiVersion": "org.ulagbulag.io/v1alpha1",
+                "kind": "Pool",
+                "metadata": { "name": "gpu" },
+                "spec": {
+                    "metricsClassName": "default",
+                    "targetRef": {},
+                    "solver": "Greedy",
+                },
+            },
+            "claims": [
+                {
+                    "apiVersion": "org.ulagbulag.io/v1alpha1",
+                    "kind": "PoolClaim",
+                    "metadata": { "name": "team-a" },
+                    "spec": { "poolName": "gpu", "resources": { "min": 2.0 } },
+                },
+                {
+                    "apiVersion": "org.ulagbulag.io/v1alpha1",
+                    "kind": "PoolClaim",
+                    "metadata": { "name": "team-b" },
+                    "spec": { "poolName": "cpu" },
+                },
+            ],
+            "resources": [
+                { "name": "10.0.0.1", "weight": 1.0, "claim": "team-a", "state": "running" },
+                { "name": "10.0.0.2", "weight": 1.0 },
+            ],
+        })
+    }
+
+    async fn write_json(name: &str, value: &::serde_json::Value) -> PathBuf {
+        let path = env::temp_dir().join(format!("openark-simulate-{}-{name}", std::process::id()));
+        fs::write(&path, value.to_string()).await.unwrap();
+        path
+    }
+
+    #[tokio::test]
+    async fn load_snapshot_with_claims() {
+        let snapshot = write_json("snapshot.json", &fixture()).await;
+        let claims = write_json(
+            "claims.json",
+            &json!([
+                {
+                    "apiVersion": "org.ulagbulag.io/v1alpha1",
+                    "kind": "PoolClaim",
+                    "metadata": { "name": "team-a" },
+                    "spec": { "poolName": "gpu", "resources": { "min": 1.0 } },
+                },
+                {
+                    "apiVersion": "org.ulagbulag.io/v1alpha1",
+                    "kind": "PoolClaim",
+                    "metadata": { "name": "team-c" },
+                    "spec": { "poolName": "gpu" },
+                },
+            ]),
+        )
+        .await;
+
+        let loaded = load_snapshot(&snapshot, None).await.unwrap();
+        assert_eq!(loaded.claims.len(), 2);
+        assert_eq!(loaded.claims[0].spec.resources.min, Some(2.0));
+        assert_eq!(loaded.resources.len(), 2);
+        assert_eq!(loaded.resources[0].state, CommitState::Running);
+
+        // the claims of the same names are replaced, and the others are appended
+        let loaded = load_snapshot(&snapshot, Some(&claims)).await.unwrap();
+        let names: Vec<_> = loaded
+            .claims
+            .iter()
+            .map(|claim| claim.metadata.name.as_deref().unwrap())
+            .collect();
+        assert_eq!(names, ["team-a", "team-b", "team-c"]);
+        assert_eq!(loaded.claims[0].spec.resources.min, Some(1.0));
+
+        // the missing or malformed files are rejected
+        let missing = env::temp_dir().join("openark-simulate-missing.json");
+        assert!(load_snapshot(&missing, None).await.is_err());
+        let malformed = write_json("malformed.json", &json!({ "claims": [] })).await;
+        assert!(load_snapshot(&malformed, None).await.is_err());
+
+        for path in [snapshot, claims, malformed] {
+            fs::remove_file(path).await.unwrap();
+        }
+    }
+
+    #[test]
+    fn print_changes() {
+        let snapshot: Snapshot = ::serde_json::from_value(fixture()).unwrap();
+        let changes = simulate(&snapshot, None).unwrap();
+
+        // the claims of the other pools are skipped
+        assert_eq!(
+            changes,
+            [Change {
+                claim: "team-a".into(),
+                last: 1,
+                next: 2,
+                load: 2.0,
+                unsatisfiable: false,
+                added: vec!["10.0.0.2".into()],
+                removed: vec![],
+            }],
+        );
+
+        assert_eq!(
+            format_table(&changes),
+            "team-a: 1 -> 2 resources (load: 2)\n  + 10.0.0.2\n",
+        );
+        assert_eq!(
+            ::serde_json::to_value(&changes).unwrap(),
+            json!([{
+                "claim": "team-a",
+                "last": 1,
+                "next": 2,
+                "load": 2.0,
+                "unsatisfiable": false,
+                "added": ["10.0.0.2"],
+                "removed": [],
+            }]),
+        );
+        assert_eq!(format_table(&[]), "No changes\n");
+    }
+}
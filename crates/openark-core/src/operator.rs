@@ -263,6 +263,24 @@ pub struct Context {
 
 impl Context {
     pub async fn commit<K, R>(&self, api: &Api<K>, object: &K, status: Status<R>) -> Result<Action>
+    where
+        K: Resource,
+        R: Reason,
+    {
+        self.commit_with_conditions(api, object, status, Vec::default())
+            .await
+    }
+
+    /// Commit the status with the additional conditions,
+    /// following the `Accepted` condition.
+    ///
+    pub async fn commit_with_conditions<K, R>(
+        &self,
+        api: &Api<K>,
+        object: &K,
+        status: Status<R>,
+        extra_conditions: Vec<Condition>,
+    ) -> Result<Action>
     where
         K: Resource,
         R: Reason,
@@ -274,7 +292,8 @@ impl Context {
         } = status.clone();
 
         let metadata = object.meta();
-        let conditions = vec![status.into_condition(metadata)];
+        let mut conditions = vec![status.into_condition(metadata)];
+        conditions.extend(extra_conditions);
 
         // Skip updating status if nothing has been changed
         let last_conditions = object.conditions();
@@ -4,7 +4,9 @@ use anyhow::{Result, anyhow, bail};
 use itertools::Itertools;
 use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
 use kube::{Api, Client, Error, api::ListParams};
-use openark_kiss_api::r#box::{BoxCrd, BoxGroupRole, BoxGroupSpec, BoxSpec, BoxState};
+use openark_kiss_api::r#box::{
+    BoxCrd, BoxGroupRole, BoxGroupSpec, BoxNetworkInterfaceRole, BoxSpec, BoxState,
+};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 #[cfg(feature = "tracing")]
@@ -115,7 +117,7 @@ impl<'a> ClusterState<'a> {
         get_nodes_as_string(nodes, NODE_ROLE, fn_sort)
     }
 
-    pub fn get_etcd_nodes_as_string(&self) -> String {
+    fn get_etcd_nodes(&self) -> Vec<&ClusterBoxState> {
         let filter = ClusterBoxFilter::RunningWith {
             uuid: self.owner_uuid,
         };
@@ -130,7 +132,11 @@ impl<'a> ClusterState<'a> {
         if nodes.len().is_multiple_of(2) {
             nodes.pop();
         }
+        nodes
+    }
 
+    pub fn get_etcd_nodes_as_string(&self) -> String {
+        let nodes = self.get_etcd_nodes();
         let fn_sort = sort_nodes_by_date;
 
         const NODE_ROLE: &str = "etcd";
@@ -215,6 +221,44 @@ impl<'a> ClusterState<'a> {
     pub fn is_new(&self) -> bool {
         self.is_node_control_plane() && !self.control_planes.is_running()
     }
+
+    /// Whether the owner can be taken down without losing the etcd quorum.
+    pub fn is_etcd_quorum_safe(&self) -> bool {
+        let members = self.get_etcd_nodes();
+        let is_safe = is_etcd_quorum_safe(&members, self.owner_uuid);
+
+        #[cfg(feature = "tracing")]
+        info!(
+            "Cluster \"{}\" status: etcd quorum is {} without the owner",
+            &self.owner_group.cluster_name,
+            if is_safe { "kept" } else { "lost" },
+        );
+        is_safe
+    }
+}
+
+/// Whether the owner can be taken down without losing the etcd quorum of the members.
+fn is_etcd_quorum_safe(members: &[&ClusterBoxState], owner: Uuid) -> bool {
+    // NOTE: the members without addresses are never joined to the etcd cluster
+    let members: Vec<_> = members
+        .iter()
+        .filter(|node| node.get_host().is_some())
+        .collect();
+    if !members.iter().any(|node| node.uuid == owner) {
+        return true;
+    }
+
+    // NOTE: a single-member etcd cannot keep its quorum anyway
+    if members.len() <= 1 {
+        return true;
+    }
+
+    let quorum = members.len() / 2 + 1;
+    let healthy = members
+        .iter()
+        .filter(|node| node.uuid != owner && node.is_healthy)
+        .count();
+    healthy >= quorum
 }
 
 struct ClusterBoxGroup {
@@ -364,6 +408,8 @@ struct ClusterBoxState {
     created_at: Option<Time>,
     hostname: String,
     ip: Option<IpAddr>,
+    ip_data: Option<IpAddr>,
+    is_healthy: bool,
     is_ready: bool,
     is_running: bool,
     uuid: Uuid,
@@ -383,23 +429,112 @@ impl ClusterBoxState {
             ip: object
                 .status
                 .as_ref()
-                .and_then(|status| status.access.primary.as_ref())
+                .and_then(|status| status.access.management())
                 .map(|interface| interface.address),
+            ip_data: object
+                .status
+                .as_ref()
+                .and_then(|status| status.access.address(BoxNetworkInterfaceRole::Data)),
+            is_healthy: object
+                .status
+                .as_ref()
+                .map(|status| matches!(status.state, BoxState::Running))
+                .unwrap_or_default(),
+            // NOTE: boxes under maintenance or upgrade are still members of the cluster
             is_ready: object
                 .status
                 .as_ref()
-                .map(|status| matches!(status.state, BoxState::Ready | BoxState::Running))
+                .map(|status| {
+                    matches!(
+                        status.state,
+                        BoxState::Ready | BoxState::Running | BoxState::Upgrading
+                    ) || status.state.is_maintenance() && status.bind_group.is_some()
+                })
                 .unwrap_or_default(),
             is_running: object
                 .status
                 .as_ref()
-                .map(|status| matches!(status.state, BoxState::Running))
+                .map(|status| {
+                    matches!(status.state, BoxState::Running | BoxState::Upgrading)
+                        || status.state.is_maintenance() && status.bind_group.is_some()
+                })
                 .unwrap_or_default(),
             uuid: object.spec.machine.uuid,
         }
     }
 
+    /// Pack the host as `<hostname>:<management ip>[:<data ip>]`.
     fn get_host(&self) -> Option<String> {
-        Some(format!("{}:{}", &self.hostname, self.ip?))
+        let ip = self.ip?;
+        match self.ip_data {
+            Some(ip_data) if ip_data != ip => Some(format!("{}:{ip}:{ip_data}", &self.hostname)),
+            _ => Some(format!("{}:{ip}", &self.hostname)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    fn node(id: u128, is_healthy: bool) -> ClusterBoxState {
+        ClusterBoxState {
+            created_at: None,
+            hostname: format!("node-{id}"),
+            ip: Some(IpAddr::V4(Ipv4Addr::new(10, 0, 0, id as u8))),
+            ip_data: None,
+            is_healthy,
+            is_ready: true,
+            is_running: true,
+            uuid: Uuid::from_u128(id),
+        }
+    }
+
+    #[test]
+    fn test_etcd_quorum_of_three_members() {
+        let owner = Uuid::from_u128(1);
+        let (a, b, c) = (node(1, true), node(2, true), node(3, true));
+        assert!(is_etcd_quorum_safe(&[&a, &b, &c], owner));
+
+        let b = node(2, false);
+        assert!(!is_etcd_quorum_safe(&[&a, &b, &c], owner));
+    }
+
+    #[test]
+    fn test_etcd_quorum_of_five_members() {
+        let owner = Uuid::from_u128(1);
+        let nodes = [
+            node(1, true),
+            node(2, true),
+            node(3, true),
+            node(4, true),
+            node(5, false),
+        ];
+        let members: Vec<_> = nodes.iter().collect();
+        assert!(is_etcd_quorum_safe(&members, owner));
+
+        let nodes = [
+            node(1, true),
+            node(2, true),
+            node(3, true),
+            node(4, false),
+            node(5, false),
+        ];
+        let members: Vec<_> = nodes.iter().collect();
+        assert!(!is_etcd_quorum_safe(&members, owner));
+    }
+
+    #[test]
+    fn test_etcd_quorum_of_non_members() {
+        let (a, b, c) = (node(1, true), node(2, false), node(3, false));
+        assert!(is_etcd_quorum_safe(&[&a, &b, &c], Uuid::from_u128(4)));
+        assert!(is_etcd_quorum_safe(&[&a], a.uuid));
+
+        // the members without addresses are not joined yet
+        let mut b = node(2, true);
+        b.ip = None;
+        assert!(is_etcd_quorum_safe(&[&a, &b], a.uuid));
     }
 }
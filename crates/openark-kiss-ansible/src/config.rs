@@ -4,7 +4,7 @@ use anyhow::{Error, Result, anyhow};
 use ipnet::Ipv4Net;
 use k8s_openapi::api::core::v1::ConfigMap;
 use kube::{Api, Client};
-use openark_kiss_api::r#box::BoxGroupRole;
+use openark_kiss_api::r#box::{BoxGroupRole, BoxVersionSpec};
 #[cfg(feature = "tracing")]
 use tracing::{Level, instrument};
 
@@ -76,6 +76,16 @@ impl KissConfig {
             os_version: infer(&config, "os_version")?,
         })
     }
+
+    /// Return the version which the boxes should be provisioned with.
+    pub fn version(&self) -> BoxVersionSpec {
+        BoxVersionSpec {
+            kubespray_image: self.kubespray_image.clone(),
+            os_dist: self.os_dist.clone(),
+            os_kernel: self.os_kernel.clone(),
+            os_version: self.os_version.clone(),
+        }
+    }
 }
 
 pub fn infer<K: AsRef<str>, R>(config: &ConfigMap, key: K) -> Result<R>
@@ -1,5 +1,5 @@
 pub mod cluster;
-mod config;
+pub mod config;
 pub mod job;
 
 use anyhow::Result;
@@ -20,7 +20,9 @@ use kube::{
     api::{DeleteParams, ListParams, PostParams},
     core::ObjectMeta,
 };
-use openark_kiss_api::r#box::{BoxCrd, BoxGroupRole, BoxGroupSpec, BoxPowerType, BoxState};
+use openark_kiss_api::r#box::{
+    BoxCrd, BoxGroupRole, BoxGroupSpec, BoxNetworkInterfaceRole, BoxPowerType, BoxState,
+};
 #[cfg(feature = "tracing")]
 use tracing::{Level, info, instrument};
 
@@ -31,6 +33,9 @@ pub struct AnsibleClient {
 }
 
 impl AnsibleClient {
+    /// Keeps the finished jobs and their pods until their history is recorded.
+    pub const FINALIZER_HISTORY: &'static str = "kiss.ulagbulag.io/history";
+
     pub const LABEL_BOX_NAME: &'static str = "kiss.ulagbulag.io/box_name";
     pub const LABEL_BOX_MACHINE_UUID: &'static str = "kiss.ulagbulag.io/box_machine_uuid";
     pub const LABEL_COMPLETED_STATE: &'static str = "kiss.ulagbulag.io/completed_state";
@@ -47,6 +52,34 @@ impl AnsibleClient {
         })
     }
 
+    /// Delete all non-critical jobs and cronjobs of the box.
+    #[cfg_attr(feature = "tracing", instrument(level = Level::INFO, skip(self, r#box), err(Display)))]
+    pub async fn cancel(&self, r#box: &BoxCrd) -> Result<(), Error> {
+        let box_name = r#box.spec.machine.uuid.to_string();
+
+        let dp = DeleteParams::background();
+        let lp = ListParams {
+            label_selector: Some(format!(
+                "{}={box_name},{}!=true",
+                AnsibleClient::LABEL_BOX_NAME,
+                AnsibleClient::LABEL_JOB_IS_CRITICAL,
+            )),
+            ..Default::default()
+        };
+
+        // delete all previous cronjobs
+        {
+            let api = Api::<CronJob>::namespaced(self.client.clone(), &self.namespace);
+            api.delete_collection(&dp, &lp).await?;
+        }
+        // delete all previous jobs
+        {
+            let api = Api::<Job>::namespaced(self.client.clone(), &self.namespace);
+            api.delete_collection(&dp, &lp).await?;
+        }
+        Ok(())
+    }
+
     #[cfg_attr(feature = "tracing", instrument(level = Level::INFO, skip(self, job), err(Display)))]
     pub async fn spawn(&self, job: AnsibleJob<'_>) -> Result<bool, Error> {
         let box_name = job.r#box.spec.machine.uuid.to_string();
@@ -75,28 +108,8 @@ impl AnsibleClient {
             _ => "k8s-cluster-critical",
         };
 
-        {
-            let dp = DeleteParams::background();
-            let lp = ListParams {
-                label_selector: Some(format!(
-                    "{}={box_name},{}!=true",
-                    AnsibleClient::LABEL_BOX_NAME,
-                    AnsibleClient::LABEL_JOB_IS_CRITICAL,
-                )),
-                ..Default::default()
-            };
-
-            // delete all previous cronjobs
-            {
-                let api = Api::<CronJob>::namespaced(self.client.clone(), &self.namespace);
-                api.delete_collection(&dp, &lp).await?;
-            }
-            // delete all previous jobs
-            {
-                let api = Api::<Job>::namespaced(self.client.clone(), &self.namespace);
-                api.delete_collection(&dp, &lp).await?;
-            }
-        }
+        // delete all previous jobs
+        self.cancel(job.r#box).await?;
 
         // realize mutual exclusivity (QUEUE)
         let cluster_state = self::cluster::ClusterState::load(
@@ -118,6 +131,17 @@ impl AnsibleClient {
             return Ok(false);
         }
 
+        // upgrade the target box only (one box at a time)
+        let limit = if matches!(job.new_state, Some(BoxState::Upgrading)) {
+            let hostname = job.r#box.spec.machine.hostname();
+            Some(match group.role {
+                BoxGroupRole::ControlPlane => format!("--limit=localhost:etcd:{hostname}"),
+                _ => format!("--limit=localhost:{hostname}"),
+            })
+        } else {
+            None
+        };
+
         // define the object
         let metadata = ObjectMeta {
             name: Some(name.clone()),
@@ -151,6 +175,7 @@ impl AnsibleClient {
             ttl_seconds_after_finished: Some(0),
             template: PodTemplateSpec {
                 metadata: Some(ObjectMeta {
+                    finalizers: Some(vec![Self::FINALIZER_HISTORY.into()]),
                     labels: metadata.labels.clone(),
                     ..Default::default()
                 }),
@@ -188,21 +213,26 @@ impl AnsibleClient {
                         image: Some(self.kiss.kubespray_image.clone()),
                         image_pull_policy: Some("Always".into()),
                         command: Some(vec!["ansible-playbook".into()]),
-                        args: Some(vec![
-                            "--become".into(),
-                            "--become-user=root".into(),
-                            "--inventory".into(),
-                            "/root/ansible/defaults/defaults.yaml".into(),
-                            "--inventory".into(),
-                            "/root/ansible/defaults/all.yaml".into(),
-                            "--inventory".into(),
-                            "/root/ansible/defaults.yaml".into(),
-                            "--inventory".into(),
-                            "/root/ansible/config.yaml".into(),
-                            "--inventory".into(),
-                            "/root/ansible/hosts.yaml".into(),
-                            format!("/opt/playbook/{}", group.role.to_playbook()),
-                        ]),
+                        args: Some(
+                            [
+                                "--become".into(),
+                                "--become-user=root".into(),
+                                "--inventory".into(),
+                                "/root/ansible/defaults/defaults.yaml".into(),
+                                "--inventory".into(),
+                                "/root/ansible/defaults/all.yaml".into(),
+                                "--inventory".into(),
+                                "/root/ansible/defaults.yaml".into(),
+                                "--inventory".into(),
+                                "/root/ansible/config.yaml".into(),
+                                "--inventory".into(),
+                                "/root/ansible/hosts.yaml".into(),
+                            ]
+                            .into_iter()
+                            .chain(limit)
+                            .chain([format!("/opt/playbook/{}", group.role.to_playbook())])
+                            .collect(),
+                        ),
                         env: Some(vec![
                             EnvVar {
                                 name: "ansible_host".into(),
@@ -226,6 +256,24 @@ impl AnsibleClient {
                                     .map(|interface| interface.address.to_string()),
                                 ..Default::default()
                             },
+                            EnvVar {
+                                name: "kiss_network_data_address".into(),
+                                value: box_status
+                                    .and_then(|status| {
+                                        status.access.address(BoxNetworkInterfaceRole::Data)
+                                    })
+                                    .map(|address| address.to_string()),
+                                ..Default::default()
+                            },
+                            EnvVar {
+                                name: "kiss_network_storage_address".into(),
+                                value: box_status
+                                    .and_then(|status| {
+                                        status.access.address(BoxNetworkInterfaceRole::Storage)
+                                    })
+                                    .map(|address| address.to_string()),
+                                ..Default::default()
+                            },
                             EnvVar {
                                 name: "ansible_ssh_private_key_file".into(),
                                 value: Some("/root/.ssh/id_ed25519".into()),
@@ -261,7 +309,10 @@ impl AnsibleClient {
                             EnvVar {
                                 name: "kiss_cluster_control_planes".into(),
                                 value: Some(
-                                    if matches!(job.new_state, None | Some(BoxState::Joining)) {
+                                    if matches!(
+                                        job.new_state,
+                                        None | Some(BoxState::Joining | BoxState::Upgrading)
+                                    ) {
                                         cluster_state.get_control_planes_as_string()
                                     } else {
                                         Default::default()
@@ -272,7 +323,10 @@ impl AnsibleClient {
                             EnvVar {
                                 name: "kiss_cluster_etcd_nodes".into(),
                                 value: Some(
-                                    if matches!(job.new_state, None | Some(BoxState::Joining)) {
+                                    if matches!(
+                                        job.new_state,
+                                        None | Some(BoxState::Joining | BoxState::Upgrading)
+                                    ) {
                                         cluster_state.get_etcd_nodes_as_string()
                                     } else {
                                         Default::default()
@@ -529,6 +583,18 @@ impl AnsibleClient {
                                 }),
                                 ..Default::default()
                             },
+                            EnvVar {
+                                name: "kiss_submit_token".into(),
+                                value_from: Some(EnvVarSource {
+                                    secret_key_ref: Some(SecretKeySelector {
+                                        name: format!("kiss-box-{box_name}"),
+                                        key: "token".into(),
+                                        optional: Some(true),
+                                    }),
+                                    ..Default::default()
+                                }),
+                                ..Default::default()
+                            },
                             EnvVar {
                                 name: "kiss_submit_base_url".into(),
                                 value_from: Some(EnvVarSource {
@@ -645,7 +711,10 @@ impl AnsibleClient {
                         schedule: schedule.into(),
                         starting_deadline_seconds: Some(180 /* 3m */),
                         job_template: JobTemplateSpec {
-                            metadata: Some(metadata),
+                            metadata: Some(ObjectMeta {
+                                finalizers: Some(vec![Self::FINALIZER_HISTORY.into()]),
+                                ..metadata
+                            }),
                             spec: Some(spec),
                         },
                         ..Default::default()
@@ -657,7 +726,10 @@ impl AnsibleClient {
             None => {
                 let api = Api::<Job>::namespaced(self.client.clone(), &self.namespace);
                 let job = Job {
-                    metadata,
+                    metadata: ObjectMeta {
+                        finalizers: Some(vec![Self::FINALIZER_HISTORY.into()]),
+                        ..metadata
+                    },
                     spec: Some(spec),
                     status: None,
                 };
@@ -1,4 +1,7 @@
-use std::net::IpAddr;
+use std::{
+    collections::BTreeMap,
+    net::{IpAddr, Ipv4Addr},
+};
 
 use jiff::{SignedDuration, Span, Timestamp};
 #[cfg(feature = "kube")]
@@ -86,6 +89,27 @@ use uuid::Uuid;
         "priority": 1,
         "description": "network interface link speed (Unit: Mbps)",
         "jsonPath": ".status.access.primary.speedMbps"
+    }"#,
+        printcolumn = r#"{
+        "name": "cpu-cores",
+        "type": "integer",
+        "priority": 1,
+        "description": "number of physical CPU cores",
+        "jsonPath": ".status.hardware.cpu.cores"
+    }"#,
+        printcolumn = r#"{
+        "name": "memory",
+        "type": "integer",
+        "priority": 1,
+        "description": "total memory size (Unit: MiB)",
+        "jsonPath": ".status.hardware.memory.totalMib"
+    }"#,
+        printcolumn = r#"{
+        "name": "upgrade",
+        "type": "string",
+        "priority": 1,
+        "description": "state of the rolling upgrade",
+        "jsonPath": ".status.upgrade.state"
     }"#,
         printcolumn = r#"{
         "name": "version",
@@ -100,8 +124,16 @@ use uuid::Uuid;
 pub struct BoxSpec {
     #[cfg_attr(feature = "serde", serde(default))]
     pub group: BoxGroupSpec,
+    /// Roles of the network interfaces by name, overriding the commissioned ones.
+    ///
+    /// The changes are re-applied to the box's status without re-commissioning,
+    /// and take effect on the box's next provisioning job.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub interfaces: BTreeMap<String, BoxNetworkInterfaceRole>,
     pub machine: BoxMachineSpec,
     #[cfg_attr(feature = "serde", serde(default))]
+    pub maintenance: Option<BoxMaintenanceSpec>,
+    #[cfg_attr(feature = "serde", serde(default))]
     pub power: Option<BoxPowerSpec>,
 }
 
@@ -126,7 +158,20 @@ pub struct BoxStatus {
     pub access: BoxAccessSpec,
     #[cfg_attr(feature = "serde", serde(default))]
     pub bind_group: Option<BoxGroupSpec>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub hardware: Option<BoxHardwareSpec>,
+    /// The recent provisioning jobs, the latest first
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub history: Vec<BoxJobRecord>,
     pub last_updated: Timestamp,
+    /// The DHCP lease of the box
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub lease: Option<BoxLeaseStatus>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub upgrade: Option<BoxUpgradeStatus>,
+    /// The configuration which the box has been provisioned with
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub version: Option<BoxVersionSpec>,
 }
 
 #[derive(
@@ -144,6 +189,9 @@ pub enum BoxState {
     GroupChanged,
     Failed,
     Disconnected,
+    Draining,
+    Maintenance,
+    Upgrading,
 }
 
 impl BoxState {
@@ -155,6 +203,8 @@ impl BoxState {
             Self::Joining => Some("join"),
             Self::Running => Some("ping"),
             Self::GroupChanged | Self::Failed | Self::Disconnected => Some("reset"),
+            Self::Draining | Self::Maintenance => None,
+            Self::Upgrading => Some("upgrade"),
         }
     }
 
@@ -176,6 +226,9 @@ impl BoxState {
             Self::GroupChanged => Self::GroupChanged,
             Self::Failed => Self::Failed,
             Self::Disconnected => Self::Disconnected,
+            Self::Draining => Self::Draining,
+            Self::Maintenance => Self::Maintenance,
+            Self::Upgrading => Self::Upgrading,
         }
     }
 
@@ -189,6 +242,9 @@ impl BoxState {
             Self::Joining => Some(fallback_update),
             Self::Running => None,
             Self::GroupChanged | Self::Failed | Self::Disconnected => None,
+            Self::Draining | Self::Maintenance => None,
+            // NOTE: failed upgrades are handled by the upgrade controller
+            Self::Upgrading => None,
         }
     }
 
@@ -196,6 +252,14 @@ impl BoxState {
         SignedDuration::new(30, 0)
     }
 
+    pub fn timeout_power_recovery() -> SignedDuration {
+        SignedDuration::from_hours(2)
+    }
+
+    pub const fn is_power_recoverable(&self) -> bool {
+        matches!(self, Self::Failed | Self::Disconnected)
+    }
+
     pub const fn complete(&self) -> Option<Self> {
         match self {
             Self::New => None,
@@ -204,8 +268,14 @@ impl BoxState {
             Self::Joining => Some(Self::Running),
             Self::Running => None,
             Self::GroupChanged | Self::Failed | Self::Disconnected => None,
+            Self::Draining | Self::Maintenance => None,
+            Self::Upgrading => Some(Self::Running),
         }
     }
+
+    pub const fn is_maintenance(&self) -> bool {
+        matches!(self, Self::Draining | Self::Maintenance)
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -214,12 +284,15 @@ impl BoxState {
 #[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
 pub struct BoxAccessSpec<Interface = BoxAccessInterfaceSpec> {
     pub primary: Option<Interface>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub interfaces: Vec<BoxNetworkInterfaceSpec>,
 }
 
 impl<T> Default for BoxAccessSpec<T> {
     fn default() -> Self {
         Self {
             primary: Default::default(),
+            interfaces: Default::default(),
         }
     }
 }
@@ -228,6 +301,38 @@ impl BoxAccessSpec {
     pub fn management(&self) -> Option<&BoxAccessInterfaceSpec> {
         self.primary.as_ref()
     }
+
+    /// Return the first addressed interface dedicated to the given role.
+    pub fn interface(&self, role: BoxNetworkInterfaceRole) -> Option<&BoxNetworkInterfaceSpec> {
+        self.interfaces
+            .iter()
+            .find(|interface| interface.role == Some(role) && !interface.addresses.is_empty())
+    }
+
+    /// Return the address for the given role, falling back to the management one.
+    pub fn address(&self, role: BoxNetworkInterfaceRole) -> Option<IpAddr> {
+        self.interface(role)
+            .and_then(|interface| interface.addresses.first().copied())
+            .or_else(|| self.management().map(|interface| interface.address))
+    }
+
+    /// Apply the role overrides to the interfaces.
+    ///
+    /// The interfaces without any overrides fall back to the commissioned roles,
+    /// so that removing an override reverts the interface's role.
+    pub fn with_roles(mut self, roles: &BTreeMap<String, BoxNetworkInterfaceRole>) -> Self {
+        let primary = self.management().map(|interface| interface.address);
+        for interface in &mut self.interfaces {
+            interface.role = match roles.get(&interface.name) {
+                Some(role) => Some(*role),
+                None if primary.is_some_and(|address| interface.addresses.contains(&address)) => {
+                    Some(BoxNetworkInterfaceRole::Management)
+                }
+                None => None,
+            };
+        }
+        self
+    }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -241,6 +346,50 @@ pub struct BoxAccessInterfaceSpec {
     pub speed_mbps: Option<u64>,
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct BoxNetworkInterfaceSpec {
+    pub name: String,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub role: Option<BoxNetworkInterfaceRole>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub mac: Option<String>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub addresses: Vec<IpAddr>,
+    // Speed (Mb/s)
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub speed_mbps: Option<u64>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub mtu: Option<u32>,
+    /// Name of the bond which this interface is enslaved to
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub bond: Option<String>,
+}
+
+#[derive(Copy, Clone, Debug, Display, EnumString, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum BoxNetworkInterfaceRole {
+    Management,
+    Data,
+    Storage,
+}
+
+impl BoxNetworkInterfaceRole {
+    pub const ALL: [Self; 3] = [Self::Management, Self::Data, Self::Storage];
+
+    /// A DNS label of the role.
+    pub const fn as_label(&self) -> &'static str {
+        match self {
+            Self::Management => "management",
+            Self::Data => "data",
+            Self::Storage => "storage",
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 #[cfg_attr(feature = "schemars", derive(JsonSchema))]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
@@ -339,6 +488,186 @@ impl BoxMachineSpec {
     }
 }
 
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct BoxHardwareSpec {
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub cpu: Option<BoxCpuSpec>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub memory: Option<BoxMemorySpec>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub disks: Vec<BoxDiskSpec>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub gpus: Vec<BoxGpuSpec>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub nics: Vec<BoxNicSpec>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct BoxCpuSpec {
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub model: Option<String>,
+    pub sockets: u32,
+    // Physical cores of all sockets
+    pub cores: u32,
+    // Logical processors of all sockets
+    pub threads: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct BoxMemorySpec {
+    // Size (MiB)
+    pub total_mib: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct BoxDiskSpec {
+    pub name: String,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub model: Option<String>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub serial: Option<String>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub rotational: bool,
+    // Size (Bytes)
+    pub size_bytes: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct BoxGpuSpec {
+    pub vendor: String,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub model: Option<String>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub pci_address: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct BoxNicSpec {
+    pub name: String,
+    pub mac: String,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub driver: Option<String>,
+    // Speed (Mb/s)
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub speed_mbps: Option<u64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct BoxVersionSpec {
+    pub kubespray_image: String,
+    pub os_dist: String,
+    pub os_kernel: String,
+    pub os_version: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct BoxUpgradeStatus {
+    pub state: BoxUpgradeState,
+    pub target: BoxVersionSpec,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub reason: Option<String>,
+    pub last_updated: Timestamp,
+}
+
+#[derive(Copy, Clone, Debug, Display, EnumString, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum BoxUpgradeState {
+    /// Waiting for its turn
+    Pending,
+    Upgrading,
+    Completed,
+    /// Failed to upgrade; the rolling upgrade of the cluster is paused
+    Failed,
+    /// Cannot be upgraded in place
+    Blocked,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct BoxLeaseStatus {
+    pub address: Ipv4Addr,
+    /// The hardware address of the client
+    pub mac: String,
+    pub expires_at: Timestamp,
+    pub last_updated: Timestamp,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct BoxJobRecord {
+    /// The name of the job
+    pub name: String,
+    /// The name of the ansible task, e.g. `commission`
+    pub task: String,
+    pub outcome: BoxJobOutcome,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub started_at: Option<Timestamp>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub finished_at: Option<Timestamp>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub exit_code: Option<i32>,
+    /// The ansible task which has stopped the playbook
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub failed_task: Option<String>,
+    /// The last log lines of the failed job
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub logs: Vec<String>,
+}
+
+impl BoxJobRecord {
+    /// The maximum number of the records to be kept per box.
+    pub const MAX_HISTORY: usize = 10;
+
+    /// The maximum number of the log lines to be kept per record.
+    pub const MAX_LOG_LINES: usize = 30;
+}
+
+#[derive(Copy, Clone, Debug, Display, EnumString, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum BoxJobOutcome {
+    Succeeded,
+    Failed,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct BoxMaintenanceSpec {
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub reason: Option<String>,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 #[cfg_attr(feature = "schemars", derive(JsonSchema))]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
@@ -387,6 +716,7 @@ pub mod request {
         fn try_from(value: BoxAccessSpec<BoxAccessInterfaceQuery>) -> Result<Self, Self::Error> {
             Ok(Self {
                 primary: value.primary.map(TryInto::try_into).transpose()?,
+                interfaces: value.interfaces,
             })
         }
     }
@@ -408,8 +738,60 @@ pub mod request {
     #[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
     pub struct BoxCommissionQuery {
         pub access: BoxAccessSpec<BoxAccessInterfaceQuery>,
+        #[cfg_attr(feature = "serde", serde(default))]
+        pub hardware: Option<BoxHardwareSpec>,
         pub machine: BoxMachineSpec,
         pub power: Option<BoxPowerSpec>,
         pub reset: bool,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn interface(name: &str, address: [u8; 4]) -> BoxNetworkInterfaceSpec {
+        BoxNetworkInterfaceSpec {
+            name: name.into(),
+            role: None,
+            mac: None,
+            addresses: vec![IpAddr::from(address)],
+            speed_mbps: None,
+            mtu: None,
+            bond: None,
+        }
+    }
+
+    #[test]
+    fn test_apply_interface_roles() {
+        let access = BoxAccessSpec {
+            primary: Some(BoxAccessInterfaceSpec {
+                address: IpAddr::from([10, 0, 0, 1]),
+                speed_mbps: None,
+            }),
+            interfaces: vec![
+                interface("eno1", [10, 0, 0, 1]),
+                interface("eno2", [10, 1, 0, 1]),
+            ],
+        };
+
+        let roles = BTreeMap::from_iter([("eno2".into(), BoxNetworkInterfaceRole::Data)]);
+        let access = access.with_roles(&roles);
+        assert_eq!(
+            access.address(BoxNetworkInterfaceRole::Data),
+            Some(IpAddr::from([10, 1, 0, 1])),
+        );
+        assert_eq!(
+            access.interfaces[0].role,
+            Some(BoxNetworkInterfaceRole::Management),
+        );
+
+        // revert the removed overrides
+        let access = access.with_roles(&BTreeMap::default());
+        assert_eq!(access.interfaces[1].role, None);
+        assert_eq!(
+            access.address(BoxNetworkInterfaceRole::Data),
+            Some(IpAddr::from([10, 0, 0, 1])),
+        );
+    }
+}
@@ -0,0 +1,313 @@
+use std::collections::BTreeMap;
+
+use actix_web::{HttpRequest, http::header};
+use anyhow::{Result, anyhow, bail};
+use base64::{Engine, engine};
+use jiff::Timestamp;
+use k8s_openapi::api::core::v1::{ObjectReference, Secret};
+use kube::{
+    Api, Resource, ResourceExt,
+    api::{DeleteParams, ListParams, ObjectMeta, Patch, PatchParams, PostParams, Preconditions},
+    runtime::{
+        events::{Event, EventType, Recorder},
+        reflector::ObjectRef,
+    },
+};
+use openark_kiss_api::r#box::{BoxCrd, BoxMachineSpec};
+use serde_json::json;
+#[cfg(feature = "tracing")]
+use tracing::{Level, info, instrument, warn};
+
+/// Validates the requests of the boxes.
+///
+/// There are two kinds of credentials:
+///
+/// * Enrollment tokens: `Secret`s labeled with `kiss.ulagbulag.io/enrollment-token=true`,
+///   which are accepted only when registering boxes (`GET /new`).
+///   A one-shot token is deleted as soon as it is used.
+/// * Box tokens: a per-box shared secret (`kiss-box-<uuid>`), issued on registration
+///   and consumed by the Ansible jobs of the box.
+///   Re-registering a box with an enrollment token, e.g. after a reset, rotates its token.
+pub(crate) struct Authenticator {
+    api: Api<Secret>,
+    recorder: Recorder,
+}
+
+impl Authenticator {
+    pub(crate) const ANNOTATION_EXPIRES_AT: &'static str = "kiss.ulagbulag.io/expires-at";
+    pub(crate) const ANNOTATION_ONE_SHOT: &'static str = "kiss.ulagbulag.io/one-shot";
+    pub(crate) const LABEL_BOX_NAME: &'static str = "kiss.ulagbulag.io/box_name";
+    pub(crate) const LABEL_ENROLLMENT_TOKEN: &'static str = "kiss.ulagbulag.io/enrollment-token";
+    const SECRET_DEFAULT_ENROLLMENT_TOKEN: &'static str = "kiss-enrollment-token-default";
+    const SECRET_KEY_TOKEN: &'static str = "token";
+    const TOKEN_SIZE: usize = 32;
+
+    pub(crate) fn new(api: Api<Secret>, recorder: Recorder) -> Self {
+        Self { api, recorder }
+    }
+
+    fn box_secret_name(machine: &BoxMachineSpec) -> String {
+        format!("kiss-box-{}", machine.uuid)
+    }
+
+    /// Accept the box's own token only.
+    #[cfg_attr(feature = "tracing", instrument(level = Level::INFO, skip(self, request), err(Display)))]
+    pub(crate) async fn authenticate_box(
+        &self,
+        request: &HttpRequest,
+        machine: &BoxMachineSpec,
+    ) -> Result<()> {
+        let token = parse_token(request)?;
+        if self.verify_box(machine, token).await? {
+            Ok(())
+        } else {
+            bail!("invalid box token")
+        }
+    }
+
+    /// Accept the box's own token, or an enrollment token.
+    ///
+    /// A reset box has lost its token, so an enrollment token re-registers it
+    /// and the caller should rotate the box token.
+    #[cfg_attr(feature = "tracing", instrument(level = Level::INFO, skip(self, request), err(Display)))]
+    pub(crate) async fn authenticate_enrollment(
+        &self,
+        request: &HttpRequest,
+        machine: &BoxMachineSpec,
+    ) -> Result<Credential> {
+        let token = parse_token(request)?;
+        if self.verify_box(machine, token).await? {
+            return Ok(Credential::Box);
+        }
+
+        let lp = ListParams {
+            label_selector: Some(format!("{}=true", Self::LABEL_ENROLLMENT_TOKEN)),
+            ..Default::default()
+        };
+        let now = Timestamp::now();
+        let secret = self
+            .api
+            .list(&lp)
+            .await?
+            .items
+            .into_iter()
+            .filter(|secret| !is_expired(secret, now))
+            .find(|secret| verify(secret, token))
+            .ok_or_else(|| anyhow!("invalid enrollment token"))?;
+
+        let is_one_shot = secret
+            .annotations()
+            .get(Self::ANNOTATION_ONE_SHOT)
+            .and_then(|value| value.parse().ok())
+            .unwrap_or_default();
+        if is_one_shot {
+            // NOTE: a concurrent request with the same token fails with a conflict
+            let dp = DeleteParams {
+                preconditions: Some(Preconditions {
+                    resource_version: secret.resource_version(),
+                    uid: secret.uid(),
+                }),
+                ..Default::default()
+            };
+            let name = secret.name_any();
+            self.api
+                .delete(&name, &dp)
+                .await
+                .map_err(|error| anyhow!("failed to consume the enrollment token: {error}"))?;
+
+            #[cfg(feature = "tracing")]
+            info!("consumed a one-shot enrollment token: {name}");
+        }
+        Ok(Credential::Enrollment)
+    }
+
+    async fn verify_box(&self, machine: &BoxMachineSpec, token: &str) -> Result<bool> {
+        let name = Self::box_secret_name(machine);
+        Ok(self
+            .api
+            .get_opt(&name)
+            .await?
+            .is_some_and(|secret| verify(&secret, token)))
+    }
+
+    /// Issue a box token if not exists, or replace it if `rotate` is set.
+    #[cfg_attr(feature = "tracing", instrument(level = Level::INFO, skip_all, err(Display)))]
+    pub(crate) async fn issue(&self, r#box: &BoxCrd, rotate: bool) -> Result<()> {
+        let name = Self::box_secret_name(&r#box.spec.machine);
+        if self.api.get_metadata_opt(&name).await?.is_some() {
+            if rotate {
+                let string_data =
+                    BTreeMap::from_iter([(Self::SECRET_KEY_TOKEN, generate_token()?)]);
+                let patch = Patch::Merge(json!({
+                    "stringData": string_data,
+                }));
+                let pp = PatchParams {
+                    field_manager: Some("kiss-gateway".into()),
+                    ..Default::default()
+                };
+                self.api.patch(&name, &pp, &patch).await?;
+
+                let message = "Rotated the box token on re-registration".into();
+                self.publish(r#box, EventType::Warning, "TokenRotated", message)
+                    .await;
+            }
+            return Ok(());
+        }
+
+        let secret = Secret {
+            metadata: ObjectMeta {
+                name: Some(name),
+                labels: Some(BTreeMap::from_iter([(
+                    Self::LABEL_BOX_NAME.into(),
+                    r#box.spec.machine.uuid.to_string(),
+                )])),
+                owner_references: r#box.controller_owner_ref(&()).map(|owner| vec![owner]),
+                ..Default::default()
+            },
+            string_data: Some(BTreeMap::from_iter([(
+                Self::SECRET_KEY_TOKEN.into(),
+                generate_token()?,
+            )])),
+            type_: Some("Opaque".into()),
+            ..Default::default()
+        };
+        let pp = PostParams {
+            dry_run: false,
+            field_manager: Some("kiss-gateway".into()),
+        };
+        self.api.create(&pp, &secret).await?;
+        Ok(())
+    }
+
+    /// Issue the box tokens of the boxes registered before the box tokens are introduced.
+    #[cfg_attr(feature = "tracing", instrument(level = Level::INFO, skip_all, err(Display)))]
+    pub(crate) async fn migrate(&self, api: &Api<BoxCrd>) -> Result<()> {
+        for r#box in api.list(&ListParams::default()).await?.items {
+            self.issue(&r#box, false).await?;
+        }
+        Ok(())
+    }
+
+    /// Create the default enrollment token if no enrollment tokens exist.
+    ///
+    /// The token is served to the PXE-booted boxes by the assets server.
+    #[cfg_attr(feature = "tracing", instrument(level = Level::INFO, skip_all, err(Display)))]
+    pub(crate) async fn ensure_enrollment_token(&self) -> Result<()> {
+        let lp = ListParams {
+            label_selector: Some(format!("{}=true", Self::LABEL_ENROLLMENT_TOKEN)),
+            limit: Some(1),
+            ..Default::default()
+        };
+        if !self.api.list_metadata(&lp).await?.items.is_empty() {
+            return Ok(());
+        }
+
+        let secret = Secret {
+            metadata: ObjectMeta {
+                name: Some(Self::SECRET_DEFAULT_ENROLLMENT_TOKEN.into()),
+                labels: Some(BTreeMap::from_iter([(
+                    Self::LABEL_ENROLLMENT_TOKEN.into(),
+                    "true".into(),
+                )])),
+                ..Default::default()
+            },
+            string_data: Some(BTreeMap::from_iter([(
+                Self::SECRET_KEY_TOKEN.into(),
+                generate_token()?,
+            )])),
+            type_: Some("Opaque".into()),
+            ..Default::default()
+        };
+        let pp = PostParams {
+            dry_run: false,
+            field_manager: Some("kiss-gateway".into()),
+        };
+        self.api.create(&pp, &secret).await?;
+
+        #[cfg(feature = "tracing")]
+        info!("created the default enrollment token");
+        Ok(())
+    }
+
+    /// Record the rejection on the box, if exists.
+    pub(crate) async fn reject(&self, r#box: Option<&BoxCrd>, message: String) {
+        #[cfg(feature = "tracing")]
+        warn!("rejected a request: {message}");
+
+        let Some(r#box) = r#box else {
+            return;
+        };
+        self.publish(r#box, EventType::Warning, "EnrollmentRejected", message)
+            .await
+    }
+
+    async fn publish(&self, r#box: &BoxCrd, type_: EventType, reason: &str, message: String) {
+        let reference: ObjectReference = ObjectRef::from_obj(r#box).into();
+        let event = Event {
+            type_,
+            reason: reason.into(),
+            note: Some(message),
+            action: "Authenticating".into(),
+            secondary: None,
+        };
+        if let Err(error) = self.recorder.publish(&event, &reference).await {
+            #[cfg(feature = "tracing")]
+            warn!("failed to record an event: {error}");
+            let _ = error;
+        }
+    }
+}
+
+/// A kind of credentials accepted on registration.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Credential {
+    /// The box's own token
+    Box,
+    /// An enrollment token
+    Enrollment,
+}
+
+fn generate_token() -> Result<String> {
+    let mut buf = [0u8; Authenticator::TOKEN_SIZE];
+    ::getrandom::fill(&mut buf).map_err(|error| anyhow!("{error}"))?;
+    Ok(engine::general_purpose::URL_SAFE_NO_PAD.encode(buf))
+}
+
+fn parse_token(request: &HttpRequest) -> Result<&str> {
+    request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+        .ok_or_else(|| anyhow!("missing bearer token"))
+}
+
+fn is_expired(secret: &Secret, now: Timestamp) -> bool {
+    match secret
+        .annotations()
+        .get(Authenticator::ANNOTATION_EXPIRES_AT)
+    {
+        Some(expires_at) => expires_at
+            .parse::<Timestamp>()
+            .map(|expires_at| now >= expires_at)
+            // malformed tokens are considered as expired
+            .unwrap_or(true),
+        None => false,
+    }
+}
+
+fn verify(secret: &Secret, token: &str) -> bool {
+    secret
+        .data
+        .as_ref()
+        .and_then(|data| data.get(Authenticator::SECRET_KEY_TOKEN))
+        // empty tokens are never accepted
+        .filter(|expected| !expected.0.is_empty())
+        .is_some_and(|expected| constant_time_eq(&expected.0, token.as_bytes()))
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
@@ -1,3 +1,4 @@
+mod auth;
 mod routes;
 
 use std::net::SocketAddr;
@@ -11,6 +12,7 @@ use clap::Parser;
 use kube::{
     Api, Client, Config,
     api::{PatchParams, ValidationDirective},
+    runtime::events::{Recorder, Reporter},
 };
 use openark_core::{client::HealthState, operator::OperatorArgs};
 use openark_kiss_api::r#box::BoxCrd;
@@ -61,14 +63,24 @@ async fn try_main(args: Args) -> Result<()> {
         base_url.pop();
     }
 
-    let api = Data::new({
+    let client = {
         let mut config = Config::infer().await?;
         if let Some(namespace) = operator.namespace {
             config.default_namespace = namespace;
         }
-        let client = Client::try_from(config)?;
-        Api::<BoxCrd>::all(client)
+        Client::try_from(config)?
+    };
+    let api = Data::new(Api::<BoxCrd>::all(client.clone()));
+    let auth = Data::new({
+        let reporter = Reporter {
+            controller: operator.controller_name.clone(),
+            instance: operator.controller_pod_name.clone(),
+        };
+        let recorder = Recorder::new(client.clone(), reporter);
+        self::auth::Authenticator::new(Api::default_namespaced(client), recorder)
     });
+    auth.ensure_enrollment_token().await?;
+    auth.migrate(&api).await?;
     let patch_params = Data::new(PatchParams {
         dry_run: false,
         force: false,
@@ -80,6 +92,7 @@ async fn try_main(args: Args) -> Result<()> {
     HttpServer::new(move || {
         let app = App::new()
             .app_data(Data::clone(&api))
+            .app_data(Data::clone(&auth))
             .app_data(Data::clone(&patch_params));
 
         let app = app.service(
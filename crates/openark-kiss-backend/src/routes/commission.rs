@@ -1,22 +1,36 @@
-use actix_web::{HttpResponse, Responder, post, web};
+use actix_web::{HttpRequest, HttpResponse, Responder, post, web};
 use anyhow::{Result, bail};
 use jiff::Timestamp;
 use kube::{
     Api, CustomResourceExt,
     api::{Patch, PatchParams},
 };
-use openark_kiss_api::r#box::{BoxCrd, BoxSpec, BoxState, BoxStatus, request::BoxCommissionQuery};
+use openark_kiss_api::r#box::{
+    BoxAccessSpec, BoxCrd, BoxSpec, BoxState, BoxStatus, request::BoxCommissionQuery,
+};
 use serde_json::json;
 #[cfg(feature = "tracing")]
 use tracing::{Level, instrument, warn};
 
-#[cfg_attr(feature = "tracing", instrument(level = Level::INFO, skip(api, patch_params)))]
+use crate::auth::Authenticator;
+
+#[cfg_attr(feature = "tracing", instrument(level = Level::INFO, skip(request, api, auth, patch_params)))]
 #[post("")]
 async fn post(
+    request: HttpRequest,
     api: web::Data<Api<BoxCrd>>,
+    auth: web::Data<Authenticator>,
     patch_params: web::Data<PatchParams>,
     web::Json(query): web::Json<BoxCommissionQuery>,
 ) -> impl Responder {
+    if let Err(error) = auth.authenticate_box(&request, &query.machine).await {
+        let name = query.machine.uuid.to_string();
+        let r#box = api.get_opt(&name).await.ok().flatten();
+        let message = format!("Rejected commissioning the box: {error}");
+        auth.reject(r#box.as_ref(), message).await;
+        return HttpResponse::Unauthorized().json("Err");
+    }
+
     match try_handle(api, patch_params, query).await {
         Ok(()) => HttpResponse::Ok().json("Ok"),
         Err(error) => {
@@ -42,12 +56,21 @@ async fn try_handle(
                 "kind": crd.kind,
                 "spec": BoxSpec {
                     group: r#box.spec.group,
+                    interfaces: r#box.spec.interfaces.clone(),
                     machine: query.machine,
+                    maintenance: r#box.spec.maintenance,
                     power: query.power,
                 },
                 "status": BoxStatus {
-                    access: query.access.try_into()?,
+                    access: BoxAccessSpec::try_from(query.access)?
+                        .with_roles(&r#box.spec.interfaces),
                     state: BoxState::Ready,
+                    hardware: query.hardware,
+                    history: r#box
+                        .status
+                        .as_ref()
+                        .map(|status| status.history.clone())
+                        .unwrap_or_default(),
                     bind_group: if query.reset {
                         None
                     } else {
@@ -58,6 +81,14 @@ async fn try_handle(
                             .cloned()
                     },
                     last_updated: Timestamp::now(),
+                    lease: r#box
+                        .status
+                        .as_ref()
+                        .and_then(|status| status.lease.as_ref())
+                        .cloned(),
+                    // NOTE: the version is recorded once the box has joined
+                    upgrade: None,
+                    version: None,
                 },
             }));
             api.patch(&name, &patch_params, &patch).await?;
@@ -1,4 +1,4 @@
-use actix_web::{HttpResponse, Responder, get, web};
+use actix_web::{HttpRequest, HttpResponse, Responder, get, web};
 use anyhow::Result;
 use jiff::Timestamp;
 use kube::{
@@ -12,14 +12,37 @@ use serde_json::json;
 #[cfg(feature = "tracing")]
 use tracing::{Level, instrument, warn};
 
-#[cfg_attr(feature = "tracing", instrument(level = Level::INFO, skip(api, patch_params)))]
+use crate::auth::{Authenticator, Credential};
+
+#[cfg_attr(feature = "tracing", instrument(level = Level::INFO, skip(request, api, auth, patch_params)))]
 #[get("")]
 async fn get(
+    request: HttpRequest,
     api: web::Data<Api<BoxCrd>>,
+    auth: web::Data<Authenticator>,
     patch_params: web::Data<PatchParams>,
     web::Query(query): web::Query<BoxNewQuery>,
 ) -> impl Responder {
-    match try_handle(api, patch_params, query).await {
+    let name = query.machine.uuid.to_string();
+    let r#box = match api.get_opt(&name).await {
+        Ok(r#box) => r#box,
+        Err(error) => {
+            #[cfg(feature = "tracing")]
+            warn!("failed to get a box: {error}");
+            return HttpResponse::InternalServerError().json("Err");
+        }
+    };
+
+    let credential = match auth.authenticate_enrollment(&request, &query.machine).await {
+        Ok(credential) => credential,
+        Err(error) => {
+            let message = format!("Rejected registering the box: {error}");
+            auth.reject(r#box.as_ref(), message).await;
+            return HttpResponse::Unauthorized().json("Err");
+        }
+    };
+
+    match try_handle(api, auth, patch_params, query, r#box, credential).await {
         Ok(()) => HttpResponse::Ok().json("Ok"),
         Err(error) => {
             #[cfg(feature = "tracing")]
@@ -31,12 +54,15 @@ async fn get(
 
 async fn try_handle(
     api: web::Data<Api<BoxCrd>>,
+    auth: web::Data<Authenticator>,
     patch_params: web::Data<PatchParams>,
     query: BoxNewQuery,
+    r#box: Option<BoxCrd>,
+    credential: Credential,
 ) -> Result<()> {
     let name = query.machine.uuid.to_string();
 
-    match api.get_opt(&name).await? {
+    match r#box {
         Some(r#box) => {
             let crd = BoxCrd::api_resource();
             let patch = Patch::Merge(json!({
@@ -45,13 +71,23 @@ async fn try_handle(
                 "status": BoxStatus {
                     access: BoxAccessSpec {
                         primary: Some(query.access_primary.try_into()?),
+                        interfaces: r#box.status.as_ref().map(|status| status.access.interfaces.clone()).unwrap_or_default(),
                     },
                     state: BoxState::New,
                     bind_group: r#box.status.as_ref().and_then(|status| status.bind_group.as_ref()).cloned(),
+                    hardware: r#box.status.as_ref().and_then(|status| status.hardware.as_ref()).cloned(),
+                    history: r#box.status.as_ref().map(|status| status.history.clone()).unwrap_or_default(),
                     last_updated: Timestamp::now(),
+                    lease: r#box.status.as_ref().and_then(|status| status.lease.as_ref()).cloned(),
+                    upgrade: None,
+                    version: None,
                 },
             }));
             api.patch_status(&name, &patch_params, &patch).await?;
+
+            // issue a box token, rotating the lost one of a reset box
+            auth.issue(&r#box, credential == Credential::Enrollment)
+                .await?;
         }
         None => {
             let data = BoxCrd {
@@ -61,7 +97,9 @@ async fn try_handle(
                 },
                 spec: BoxSpec {
                     group: Default::default(),
+                    interfaces: Default::default(),
                     machine: query.machine,
+                    maintenance: None,
                     power: None,
                 },
                 status: None,
@@ -70,7 +108,7 @@ async fn try_handle(
                 dry_run: false,
                 field_manager: Some("kiss-gateway".into()),
             };
-            api.create(&pp, &data).await?;
+            let r#box = api.create(&pp, &data).await?;
 
             let crd = BoxCrd::api_resource();
             let patch = Patch::Merge(json!({
@@ -79,13 +117,22 @@ async fn try_handle(
                 "status": BoxStatus {
                     access: BoxAccessSpec {
                         primary: Some(query.access_primary.try_into()?),
+                        interfaces: Vec::default(),
                     },
                     state: BoxState::New,
                     bind_group: None,
+                    hardware: None,
+                    history: Vec::default(),
                     last_updated: Timestamp::now(),
+                    lease: None,
+                    upgrade: None,
+                    version: None,
                 },
             }));
             api.patch_status(&name, &patch_params, &patch).await?;
+
+            // issue a box token
+            auth.issue(&r#box, false).await?;
         }
     }
     Ok(())
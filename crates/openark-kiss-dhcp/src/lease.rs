@@ -0,0 +1,528 @@
+use std::{collections::BTreeMap, net::Ipv4Addr, ops::RangeInclusive};
+
+use anyhow::{Result, anyhow, bail};
+use ipnet::Ipv4Net;
+use jiff::{SignedDuration, Timestamp};
+use openark_kiss_ansible::config::KissConfig;
+use uuid::Uuid;
+
+use crate::packet::MacAddr;
+
+/// A client which asks for an address.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Client {
+    pub(crate) mac: MacAddr,
+    pub(crate) uuid: Option<Uuid>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum LeaseState {
+    Offered,
+    Bound,
+    /// The address is in use by an unknown host
+    Declined,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Lease {
+    pub(crate) address: Ipv4Addr,
+    pub(crate) mac: MacAddr,
+    pub(crate) state: LeaseState,
+    pub(crate) expires_at: Timestamp,
+}
+
+/// The pinned addresses of a box.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Reservation {
+    /// The address of the interfaces unknown yet, e.g. PXE clients found by the machine UUID
+    pub(crate) address: Option<Ipv4Addr>,
+    /// The known interfaces and their pinned addresses, keyed by the MAC addresses
+    pub(crate) interfaces: BTreeMap<MacAddr, Option<Ipv4Addr>>,
+}
+
+impl Reservation {
+    fn get(&self, mac: &MacAddr) -> Option<Ipv4Addr> {
+        match self.interfaces.get(mac) {
+            Some(address) => *address,
+            None => self.address,
+        }
+    }
+
+    fn addresses(&self) -> impl Iterator<Item = Ipv4Addr> + '_ {
+        self.address
+            .into_iter()
+            .chain(self.interfaces.values().flatten().copied())
+    }
+}
+
+/// An address to be offered to the client.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Candidate {
+    pub(crate) address: Ipv4Addr,
+    /// Whether the address should be probed before offering,
+    /// i.e. is neither pinned nor leased to the client
+    pub(crate) probe: bool,
+}
+
+/// Hands out the addresses of the DHCP range, pinning the boxes to their addresses.
+pub(crate) struct LeaseStore {
+    duration: SignedDuration,
+    leases: BTreeMap<Ipv4Addr, Lease>,
+    range: RangeInclusive<u32>,
+    /// The reservations, keyed by the machine UUIDs
+    reservations: BTreeMap<Uuid, Reservation>,
+    subnet: Ipv4Net,
+}
+
+impl LeaseStore {
+    /// The duration of the offers, which are not requested yet.
+    const OFFER_DURATION: SignedDuration = SignedDuration::from_secs(60);
+
+    pub(crate) fn try_new(config: &KissConfig) -> Result<Self> {
+        let begin = config.network_ipv4_dhcp_range_begin;
+        let end = config.network_ipv4_dhcp_range_end;
+        let subnet = config.network_ipv4_subnet;
+        if !subnet.contains(&begin) || !subnet.contains(&end) || begin > end {
+            bail!("invalid DHCP range: {begin} - {end} ({subnet})");
+        }
+
+        Ok(Self {
+            duration: parse_duration(&config.network_ipv4_dhcp_duration)?,
+            leases: BTreeMap::default(),
+            range: begin.to_bits()..=end.to_bits(),
+            reservations: BTreeMap::default(),
+            subnet,
+        })
+    }
+
+    pub(crate) const fn duration(&self) -> SignedDuration {
+        self.duration
+    }
+
+    pub(crate) const fn subnet(&self) -> Ipv4Net {
+        self.subnet
+    }
+
+    /// Returns the machine UUID which the client belongs to.
+    pub(crate) fn find_box(&self, client: &Client) -> Option<Uuid> {
+        client
+            .uuid
+            .filter(|uuid| self.reservations.contains_key(uuid))
+            .or_else(|| {
+                self.reservations
+                    .iter()
+                    .find(|(_, reservation)| reservation.interfaces.contains_key(&client.mac))
+                    .map(|(uuid, _)| *uuid)
+            })
+    }
+
+    /// Pins the interfaces of the box to their addresses.
+    pub(crate) fn reserve(&mut self, uuid: Uuid, reservation: Reservation) {
+        let subnet = self.subnet;
+        let reservation = Reservation {
+            address: reservation
+                .address
+                .filter(|address| subnet.contains(address)),
+            interfaces: reservation
+                .interfaces
+                .into_iter()
+                .map(|(mac, address)| (mac, address.filter(|address| subnet.contains(address))))
+                .collect(),
+        };
+        self.reservations.insert(uuid, reservation);
+    }
+
+    pub(crate) fn unreserve(&mut self, uuid: &Uuid) {
+        self.reservations.remove(uuid);
+    }
+
+    /// Restores the lease, e.g. after restarting the server.
+    pub(crate) fn restore(&mut self, lease: Lease) {
+        if lease.expires_at > Timestamp::now() && self.subnet.contains(&lease.address) {
+            self.leases.insert(lease.address, lease);
+        }
+    }
+
+    /// Returns the bound leases, which are not expired yet.
+    pub(crate) fn bound(&self) -> impl Iterator<Item = &Lease> {
+        let now = Timestamp::now();
+        self.leases
+            .values()
+            .filter(move |lease| lease.state == LeaseState::Bound && lease.expires_at > now)
+    }
+
+    /// Picks an address to be offered to the client.
+    pub(crate) fn candidate(
+        &self,
+        client: &Client,
+        requested: Option<Ipv4Addr>,
+    ) -> Option<Candidate> {
+        let now = Timestamp::now();
+        if let Some(address) = self.reserved(client).or_else(|| self.leased(client, now)) {
+            return Some(Candidate {
+                address,
+                probe: false,
+            });
+        }
+
+        let address = requested
+            .filter(|&address| self.is_available(client, address, now))
+            .or_else(|| self.next_available(client, now))?;
+        Some(Candidate {
+            address,
+            probe: true,
+        })
+    }
+
+    /// Offers the address to the client, if it is still available.
+    pub(crate) fn offer(&mut self, client: &Client, address: Ipv4Addr) -> Option<Lease> {
+        let now = Timestamp::now();
+        if !self.is_available(client, address, now) {
+            return None;
+        }
+
+        let lease = Lease {
+            address,
+            mac: client.mac,
+            state: LeaseState::Offered,
+            expires_at: now + Self::OFFER_DURATION,
+        };
+        match self.leases.get(&address) {
+            // keep the bound lease as-is
+            Some(prev) if prev.state == LeaseState::Bound && prev.mac == client.mac => Some(*prev),
+            _ => {
+                self.leases.insert(address, lease);
+                Some(lease)
+            }
+        }
+    }
+
+    /// Binds the requested address to the client.
+    pub(crate) fn bind(&mut self, client: &Client, address: Ipv4Addr) -> Result<Lease> {
+        let now = Timestamp::now();
+        if let Some(reserved) = self.reserved(client)
+            && reserved != address
+        {
+            bail!("{address} is not reserved for {}", client.mac);
+        }
+        if !self.is_available(client, address, now) {
+            bail!("{address} is not available for {}", client.mac);
+        }
+
+        let lease = Lease {
+            address,
+            mac: client.mac,
+            state: LeaseState::Bound,
+            expires_at: now + self.duration,
+        };
+        self.leases.insert(address, lease);
+        Ok(lease)
+    }
+
+    /// Marks the address as in use by an unknown host, as declined by the client.
+    pub(crate) fn decline(&mut self, client: &Client, address: Ipv4Addr) {
+        if self.is_owned(client, address) {
+            self.conflict(address);
+        }
+    }
+
+    /// Marks the address as in use by an unknown host, e.g. answering to the probes.
+    pub(crate) fn conflict(&mut self, address: Ipv4Addr) {
+        let lease = Lease {
+            address,
+            mac: MacAddr::default(),
+            state: LeaseState::Declined,
+            expires_at: Timestamp::now() + self.duration,
+        };
+        self.leases.insert(address, lease);
+    }
+
+    pub(crate) fn release(&mut self, client: &Client, address: Ipv4Addr) -> Option<Lease> {
+        if self.is_owned(client, address) {
+            self.leases.remove(&address)
+        } else {
+            None
+        }
+    }
+
+    fn is_owned(&self, client: &Client, address: Ipv4Addr) -> bool {
+        self.leases
+            .get(&address)
+            .is_some_and(|lease| lease.mac == client.mac)
+    }
+
+    fn is_available(&self, client: &Client, address: Ipv4Addr, now: Timestamp) -> bool {
+        // NOTE: the addresses pinned to the other interfaces of the same box are excluded too
+        let is_reserved_to_others = self.reserved(client) != Some(address)
+            && self
+                .reservations
+                .values()
+                .flat_map(Reservation::addresses)
+                .any(|reserved| reserved == address);
+
+        self.subnet.contains(&address)
+            && address != self.subnet.network()
+            && address != self.subnet.broadcast()
+            && !is_reserved_to_others
+            && self.leases.get(&address).is_none_or(|lease| {
+                lease.expires_at <= now
+                    || (lease.mac == client.mac && lease.state != LeaseState::Declined)
+            })
+    }
+
+    fn leased(&self, client: &Client, now: Timestamp) -> Option<Ipv4Addr> {
+        self.leases
+            .values()
+            .find(|lease| {
+                lease.mac == client.mac
+                    && lease.state != LeaseState::Declined
+                    && lease.expires_at > now
+            })
+            .map(|lease| lease.address)
+    }
+
+    fn next_available(&self, client: &Client, now: Timestamp) -> Option<Ipv4Addr> {
+        self.range
+            .clone()
+            .map(Ipv4Addr::from_bits)
+            .find(|&address| self.is_available(client, address, now))
+    }
+
+    fn reserved(&self, client: &Client) -> Option<Ipv4Addr> {
+        let uuid = self.find_box(client)?;
+        self.reservations.get(&uuid)?.get(&client.mac)
+    }
+}
+
+/// Formats the leases to be persisted, one per line: `<expires_at> <mac> <address>`.
+pub(crate) fn format_leases<'a>(leases: impl IntoIterator<Item = &'a Lease>) -> String {
+    leases
+        .into_iter()
+        .map(|lease| {
+            format!(
+                "{} {} {}\n",
+                lease.expires_at.as_second(),
+                lease.mac,
+                lease.address,
+            )
+        })
+        .collect()
+}
+
+/// Parses the persisted leases, skipping the malformed lines.
+pub(crate) fn parse_leases(value: &str) -> Vec<Lease> {
+    value
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let expires_at = fields.next()?.parse().ok()?;
+            let mac = fields.next()?.parse().ok()?;
+            let address = fields.next()?.parse().ok()?;
+            Some(Lease {
+                address,
+                mac,
+                state: LeaseState::Bound,
+                expires_at: Timestamp::from_second(expires_at).ok()?,
+            })
+        })
+        .collect()
+}
+
+/// Parses a lease duration in the dnsmasq format, e.g. `12h`, `7d` or `infinite`.
+fn parse_duration(value: &str) -> Result<SignedDuration> {
+    let value = value.trim();
+    if value == "infinite" {
+        // NOTE: the maximum lease time is `u32::MAX - 1` seconds
+        return Ok(SignedDuration::from_secs(u32::MAX as i64 - 1));
+    }
+
+    let (number, unit) = value.split_at(
+        value
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(value.len()),
+    );
+    let number: i64 = number
+        .parse()
+        .map_err(|error| anyhow!("invalid lease duration {value:?}: {error}"))?;
+    let unit = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => bail!("invalid lease duration unit: {value:?}"),
+    };
+    Ok(SignedDuration::from_secs(number * unit))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store() -> LeaseStore {
+        LeaseStore {
+            duration: SignedDuration::from_hours(1),
+            leases: BTreeMap::default(),
+            range: Ipv4Addr::new(10, 32, 0, 10).to_bits()..=Ipv4Addr::new(10, 32, 0, 12).to_bits(),
+            reservations: BTreeMap::default(),
+            subnet: "10.32.0.0/24".parse().unwrap(),
+        }
+    }
+
+    fn client(id: u8) -> Client {
+        Client {
+            mac: MacAddr([0x52, 0x54, 0, 0, 0, id]),
+            uuid: None,
+        }
+    }
+
+    fn offer_to(
+        store: &mut LeaseStore,
+        client: &Client,
+        requested: Option<Ipv4Addr>,
+    ) -> Option<Lease> {
+        let candidate = store.candidate(client, requested)?;
+        store.offer(client, candidate.address)
+    }
+
+    #[test]
+    fn lease_dynamic() {
+        let mut store = store();
+
+        let offer = offer_to(&mut store, &client(1), None).unwrap();
+        assert_eq!(offer.address, Ipv4Addr::new(10, 32, 0, 10));
+        assert_eq!(offer.state, LeaseState::Offered);
+
+        // the offered address is not given to the others
+        let other = offer_to(&mut store, &client(2), Some(offer.address)).unwrap();
+        assert_eq!(other.address, Ipv4Addr::new(10, 32, 0, 11));
+
+        let lease = store.bind(&client(1), offer.address).unwrap();
+        assert_eq!(lease.state, LeaseState::Bound);
+        assert!(store.bind(&client(2), offer.address).is_err());
+
+        // the same address is given on renewal
+        let renewed = offer_to(&mut store, &client(1), None).unwrap();
+        assert_eq!(renewed.address, offer.address);
+
+        store.release(&client(1), offer.address);
+        assert!(store.bind(&client(2), offer.address).is_ok());
+    }
+
+    #[test]
+    fn lease_reserved() {
+        let mut store = store();
+        let uuid = Uuid::from_u128(1);
+        let reserved = Ipv4Addr::new(10, 32, 0, 11);
+        store.reserve(
+            uuid,
+            Reservation {
+                address: Some(reserved),
+                interfaces: [(client(1).mac, Some(reserved))].into_iter().collect(),
+            },
+        );
+
+        // pinned by the MAC address
+        let offer = offer_to(&mut store, &client(1), None).unwrap();
+        assert_eq!(offer.address, reserved);
+
+        // pinned by the machine UUID
+        let pxe = Client {
+            mac: client(3).mac,
+            uuid: Some(uuid),
+        };
+        assert_eq!(store.find_box(&pxe), Some(uuid));
+        assert!(store.bind(&pxe, Ipv4Addr::new(10, 32, 0, 10)).is_err());
+
+        // the reserved address is not given to the others
+        assert!(store.bind(&client(2), reserved).is_err());
+        let other = offer_to(&mut store, &client(2), Some(reserved)).unwrap();
+        assert_ne!(other.address, reserved);
+    }
+
+    #[test]
+    fn lease_reserved_per_interface() {
+        let mut store = store();
+        let uuid = Uuid::from_u128(1);
+        let management = Ipv4Addr::new(10, 32, 0, 11);
+        store.reserve(
+            uuid,
+            Reservation {
+                address: Some(management),
+                interfaces: [(client(1).mac, Some(management)), (client(2).mac, None)]
+                    .into_iter()
+                    .collect(),
+            },
+        );
+
+        // the other interfaces of the box are not pinned to the management address
+        let offer = offer_to(&mut store, &client(2), Some(management)).unwrap();
+        assert_eq!(offer.address, Ipv4Addr::new(10, 32, 0, 10));
+        assert_eq!(store.find_box(&client(2)), Some(uuid));
+        assert!(store.bind(&client(2), management).is_err());
+
+        // the unknown interfaces are pinned to the management address
+        let pxe = Client {
+            mac: client(3).mac,
+            uuid: Some(uuid),
+        };
+        let candidate = store.candidate(&pxe, None).unwrap();
+        assert_eq!(candidate.address, management);
+        assert!(!candidate.probe);
+    }
+
+    #[test]
+    fn lease_conflict() {
+        let mut store = store();
+
+        let candidate = store.candidate(&client(1), None).unwrap();
+        assert!(candidate.probe);
+
+        // the address in use by an unknown host is skipped
+        store.conflict(candidate.address);
+        assert!(store.offer(&client(1), candidate.address).is_none());
+        let offer = offer_to(&mut store, &client(1), None).unwrap();
+        assert_eq!(offer.address, Ipv4Addr::new(10, 32, 0, 11));
+
+        // the leased address is not probed again
+        let candidate = store.candidate(&client(1), None).unwrap();
+        assert_eq!(candidate.address, offer.address);
+        assert!(!candidate.probe);
+    }
+
+    #[test]
+    fn persist_leases() {
+        let mut store = store();
+        let offer = offer_to(&mut store, &client(1), None).unwrap();
+        let lease = store.bind(&client(1), offer.address).unwrap();
+        let lease = Lease {
+            expires_at: Timestamp::from_second(lease.expires_at.as_second()).unwrap(),
+            ..lease
+        };
+
+        let value = format_leases(store.bound());
+        assert_eq!(parse_leases(&value), [lease]);
+        assert!(parse_leases("malformed\n").is_empty());
+    }
+
+    #[test]
+    fn lease_exhausted() {
+        let mut store = store();
+        for id in 0..3 {
+            let offer = offer_to(&mut store, &client(id), None).unwrap();
+            store.bind(&client(id), offer.address).unwrap();
+        }
+        assert!(offer_to(&mut store, &client(3), None).is_none());
+    }
+
+    #[test]
+    fn parse_lease_duration() {
+        assert_eq!(
+            parse_duration("7d").unwrap(),
+            SignedDuration::from_hours(7 * 24)
+        );
+        assert_eq!(parse_duration("90").unwrap(), SignedDuration::from_secs(90));
+        assert!(parse_duration("infinite").is_ok());
+        assert!(parse_duration("7y").is_err());
+    }
+}
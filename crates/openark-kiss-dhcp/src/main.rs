@@ -0,0 +1,144 @@
+mod lease;
+mod packet;
+mod persist;
+mod probe;
+mod reloader;
+mod server;
+mod tftp;
+
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use anyhow::{Result, bail};
+use clap::Parser;
+use kube::Client;
+use openark_kiss_ansible::config::KissConfig;
+use tokio::{spawn, try_join};
+#[cfg(feature = "tracing")]
+use tracing::info;
+
+use crate::{
+    lease::LeaseStore,
+    persist::LeasePersister,
+    server::{ServerContext, ServerSettings},
+};
+
+/// The name of this server, used as the field manager.
+const NAME: &str = "kiss-dhcp";
+
+#[derive(Clone, Debug, Parser)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// The base URL of the boot assets, serving the iPXE scripts
+    #[arg(
+        long,
+        env = "ASSETS_URL",
+        value_name = "URL",
+        default_value = "http://assets.kiss.svc.ops.openark"
+    )]
+    assets_url: String,
+
+    /// An address to bind the DHCP server
+    #[arg(
+        long,
+        env = "BIND_ADDR",
+        value_name = "ADDR",
+        default_value = "0.0.0.0:67"
+    )]
+    bind_addr: SocketAddr,
+
+    /// The name of a config map to persist the leases
+    #[arg(
+        long,
+        env = "LEASES_CONFIG_MAP",
+        value_name = "NAME",
+        default_value = "kiss-dhcp-leases"
+    )]
+    leases_config_map: String,
+
+    /// Target namespace of the KISS configuration
+    #[arg(long, env = "NAMESPACE", value_name = "NAME")]
+    namespace: Option<String>,
+
+    /// The address of this server, which is advertised to the clients
+    #[arg(long, env = "SERVER_ADDR", value_name = "ADDR")]
+    server_addr: Ipv4Addr,
+
+    /// An address to bind the TFTP server
+    #[arg(
+        long,
+        env = "TFTP_BIND_ADDR",
+        value_name = "ADDR",
+        default_value = "0.0.0.0:69"
+    )]
+    tftp_bind_addr: SocketAddr,
+
+    /// A directory of the iPXE binaries to be served over TFTP, disabling the TFTP server if not given
+    #[arg(long, env = "TFTP_ROOT", value_name = "PATH")]
+    tftp_root: Option<PathBuf>,
+}
+
+async fn try_main(args: Args) -> Result<()> {
+    let kube = match Client::try_default().await {
+        Ok(kube) => kube,
+        Err(error) => bail!("failed to init kubernetes client: {error}"),
+    };
+
+    let namespace = args
+        .namespace
+        .as_deref()
+        .unwrap_or(kube.default_namespace());
+    let config = match KissConfig::try_default(&kube, namespace).await {
+        Ok(config) => config,
+        Err(error) => bail!("failed to load KISS configuration: {error}"),
+    };
+
+    let ctx = Arc::new(ServerContext::new(
+        kube.clone(),
+        ServerSettings::new(&config, args.assets_url, args.server_addr),
+        LeaseStore::try_new(&config)?,
+    ));
+
+    // NOTE: restore the leases before serving, not to offer the bound addresses
+    let persister = LeasePersister::new(kube.clone(), namespace, args.leases_config_map);
+    if let Err(error) = persister.restore(&ctx).await {
+        bail!("failed to restore the leases: {error}")
+    }
+
+    #[cfg(feature = "tracing")]
+    info!("Registering side workers...");
+    let persister = spawn(persister.loop_forever(ctx.clone()));
+    let reloader = spawn(self::reloader::loop_forever(ctx.clone(), kube));
+
+    #[cfg(feature = "tracing")]
+    info!("Ready");
+    let result = match args.tftp_root {
+        Some(root) => try_join!(
+            self::server::loop_forever(ctx, args.bind_addr),
+            self::tftp::loop_forever(root, args.tftp_bind_addr),
+        )
+        .map(|((), ())| ()),
+        None => self::server::loop_forever(ctx, args.bind_addr).await,
+    };
+
+    #[cfg(feature = "tracing")]
+    info!("Terminating...");
+    persister.abort();
+    reloader.abort();
+    result
+}
+
+#[::tokio::main]
+async fn main() {
+    let args = Args::parse();
+
+    ::openark_core::init_once();
+
+    #[cfg(feature = "tracing")]
+    ::tracing::info!("Welcome to OpenARK KISS DHCP!");
+
+    try_main(args).await.expect("running a server")
+}
@@ -0,0 +1,417 @@
+use std::{collections::BTreeMap, fmt, net::Ipv4Addr};
+
+use anyhow::{Result, bail};
+use uuid::Uuid;
+
+/// A hardware (MAC) address of a client.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub(crate) struct MacAddr(pub(crate) [u8; 6]);
+
+impl fmt::Display for MacAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [a, b, c, d, e, g] = self.0;
+        write!(f, "{a:02x}:{b:02x}:{c:02x}:{d:02x}:{e:02x}:{g:02x}")
+    }
+}
+
+impl ::core::str::FromStr for MacAddr {
+    type Err = ::anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut addr = [0; 6];
+        let mut parts = s.split([':', '-']);
+        for byte in &mut addr {
+            match parts.next() {
+                Some(part) => *byte = u8::from_str_radix(part, 16)?,
+                None => bail!("too short MAC address: {s}"),
+            }
+        }
+        if parts.next().is_some() {
+            bail!("too long MAC address: {s}");
+        }
+        Ok(Self(addr))
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum MessageType {
+    Discover,
+    Offer,
+    Request,
+    Decline,
+    Ack,
+    Nak,
+    Release,
+    Inform,
+}
+
+impl MessageType {
+    const fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            1 => Some(Self::Discover),
+            2 => Some(Self::Offer),
+            3 => Some(Self::Request),
+            4 => Some(Self::Decline),
+            5 => Some(Self::Ack),
+            6 => Some(Self::Nak),
+            7 => Some(Self::Release),
+            8 => Some(Self::Inform),
+            _ => None,
+        }
+    }
+
+    const fn to_u8(self) -> u8 {
+        match self {
+            Self::Discover => 1,
+            Self::Offer => 2,
+            Self::Request => 3,
+            Self::Decline => 4,
+            Self::Ack => 5,
+            Self::Nak => 6,
+            Self::Release => 7,
+            Self::Inform => 8,
+        }
+    }
+}
+
+/// The DHCP option codes in use (RFC 2132, RFC 4578).
+pub(crate) mod options {
+    pub(crate) const SUBNET_MASK: u8 = 1;
+    pub(crate) const ROUTER: u8 = 3;
+    pub(crate) const DOMAIN_NAME_SERVER: u8 = 6;
+    pub(crate) const INTERFACE_MTU: u8 = 26;
+    pub(crate) const ALL_SUBNETS_ARE_LOCAL: u8 = 27;
+    pub(crate) const REQUESTED_IP_ADDRESS: u8 = 50;
+    pub(crate) const IP_ADDRESS_LEASE_TIME: u8 = 51;
+    pub(crate) const MESSAGE_TYPE: u8 = 53;
+    pub(crate) const SERVER_IDENTIFIER: u8 = 54;
+    pub(crate) const RENEWAL_TIME: u8 = 58;
+    pub(crate) const REBINDING_TIME: u8 = 59;
+    pub(crate) const VENDOR_CLASS_IDENTIFIER: u8 = 60;
+    pub(crate) const TFTP_SERVER_NAME: u8 = 66;
+    pub(crate) const BOOTFILE_NAME: u8 = 67;
+    pub(crate) const USER_CLASS: u8 = 77;
+    pub(crate) const CLIENT_SYSTEM_ARCHITECTURE: u8 = 93;
+    pub(crate) const CLIENT_MACHINE_IDENTIFIER: u8 = 97;
+
+    pub(crate) const PAD: u8 = 0;
+    pub(crate) const END: u8 = 255;
+}
+
+/// A DHCPv4 message (RFC 2131).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Packet {
+    pub(crate) op: u8,
+    pub(crate) xid: u32,
+    pub(crate) secs: u16,
+    pub(crate) flags: u16,
+    pub(crate) ciaddr: Ipv4Addr,
+    pub(crate) yiaddr: Ipv4Addr,
+    pub(crate) siaddr: Ipv4Addr,
+    pub(crate) giaddr: Ipv4Addr,
+    pub(crate) chaddr: MacAddr,
+    pub(crate) file: String,
+    pub(crate) options: BTreeMap<u8, Vec<u8>>,
+}
+
+impl Packet {
+    const OP_REQUEST: u8 = 1;
+    const OP_REPLY: u8 = 2;
+
+    const HTYPE_ETHERNET: u8 = 1;
+    const MAGIC_COOKIE: [u8; 4] = [99, 130, 83, 99];
+    const FLAG_BROADCAST: u16 = 0x8000;
+
+    const HEADER_LEN: usize = 236;
+    const MIN_LEN: usize = 300;
+
+    pub(crate) fn parse(buf: &[u8]) -> Result<Self> {
+        if buf.len() < Self::HEADER_LEN + Self::MAGIC_COOKIE.len() {
+            bail!("too short packet: {} bytes", buf.len());
+        }
+        if buf[236..240] != Self::MAGIC_COOKIE {
+            bail!("invalid magic cookie");
+        }
+        if buf[1] != Self::HTYPE_ETHERNET || buf[2] != 6 {
+            bail!("unsupported hardware type: {}", buf[1]);
+        }
+
+        let u16_at = |i: usize| u16::from_be_bytes([buf[i], buf[i + 1]]);
+        let addr_at = |i: usize| Ipv4Addr::new(buf[i], buf[i + 1], buf[i + 2], buf[i + 3]);
+
+        let mut chaddr = [0; 6];
+        chaddr.copy_from_slice(&buf[28..34]);
+
+        let file = &buf[108..236];
+        let file =
+            String::from_utf8_lossy(&file[..file.iter().position(|&b| b == 0).unwrap_or(128)])
+                .into_owned();
+
+        // parse options
+        let mut options: BTreeMap<_, Vec<u8>> = BTreeMap::default();
+        let mut cursor = 240;
+        while cursor < buf.len() {
+            let code = buf[cursor];
+            cursor += 1;
+            match code {
+                options::PAD => continue,
+                options::END => break,
+                code => {
+                    let Some(&len) = buf.get(cursor) else {
+                        bail!("truncated option: {code}");
+                    };
+                    let begin = cursor + 1;
+                    let end = begin + len as usize;
+                    if end > buf.len() {
+                        bail!("truncated option: {code}");
+                    }
+                    // NOTE: the long options are split into multiple ones (RFC 3396)
+                    options
+                        .entry(code)
+                        .or_default()
+                        .extend_from_slice(&buf[begin..end]);
+                    cursor = end;
+                }
+            }
+        }
+
+        Ok(Self {
+            op: buf[0],
+            xid: u32::from_be_bytes([buf[4], buf[5], buf[6], buf[7]]),
+            secs: u16_at(8),
+            flags: u16_at(10),
+            ciaddr: addr_at(12),
+            yiaddr: addr_at(16),
+            siaddr: addr_at(20),
+            giaddr: addr_at(24),
+            chaddr: MacAddr(chaddr),
+            file,
+            options,
+        })
+    }
+
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(Self::MIN_LEN);
+        buf.extend_from_slice(&[self.op, Self::HTYPE_ETHERNET, 6, 0]);
+        buf.extend_from_slice(&self.xid.to_be_bytes());
+        buf.extend_from_slice(&self.secs.to_be_bytes());
+        buf.extend_from_slice(&self.flags.to_be_bytes());
+        for addr in [self.ciaddr, self.yiaddr, self.siaddr, self.giaddr] {
+            buf.extend_from_slice(&addr.octets());
+        }
+        buf.extend_from_slice(&self.chaddr.0);
+        buf.resize(44 + 64, 0); // chaddr padding + sname
+
+        let file = self.file.as_bytes();
+        buf.extend_from_slice(&file[..file.len().min(127)]);
+        buf.resize(Self::HEADER_LEN, 0);
+        buf.extend_from_slice(&Self::MAGIC_COOKIE);
+
+        // NOTE: the message type comes first, as some PXE firmwares expect
+        let message_type = self.options.get_key_value(&options::MESSAGE_TYPE);
+        let others = self
+            .options
+            .iter()
+            .filter(|(code, _)| **code != options::MESSAGE_TYPE);
+        for (&code, value) in message_type.into_iter().chain(others) {
+            for chunk in value.chunks(u8::MAX as usize) {
+                buf.push(code);
+                buf.push(chunk.len() as u8);
+                buf.extend_from_slice(chunk);
+            }
+        }
+        buf.push(options::END);
+
+        if buf.len() < Self::MIN_LEN {
+            buf.resize(Self::MIN_LEN, 0);
+        }
+        buf
+    }
+
+    /// Creates a reply to the request, without any options.
+    pub(crate) fn reply(&self, message_type: MessageType) -> Self {
+        Self {
+            op: Self::OP_REPLY,
+            xid: self.xid,
+            secs: 0,
+            flags: self.flags,
+            ciaddr: Ipv4Addr::UNSPECIFIED,
+            yiaddr: Ipv4Addr::UNSPECIFIED,
+            siaddr: Ipv4Addr::UNSPECIFIED,
+            giaddr: self.giaddr,
+            chaddr: self.chaddr,
+            file: String::default(),
+            options: [(options::MESSAGE_TYPE, vec![message_type.to_u8()])]
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    pub(crate) fn is_request(&self) -> bool {
+        self.op == Self::OP_REQUEST
+    }
+
+    pub(crate) fn is_broadcast(&self) -> bool {
+        self.flags & Self::FLAG_BROADCAST != 0
+    }
+
+    pub(crate) fn message_type(&self) -> Option<MessageType> {
+        match self.options.get(&options::MESSAGE_TYPE)?.as_slice() {
+            [value] => MessageType::from_u8(*value),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn get_addr(&self, code: u8) -> Option<Ipv4Addr> {
+        let value: [u8; 4] = self.options.get(&code)?.as_slice().try_into().ok()?;
+        Some(value.into())
+    }
+
+    pub(crate) fn get_str(&self, code: u8) -> Option<String> {
+        self.options
+            .get(&code)
+            .map(|value| String::from_utf8_lossy(value).into_owned())
+    }
+
+    /// Returns the client system architecture (RFC 4578).
+    pub(crate) fn client_arch(&self) -> Option<u16> {
+        match self
+            .options
+            .get(&options::CLIENT_SYSTEM_ARCHITECTURE)?
+            .as_slice()
+        {
+            [a, b, ..] => Some(u16::from_be_bytes([*a, *b])),
+            _ => None,
+        }
+    }
+
+    /// Returns the machine UUID of the PXE client (RFC 4578).
+    pub(crate) fn client_uuid(&self) -> Option<Uuid> {
+        match self
+            .options
+            .get(&options::CLIENT_MACHINE_IDENTIFIER)?
+            .as_slice()
+        {
+            // NOTE: the UUID is encoded as in SMBIOS, i.e. mixed-endian
+            [0, uuid @ ..] => Some(Uuid::from_bytes_le(uuid.try_into().ok()?)),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if the client has booted into iPXE.
+    pub(crate) fn is_ipxe(&self) -> bool {
+        // NOTE: the user classes may be either a plain string or RFC 3004-encoded
+        self.options
+            .get(&options::USER_CLASS)
+            .is_some_and(|value| value.ends_with(b"iPXE"))
+    }
+
+    pub(crate) fn is_pxe(&self) -> bool {
+        self.get_str(options::VENDOR_CLASS_IDENTIFIER)
+            .is_some_and(|vendor| vendor.starts_with("PXEClient"))
+    }
+
+    pub(crate) fn set_addr(&mut self, code: u8, addr: Ipv4Addr) {
+        self.options.insert(code, addr.octets().to_vec());
+    }
+
+    pub(crate) fn set_addrs(&mut self, code: u8, addrs: &[Ipv4Addr]) {
+        self.options
+            .insert(code, addrs.iter().flat_map(|addr| addr.octets()).collect());
+    }
+
+    pub(crate) fn set_str(&mut self, code: u8, value: &str) {
+        self.options.insert(code, value.as_bytes().to_vec());
+    }
+
+    pub(crate) fn set_u8(&mut self, code: u8, value: u8) {
+        self.options.insert(code, vec![value]);
+    }
+
+    pub(crate) fn set_u16(&mut self, code: u8, value: u16) {
+        self.options.insert(code, value.to_be_bytes().to_vec());
+    }
+
+    pub(crate) fn set_u32(&mut self, code: u8, value: u32) {
+        self.options.insert(code, value.to_be_bytes().to_vec());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn discover() -> Packet {
+        let mut packet = Packet {
+            op: Packet::OP_REQUEST,
+            xid: 0x12345678,
+            secs: 4,
+            flags: Packet::FLAG_BROADCAST,
+            ciaddr: Ipv4Addr::UNSPECIFIED,
+            yiaddr: Ipv4Addr::UNSPECIFIED,
+            siaddr: Ipv4Addr::UNSPECIFIED,
+            giaddr: Ipv4Addr::UNSPECIFIED,
+            chaddr: "52:54:00:12:34:56".parse().unwrap(),
+            file: String::default(),
+            options: BTreeMap::default(),
+        };
+        packet.set_u8(options::MESSAGE_TYPE, MessageType::Discover.to_u8());
+        packet.set_str(options::VENDOR_CLASS_IDENTIFIER, "PXEClient:Arch:00007");
+        packet.set_u16(options::CLIENT_SYSTEM_ARCHITECTURE, 7);
+        packet.options.insert(
+            options::CLIENT_MACHINE_IDENTIFIER,
+            [0, 0x33, 0x22, 0x11, 0x00, 0x55, 0x44, 0x77, 0x66]
+                .into_iter()
+                .chain([0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff])
+                .collect(),
+        );
+        packet
+    }
+
+    #[test]
+    fn parse_encoded() {
+        let packet = discover();
+        let buf = packet.encode();
+        assert!(buf.len() >= Packet::MIN_LEN);
+        assert_eq!(buf[240], options::MESSAGE_TYPE);
+
+        let parsed = Packet::parse(&buf).unwrap();
+        assert_eq!(parsed, packet);
+        assert_eq!(parsed.message_type(), Some(MessageType::Discover));
+        assert_eq!(parsed.client_arch(), Some(7));
+        assert_eq!(
+            parsed.client_uuid(),
+            Some("00112233-4455-6677-8899-aabbccddeeff".parse().unwrap()),
+        );
+        assert!(parsed.is_broadcast());
+        assert!(parsed.is_pxe());
+        assert!(!parsed.is_ipxe());
+    }
+
+    #[test]
+    fn parse_split_options() {
+        let mut packet = discover();
+        packet.set_str(options::BOOTFILE_NAME, &"a".repeat(300));
+
+        let parsed = Packet::parse(&packet.encode()).unwrap();
+        assert_eq!(
+            parsed
+                .get_str(options::BOOTFILE_NAME)
+                .map(|file| file.len()),
+            Some(300),
+        );
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert!(Packet::parse(&[0; 100]).is_err());
+        assert!(Packet::parse(&[0; 300]).is_err());
+    }
+
+    #[test]
+    fn parse_mac_addr() {
+        let addr: MacAddr = "52-54-00-AB-CD-EF".parse().unwrap();
+        assert_eq!(addr.to_string(), "52:54:00:ab:cd:ef");
+        assert!("52:54:00".parse::<MacAddr>().is_err());
+    }
+}
@@ -0,0 +1,95 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::Result;
+use k8s_openapi::api::core::v1::ConfigMap;
+use kube::{
+    Api, Client,
+    api::{Patch, PatchParams},
+};
+use serde_json::json;
+use tokio::time::sleep;
+#[cfg(feature = "tracing")]
+use tracing::{Level, info, instrument, warn};
+
+use crate::{
+    lease::{format_leases, parse_leases},
+    server::ServerContext,
+};
+
+/// The key of the persisted leases in the config map.
+const KEY: &str = "leases";
+
+/// Persists the bound leases into a config map, to be restored after restarting the server.
+pub(crate) struct LeasePersister {
+    api: Api<ConfigMap>,
+    name: String,
+}
+
+impl LeasePersister {
+    pub(crate) fn new(kube: Client, namespace: &str, name: String) -> Self {
+        Self {
+            api: Api::namespaced(kube, namespace),
+            name,
+        }
+    }
+
+    /// Restores the persisted leases, including the ones of the unknown hosts.
+    #[cfg_attr(feature = "tracing", instrument(level = Level::INFO, skip_all))]
+    pub(crate) async fn restore(&self, ctx: &ServerContext) -> Result<()> {
+        let Some(object) = self.api.get_opt(&self.name).await? else {
+            return Ok(());
+        };
+        let Some(value) = object.data.as_ref().and_then(|data| data.get(KEY)) else {
+            return Ok(());
+        };
+
+        let leases = parse_leases(value);
+        #[cfg(feature = "tracing")]
+        info!("Restoring {} leases", leases.len());
+
+        let mut store = ctx.store.lock().unwrap();
+        for lease in leases {
+            store.restore(lease);
+        }
+        Ok(())
+    }
+
+    pub(crate) async fn loop_forever(self, ctx: Arc<ServerContext>) {
+        let interval = Duration::from_secs(10);
+
+        let mut last = None;
+        loop {
+            sleep(interval).await;
+
+            // NOTE: the leases are written only if changed
+            let value = format_leases(ctx.store.lock().unwrap().bound());
+            if last.as_ref() == Some(&value) {
+                continue;
+            }
+            match self.persist(&value).await {
+                Ok(()) => last = Some(value),
+                Err(error) => {
+                    #[cfg(feature = "tracing")]
+                    warn!("failed to persist the leases: {error}");
+                    let _ = error;
+                }
+            }
+        }
+    }
+
+    async fn persist(&self, value: &str) -> Result<()> {
+        let patch = Patch::Apply(json!({
+            "apiVersion": "v1",
+            "kind": "ConfigMap",
+            "metadata": {
+                "name": &self.name,
+            },
+            "data": {
+                KEY: value,
+            },
+        }));
+        let pp = PatchParams::apply(crate::NAME).force();
+        self.api.patch(&self.name, &pp, &patch).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,41 @@
+use std::{net::Ipv4Addr, process::Stdio, time::Duration};
+
+use tokio::{join, process::Command, time::timeout};
+#[cfg(feature = "tracing")]
+use tracing::warn;
+
+/// The timeout of each probe, including the process startup.
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Returns `true` if any host answers to the ARP or ICMP probes of the address.
+pub(crate) async fn is_in_use(address: Ipv4Addr) -> bool {
+    // NOTE: the hosts dropping ICMP still answer to ARP on the same link,
+    //       and the hosts behind the relays to ICMP only
+    let (arp, icmp) = join!(
+        probe("arping", &["-c", "1", "-w", "1", "-q"], address),
+        probe("ping", &["-c", "1", "-W", "1", "-n", "-q"], address),
+    );
+    arp || icmp
+}
+
+async fn probe(program: &str, args: &[&str], address: Ipv4Addr) -> bool {
+    let status = Command::new(program)
+        .args(args)
+        .arg(address.to_string())
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .status();
+
+    match timeout(TIMEOUT, status).await {
+        Ok(Ok(status)) => status.success(),
+        Ok(Err(error)) => {
+            #[cfg(feature = "tracing")]
+            warn!("failed to probe {address} with {program}: {error}");
+            let _ = error;
+            false
+        }
+        Err(_) => false,
+    }
+}
@@ -0,0 +1,182 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    net::{IpAddr, Ipv4Addr},
+    sync::Arc,
+    time::Duration,
+};
+
+use futures::TryStreamExt;
+use kube::{
+    Api, Client, ResourceExt,
+    runtime::watcher::{Config, Error, Event, watcher},
+};
+use openark_kiss_api::r#box::{BoxCrd, BoxLeaseStatus};
+use tokio::time::sleep;
+#[cfg(feature = "tracing")]
+use tracing::{Level, error, info, instrument, warn};
+use uuid::Uuid;
+
+use crate::{
+    lease::{Lease, LeaseState, Reservation},
+    packet::MacAddr,
+    server::ServerContext,
+};
+
+/// The known boxes, keyed by the machine UUIDs.
+pub(crate) type BoxEntries = BTreeMap<Uuid, BoxEntry>;
+
+/// A box known to the server.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct BoxEntry {
+    pub(crate) name: String,
+    /// Whether the box has been registered, i.e. has a status to be patched
+    pub(crate) has_status: bool,
+    pub(crate) lease: Option<BoxLeaseStatus>,
+}
+
+impl BoxEntry {
+    fn from_box(object: &BoxCrd) -> (Self, Reservation) {
+        let status = object.status.as_ref();
+
+        // NOTE: each interface is pinned to its own address, and the unknown ones
+        //       (e.g. PXE clients) to the management address, falling back to the last lease
+        let lease = status
+            .and_then(|status| status.lease.as_ref())
+            .and_then(|lease| Some((lease.mac.parse::<MacAddr>().ok()?, lease.address)));
+        let address = status
+            .and_then(|status| status.access.management())
+            .and_then(|interface| as_ipv4(interface.address))
+            .or_else(|| lease.map(|(_, address)| address));
+
+        let mut interfaces: BTreeMap<_, _> = status
+            .into_iter()
+            .flat_map(|status| &status.access.interfaces)
+            .filter_map(|interface| {
+                let mac = interface.mac.as_deref()?.parse().ok()?;
+                let address = interface.addresses.iter().copied().find_map(as_ipv4);
+                Some((mac, address))
+            })
+            .collect();
+        if let Some((mac, address)) = lease {
+            interfaces.entry(mac).or_default().get_or_insert(address);
+        }
+
+        let entry = Self {
+            name: object.name_any(),
+            has_status: status.is_some(),
+            lease: status.and_then(|status| status.lease.clone()),
+        };
+        (
+            entry,
+            Reservation {
+                address,
+                interfaces,
+            },
+        )
+    }
+}
+
+fn as_ipv4(address: IpAddr) -> Option<Ipv4Addr> {
+    match address {
+        IpAddr::V4(address) => Some(address),
+        IpAddr::V6(_) => None,
+    }
+}
+
+pub(crate) async fn loop_forever(ctx: Arc<ServerContext>, kube: Client) {
+    let api = Api::all(kube);
+
+    loop {
+        // NOTE: the boxes being listed on (re)starting the watcher
+        let mut init = None;
+        if let Err(error) = watcher(api.clone(), Config::default())
+            .try_for_each(|e| {
+                let result = handle_event(&ctx, &mut init, e);
+                async move { result }
+            })
+            .await
+        {
+            #[cfg(feature = "tracing")]
+            error!("failed to operate reloader: {error}");
+            let _ = error;
+
+            let interval = Duration::from_secs(5);
+            #[cfg(feature = "tracing")]
+            warn!("restarting reloader in {interval:?}...");
+            sleep(interval).await
+        }
+    }
+}
+
+#[cfg_attr(feature = "tracing", instrument(level = Level::INFO, skip_all))]
+fn handle_event(
+    ctx: &ServerContext,
+    init: &mut Option<BTreeSet<Uuid>>,
+    event: Event<BoxCrd>,
+) -> Result<(), Error> {
+    match event {
+        Event::Apply(object) => handle_apply(ctx, object),
+        Event::Delete(object) => handle_delete(ctx, &object.spec.machine.uuid),
+        Event::Init => *init = Some(BTreeSet::default()),
+        Event::InitApply(object) => {
+            if let Some(init) = init.as_mut() {
+                init.insert(object.spec.machine.uuid);
+            }
+            handle_apply(ctx, object)
+        }
+        Event::InitDone => {
+            // NOTE: the boxes deleted while restarting are dropped here
+            if let Some(init) = init.take() {
+                let stale: Vec<_> = ctx
+                    .boxes
+                    .lock()
+                    .unwrap()
+                    .keys()
+                    .filter(|uuid| !init.contains(uuid))
+                    .copied()
+                    .collect();
+                for uuid in stale {
+                    handle_delete(ctx, &uuid)
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn handle_apply(ctx: &ServerContext, object: BoxCrd) {
+    let uuid = object.spec.machine.uuid;
+    let (entry, reservation) = BoxEntry::from_box(&object);
+
+    // NOTE: the store is locked first, as in the server
+    let mut store = ctx.store.lock().unwrap();
+    let mut boxes = ctx.boxes.lock().unwrap();
+
+    #[cfg(feature = "tracing")]
+    info!("Applying box: {} ({uuid})", &entry.name);
+
+    if let Some(lease) = entry.lease.as_ref()
+        && let Ok(mac) = lease.mac.parse()
+    {
+        store.restore(Lease {
+            address: lease.address,
+            mac,
+            state: LeaseState::Bound,
+            expires_at: lease.expires_at,
+        });
+    }
+    store.reserve(uuid, reservation);
+    boxes.insert(uuid, entry);
+}
+
+fn handle_delete(ctx: &ServerContext, uuid: &Uuid) {
+    let mut store = ctx.store.lock().unwrap();
+    let mut boxes = ctx.boxes.lock().unwrap();
+    if let Some(entry) = boxes.remove(uuid) {
+        #[cfg(feature = "tracing")]
+        info!("Deleting box: {} ({uuid})", &entry.name);
+        let _ = entry;
+
+        store.unreserve(uuid);
+    }
+}
@@ -0,0 +1,365 @@
+use std::{
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Result;
+use jiff::Timestamp;
+use kube::{
+    Api, Client,
+    api::{Patch, PatchParams},
+};
+use openark_kiss_ansible::config::KissConfig;
+use openark_kiss_api::r#box::{BoxCrd, BoxLeaseStatus};
+use serde_json::json;
+use tokio::{net::UdpSocket, spawn};
+#[cfg(feature = "tracing")]
+use tracing::{Level, debug, info, instrument, warn};
+
+use crate::{
+    lease::{Client as LeaseClient, Lease, LeaseStore},
+    packet::{MessageType, Packet, options},
+    probe,
+    reloader::BoxEntries,
+};
+
+/// The settings of the replies, derived from the KISS configuration.
+pub(crate) struct ServerSettings {
+    /// The base URL of the boot assets, e.g. `http://assets.kiss.svc`
+    pub(crate) assets_url: String,
+    pub(crate) gateway: Ipv4Addr,
+    pub(crate) mtu: u16,
+    pub(crate) nameservers: Vec<Ipv4Addr>,
+    pub(crate) os_dist: String,
+    /// The address of this server, which is advertised to the clients
+    pub(crate) server_addr: Ipv4Addr,
+}
+
+impl ServerSettings {
+    pub(crate) fn new(config: &KissConfig, assets_url: String, server_addr: Ipv4Addr) -> Self {
+        Self {
+            assets_url: assets_url.trim_end_matches('/').into(),
+            gateway: config.network_ipv4_gateway,
+            mtu: config.network_interface_mtu_size,
+            nameservers: vec![
+                config.network_nameserver_incluster_ipv4,
+                config.bootstrapper_network_dns_server_ns1,
+                config.bootstrapper_network_dns_server_ns2,
+            ],
+            os_dist: config.os_dist.clone(),
+            server_addr,
+        }
+    }
+}
+
+pub(crate) struct ServerContext {
+    pub(crate) boxes: Mutex<BoxEntries>,
+    pub(crate) settings: ServerSettings,
+    pub(crate) store: Mutex<LeaseStore>,
+    api: Api<BoxCrd>,
+    patch_params: PatchParams,
+}
+
+impl ServerContext {
+    pub(crate) fn new(kube: Client, settings: ServerSettings, store: LeaseStore) -> Self {
+        Self {
+            boxes: Mutex::default(),
+            settings,
+            store: Mutex::new(store),
+            api: Api::all(kube),
+            patch_params: PatchParams {
+                field_manager: Some(crate::NAME.into()),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+/// A reply to be sent.
+struct Reply {
+    packet: Packet,
+    /// The lease to be recorded into the box status
+    lease: Option<(String, BoxLeaseStatus)>,
+}
+
+pub(crate) async fn loop_forever(ctx: Arc<ServerContext>, bind_addr: SocketAddr) -> Result<()> {
+    let socket = UdpSocket::bind(bind_addr).await?;
+    socket.set_broadcast(true)?;
+    let socket = Arc::new(socket);
+
+    let mut buf = [0; 1500];
+    loop {
+        let (len, src) = socket.recv_from(&mut buf).await?;
+        let request = match Packet::parse(&buf[..len]) {
+            Ok(request) if request.is_request() => request,
+            Ok(_) => continue,
+            Err(error) => {
+                #[cfg(feature = "tracing")]
+                debug!("ignoring invalid packet from {src}: {error}");
+                let _ = (src, error);
+                continue;
+            }
+        };
+
+        // NOTE: the offers are probed in background, not to block the other clients
+        let ctx = ctx.clone();
+        let socket = socket.clone();
+        spawn(async move { reply(&ctx, &socket, &request).await });
+    }
+}
+
+async fn reply(ctx: &ServerContext, socket: &UdpSocket, request: &Packet) {
+    let Some(Reply { packet, lease }) = handle_request(ctx, request).await else {
+        return;
+    };
+    let dst = destination(request, &packet);
+    if let Err(error) = socket.send_to(&packet.encode(), dst).await {
+        #[cfg(feature = "tracing")]
+        warn!("failed to reply to {dst}: {error}");
+        let _ = error;
+        return;
+    }
+
+    if let Some((name, lease)) = lease {
+        update_lease(ctx, &name, lease).await
+    }
+}
+
+#[cfg_attr(feature = "tracing", instrument(
+    level = Level::INFO,
+    skip_all,
+    fields(mac = %request.chaddr, xid = request.xid),
+))]
+async fn handle_request(ctx: &ServerContext, request: &Packet) -> Option<Reply> {
+    let client = LeaseClient {
+        mac: request.chaddr,
+        uuid: request.client_uuid(),
+    };
+    let requested = request.get_addr(options::REQUESTED_IP_ADDRESS);
+
+    let message_type = request.message_type()?;
+    if message_type == MessageType::Discover {
+        let lease = offer(ctx, &client, requested).await?;
+        #[cfg(feature = "tracing")]
+        info!("Offering {} to {}", lease.address, client.mac);
+
+        let store = ctx.store.lock().unwrap();
+        let mut packet = request.reply(MessageType::Offer);
+        build_lease(ctx, &store, &mut packet, &lease);
+        build_boot(&ctx.settings, request, &mut packet);
+        return Some(Reply {
+            packet,
+            lease: None,
+        });
+    }
+
+    let mut store = ctx.store.lock().unwrap();
+    match message_type {
+        MessageType::Request => {
+            // NOTE: the requests to the other servers are ignored
+            if let Some(server) = request.get_addr(options::SERVER_IDENTIFIER)
+                && server != ctx.settings.server_addr
+            {
+                return None;
+            }
+
+            let address = requested.unwrap_or(request.ciaddr);
+            match store.bind(&client, address) {
+                Ok(lease) => {
+                    #[cfg(feature = "tracing")]
+                    info!("Binding {} to {}", lease.address, client.mac);
+
+                    let mut packet = request.reply(MessageType::Ack);
+                    build_lease(ctx, &store, &mut packet, &lease);
+                    build_boot(&ctx.settings, request, &mut packet);
+                    Some(Reply {
+                        packet,
+                        lease: find_box_lease(ctx, &store, &client, &lease),
+                    })
+                }
+                Err(error) => {
+                    #[cfg(feature = "tracing")]
+                    warn!("Rejecting {address} to {}: {error}", client.mac);
+                    let _ = error;
+
+                    let mut packet = request.reply(MessageType::Nak);
+                    packet.set_addr(options::SERVER_IDENTIFIER, ctx.settings.server_addr);
+                    Some(Reply {
+                        packet,
+                        lease: None,
+                    })
+                }
+            }
+        }
+        MessageType::Decline => {
+            let address = requested?;
+            #[cfg(feature = "tracing")]
+            warn!("Declined {address} by {}", client.mac);
+            store.decline(&client, address);
+            None
+        }
+        MessageType::Release => {
+            #[cfg(feature = "tracing")]
+            info!("Releasing {} by {}", request.ciaddr, client.mac);
+            store.release(&client, request.ciaddr);
+            None
+        }
+        MessageType::Inform => {
+            // NOTE: the client has already configured its address
+            let mut packet = request.reply(MessageType::Ack);
+            build_network(&ctx.settings, &store, &mut packet);
+            Some(Reply {
+                packet,
+                lease: None,
+            })
+        }
+        MessageType::Discover | MessageType::Offer | MessageType::Ack | MessageType::Nak => None,
+    }
+}
+
+/// Offers a lease, skipping the new addresses which are already in use by the unknown hosts.
+async fn offer(
+    ctx: &ServerContext,
+    client: &LeaseClient,
+    mut requested: Option<Ipv4Addr>,
+) -> Option<Lease> {
+    const MAX_ATTEMPTS: usize = 3;
+
+    for _ in 0..MAX_ATTEMPTS {
+        // NOTE: the store is unlocked while probing
+        let candidate = ctx.store.lock().unwrap().candidate(client, requested)?;
+        if candidate.probe && probe::is_in_use(candidate.address).await {
+            #[cfg(feature = "tracing")]
+            warn!("Conflicted {} with an unknown host", candidate.address);
+            ctx.store.lock().unwrap().conflict(candidate.address);
+            requested = None;
+            continue;
+        }
+
+        // NOTE: the address may be taken by the others while probing
+        if let Some(lease) = ctx.store.lock().unwrap().offer(client, candidate.address) {
+            return Some(lease);
+        }
+        requested = None;
+    }
+    None
+}
+
+fn build_network(settings: &ServerSettings, store: &LeaseStore, packet: &mut Packet) {
+    packet.set_addr(options::SERVER_IDENTIFIER, settings.server_addr);
+    packet.set_addr(options::SUBNET_MASK, store.subnet().netmask());
+    packet.set_addr(options::ROUTER, settings.gateway);
+    packet.set_addrs(options::DOMAIN_NAME_SERVER, &settings.nameservers);
+    packet.set_u16(options::INTERFACE_MTU, settings.mtu);
+    packet.set_u8(options::ALL_SUBNETS_ARE_LOCAL, 1);
+}
+
+fn build_lease(ctx: &ServerContext, store: &LeaseStore, packet: &mut Packet, lease: &Lease) {
+    build_network(&ctx.settings, store, packet);
+    packet.yiaddr = lease.address;
+
+    let duration = store.duration().as_secs().clamp(0, u32::MAX as i64) as u32;
+    packet.set_u32(options::IP_ADDRESS_LEASE_TIME, duration);
+    packet.set_u32(options::RENEWAL_TIME, duration / 2);
+    packet.set_u32(options::REBINDING_TIME, duration / 8 * 7);
+}
+
+/// Chains the PXE clients to iPXE, and then iPXE to the boot script.
+fn build_boot(settings: &ServerSettings, request: &Packet, packet: &mut Packet) {
+    let file = if request.is_ipxe() {
+        format!(
+            "{}/boot/boot_{}.ipxe",
+            &settings.assets_url, &settings.os_dist,
+        )
+    } else if request.is_pxe() {
+        match request.client_arch() {
+            // BIOS
+            Some(0) | None => "undionly.kpxe".into(),
+            // EFI x86-64
+            Some(7 | 9) => "ipxe-x86_64.efi".into(),
+            // EFI ARM64
+            Some(11) => "ipxe-arm64.efi".into(),
+            Some(_) => return,
+        }
+    } else {
+        return;
+    };
+
+    if request.is_pxe() {
+        packet.set_str(options::VENDOR_CLASS_IDENTIFIER, "PXEClient");
+    }
+    packet.siaddr = settings.server_addr;
+    packet.set_str(options::TFTP_SERVER_NAME, &settings.server_addr.to_string());
+    packet.set_str(options::BOOTFILE_NAME, &file);
+    packet.file = file;
+}
+
+/// Returns the lease to be recorded, if it has been changed materially.
+fn find_box_lease(
+    ctx: &ServerContext,
+    store: &LeaseStore,
+    client: &LeaseClient,
+    lease: &Lease,
+) -> Option<(String, BoxLeaseStatus)> {
+    let uuid = store.find_box(client)?;
+    let boxes = ctx.boxes.lock().unwrap();
+    let entry = boxes.get(&uuid).filter(|entry| entry.has_status)?;
+
+    let status = BoxLeaseStatus {
+        address: lease.address,
+        mac: lease.mac.to_string(),
+        expires_at: lease.expires_at,
+        last_updated: Timestamp::now(),
+    };
+
+    // NOTE: skip the renewals within the first half of the lease
+    match entry.lease.as_ref() {
+        Some(prev)
+            if prev.address == status.address
+                && prev.mac == status.mac
+                && prev.expires_at.duration_since(status.last_updated) > store.duration() / 2 =>
+        {
+            None
+        }
+        _ => Some((entry.name.clone(), status)),
+    }
+}
+
+/// Returns the destination of the reply (RFC 2131, Section 4.1).
+fn destination(request: &Packet, reply: &Packet) -> SocketAddr {
+    const SERVER_PORT: u16 = 67;
+    const CLIENT_PORT: u16 = 68;
+
+    let (addr, port) = if !request.giaddr.is_unspecified() {
+        (request.giaddr, SERVER_PORT)
+    } else if !request.ciaddr.is_unspecified()
+        && reply.message_type() != Some(MessageType::Nak)
+        && !request.is_broadcast()
+    {
+        (request.ciaddr, CLIENT_PORT)
+    } else {
+        (Ipv4Addr::BROADCAST, CLIENT_PORT)
+    };
+    SocketAddrV4::new(addr, port).into()
+}
+
+#[cfg_attr(feature = "tracing", instrument(level = Level::INFO, skip(ctx, lease)))]
+async fn update_lease(ctx: &ServerContext, name: &str, lease: BoxLeaseStatus) {
+    if let Err(error) = try_update_lease(ctx, name, &lease).await {
+        #[cfg(feature = "tracing")]
+        warn!("failed to update the lease: {error}");
+        let _ = error;
+    }
+}
+
+async fn try_update_lease(ctx: &ServerContext, name: &str, lease: &BoxLeaseStatus) -> Result<()> {
+    let patch = Patch::Merge(json!({
+        "status": {
+            "lease": lease,
+        },
+    }));
+    ctx.api
+        .patch_status(name, &ctx.patch_params, &patch)
+        .await?;
+    Ok(())
+}
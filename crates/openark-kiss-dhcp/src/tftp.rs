@@ -0,0 +1,247 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::{Component, Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{Result, bail};
+use tokio::{fs, net::UdpSocket, spawn, time::timeout};
+#[cfg(feature = "tracing")]
+use tracing::{Level, debug, info, instrument, warn};
+
+const OP_RRQ: u16 = 1;
+const OP_DATA: u16 = 3;
+const OP_ACK: u16 = 4;
+const OP_ERROR: u16 = 5;
+const OP_OACK: u16 = 6;
+
+const ERROR_NOT_FOUND: u16 = 1;
+const ERROR_ILLEGAL_OPERATION: u16 = 4;
+const ERROR_OPTION: u16 = 8;
+
+const DEFAULT_BLOCK_SIZE: usize = 512;
+const MAX_BLOCK_SIZE: usize = 65464;
+const MAX_RETRIES: usize = 5;
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A read request of a file (RFC 1350, RFC 2347).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct ReadRequest {
+    filename: String,
+    mode: String,
+    block_size: Option<usize>,
+    /// Whether the client asks for the transfer size
+    transfer_size: bool,
+}
+
+impl ReadRequest {
+    fn parse(buf: &[u8]) -> Result<Self> {
+        let mut fields = match buf {
+            [0, 1, fields @ ..] => fields
+                .split(|&byte| byte == 0)
+                .map(|field| String::from_utf8_lossy(field).into_owned()),
+            _ => bail!("not a read request"),
+        };
+
+        let (Some(filename), Some(mode)) = (fields.next(), fields.next()) else {
+            bail!("malformed read request");
+        };
+        let mut request = Self {
+            filename,
+            mode: mode.to_lowercase(),
+            ..Default::default()
+        };
+
+        // parse the options
+        while let (Some(key), Some(value)) = (fields.next(), fields.next()) {
+            match key.to_lowercase().as_str() {
+                "blksize" => {
+                    request.block_size = value
+                        .parse::<usize>()
+                        .ok()
+                        .map(|size| size.clamp(8, MAX_BLOCK_SIZE))
+                }
+                "tsize" => request.transfer_size = true,
+                _ => continue,
+            }
+        }
+        Ok(request)
+    }
+}
+
+/// Serves the files of the root directory over TFTP, read-only.
+pub(crate) async fn loop_forever(root: PathBuf, bind_addr: SocketAddr) -> Result<()> {
+    let socket = UdpSocket::bind(bind_addr).await?;
+
+    let mut buf = [0; 1500];
+    loop {
+        let (len, peer) = socket.recv_from(&mut buf).await?;
+        let op = match &buf[..len] {
+            [a, b, ..] => u16::from_be_bytes([*a, *b]),
+            _ => continue,
+        };
+        if op != OP_RRQ {
+            let _ = send_error(&socket, peer, ERROR_ILLEGAL_OPERATION, "read-only").await;
+            continue;
+        }
+
+        match ReadRequest::parse(&buf[..len]) {
+            Ok(request) => {
+                let root = root.clone();
+                spawn(async move { handle_request(&root, peer, request).await });
+            }
+            Err(error) => {
+                #[cfg(feature = "tracing")]
+                debug!("ignoring invalid request from {peer}: {error}");
+                let _ = error;
+            }
+        }
+    }
+}
+
+#[cfg_attr(feature = "tracing", instrument(level = Level::INFO, skip(root)))]
+async fn handle_request(root: &Path, peer: SocketAddr, request: ReadRequest) {
+    if let Err(error) = try_handle_request(root, peer, &request).await {
+        #[cfg(feature = "tracing")]
+        warn!("failed to transfer {:?}: {error}", &request.filename);
+        let _ = error;
+    }
+}
+
+async fn try_handle_request(root: &Path, peer: SocketAddr, request: &ReadRequest) -> Result<()> {
+    // NOTE: each transfer has its own port (TID)
+    let bind_addr = match peer.ip() {
+        IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        IpAddr::V6(_) => IpAddr::V6(::std::net::Ipv6Addr::UNSPECIFIED),
+    };
+    let socket = UdpSocket::bind(SocketAddr::new(bind_addr, 0)).await?;
+    socket.connect(peer).await?;
+
+    if request.mode != "octet" {
+        send_error(&socket, peer, ERROR_OPTION, "only octet mode is supported").await?;
+        bail!("unsupported mode: {}", &request.mode);
+    }
+    let Some(path) = resolve(root, &request.filename) else {
+        send_error(&socket, peer, ERROR_NOT_FOUND, "file not found").await?;
+        bail!("illegal path");
+    };
+    let data = match fs::read(&path).await {
+        Ok(data) => data,
+        Err(error) => {
+            send_error(&socket, peer, ERROR_NOT_FOUND, "file not found").await?;
+            bail!("{error}");
+        }
+    };
+
+    #[cfg(feature = "tracing")]
+    info!("Sending {} ({} bytes)", path.display(), data.len());
+
+    // negotiate the options
+    let block_size = request.block_size.unwrap_or(DEFAULT_BLOCK_SIZE);
+    if request.block_size.is_some() || request.transfer_size {
+        let mut packet = OP_OACK.to_be_bytes().to_vec();
+        if let Some(block_size) = request.block_size {
+            push_option(&mut packet, "blksize", block_size);
+        }
+        if request.transfer_size {
+            push_option(&mut packet, "tsize", data.len());
+        }
+        send_and_wait(&socket, &packet, 0).await?;
+    }
+
+    // NOTE: the last block is shorter than the block size, even if empty
+    let num_blocks = data.len() / block_size + 1;
+    for index in 0..num_blocks {
+        let block = (index + 1) as u16;
+        let begin = index * block_size;
+        let end = (begin + block_size).min(data.len());
+
+        let mut packet = OP_DATA.to_be_bytes().to_vec();
+        packet.extend_from_slice(&block.to_be_bytes());
+        packet.extend_from_slice(&data[begin..end]);
+        send_and_wait(&socket, &packet, block).await?;
+    }
+    Ok(())
+}
+
+/// Sends the packet, retransmitting it until the block is acknowledged.
+async fn send_and_wait(socket: &UdpSocket, packet: &[u8], block: u16) -> Result<()> {
+    let mut buf = [0; 516];
+    for _ in 0..MAX_RETRIES {
+        socket.send(packet).await?;
+
+        loop {
+            let len = match timeout(TIMEOUT, socket.recv(&mut buf)).await {
+                Ok(len) => len?,
+                // retransmit
+                Err(_) => break,
+            };
+            match &buf[..len] {
+                [a, b, c, d] if u16::from_be_bytes([*a, *b]) == OP_ACK => {
+                    // NOTE: the block numbers may wrap around on large files
+                    if u16::from_be_bytes([*c, *d]) == block {
+                        return Ok(());
+                    }
+                }
+                [a, b, ..] if u16::from_be_bytes([*a, *b]) == OP_ERROR => {
+                    bail!("aborted by the client")
+                }
+                _ => continue,
+            }
+        }
+    }
+    bail!("timed out")
+}
+
+async fn send_error(socket: &UdpSocket, peer: SocketAddr, code: u16, message: &str) -> Result<()> {
+    let mut packet = OP_ERROR.to_be_bytes().to_vec();
+    packet.extend_from_slice(&code.to_be_bytes());
+    packet.extend_from_slice(message.as_bytes());
+    packet.push(0);
+    socket.send_to(&packet, peer).await?;
+    Ok(())
+}
+
+fn push_option(packet: &mut Vec<u8>, key: &str, value: usize) {
+    packet.extend_from_slice(key.as_bytes());
+    packet.push(0);
+    packet.extend_from_slice(value.to_string().as_bytes());
+    packet.push(0);
+}
+
+/// Resolves the file path under the root, rejecting any escapes.
+fn resolve(root: &Path, filename: &str) -> Option<PathBuf> {
+    let filename = Path::new(filename.trim_start_matches('/'));
+    filename
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+        .then(|| root.join(filename))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_read_request() {
+        let request =
+            ReadRequest::parse(b"\0\x01undionly.kpxe\0octet\0blksize\01468\0tsize\00\0").unwrap();
+        assert_eq!(request.filename, "undionly.kpxe");
+        assert_eq!(request.mode, "octet");
+        assert_eq!(request.block_size, Some(1468));
+        assert!(request.transfer_size);
+
+        assert!(ReadRequest::parse(b"\0\x02undionly.kpxe\0octet\0").is_err());
+    }
+
+    #[test]
+    fn resolve_path() {
+        let root = Path::new("/var/lib/tftpboot");
+        assert_eq!(
+            resolve(root, "/ipxe-x86_64.efi"),
+            Some(root.join("ipxe-x86_64.efi")),
+        );
+        assert_eq!(resolve(root, "../etc/passwd"), None);
+        assert_eq!(resolve(root, "boot/./ipxe"), Some(root.join("boot/ipxe")));
+    }
+}
@@ -1,4 +1,5 @@
 mod reloader;
+mod zone;
 
 use std::{net::SocketAddr, sync::Arc, time::Duration};
 
@@ -11,6 +12,7 @@ use hickory_server::{
     zone_handler::Catalog,
 };
 use kube::Client;
+use openark_kiss_dns::transfer::{TransferPeer, TransferPolicy};
 use tokio::{
     net::{TcpListener, UdpSocket},
     spawn,
@@ -39,17 +41,80 @@ struct Args {
         default_value = "cluster.local"
     )]
     cluster_domain_name: String,
+
+    /// Secondary nameservers allowed to transfer the zones (AXFR/IXFR) and notified of the changes
+    #[arg(
+        long,
+        env = "TRANSFER_PEERS",
+        value_name = "ADDR",
+        value_delimiter = ','
+    )]
+    transfer_peers: Vec<TransferPeer>,
+
+    /// TSIG algorithm of the zone transfers and notifications
+    #[arg(
+        long,
+        env = "TSIG_ALGORITHM",
+        value_name = "NAME",
+        default_value = "hmac-sha256"
+    )]
+    tsig_algorithm: String,
+
+    /// TSIG secret of the zone transfers and notifications, encoded in base64
+    #[arg(long, env = "TSIG_KEY", value_name = "KEY", requires = "tsig_key_name")]
+    tsig_key: Option<String>,
+
+    /// TSIG key name of the zone transfers and notifications
+    #[arg(
+        long,
+        env = "TSIG_KEY_NAME",
+        value_name = "NAME",
+        requires = "tsig_key"
+    )]
+    tsig_key_name: Option<String>,
+
+    /// An e-mail address of the zone administrator, in the SOA format
+    #[arg(
+        long,
+        env = "ZONE_ADMIN",
+        value_name = "NAME",
+        default_value = "hostmaster.box"
+    )]
+    zone_admin: String,
+
+    /// The primary nameserver name of the zones
+    #[arg(
+        long,
+        env = "ZONE_NAMESERVER",
+        value_name = "NAME",
+        default_value = "ns.box"
+    )]
+    zone_nameserver: String,
 }
 
 #[derive(Clone)]
 struct Handler {
     catalog: Arc<RwLock<Catalog>>,
+    /// The zones served to the transfer peers
+    transfer_catalog: Arc<RwLock<Catalog>>,
+    transfer: Arc<TransferPolicy>,
 }
 
 impl Handler {
-    async fn try_default() -> Result<Self> {
+    async fn try_new(args: &Args) -> Result<Self> {
+        let signer = match (args.tsig_key_name.as_deref(), args.tsig_key.as_deref()) {
+            (Some(name), Some(key)) => Some(TransferPolicy::load_signer(
+                name,
+                &args.tsig_algorithm,
+                key,
+            )?),
+            _ => None,
+        };
+
         Ok(Self {
             catalog: Arc::default(),
+            transfer_catalog: Arc::default(),
+            transfer: Arc::new(TransferPolicy::new(args.transfer_peers.clone(), signer)),
         })
     }
 }
@@ -61,7 +126,14 @@ impl RequestHandler for Handler {
         R: ResponseHandler,
         T: Time,
     {
-        self.catalog
+        // NOTE: only the transfer peers signed with the TSIG key can transfer the zones
+        let catalog = if self.transfer.is_authorized(request) {
+            &self.transfer_catalog
+        } else {
+            &self.catalog
+        };
+
+        catalog
             .read()
             .await
             .handle_request::<R, T>(request, response_handle)
@@ -90,7 +162,7 @@ async fn try_main(args: &Args) -> Result<()> {
     #[cfg(feature = "tracing")]
     info!("Booting...");
 
-    let handler = match Handler::try_default().await {
+    let handler = match Handler::try_new(args).await {
         Ok(handler) => handler,
         Err(error) => bail!("failed to init handler: {error}"),
     };
@@ -104,7 +176,7 @@ async fn try_main(args: &Args) -> Result<()> {
         Err(error) => bail!("failed to init kubernetes client: {error}"),
     };
 
-    let ctx = match self::reloader::ReloaderContext::try_new(&args.cluster_domain_name).await {
+    let ctx = match self::reloader::ReloaderContext::try_new(args).await {
         Ok(ctx) => ctx,
         Err(error) => bail!("failed to init reloader context: {error}"),
     };
@@ -1,13 +1,10 @@
-use std::{net::IpAddr, str::FromStr, sync::Arc, time::Duration};
+use std::{collections::BTreeMap, str::FromStr, sync::Arc, time::Duration};
 
 use anyhow::Result;
-use futures::{TryStreamExt, stream::FuturesUnordered};
+use futures::TryStreamExt;
 use hickory_net::runtime::TokioRuntimeProvider;
 use hickory_server::{
-    proto::rr::{
-        Name, RData, Record,
-        rdata::{A, AAAA},
-    },
+    proto::rr::{Name, Record},
     store::in_memory::InMemoryZoneHandler,
     zone_handler::{AxfrPolicy, ZoneType},
 };
@@ -16,28 +13,56 @@ use kube::{
     runtime::watcher::{Config, Error, Event, watcher},
 };
 use openark_kiss_api::r#box::BoxCrd;
-use tokio::time::sleep;
+use openark_kiss_dns::transfer::TransferZoneHandler;
+use tokio::{spawn, sync::Mutex, time::sleep};
 use tracing::{Level, error, info, instrument, warn};
 
+use crate::zone::{BoxRecords, ZoneBuilder};
+
 pub(super) struct ReloaderContext {
-    origins: Vec<Name>,
+    admin: Name,
+    builder: ZoneBuilder,
+    nameserver: Name,
+    state: Mutex<ReloaderState>,
 }
 
 impl ReloaderContext {
-    pub(super) async fn try_new(domain: &str) -> Result<Self> {
+    pub(super) async fn try_new(args: &super::Args) -> Result<Self> {
+        let domain = &args.cluster_domain_name;
         Ok(Self {
-            origins: vec![
-                Name::from_str(".")?,
-                Name::from_str("box")?,
-                Name::from_str("node")?,
-                Name::from_str(domain)?,
-                Name::from_str(&format!("box.{domain}"))?,
-                Name::from_str(&format!("node.{domain}"))?,
-            ],
+            admin: crate::zone::fqdn(&args.zone_admin)?,
+            builder: ZoneBuilder {
+                origins: vec![
+                    crate::zone::fqdn("box")?,
+                    crate::zone::fqdn("node")?,
+                    crate::zone::fqdn(&format!("box.{domain}"))?,
+                    crate::zone::fqdn(&format!("node.{domain}"))?,
+                ],
+                shared_origins: vec![Name::from_str(".")?, crate::zone::fqdn(domain)?],
+                ptr_origin: crate::zone::fqdn(&format!("box.{domain}"))?,
+                ttl: 300,
+            },
+            nameserver: crate::zone::fqdn(&args.zone_nameserver)?,
+            state: Mutex::default(),
         })
     }
 }
 
+#[derive(Default)]
+struct ReloaderState {
+    /// The published boxes
+    boxes: BTreeMap<String, BoxRecords>,
+    /// The boxes being listed on (re)starting the watcher
+    init: Option<BTreeMap<String, BoxRecords>>,
+    /// The served zones
+    zones: BTreeMap<Name, ZoneState>,
+}
+
+struct ZoneState {
+    records: Vec<Record>,
+    serial: u32,
+}
+
 pub(super) async fn loop_forever(ctx: ReloaderContext, kube: Client, handler: super::Handler) {
     let api = Api::all(kube);
 
@@ -62,99 +87,148 @@ async fn handle_event(
     handler: &super::Handler,
     event: Event<BoxCrd>,
 ) -> Result<(), Error> {
-    match event {
-        Event::Apply(object) | Event::InitApply(object) => handle_apply(ctx, handler, object).await,
-        Event::Delete(object) => handle_delete(ctx, handler, object).await,
-        Event::Init | Event::InitDone => Ok(()),
+    let mut state = ctx.state.lock().await;
+    let changed = match event {
+        Event::Apply(object) => {
+            handle_apply(&mut state.boxes, object);
+            reload(ctx, handler, &mut state).await?
+        }
+        Event::Delete(object) => {
+            handle_delete(&mut state.boxes, object);
+            reload(ctx, handler, &mut state).await?
+        }
+        Event::Init => {
+            state.init = Some(BTreeMap::default());
+            return Ok(());
+        }
+        Event::InitApply(object) => {
+            if let Some(boxes) = state.init.as_mut() {
+                handle_apply(boxes, object);
+            }
+            return Ok(());
+        }
+        Event::InitDone => {
+            // NOTE: the boxes deleted while restarting are dropped here
+            if let Some(boxes) = state.init.take() {
+                state.boxes = boxes;
+            }
+            reload(ctx, handler, &mut state).await?
+        }
+    };
+
+    // notify the transfer peers in background
+    if handler.transfer.is_enabled() && !changed.is_empty() {
+        let transfer = handler.transfer.clone();
+        spawn(async move {
+            for origin in changed {
+                transfer.notify(&origin).await
+            }
+        });
     }
+    Ok(())
 }
 
-#[instrument(level = Level::INFO, skip(ctx, handler, object))]
-async fn handle_apply(
-    ctx: &ReloaderContext,
-    handler: &super::Handler,
-    object: BoxCrd,
-) -> Result<(), Error> {
-    let ReloaderContext { origins } = ctx;
-
-    let addr = match object
-        .status
-        .as_ref()
-        .and_then(|status| status.access.primary)
-        .map(|spec| spec.address)
-    {
-        Some(addr) => addr,
-        None => return handle_delete(ctx, handler, object).await,
-    };
+fn handle_apply(boxes: &mut BTreeMap<String, BoxRecords>, object: BoxCrd) {
+    let name = object.name_any();
+    match BoxRecords::from_box(&object) {
+        Some(records) => {
+            info!("Applying box: {name}");
+            boxes.insert(name, records);
+        }
+        None => handle_delete(boxes, object),
+    }
+}
 
+fn handle_delete(boxes: &mut BTreeMap<String, BoxRecords>, object: BoxCrd) {
     let name = object.name_any();
-    let name = name.as_str();
-    info!("Applying box: {name}");
-
-    origins
-        .iter()
-        .map(|origin| async move {
-            let name = Name::from_str(name)
-                .and_then(|name| name.append_domain(origin))
-                .map_err(handle_error)?;
-
-            let zone_type = ZoneType::Primary;
-            let allow_axfr = AxfrPolicy::Deny;
-            let nx_proof_kind = None;
-            let authority = InMemoryZoneHandler::<TokioRuntimeProvider>::empty(
-                origin.clone(),
-                zone_type,
-                allow_axfr,
-                nx_proof_kind,
-            );
-
-            let ttl = 300;
-            let rdata = match addr {
-                IpAddr::V4(addr) => RData::A(A(addr)),
-                IpAddr::V6(addr) => RData::AAAA(AAAA(addr)),
-            };
-            let record = Record::from_rdata(name.clone(), ttl, rdata);
-
-            let serial = 0;
-            authority.upsert(record, serial).await;
-
-            handler
-                .catalog
-                .write()
-                .await
-                .upsert(name.into(), vec![Arc::new(authority)]);
-            Ok(())
-        })
-        .collect::<FuturesUnordered<_>>()
-        .try_collect()
-        .await
+    if boxes.remove(&name).is_some() {
+        info!("Deleting box: {name}");
+    }
 }
 
-#[instrument(level = Level::INFO, skip(ctx, handler, object))]
-async fn handle_delete(
+/// Rebuilds the zones, bumping the SOA serials of the changed ones.
+///
+/// Returns the changed zones.
+#[instrument(level = Level::INFO, skip_all)]
+async fn reload(
     ctx: &ReloaderContext,
     handler: &super::Handler,
-    object: BoxCrd,
-) -> Result<(), Error> {
-    let ReloaderContext { origins } = ctx;
+    state: &mut ReloaderState,
+) -> Result<Vec<Name>, Error> {
+    let zones = ctx
+        .builder
+        .build(state.boxes.values())
+        .map_err(handle_error)?;
+
+    let mut catalog = handler.catalog.write().await;
+    let mut transfer_catalog = handler.transfer_catalog.write().await;
+
+    // remove the stale zones
+    let stale: Vec<_> = state
+        .zones
+        .keys()
+        .filter(|origin| !zones.contains_key(origin))
+        .cloned()
+        .collect();
+    for origin in stale {
+        info!("Removing zone: {origin}");
+        state.zones.remove(&origin);
+        catalog.remove(&origin.clone().into());
+        transfer_catalog.remove(&origin.into());
+    }
 
-    let name = object.name_any();
-    let name = name.as_str();
-    info!("Deleting box: {name}");
-
-    origins
-        .iter()
-        .map(|origin| async move {
-            let name = Name::from_str(name)
-                .and_then(|name| name.append_domain(origin))
-                .map_err(handle_error)?;
-
-            handler.catalog.write().await.remove(&name.into());
-            Ok(())
-        })
-        .collect::<FuturesUnordered<_>>()
-        .try_collect()
-        .await
+    // upsert the changed zones
+    let mut changed = Vec::default();
+    for (origin, records) in zones {
+        let serial = match state.zones.get(&origin) {
+            Some(zone) if zone.records == records => continue,
+            Some(zone) => crate::zone::next_serial(Some(zone.serial)),
+            None => crate::zone::next_serial(None),
+        };
+        info!("Reloading zone: {origin} (serial: {serial})");
+
+        let authority = build_zone(ctx, &origin, &records, serial, AxfrPolicy::Deny).await;
+        catalog.upsert(origin.clone().into(), vec![Arc::new(authority)]);
+
+        if handler.transfer.is_enabled() {
+            // NOTE: the transfers are verified by the wrapping handler
+            let authority = build_zone(ctx, &origin, &records, serial, AxfrPolicy::AllowAll).await;
+            let signer = handler.transfer.signer().cloned();
+            let authority = TransferZoneHandler::new(authority, signer);
+            transfer_catalog.upsert(origin.clone().into(), vec![Arc::new(authority)]);
+        }
+
+        state
+            .zones
+            .insert(origin.clone(), ZoneState { records, serial });
+        changed.push(origin);
+    }
+    Ok(changed)
+}
+
+async fn build_zone(
+    ctx: &ReloaderContext,
+    origin: &Name,
+    records: &[Record],
+    serial: u32,
+    allow_axfr: AxfrPolicy,
+) -> InMemoryZoneHandler<TokioRuntimeProvider> {
+    let zone_type = ZoneType::Primary;
+    let nx_proof_kind = None;
+    let authority = InMemoryZoneHandler::<TokioRuntimeProvider>::empty(
+        origin.clone(),
+        zone_type,
+        allow_axfr,
+        nx_proof_kind,
+    );
+
+    let authority_records = ctx
+        .builder
+        .authority(origin, &ctx.nameserver, &ctx.admin, serial);
+    for record in authority_records.into_iter().chain(records.iter().cloned()) {
+        authority.upsert(record, serial).await;
+    }
+    authority
 }
 
 fn handle_error(error: impl Into<Box<dyn ::std::error::Error + Send + Sync>>) -> Error {
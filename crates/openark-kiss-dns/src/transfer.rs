@@ -0,0 +1,332 @@
+use std::{
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Result, anyhow, bail};
+use async_trait::async_trait;
+use base64::{Engine, engine};
+use futures::{StreamExt, stream::FuturesUnordered};
+use hickory_server::{
+    dnssec::NxProofKind,
+    net::runtime::TokioRuntimeProvider,
+    proto::{
+        op::{Message, MessageType, OpCode, Query, ResponseCode},
+        rr::{
+            LowerName, Name, RecordType, TSigResponseContext, TSigner,
+            rdata::tsig::{TsigAlgorithm, TsigError},
+        },
+    },
+    server::{Request, RequestInfo},
+    store::in_memory::InMemoryZoneHandler,
+    zone_handler::{
+        AuthLookup, AxfrPolicy, LookupControlFlow, LookupError, LookupOptions, Nsec3QueryInfo,
+        ZoneHandler, ZoneTransfer, ZoneType,
+    },
+};
+use tokio::{net::UdpSocket, time::timeout};
+#[cfg(feature = "tracing")]
+use tracing::{Level, info, instrument, warn};
+
+/// A secondary nameserver, which is allowed to transfer the zones and notified of the changes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TransferPeer(pub SocketAddr);
+
+impl TransferPeer {
+    const DEFAULT_PORT: u16 = 53;
+}
+
+impl FromStr for TransferPeer {
+    type Err = ::std::net::AddrParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.parse() {
+            Ok(addr) => Ok(Self(addr)),
+            Err(_) => s
+                .parse()
+                .map(|addr| Self(SocketAddr::new(addr, Self::DEFAULT_PORT))),
+        }
+    }
+}
+
+impl fmt::Display for TransferPeer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Controls the zone transfers (AXFR/IXFR) and the change notifications (NOTIFY).
+#[derive(Clone, Default)]
+pub struct TransferPolicy {
+    peers: Vec<TransferPeer>,
+    signer: Option<TSigner>,
+}
+
+impl TransferPolicy {
+    const NOTIFY_RETRIES: usize = 5;
+    const NOTIFY_TIMEOUT: Duration = Duration::from_secs(2);
+    const TSIG_FUDGE: u16 = 300;
+
+    pub fn new(peers: Vec<TransferPeer>, signer: Option<TSigner>) -> Self {
+        Self { peers, signer }
+    }
+
+    /// Loads a TSIG key, which is encoded in base64.
+    pub fn load_signer(name: &str, algorithm: &str, key: &str) -> Result<TSigner> {
+        let name = Name::from_str(name)?;
+        let algorithm = TsigAlgorithm::from_name(Name::from_str(algorithm)?);
+        let key = engine::general_purpose::STANDARD.decode(key.trim())?;
+        TSigner::new(key, algorithm, name, Self::TSIG_FUDGE).map_err(|error| anyhow!("{error}"))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.peers.is_empty()
+    }
+
+    /// Returns `true` if the address belongs to one of the peers.
+    pub fn is_peer(&self, addr: &IpAddr) -> bool {
+        self.peers.iter().any(|peer| peer.0.ip() == *addr)
+    }
+
+    /// Returns `true` if the request comes from one of the peers,
+    /// signed with the TSIG key if any.
+    ///
+    /// NOTE: the signature itself is verified by the [`TransferZoneHandler`].
+    pub fn is_authorized(&self, request: &Request) -> bool {
+        self.is_peer(&request.src().ip())
+            && self.signer.as_ref().is_none_or(|signer| {
+                request
+                    .signature
+                    .as_deref()
+                    .is_some_and(|tsig| tsig.name == *signer.signer_name())
+            })
+    }
+
+    pub fn signer(&self) -> Option<&TSigner> {
+        self.signer.as_ref()
+    }
+
+    /// Notifies all peers that the zone has been changed.
+    #[cfg_attr(feature = "tracing", instrument(level = Level::INFO, skip(self)))]
+    pub async fn notify(&self, origin: &Name) {
+        self.peers
+            .iter()
+            .map(|peer| async move {
+                match self.notify_peer(*peer, origin).await {
+                    Ok(()) => {
+                        #[cfg(feature = "tracing")]
+                        info!("Notified {peer}: {origin}");
+                    }
+                    Err(error) => {
+                        #[cfg(feature = "tracing")]
+                        warn!("failed to notify {peer}: {origin}: {error}");
+                        let _ = error;
+                    }
+                }
+            })
+            .collect::<FuturesUnordered<_>>()
+            .collect::<()>()
+            .await
+    }
+
+    /// Sends a NOTIFY message to the peer, retrying until it is acknowledged.
+    pub async fn notify_peer(&self, peer: TransferPeer, origin: &Name) -> Result<()> {
+        let mut message = Message::query();
+        message.metadata.op_code = OpCode::Notify;
+        message.metadata.authoritative = true;
+        message.add_query(Query::query(origin.clone(), RecordType::SOA));
+        let mut verifier = match self.signer.as_ref() {
+            Some(signer) => message.finalize(signer, now()?)?,
+            None => None,
+        };
+        let request = message.to_vec()?;
+
+        let bind_addr = match peer.0 {
+            SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        };
+        let socket = UdpSocket::bind(SocketAddr::new(bind_addr, 0)).await?;
+        socket.connect(peer.0).await?;
+
+        let mut buf = [0; 4096];
+        for _ in 0..Self::NOTIFY_RETRIES {
+            socket.send(&request).await?;
+
+            let len = match timeout(Self::NOTIFY_TIMEOUT, socket.recv(&mut buf)).await {
+                Ok(len) => len?,
+                Err(_) => continue,
+            };
+            let response = match verifier.as_mut() {
+                Some(verifier) => match verifier.verify(&buf[..len]) {
+                    Ok(response) => response.into_message(),
+                    Err(error) => bail!("invalid signature: {error}"),
+                },
+                None => Message::from_vec(&buf[..len])?,
+            };
+            if response.metadata.id != message.metadata.id
+                || response.metadata.message_type != MessageType::Response
+            {
+                continue;
+            }
+            return match response.metadata.response_code {
+                ResponseCode::NoError => Ok(()),
+                code => bail!("rejected: {code}"),
+            };
+        }
+        bail!("timed out")
+    }
+}
+
+/// A zone served to the transfer peers, which verifies the TSIG key of the transfers
+/// and signs the responses with it.
+///
+/// NOTE: the in-memory zones do not verify the signatures by themselves,
+///       so the inner zone should allow all transfers.
+pub struct TransferZoneHandler {
+    inner: InMemoryZoneHandler<TokioRuntimeProvider>,
+    signer: Option<TSigner>,
+}
+
+impl TransferZoneHandler {
+    pub fn new(inner: InMemoryZoneHandler<TokioRuntimeProvider>, signer: Option<TSigner>) -> Self {
+        Self { inner, signer }
+    }
+
+    /// Verifies the TSIG signature of the request, following RFC 8945 section 5.2.
+    fn authorize(
+        &self,
+        request: &Request,
+        now: u64,
+    ) -> (Result<(), ResponseCode>, Option<TSigResponseContext>) {
+        let Some(signer) = self.signer.as_ref() else {
+            return (Ok(()), None);
+        };
+        let id = request.metadata.id;
+        let Some(tsig) = request.signature.as_deref() else {
+            return (Err(ResponseCode::Refused), None);
+        };
+        if tsig.name != *signer.signer_name() {
+            let context = TSigResponseContext::unknown_key(id, now, tsig.name.clone());
+            return (Err(ResponseCode::NotAuth), Some(context));
+        }
+
+        let Ok((_, _, range)) = signer.verify_message_byte(request.as_slice(), None, true) else {
+            let context = TSigResponseContext::bad_signature(id, now, signer.clone());
+            return (Err(ResponseCode::NotAuth), Some(context));
+        };
+        let (result, error) = if range.contains(&now) {
+            (Ok(()), None)
+        } else {
+            (Err(ResponseCode::NotAuth), Some(TsigError::BadTime))
+        };
+        let mac = tsig.data.mac.clone();
+        let context = TSigResponseContext::new(id, now, signer.clone(), mac, error);
+        (result, Some(context))
+    }
+}
+
+#[async_trait]
+impl ZoneHandler for TransferZoneHandler {
+    fn zone_type(&self) -> ZoneType {
+        self.inner.zone_type()
+    }
+
+    fn axfr_policy(&self) -> AxfrPolicy {
+        if self.signer.is_some() {
+            AxfrPolicy::AllowSigned
+        } else {
+            AxfrPolicy::AllowAll
+        }
+    }
+
+    fn origin(&self) -> &LowerName {
+        self.inner.origin()
+    }
+
+    async fn lookup(
+        &self,
+        name: &LowerName,
+        rtype: RecordType,
+        request_info: Option<&RequestInfo<'_>>,
+        lookup_options: LookupOptions,
+    ) -> LookupControlFlow<AuthLookup> {
+        self.inner
+            .lookup(name, rtype, request_info, lookup_options)
+            .await
+    }
+
+    /// Responds to the IXFR requests with the whole zone, following RFC 1995 section 4.
+    async fn search(
+        &self,
+        request: &Request,
+        lookup_options: LookupOptions,
+    ) -> (LookupControlFlow<AuthLookup>, Option<TSigResponseContext>) {
+        let is_ixfr = request
+            .queries
+            .queries()
+            .iter()
+            .any(|query| query.query_type() == RecordType::IXFR);
+        if !is_ixfr {
+            return self.inner.search(request, lookup_options).await;
+        }
+
+        let now = now().unwrap_or_default();
+        let Some((result, context)) = self.zone_transfer(request, lookup_options, now).await else {
+            return (LookupControlFlow::Skip, None);
+        };
+        let result = result.map(|transfer| {
+            let mut message =
+                Message::new(request.metadata.id, MessageType::Response, OpCode::Query);
+            message.answers = transfer.iter().cloned().collect();
+            AuthLookup::Response(message)
+        });
+        (LookupControlFlow::Break(result), context)
+    }
+
+    async fn nsec_records(
+        &self,
+        name: &LowerName,
+        lookup_options: LookupOptions,
+    ) -> LookupControlFlow<AuthLookup> {
+        self.inner.nsec_records(name, lookup_options).await
+    }
+
+    async fn nsec3_records(
+        &self,
+        info: Nsec3QueryInfo<'_>,
+        lookup_options: LookupOptions,
+    ) -> LookupControlFlow<AuthLookup> {
+        self.inner.nsec3_records(info, lookup_options).await
+    }
+
+    async fn zone_transfer(
+        &self,
+        request: &Request,
+        lookup_options: LookupOptions,
+        now: u64,
+    ) -> Option<(
+        Result<ZoneTransfer, LookupError>,
+        Option<TSigResponseContext>,
+    )> {
+        let (result, context) = self.authorize(request, now);
+        if let Err(code) = result {
+            return Some((Err(LookupError::from(code)), context));
+        }
+
+        let (result, _) = self
+            .inner
+            .zone_transfer(request, lookup_options, now)
+            .await?;
+        Some((result, context))
+    }
+
+    fn nx_proof_kind(&self) -> Option<&NxProofKind> {
+        self.inner.nx_proof_kind()
+    }
+}
+
+fn now() -> Result<u64> {
+    Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs())
+}
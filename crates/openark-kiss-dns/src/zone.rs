@@ -0,0 +1,381 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, btree_map::Entry},
+    net::IpAddr,
+    str::FromStr,
+};
+
+use hickory_server::proto::{
+    ProtoError,
+    rr::{
+        Name, RData, Record,
+        rdata::{A, AAAA, NS, PTR, SOA, SRV},
+    },
+};
+use kube::ResourceExt;
+use openark_kiss_api::r#box::{BoxCrd, BoxGroupRole, BoxNetworkInterfaceRole};
+
+/// A label to publish the additional names of the box.
+pub(super) const LABEL_ALIAS: &str = "dash.ulagbulag.io/alias";
+
+/// The port of the kubernetes API servers on the control planes.
+const KUBE_APISERVER_PORT: u16 = 6443;
+
+/// The records of a box to be published.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(super) struct BoxRecords {
+    /// The relative host names and their addresses
+    hosts: Vec<(String, IpAddr)>,
+    /// The relative alias names and their addresses
+    aliases: Vec<(String, IpAddr)>,
+    /// The cluster domain, e.g. `<cluster>.openark`
+    cluster_domain: Option<String>,
+    is_control_plane: bool,
+}
+
+impl BoxRecords {
+    /// Returns `None` if the box has no management address.
+    pub(super) fn from_box(object: &BoxCrd) -> Option<Self> {
+        let status = object.status.as_ref()?;
+        let access = &status.access;
+        let management = access.management()?.address;
+
+        // NOTE: `<name>` points to the management interface, and
+        //       `<role>.<name>` to the dedicated one, falling back to the management one.
+        let name = object.name_any();
+        let hosts = Some((name.clone(), management))
+            .into_iter()
+            .chain(BoxNetworkInterfaceRole::ALL.into_iter().filter_map(|role| {
+                let addr = access.address(role)?;
+                Some((format!("{}.{name}", role.as_label()), addr))
+            }))
+            .collect();
+
+        let aliases = object
+            .labels()
+            .get(LABEL_ALIAS)
+            .map(|alias| alias.trim().to_lowercase())
+            .filter(|alias| !alias.is_empty() && *alias != name)
+            .map(|alias| (alias, management))
+            .into_iter()
+            .collect();
+
+        Some(Self {
+            hosts,
+            aliases,
+            cluster_domain: status
+                .bind_group
+                .as_ref()
+                .map(|group| group.cluster_domain()),
+            is_control_plane: status
+                .bind_group
+                .as_ref()
+                .is_some_and(|group| matches!(group.role, BoxGroupRole::ControlPlane)),
+        })
+    }
+
+    fn names(&self) -> impl Iterator<Item = &(String, IpAddr)> {
+        self.hosts.iter().chain(&self.aliases)
+    }
+}
+
+/// Builds the zones to be served, without the SOA and NS records.
+pub(super) struct ZoneBuilder {
+    /// The zones dedicated to the boxes, e.g. `box`
+    pub(super) origins: Vec<Name>,
+    /// The shared namespaces, where each name becomes a zone on its own
+    pub(super) shared_origins: Vec<Name>,
+    /// The domain which the reverse records point to
+    pub(super) ptr_origin: Name,
+    pub(super) ttl: u32,
+}
+
+impl ZoneBuilder {
+    pub(super) fn build<'a>(
+        &self,
+        boxes: impl IntoIterator<Item = &'a BoxRecords>,
+    ) -> Result<BTreeMap<Name, Vec<Record>>, ProtoError> {
+        let mut zones = BTreeMap::default();
+        for records in boxes {
+            self.build_forward(&mut zones, records)?;
+            self.build_cluster(&mut zones, records)?;
+            self.build_reverse(&mut zones, records)?;
+        }
+        Ok(zones)
+    }
+
+    fn build_forward(
+        &self,
+        zones: &mut BTreeMap<Name, Vec<Record>>,
+        records: &BoxRecords,
+    ) -> Result<(), ProtoError> {
+        for (host, addr) in records.names() {
+            for origin in &self.origins {
+                let name = Name::from_str(host)?.append_domain(origin)?;
+                push(zones, origin, self.address(name, *addr));
+            }
+            for origin in &self.shared_origins {
+                let name = Name::from_str(host)?.append_domain(origin)?;
+                push(zones, &name, self.address(name.clone(), *addr));
+            }
+        }
+        Ok(())
+    }
+
+    fn build_cluster(
+        &self,
+        zones: &mut BTreeMap<Name, Vec<Record>>,
+        records: &BoxRecords,
+    ) -> Result<(), ProtoError> {
+        let Some(cluster_domain) = records.cluster_domain.as_deref() else {
+            return Ok(());
+        };
+        let origin = fqdn(cluster_domain)?;
+
+        for (host, addr) in records.names() {
+            let name = Name::from_str(host)?.append_domain(&origin)?;
+            push(zones, &origin, self.address(name, *addr));
+        }
+
+        // publish the kubernetes API endpoints
+        if records.is_control_plane
+            && let Some((host, addr)) = records.hosts.first()
+        {
+            let target = Name::from_str(host)?.append_domain(&origin)?;
+            let api = Name::from_str("api")?.append_domain(&origin)?;
+            let srv = Name::from_str("_kube-apiserver._tcp")?.append_domain(&origin)?;
+
+            push(zones, &origin, self.address(api, *addr));
+            push(
+                zones,
+                &origin,
+                Record::from_rdata(
+                    srv,
+                    self.ttl,
+                    RData::SRV(SRV::new(0, 0, KUBE_APISERVER_PORT, target)),
+                ),
+            );
+        }
+        Ok(())
+    }
+
+    fn build_reverse(
+        &self,
+        zones: &mut BTreeMap<Name, Vec<Record>>,
+        records: &BoxRecords,
+    ) -> Result<(), ProtoError> {
+        // NOTE: the role names may share the management address, so only the
+        //       first (canonical) host of each address is published.
+        let mut addrs = BTreeSet::default();
+        for (host, addr) in &records.hosts {
+            if !addrs.insert(*addr) {
+                continue;
+            }
+            let name = Name::from(*addr);
+            let origin = reverse_origin(&name, addr);
+            let target = Name::from_str(host)?.append_domain(&self.ptr_origin)?;
+            push(
+                zones,
+                &origin,
+                Record::from_rdata(name, self.ttl, RData::PTR(PTR(target))),
+            );
+        }
+        Ok(())
+    }
+
+    fn address(&self, name: Name, addr: IpAddr) -> Record {
+        let rdata = match addr {
+            IpAddr::V4(addr) => RData::A(A(addr)),
+            IpAddr::V6(addr) => RData::AAAA(AAAA(addr)),
+        };
+        Record::from_rdata(name, self.ttl, rdata)
+    }
+
+    /// Returns the SOA and NS records of the zone.
+    pub(super) fn authority(
+        &self,
+        origin: &Name,
+        nameserver: &Name,
+        admin: &Name,
+        serial: u32,
+    ) -> [Record; 2] {
+        let refresh = 3600;
+        let retry = 600;
+        let expire = 86400;
+        let soa = SOA::new(
+            nameserver.clone(),
+            admin.clone(),
+            serial,
+            refresh,
+            retry,
+            expire,
+            self.ttl,
+        );
+        [
+            Record::from_rdata(origin.clone(), self.ttl, RData::SOA(soa)),
+            Record::from_rdata(origin.clone(), self.ttl, RData::NS(NS(nameserver.clone()))),
+        ]
+    }
+}
+
+/// Parses a fully qualified domain name.
+pub(super) fn fqdn(name: &str) -> Result<Name, ProtoError> {
+    Name::from_str(name)?.append_domain(&Name::root())
+}
+
+/// Returns the next SOA serial, following the unix time if possible.
+pub(super) fn next_serial(serial: Option<u32>) -> u32 {
+    let now = ::std::time::SystemTime::now()
+        .duration_since(::std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as u32)
+        .unwrap_or_default();
+    match serial {
+        Some(serial) => now.max(serial.wrapping_add(1)),
+        None => now,
+    }
+}
+
+fn push(zones: &mut BTreeMap<Name, Vec<Record>>, origin: &Name, record: Record) {
+    match zones.entry(origin.clone()) {
+        Entry::Occupied(mut entry) => {
+            if !entry.get().contains(&record) {
+                entry.get_mut().push(record)
+            }
+        }
+        Entry::Vacant(entry) => {
+            entry.insert(vec![record]);
+        }
+    }
+}
+
+/// Returns the reverse zone of the address, i.e. `/24` for IPv4 and `/64` for IPv6.
+fn reverse_origin(name: &Name, addr: &IpAddr) -> Name {
+    let num_labels = name.num_labels() as usize;
+    match addr {
+        // <3 octets>.in-addr.arpa
+        IpAddr::V4(_) => name.trim_to(num_labels - 1),
+        // <16 nibbles>.ip6.arpa
+        IpAddr::V6(_) => name.trim_to(num_labels - 16),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    use jiff::Timestamp;
+    use openark_kiss_api::r#box::{
+        BoxAccessInterfaceSpec, BoxAccessSpec, BoxGroupSpec, BoxMachineSpec,
+        BoxNetworkInterfaceSpec, BoxSpec, BoxState, BoxStatus,
+    };
+
+    use super::*;
+
+    fn builder() -> ZoneBuilder {
+        ZoneBuilder {
+            origins: vec![fqdn("box").unwrap()],
+            shared_origins: vec![Name::root()],
+            ptr_origin: fqdn("box").unwrap(),
+            ttl: 300,
+        }
+    }
+
+    #[test]
+    fn build_zones() {
+        let records = BoxRecords {
+            hosts: vec![
+                ("node1".into(), IpAddr::V4(Ipv4Addr::new(10, 32, 0, 1))),
+                ("data.node1".into(), IpAddr::V4(Ipv4Addr::new(10, 48, 0, 1))),
+            ],
+            aliases: vec![("gpu1".into(), IpAddr::V4(Ipv4Addr::new(10, 32, 0, 1)))],
+            cluster_domain: Some("ops.openark".into()),
+            is_control_plane: true,
+        };
+        let zones = builder().build([&records]).unwrap();
+
+        let get = |origin: &str| zones.get(&fqdn(origin).unwrap()).map(Vec::len);
+        assert_eq!(get("box"), Some(3));
+        assert_eq!(get("node1"), Some(1));
+        assert_eq!(get("gpu1"), Some(1));
+        // hosts, aliases, api and srv
+        assert_eq!(get("ops.openark"), Some(5));
+        assert_eq!(get("0.32.10.in-addr.arpa"), Some(1));
+        assert_eq!(get("0.48.10.in-addr.arpa"), Some(1));
+    }
+
+    #[test]
+    fn build_reverse_canonical() {
+        let access = BoxAccessSpec {
+            primary: Some(BoxAccessInterfaceSpec {
+                address: IpAddr::V4(Ipv4Addr::new(10, 32, 0, 1)),
+                speed_mbps: None,
+            }),
+            interfaces: vec![BoxNetworkInterfaceSpec {
+                name: "eno2".into(),
+                role: Some(BoxNetworkInterfaceRole::Data),
+                mac: None,
+                addresses: vec![IpAddr::V4(Ipv4Addr::new(10, 48, 0, 1))],
+                speed_mbps: None,
+                mtu: None,
+                bond: None,
+            }],
+        };
+        let mut object = BoxCrd::new(
+            "node1",
+            BoxSpec {
+                group: BoxGroupSpec::default(),
+                interfaces: Default::default(),
+                machine: BoxMachineSpec {
+                    uuid: Default::default(),
+                },
+                maintenance: None,
+                power: None,
+            },
+        );
+        object.status = Some(BoxStatus {
+            state: BoxState::Running,
+            access,
+            bind_group: None,
+            hardware: None,
+            history: Vec::default(),
+            last_updated: Timestamp::UNIX_EPOCH,
+            lease: None,
+            upgrade: None,
+            version: None,
+        });
+
+        let records = BoxRecords::from_box(&object).unwrap();
+        let zones = builder().build([&records]).unwrap();
+
+        let ptr = |addr: Ipv4Addr, host: &str| {
+            let name = Name::from(IpAddr::V4(addr));
+            let target = RData::PTR(PTR(fqdn(host).unwrap()));
+            vec![Record::from_rdata(name, 300, target)]
+        };
+        // the management and storage names share the box's address
+        assert_eq!(
+            zones[&fqdn("0.32.10.in-addr.arpa").unwrap()],
+            ptr(Ipv4Addr::new(10, 32, 0, 1), "node1.box"),
+        );
+        assert_eq!(
+            zones[&fqdn("0.48.10.in-addr.arpa").unwrap()],
+            ptr(Ipv4Addr::new(10, 48, 0, 1), "data.node1.box"),
+        );
+    }
+
+    #[test]
+    fn reverse_origin_v6() {
+        let addr = IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 1, 0, 0, 0, 1));
+        let name = Name::from(addr);
+        assert_eq!(
+            reverse_origin(&name, &addr),
+            fqdn("1.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6.arpa").unwrap(),
+        );
+    }
+
+    #[test]
+    fn next_serial_increases() {
+        let serial = next_serial(None);
+        assert!(next_serial(Some(serial)) > serial);
+        assert!(next_serial(Some(u32::MAX - 1)) == u32::MAX);
+    }
+}
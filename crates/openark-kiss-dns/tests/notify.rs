@@ -0,0 +1,121 @@
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    str::FromStr,
+};
+
+use hickory_server::proto::{
+    op::{Message, MessageType, OpCode, ResponseCode},
+    rr::{Name, RecordType, TSigResponseContext, TSigner},
+};
+use openark_kiss_dns::transfer::{TransferPeer, TransferPolicy};
+use tokio::{net::UdpSocket, spawn, task::JoinHandle};
+
+fn signer(key: &str) -> TSigner {
+    TransferPolicy::load_signer("kiss-transfer", "hmac-sha256", key).unwrap()
+}
+
+/// A stub secondary nameserver, which acknowledges a NOTIFY message.
+///
+/// The response is signed with the given TSIG key, if any.
+async fn spawn_secondary(
+    response_code: ResponseCode,
+    signer: Option<TSigner>,
+) -> (TransferPeer, JoinHandle<Message>) {
+    let socket = UdpSocket::bind(SocketAddr::from((Ipv4Addr::LOCALHOST, 0)))
+        .await
+        .unwrap();
+    let peer = TransferPeer(socket.local_addr().unwrap());
+
+    let handle = spawn(async move {
+        let mut buf = [0; 4096];
+        let (len, src) = socket.recv_from(&mut buf).await.unwrap();
+        let request = Message::from_vec(&buf[..len]).unwrap();
+
+        let mut response = Message::response(request.metadata.id, request.metadata.op_code);
+        response.metadata.response_code = response_code;
+        if let (Some(signer), Some(tsig)) = (signer, request.signature()) {
+            let context = TSigResponseContext::new(
+                request.metadata.id,
+                tsig.data.time,
+                signer,
+                tsig.data.mac.clone(),
+                None,
+            );
+            let signature = context.sign(&response.to_vec().unwrap()).unwrap();
+            response.set_signature(signature);
+        }
+        socket
+            .send_to(&response.to_vec().unwrap(), src)
+            .await
+            .unwrap();
+        request
+    });
+    (peer, handle)
+}
+
+#[tokio::test]
+async fn test_notify() {
+    let (peer, secondary) = spawn_secondary(ResponseCode::NoError, None).await;
+    let policy = TransferPolicy::new(vec![peer], None);
+    let origin = Name::from_str("box.").unwrap();
+
+    policy.notify_peer(peer, &origin).await.unwrap();
+
+    let request = secondary.await.unwrap();
+    assert_eq!(request.metadata.op_code, OpCode::Notify);
+    assert_eq!(request.metadata.message_type, MessageType::Query);
+    assert!(request.metadata.authoritative);
+    assert_eq!(request.queries.len(), 1);
+    assert_eq!(request.queries[0].name(), &origin);
+    assert_eq!(request.queries[0].query_type(), RecordType::SOA);
+    assert!(request.signature().is_none());
+}
+
+#[tokio::test]
+async fn test_notify_signed() {
+    let signer = signer("c2VjcmV0LWtleS1mb3ItdGhlLXN0dWItc2Vjb25kYXJ5");
+    let (peer, secondary) = spawn_secondary(ResponseCode::NoError, Some(signer.clone())).await;
+    let policy = TransferPolicy::new(vec![peer], Some(signer));
+    let origin = Name::from_str("box.").unwrap();
+
+    policy.notify_peer(peer, &origin).await.unwrap();
+
+    let request = secondary.await.unwrap();
+    let signature = request.signature().expect("signed notify");
+    assert_eq!(signature.name, Name::from_str("kiss-transfer.").unwrap());
+}
+
+#[tokio::test]
+async fn test_notify_bad_signature() {
+    let signer_secondary = signer("YW5vdGhlci1rZXktb2YtdGhlLXN0dWItc2Vjb25kYXJ5");
+    let (peer, secondary) = spawn_secondary(ResponseCode::NoError, Some(signer_secondary)).await;
+    let signer = signer("c2VjcmV0LWtleS1mb3ItdGhlLXN0dWItc2Vjb25kYXJ5");
+    let policy = TransferPolicy::new(vec![peer], Some(signer));
+    let origin = Name::from_str("box.").unwrap();
+
+    assert!(policy.notify_peer(peer, &origin).await.is_err());
+    secondary.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_notify_rejected() {
+    let (peer, secondary) = spawn_secondary(ResponseCode::Refused, None).await;
+    let policy = TransferPolicy::new(vec![peer], None);
+    let origin = Name::from_str("box.").unwrap();
+
+    assert!(policy.notify_peer(peer, &origin).await.is_err());
+    secondary.await.unwrap();
+}
+
+#[test]
+fn test_transfer_peer() {
+    let peer: TransferPeer = "10.0.0.1".parse().unwrap();
+    assert_eq!(peer.0, SocketAddr::from(([10, 0, 0, 1], 53)));
+
+    let peer: TransferPeer = "10.0.0.1:5353".parse().unwrap();
+    assert_eq!(peer.0, SocketAddr::from(([10, 0, 0, 1], 5353)));
+
+    let policy = TransferPolicy::new(vec![peer], None);
+    assert!(policy.is_peer(&[10, 0, 0, 1].into()));
+    assert!(!policy.is_peer(&[10, 0, 0, 2].into()));
+}
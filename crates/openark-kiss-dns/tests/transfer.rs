@@ -0,0 +1,167 @@
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use hickory_server::{
+    Server,
+    net::runtime::TokioRuntimeProvider,
+    proto::{
+        op::{Message, Query, ResponseCode},
+        rr::{
+            Name, RData, Record, RecordType, TSigVerifier, TSigner,
+            rdata::{A, SOA},
+        },
+    },
+    store::in_memory::InMemoryZoneHandler,
+    zone_handler::{AxfrPolicy, Catalog, ZoneType},
+};
+use openark_kiss_dns::transfer::{TransferPolicy, TransferZoneHandler};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    spawn,
+};
+
+const ORIGIN: &str = "box.";
+const SERIAL: u32 = 1;
+
+fn signer(key: &str) -> TSigner {
+    TransferPolicy::load_signer("kiss-transfer", "hmac-sha256", key).unwrap()
+}
+
+/// A primary nameserver, which serves a zone of a single box to the transfer peers.
+async fn spawn_primary(signer: Option<TSigner>) -> SocketAddr {
+    let origin = Name::from_str(ORIGIN).unwrap();
+    let zone = InMemoryZoneHandler::<TokioRuntimeProvider>::empty(
+        origin.clone(),
+        ZoneType::Primary,
+        AxfrPolicy::AllowAll,
+        None,
+    );
+    let soa = SOA::new(
+        Name::from_str("ns.box.").unwrap(),
+        Name::from_str("admin.box.").unwrap(),
+        SERIAL,
+        3600,
+        600,
+        86400,
+        300,
+    );
+    let host = Name::from_str("node1.box.").unwrap();
+    zone.upsert(
+        Record::from_rdata(origin.clone(), 300, RData::SOA(soa)),
+        SERIAL,
+    )
+    .await;
+    zone.upsert(
+        Record::from_rdata(host, 300, RData::A(A(Ipv4Addr::new(10, 32, 0, 1)))),
+        SERIAL,
+    )
+    .await;
+
+    let mut catalog = Catalog::new();
+    catalog.upsert(
+        origin.into(),
+        vec![Arc::new(TransferZoneHandler::new(zone, signer))],
+    );
+
+    let listener = TcpListener::bind(SocketAddr::from((Ipv4Addr::LOCALHOST, 0)))
+        .await
+        .unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut server = Server::new(catalog);
+    server.register_listener(listener, Duration::from_secs(5), 32);
+    spawn(async move { server.block_until_done().await });
+    addr
+}
+
+/// Builds a zone transfer request of a stub secondary nameserver, signed with the TSIG key if any.
+fn build_request(
+    query_type: RecordType,
+    signer: Option<&TSigner>,
+) -> (Vec<u8>, Option<TSigVerifier>) {
+    let mut message = Message::query();
+    message.add_query(Query::query(Name::from_str(ORIGIN).unwrap(), query_type));
+    let verifier = signer.and_then(|signer| {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        message.finalize(signer, now).unwrap()
+    });
+    (message.to_vec().unwrap(), verifier)
+}
+
+/// Sends the request over TCP and returns the raw response.
+async fn exchange(addr: SocketAddr, request: &[u8]) -> Vec<u8> {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_u16(request.len() as u16).await.unwrap();
+    stream.write_all(request).await.unwrap();
+
+    let len = stream.read_u16().await.unwrap();
+    let mut buf = vec![0; len as usize];
+    stream.read_exact(&mut buf).await.unwrap();
+    buf
+}
+
+fn assert_transferred(response: &Message) {
+    assert_eq!(response.metadata.response_code, ResponseCode::NoError);
+
+    // the zone starts and ends with the SOA record
+    let types: Vec<_> = response
+        .answers
+        .iter()
+        .map(|record| record.record_type())
+        .collect();
+    assert_eq!(types, [RecordType::SOA, RecordType::A, RecordType::SOA]);
+}
+
+#[tokio::test]
+async fn test_transfer_unsigned() {
+    let addr = spawn_primary(None).await;
+
+    let (request, _) = build_request(RecordType::AXFR, None);
+    let response = Message::from_vec(&exchange(addr, &request).await).unwrap();
+    assert_transferred(&response);
+}
+
+#[tokio::test]
+async fn test_transfer_signed() {
+    let signer = signer("c2VjcmV0LWtleS1mb3ItdGhlLXN0dWItc2Vjb25kYXJ5");
+    let addr = spawn_primary(Some(signer.clone())).await;
+
+    for query_type in [RecordType::AXFR, RecordType::IXFR] {
+        let (request, verifier) = build_request(query_type, Some(&signer));
+        let response = exchange(addr, &request).await;
+        let response = verifier
+            .expect("signed request")
+            .verify(&response)
+            .expect("signed response")
+            .into_message();
+        assert_transferred(&response);
+    }
+}
+
+#[tokio::test]
+async fn test_transfer_unauthorized() {
+    let signer_primary = signer("c2VjcmV0LWtleS1mb3ItdGhlLXN0dWItc2Vjb25kYXJ5");
+    let addr = spawn_primary(Some(signer_primary)).await;
+
+    // unsigned
+    let (request, _) = build_request(RecordType::AXFR, None);
+    let response = Message::from_vec(&exchange(addr, &request).await).unwrap();
+    assert_eq!(response.metadata.response_code, ResponseCode::Refused);
+    assert!(response.answers.is_empty());
+
+    // signed with another key
+    let signer = signer("YW5vdGhlci1rZXktb2YtdGhlLXN0dWItc2Vjb25kYXJ5");
+    for query_type in [RecordType::AXFR, RecordType::IXFR] {
+        let (request, _) = build_request(query_type, Some(&signer));
+        let response = Message::from_vec(&exchange(addr, &request).await).unwrap();
+        assert_eq!(response.metadata.response_code, ResponseCode::NotAuth);
+        assert!(response.answers.is_empty());
+    }
+}
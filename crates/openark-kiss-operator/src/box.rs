@@ -17,21 +17,32 @@ use kube::{
 };
 use openark_core::operator::RecorderExt;
 use openark_kiss_ansible::{AnsibleClient, AnsibleJob, AnsibleResourceType};
-use openark_kiss_api::r#box::{BoxCrd, BoxGroupRole, BoxGroupSpec, BoxSpec, BoxState, BoxStatus};
+use openark_kiss_api::r#box::{
+    BoxCrd, BoxGroupRole, BoxGroupSpec, BoxSpec, BoxState, BoxStatus, BoxUpgradeState,
+    BoxUpgradeStatus,
+};
 use serde_json::json;
 #[cfg(feature = "tracing")]
 use tracing::{Level, info, instrument};
 
-use crate::status::Reason;
+use crate::{
+    drain::NodeDrainer,
+    power::{PowerAction, PowerClient},
+    status::Reason,
+    upgrade::{UpgradeDecision, UpgradePlanner},
+};
 
 struct Context {
     ansible: AnsibleClient,
     api: Api<BoxCrd>,
     crd: ApiResource,
+    drainer: NodeDrainer,
     enable_cronjobs: bool,
     interval: Duration,
     patch_params: PatchParams,
+    power: Option<PowerClient>,
     recorder: Recorder,
+    upgrade: Option<UpgradePlanner>,
 }
 
 #[cfg_attr(feature = "tracing", instrument(level = Level::INFO, skip_all))]
@@ -74,6 +85,64 @@ async fn reconcile(r#box: Arc<BoxCrd>, ctx: Arc<Context>) -> Result<Action, Erro
         return Ok(Action::requeue(ctx.interval));
     }
 
+    // execute the requested power action
+    if let Some(action) = r#box
+        .annotations()
+        .get(PowerClient::ANNOTATION_POWER_ACTION)
+    {
+        let message = match (ctx.power.as_ref(), r#box.spec.power.as_ref()) {
+            (Some(power), Some(spec)) => match action.parse::<PowerAction>() {
+                Ok(action) => match power.execute(spec, action).await {
+                    Ok(state) => format!("Executed {action} (power: {state})"),
+                    Err(error) => format!("Cannot execute {action}: {error}"),
+                },
+                Err(_) => format!("Unknown power action: {action}"),
+            },
+            (None, _) => "Skipped power action (power control is disabled)".into(),
+            (_, None) => "Skipped power action (box has no power spec)".into(),
+        };
+
+        let patch = Patch::Merge(json!({
+            "apiVersion": &ctx.crd.api_version,
+            "kind": &ctx.crd.kind,
+            "metadata": {
+                "annotations": {
+                    PowerClient::ANNOTATION_POWER_ACTION: null,
+                },
+            },
+        }));
+        ctx.api.patch(&name, &ctx.patch_params, &patch).await?;
+
+        report_update(&ctx.recorder, &reference, message).await?;
+        return Ok(Action::requeue(ctx.interval));
+    }
+
+    // re-apply the network interface roles
+    if let Some(status) = status {
+        let access = status.access.clone().with_roles(&r#box.spec.interfaces);
+        if access != status.access {
+            let patch = Patch::Merge(json!({
+                "apiVersion": &ctx.crd.api_version,
+                "kind": &ctx.crd.kind,
+                "status": {
+                    "access": access,
+                },
+            }));
+            ctx.api
+                .patch_status(&name, &ctx.patch_params, &patch)
+                .await?;
+
+            let message = "Applied the network interface roles".into();
+            report_update(&ctx.recorder, &reference, message).await?;
+            return Ok(Action::requeue(ctx.interval));
+        }
+    }
+
+    // enter or lift the maintenance mode
+    if r#box.spec.maintenance.is_some() || old_state.is_maintenance() {
+        return reconcile_maintenance(&r#box, &ctx, &reference, old_state).await;
+    }
+
     // detect the box's group is changed
     let is_bind_group_updated = status
         .as_ref()
@@ -81,6 +150,15 @@ async fn reconcile(r#box: Arc<BoxCrd>, ctx: Arc<Context>) -> Result<Action, Erro
         .map(|bind_group| bind_group != &r#box.spec.group)
         .unwrap_or(true);
 
+    // roll out the new version to the running boxes
+    if let Some(planner) = ctx.upgrade.as_ref()
+        && matches!(old_state, BoxState::Running)
+        && !is_bind_group_updated
+        && let Some(action) = reconcile_upgrade(&r#box, &ctx, planner, &reference).await?
+    {
+        return Ok(action);
+    }
+
     // wait new boxes with no access methods for begin provisioned
     if matches!(old_state, BoxState::New)
         && !status
@@ -116,6 +194,48 @@ async fn reconcile(r#box: Arc<BoxCrd>, ctx: Arc<Context>) -> Result<Action, Erro
         new_state = BoxState::Disconnected;
     }
 
+    // recover the stuck box via out-of-band power control
+    let is_power_recoverable = ctx.power.is_some()
+        && r#box.spec.power.is_some()
+        && old_state == new_state
+        && new_state.is_power_recoverable();
+    if is_power_recoverable
+        && let Some(power) = ctx.power.as_ref()
+        && let Some(spec) = r#box.spec.power.as_ref()
+        && let Some(last_updated) = r#box.last_updated()
+        && now > last_updated + BoxState::timeout_power_recovery()
+    {
+        let message = match power.recover(spec).await {
+            Ok(action) => format!("Recovered the box: {action}"),
+            Err(error) => format!("Cannot recover the box: {error}"),
+        };
+
+        // postpone the next recovery
+        let patch = Patch::Merge(json!({
+            "apiVersion": &ctx.crd.api_version,
+            "kind": &ctx.crd.kind,
+            "status": {
+                "lastUpdated": Timestamp::now(),
+            },
+        }));
+        ctx.api
+            .patch_status(&name, &ctx.patch_params, &patch)
+            .await?;
+
+        report_update(&ctx.recorder, &reference, message).await?;
+        return Ok(Action::requeue(
+            BoxState::timeout_power_recovery().unsigned_abs(),
+        ));
+    }
+    let await_change = || {
+        if is_power_recoverable {
+            // check back for the next recovery
+            Action::requeue(BoxState::timeout_power_recovery().unsigned_abs())
+        } else {
+            Action::await_change()
+        }
+    };
+
     if !matches!(old_state, BoxState::Joining) && matches!(new_state, BoxState::Joining) {
         // skip joining to default cluster as worker nodes when external
         if matches!(r#box.spec.group.role, BoxGroupRole::ExternalWorker) {
@@ -143,7 +263,12 @@ async fn reconcile(r#box: Arc<BoxCrd>, ctx: Arc<Context>) -> Result<Action, Erro
                     access: status.map(|status| status.access.clone()).unwrap_or_default(),
                     state: BoxState::Running,
                     bind_group: status.and_then(|status| status.bind_group.clone()),
+                    hardware: status.and_then(|status| status.hardware.clone()),
+                    history: status.map(|status| status.history.clone()).unwrap_or_default(),
                     last_updated: Timestamp::now(),
+                    lease: status.and_then(|status| status.lease.clone()),
+                    upgrade: status.and_then(|status| status.upgrade.clone()),
+                    version: status.and_then(|status| status.version.clone()),
                 },
             }));
             ctx.api
@@ -175,11 +300,14 @@ async fn reconcile(r#box: Arc<BoxCrd>, ctx: Arc<Context>) -> Result<Action, Erro
                         BoxState::New
                         | BoxState::Commissioning
                         | BoxState::Ready
-                        | BoxState::Joining => AnsibleResourceType::Normal,
+                        | BoxState::Joining
+                        | BoxState::Upgrading => AnsibleResourceType::Normal,
                         BoxState::Running
                         | BoxState::GroupChanged
                         | BoxState::Failed
-                        | BoxState::Disconnected => AnsibleResourceType::Minimal,
+                        | BoxState::Disconnected
+                        | BoxState::Draining
+                        | BoxState::Maintenance => AnsibleResourceType::Minimal,
                     },
                     use_workers: false,
                 })
@@ -200,7 +328,7 @@ async fn reconcile(r#box: Arc<BoxCrd>, ctx: Arc<Context>) -> Result<Action, Erro
         if old_state == new_state {
             let message = "Waiting for being changed".into();
             report_update(&ctx.recorder, &reference, message).await?;
-            return Ok(Action::await_change());
+            return Ok(await_change());
         }
 
         // bind group before joining to a cluster
@@ -219,7 +347,12 @@ async fn reconcile(r#box: Arc<BoxCrd>, ctx: Arc<Context>) -> Result<Action, Erro
                 access: status.map(|status| status.access.clone()).unwrap_or_default(),
                 state: new_state,
                 bind_group: bind_group.cloned(),
+                hardware: status.and_then(|status| status.hardware.clone()),
+                history: status.map(|status| status.history.clone()).unwrap_or_default(),
                 last_updated: Timestamp::now(),
+                lease: status.and_then(|status| status.lease.clone()),
+                upgrade: status.and_then(|status| status.upgrade.clone()),
+                version: status.and_then(|status| status.version.clone()),
             },
         }));
         ctx.api
@@ -233,13 +366,239 @@ async fn reconcile(r#box: Arc<BoxCrd>, ctx: Arc<Context>) -> Result<Action, Erro
     if old_state == new_state {
         let message = "Waiting for being changed".into();
         report_update(&ctx.recorder, &reference, message).await?;
-        Ok(Action::await_change())
+        Ok(await_change())
     } else {
         // If no events were received, check back after a few seconds
         Ok(Action::requeue(ctx.interval))
     }
 }
 
+async fn reconcile_maintenance(
+    r#box: &BoxCrd,
+    ctx: &Context,
+    reference: &ObjectReference,
+    old_state: BoxState,
+) -> Result<Action, Error> {
+    let name = r#box.name_any();
+    let node_name = r#box.spec.machine.hostname();
+    let bind_group = r#box
+        .status
+        .as_ref()
+        .and_then(|status| status.bind_group.as_ref());
+
+    // only the nodes of this cluster can be drained
+    let is_managed = bind_group
+        .map(|group| group.cluster_name == ctx.ansible.kiss.kiss_cluster_name)
+        .unwrap_or_default();
+
+    let new_state = match (r#box.spec.maintenance.as_ref(), old_state) {
+        (Some(_), BoxState::Maintenance) => return Ok(Action::await_change()),
+        (Some(_), BoxState::Draining) => {
+            let remaining = ctx.drainer.drain(&node_name).await?;
+            if remaining > 0 {
+                let message = format!("Draining: {remaining} pods are remaining");
+                report_update(&ctx.recorder, reference, message).await?;
+                return Ok(Action::requeue(ctx.interval));
+            }
+            BoxState::Maintenance
+        }
+        (Some(maintenance), _) => {
+            // suppress all scheduled jobs
+            ctx.ansible.cancel(r#box).await?;
+
+            let message = match maintenance.reason.as_deref() {
+                Some(reason) => format!("Entering maintenance: {reason}"),
+                None => "Entering maintenance".into(),
+            };
+            report_update(&ctx.recorder, reference, message).await?;
+
+            if matches!(old_state, BoxState::Running)
+                && is_managed
+                && ctx.drainer.cordon(&node_name, true).await?
+            {
+                BoxState::Draining
+            } else {
+                BoxState::Maintenance
+            }
+        }
+        (None, _) => {
+            if is_managed {
+                ctx.drainer.cordon(&node_name, false).await?;
+            }
+
+            // re-commission the box if it has not been joined yet
+            if bind_group.is_some() {
+                BoxState::Running
+            } else {
+                BoxState::New
+            }
+        }
+    };
+
+    let patch = Patch::Merge(json!({
+        "apiVersion": &ctx.crd.api_version,
+        "kind": &ctx.crd.kind,
+        "status": {
+            "state": new_state,
+            "lastUpdated": Timestamp::now(),
+        },
+    }));
+    ctx.api
+        .patch_status(&name, &ctx.patch_params, &patch)
+        .await?;
+
+    let message = format!("Updated state: {new_state}");
+    report_update(&ctx.recorder, reference, message).await?;
+    Ok(Action::requeue(ctx.interval))
+}
+
+async fn reconcile_upgrade(
+    r#box: &BoxCrd,
+    ctx: &Context,
+    planner: &UpgradePlanner,
+    reference: &ObjectReference,
+) -> Result<Option<Action>, Error> {
+    let name = r#box.name_any();
+    let target = ctx.ansible.kiss.version();
+    let upgrade = r#box
+        .status
+        .as_ref()
+        .and_then(|status| status.upgrade.as_ref());
+
+    // retry the failed upgrade on demand
+    if r#box
+        .annotations()
+        .contains_key(UpgradePlanner::ANNOTATION_RETRY_UPGRADE)
+    {
+        let patch = Patch::Merge(json!({
+            "apiVersion": &ctx.crd.api_version,
+            "kind": &ctx.crd.kind,
+            "metadata": {
+                "annotations": {
+                    UpgradePlanner::ANNOTATION_RETRY_UPGRADE: null,
+                },
+            },
+        }));
+        ctx.api.patch(&name, &ctx.patch_params, &patch).await?;
+
+        if upgrade.is_some_and(|upgrade| upgrade.state == BoxUpgradeState::Failed) {
+            let patch = Patch::Merge(json!({
+                "apiVersion": &ctx.crd.api_version,
+                "kind": &ctx.crd.kind,
+                "status": {
+                    "upgrade": null,
+                },
+            }));
+            ctx.api
+                .patch_status(&name, &ctx.patch_params, &patch)
+                .await?;
+
+            let message = "Retrying the upgrade".into();
+            report_update(&ctx.recorder, reference, message).await?;
+        }
+        return Ok(Some(Action::requeue(ctx.interval)));
+    }
+
+    // keep the failed box as is, pausing the cluster
+    if upgrade
+        .is_some_and(|upgrade| upgrade.state == BoxUpgradeState::Failed && upgrade.target == target)
+    {
+        return Ok(None);
+    }
+
+    let (state, reason) = match planner.plan(&ctx.ansible, r#box, &target).await? {
+        UpgradeDecision::UpToDate => return Ok(None),
+        UpgradeDecision::Adopt => {
+            let patch = Patch::Merge(json!({
+                "apiVersion": &ctx.crd.api_version,
+                "kind": &ctx.crd.kind,
+                "status": {
+                    "version": &target,
+                },
+            }));
+            ctx.api
+                .patch_status(&name, &ctx.patch_params, &patch)
+                .await?;
+
+            let message = "Recorded the provisioned version".into();
+            report_update(&ctx.recorder, reference, message).await?;
+            return Ok(Some(Action::requeue(ctx.interval)));
+        }
+        UpgradeDecision::Blocked(reason) => (BoxUpgradeState::Blocked, Some(reason)),
+        UpgradeDecision::Pending(reason) => (BoxUpgradeState::Pending, Some(reason)),
+        UpgradeDecision::Ready => (BoxUpgradeState::Upgrading, None),
+    };
+
+    // spawn an Ansible job
+    let new_state = BoxState::Upgrading;
+    if let (BoxUpgradeState::Upgrading, Some(task)) = (state, new_state.as_task()) {
+        let is_spawned = ctx
+            .ansible
+            .spawn(AnsibleJob {
+                cron: None,
+                task,
+                r#box,
+                new_group: None,
+                new_state: Some(new_state),
+                is_critical: false,
+                resource_type: AnsibleResourceType::Normal,
+                use_workers: false,
+            })
+            .await?;
+
+        // If there is a problem spawning a job, check back after a few minutes
+        if !is_spawned {
+            let message = "Cannot spawn an Ansible job; waiting".into();
+            report_update(&ctx.recorder, reference, message).await?;
+            return Ok(Some(Action::requeue(
+                #[allow(clippy::identity_op)]
+                Duration::from_secs(1 * 60),
+            )));
+        }
+    }
+
+    // update the status only if changed
+    let is_changed = upgrade.is_none_or(|upgrade| {
+        upgrade.state != state || upgrade.target != target || upgrade.reason != reason
+    });
+    if is_changed {
+        let message = match reason.as_deref() {
+            Some(reason) => format!("Upgrade {state}: {reason}"),
+            None => format!("Upgrade {state}"),
+        };
+
+        let mut status = json!({
+            "upgrade": BoxUpgradeStatus {
+                state,
+                target,
+                reason,
+                last_updated: Timestamp::now(),
+            },
+        });
+        if matches!(state, BoxUpgradeState::Upgrading) {
+            status["state"] = json!(new_state);
+            status["lastUpdated"] = json!(Timestamp::now());
+        }
+        let patch = Patch::Merge(json!({
+            "apiVersion": &ctx.crd.api_version,
+            "kind": &ctx.crd.kind,
+            "status": status,
+        }));
+        ctx.api
+            .patch_status(&name, &ctx.patch_params, &patch)
+            .await?;
+
+        report_update(&ctx.recorder, reference, message).await?;
+    }
+
+    match state {
+        // keep the box running as is
+        BoxUpgradeState::Blocked => Ok(None),
+        // check back for its turn
+        _ => Ok(Some(Action::requeue(ctx.interval))),
+    }
+}
+
 async fn report_update(
     recorder: &Recorder,
     reference: &ObjectReference,
@@ -295,10 +654,25 @@ pub async fn loop_forever(args: super::Args, client: Client) -> Result<()> {
         ansible: AnsibleClient::try_new(&client, namespace).await?,
         api: api.clone(),
         crd: BoxCrd::api_resource(),
+        drainer: NodeDrainer::new(
+            Api::all(client.clone()),
+            Api::all(client.clone()),
+            patch_params.clone(),
+        ),
         enable_cronjobs: args.enable_cronjobs,
         interval: Duration::from_secs(30),
         patch_params,
+        power: if args.enable_power_recovery {
+            Some(PowerClient::new(&client, namespace))
+        } else {
+            None
+        },
         recorder: recorder.clone(),
+        upgrade: if args.enable_rolling_upgrade {
+            Some(UpgradePlanner::new(client.clone()))
+        } else {
+            None
+        },
     });
 
     Controller::new(api, watcher_config)
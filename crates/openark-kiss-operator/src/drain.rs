@@ -0,0 +1,189 @@
+use k8s_openapi::api::core::v1::{Node, Pod};
+use kube::{
+    Api, Error, ResourceExt,
+    api::{EvictParams, ListParams, Patch, PatchParams},
+};
+use serde_json::json;
+#[cfg(feature = "tracing")]
+use tracing::{Level, instrument, warn};
+
+pub(crate) struct NodeDrainer {
+    api_node: Api<Node>,
+    api_pod: Api<Pod>,
+    patch_params: PatchParams,
+}
+
+impl NodeDrainer {
+    const ANNOTATION_MIRROR_POD: &'static str = "kubernetes.io/config.mirror";
+
+    pub(crate) fn new(api_node: Api<Node>, api_pod: Api<Pod>, patch_params: PatchParams) -> Self {
+        Self {
+            api_node,
+            api_pod,
+            patch_params,
+        }
+    }
+
+    /// Mark the node as (un)schedulable.
+    ///
+    /// Returns `false` if there is no such node.
+    #[cfg_attr(feature = "tracing", instrument(level = Level::INFO, skip(self), err(Display)))]
+    pub(crate) async fn cordon(&self, name: &str, unschedulable: bool) -> Result<bool, Error> {
+        if self.api_node.get_opt(name).await?.is_none() {
+            return Ok(false);
+        }
+
+        let patch = Patch::Merge(json!({
+            "spec": {
+                "unschedulable": unschedulable,
+            },
+        }));
+        self.api_node
+            .patch(name, &self.patch_params, &patch)
+            .await?;
+        Ok(true)
+    }
+
+    /// Evict all evictable pods on the node.
+    ///
+    /// Returns the number of the remaining pods to be evicted.
+    #[cfg_attr(feature = "tracing", instrument(level = Level::INFO, skip(self), err(Display)))]
+    pub(crate) async fn drain(&self, name: &str) -> Result<usize, Error> {
+        let lp = ListParams {
+            field_selector: Some(format!("spec.nodeName={name}")),
+            ..Default::default()
+        };
+        let pods = self.api_pod.list(&lp).await?.items;
+
+        let mut remaining = 0;
+        for pod in pods.iter().filter(|pod| is_evictable(pod)) {
+            remaining += 1;
+
+            // skip if already terminating
+            if pod.metadata.deletion_timestamp.is_some() {
+                continue;
+            }
+
+            let api = Api::<Pod>::namespaced(
+                self.api_pod.clone().into_client(),
+                pod.namespace().as_deref().unwrap_or("default"),
+            );
+            let ep = EvictParams::default();
+            match api.evict(&pod.name_any(), &ep).await {
+                Ok(_) => (),
+                // blocked by PodDisruptionBudget; retry later
+                Err(Error::Api(error)) if error.code == 429 => {
+                    #[cfg(feature = "tracing")]
+                    warn!("eviction is blocked: {}", pod.name_any());
+                }
+                Err(Error::Api(error)) if error.code == 404 => remaining -= 1,
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(remaining)
+    }
+}
+
+fn is_evictable(pod: &Pod) -> bool {
+    // skip the finished pods
+    let phase = pod
+        .status
+        .as_ref()
+        .and_then(|status| status.phase.as_deref());
+    if matches!(phase, Some("Failed" | "Succeeded")) {
+        return false;
+    }
+
+    // skip the static pods
+    if pod
+        .annotations()
+        .contains_key(NodeDrainer::ANNOTATION_MIRROR_POD)
+    {
+        return false;
+    }
+
+    // skip the pods managed by DaemonSets
+    // NOTE: the pods with local (emptyDir) volumes are evicted as well,
+    //       like `kubectl drain --delete-emptydir-data`
+    !pod.owner_references()
+        .iter()
+        .any(|owner| owner.kind == "DaemonSet")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use k8s_openapi::{
+        api::core::v1::{EmptyDirVolumeSource, PodSpec, PodStatus, Volume},
+        apimachinery::pkg::apis::meta::v1::{ObjectMeta, OwnerReference},
+    };
+
+    use super::*;
+
+    fn pod(owner_kind: Option<&str>) -> Pod {
+        Pod {
+            metadata: ObjectMeta {
+                name: Some("my-pod".into()),
+                namespace: Some("default".into()),
+                owner_references: owner_kind.map(|kind| {
+                    vec![OwnerReference {
+                        api_version: "apps/v1".into(),
+                        kind: kind.into(),
+                        name: "my-owner".into(),
+                        uid: "00000000-0000-0000-0000-000000000000".into(),
+                        ..Default::default()
+                    }]
+                }),
+                ..Default::default()
+            },
+            spec: Some(PodSpec::default()),
+            status: Some(PodStatus {
+                phase: Some("Running".into()),
+                ..Default::default()
+            }),
+        }
+    }
+
+    #[test]
+    fn test_evict_replicated_pods() {
+        assert!(is_evictable(&pod(None)));
+        assert!(is_evictable(&pod(Some("ReplicaSet"))));
+        assert!(is_evictable(&pod(Some("StatefulSet"))));
+    }
+
+    #[test]
+    fn test_skip_daemonset_pods() {
+        assert!(!is_evictable(&pod(Some("DaemonSet"))));
+    }
+
+    #[test]
+    fn test_skip_mirror_pods() {
+        let mut pod = pod(Some("Node"));
+        pod.metadata.annotations = Some(BTreeMap::from_iter([(
+            NodeDrainer::ANNOTATION_MIRROR_POD.into(),
+            "0123456789abcdef".into(),
+        )]));
+        assert!(!is_evictable(&pod));
+    }
+
+    #[test]
+    fn test_skip_finished_pods() {
+        for phase in ["Failed", "Succeeded"] {
+            let mut pod = pod(Some("Job"));
+            pod.status.as_mut().unwrap().phase = Some(phase.into());
+            assert!(!is_evictable(&pod));
+        }
+    }
+
+    #[test]
+    fn test_evict_emptydir_pods() {
+        let mut pod = pod(Some("ReplicaSet"));
+        pod.spec.as_mut().unwrap().volumes = Some(vec![Volume {
+            name: "cache".into(),
+            empty_dir: Some(EmptyDirVolumeSource::default()),
+            ..Default::default()
+        }]);
+        assert!(is_evictable(&pod));
+    }
+}
@@ -0,0 +1,321 @@
+use jiff::Timestamp;
+use k8s_openapi::api::{batch::v1::Job, core::v1::Pod};
+use kube::{
+    Api, Client, Error, Resource, ResourceExt,
+    api::{ListParams, LogParams, Patch, PatchParams},
+};
+use openark_kiss_ansible::AnsibleClient;
+use openark_kiss_api::r#box::{BoxCrd, BoxJobOutcome, BoxJobRecord};
+use serde_json::json;
+#[cfg(feature = "tracing")]
+use tracing::{Level, info, instrument, warn};
+
+/// Records the finished jobs into the box history,
+/// releasing them and their pods afterwards.
+pub(crate) struct HistoryRecorder {
+    api_box: Api<BoxCrd>,
+    api_job: Api<Job>,
+    api_pod: Api<Pod>,
+    patch_params: PatchParams,
+}
+
+impl HistoryRecorder {
+    const CONTAINER_NAME: &'static str = "ansible";
+    const LABEL_JOB_NAME: &'static str = "job-name";
+    const TAIL_LINES: i64 = 500;
+
+    pub(crate) fn new(client: Client, namespace: &str, field_manager: String) -> Self {
+        Self {
+            api_box: Api::all(client.clone()),
+            api_job: Api::namespaced(client.clone(), namespace),
+            api_pod: Api::namespaced(client, namespace),
+            patch_params: PatchParams {
+                field_manager: Some(field_manager),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Returns `true` if the job has been released.
+    #[cfg_attr(feature = "tracing", instrument(
+        level = Level::INFO,
+        skip_all,
+        fields(name = %job.name_any(), namespace = job.namespace()),
+        err(Display),
+    ))]
+    pub(crate) async fn reconcile(&self, job: &Job, box_name: &str) -> Result<bool, Error> {
+        if !has_finalizer(job) {
+            return Ok(false);
+        }
+
+        let outcome = get_outcome(job);
+        let is_deleting = job.meta().deletion_timestamp.is_some();
+        if outcome.is_none() && !is_deleting {
+            return Ok(false);
+        }
+
+        let pods = {
+            let lp = ListParams {
+                label_selector: Some(format!("{}={}", Self::LABEL_JOB_NAME, job.name_any())),
+                ..Default::default()
+            };
+            self.api_pod.list(&lp).await?.items
+        };
+
+        // NOTE: the cancelled jobs are released without being recorded
+        if let Some(outcome) = outcome
+            && !(outcome == BoxJobOutcome::Succeeded && is_scheduled(job))
+        {
+            let record = self.collect(job, &pods, outcome).await;
+            self.record(box_name, record).await?;
+        }
+
+        // release the pods first, as they are collected along with the job
+        for pod in &pods {
+            if has_finalizer(pod) {
+                release(&self.api_pod, pod, &self.patch_params).await?;
+            }
+        }
+        release(&self.api_job, job, &self.patch_params).await?;
+
+        #[cfg(feature = "tracing")]
+        info!("released a job: {}", job.name_any());
+        Ok(true)
+    }
+
+    async fn collect(&self, job: &Job, pods: &[Pod], outcome: BoxJobOutcome) -> BoxJobRecord {
+        let status = job.status.as_ref();
+
+        // the latest pod is the most relevant one
+        let pod = pods
+            .iter()
+            .max_by_key(|pod| pod.metadata.creation_timestamp.clone());
+
+        let exit_code = pod
+            .and_then(|pod| pod.status.as_ref())
+            .and_then(|status| status.container_statuses.as_ref())
+            .and_then(|statuses| {
+                statuses
+                    .iter()
+                    .find(|status| status.name == Self::CONTAINER_NAME)
+            })
+            .and_then(|status| {
+                status
+                    .state
+                    .as_ref()
+                    .and_then(|state| state.terminated.as_ref())
+                    .or_else(|| {
+                        status
+                            .last_state
+                            .as_ref()
+                            .and_then(|state| state.terminated.as_ref())
+                    })
+            })
+            .map(|terminated| terminated.exit_code);
+
+        // collect the logs of the failed jobs only
+        let logs = match (outcome, pod) {
+            (BoxJobOutcome::Failed, Some(pod)) => {
+                let lp = LogParams {
+                    container: Some(Self::CONTAINER_NAME.into()),
+                    tail_lines: Some(Self::TAIL_LINES),
+                    ..Default::default()
+                };
+                match self.api_pod.logs(&pod.name_any(), &lp).await {
+                    Ok(logs) => logs,
+                    Err(error) => {
+                        #[cfg(feature = "tracing")]
+                        warn!("failed to get the logs of {}: {error}", pod.name_any());
+                        let _ = error;
+                        String::default()
+                    }
+                }
+            }
+            _ => String::default(),
+        };
+
+        BoxJobRecord {
+            name: job.name_any(),
+            task: job
+                .labels()
+                .get(AnsibleClient::LABEL_JOB_NAME)
+                .cloned()
+                .unwrap_or_default(),
+            outcome,
+            started_at: status.and_then(|status| status.start_time.as_ref().map(|time| time.0)),
+            finished_at: status
+                .and_then(|status| status.completion_time.as_ref().map(|time| time.0))
+                .or_else(|| Some(Timestamp::now())),
+            exit_code,
+            failed_task: parse_failed_task(&logs),
+            logs: tail_lines(&logs, BoxJobRecord::MAX_LOG_LINES),
+        }
+    }
+
+    async fn record(&self, box_name: &str, record: BoxJobRecord) -> Result<(), Error> {
+        let Some(r#box) = self.api_box.get_opt(box_name).await? else {
+            return Ok(());
+        };
+
+        let history: Vec<_> = Some(record)
+            .into_iter()
+            .chain(
+                r#box
+                    .status
+                    .map(|status| status.history)
+                    .unwrap_or_default()
+                    .into_iter(),
+            )
+            .take(BoxJobRecord::MAX_HISTORY)
+            .collect();
+
+        // NOTE: the history is replaced as a whole
+        let patch = Patch::Merge(json!({
+            "status": {
+                "history": history,
+            },
+        }));
+        self.api_box
+            .patch_status(box_name, &self.patch_params, &patch)
+            .await?;
+        Ok(())
+    }
+}
+
+/// Returns the outcome of the job if it has finished.
+fn get_outcome(job: &Job) -> Option<BoxJobOutcome> {
+    job.status
+        .as_ref()?
+        .conditions
+        .as_ref()?
+        .iter()
+        .filter(|condition| condition.status == "True")
+        .find_map(|condition| match condition.type_.as_str() {
+            "Complete" => Some(BoxJobOutcome::Succeeded),
+            "Failed" => Some(BoxJobOutcome::Failed),
+            _ => None,
+        })
+}
+
+fn has_finalizer<K>(object: &K) -> bool
+where
+    K: Resource,
+{
+    object
+        .finalizers()
+        .iter()
+        .any(|finalizer| finalizer == AnsibleClient::FINALIZER_HISTORY)
+}
+
+/// Returns `true` if the job is spawned by a cronjob.
+fn is_scheduled(job: &Job) -> bool {
+    job.owner_references()
+        .iter()
+        .any(|owner| owner.kind == "CronJob")
+}
+
+async fn release<K>(api: &Api<K>, object: &K, pp: &PatchParams) -> Result<(), Error>
+where
+    K: Clone + Resource + ::serde::de::DeserializeOwned + ::core::fmt::Debug,
+{
+    let Some(index) = object
+        .finalizers()
+        .iter()
+        .position(|finalizer| finalizer == AnsibleClient::FINALIZER_HISTORY)
+    else {
+        return Ok(());
+    };
+
+    // NOTE: remove our finalizer only, keeping the others (e.g. job tracking)
+    let path = format!("/metadata/finalizers/{index}");
+    let patch: ::json_patch::Patch = ::serde_json::from_value(json!([
+        { "op": "test", "path": &path, "value": AnsibleClient::FINALIZER_HISTORY },
+        { "op": "remove", "path": &path },
+    ]))
+    .map_err(Error::SerdeError)?;
+
+    match api
+        .patch(&object.name_any(), pp, &Patch::Json::<()>(patch))
+        .await
+    {
+        Ok(_) => Ok(()),
+        // already released
+        Err(Error::Api(error)) if error.code == 404 => Ok(()),
+        Err(error) => Err(error),
+    }
+}
+
+/// Finds the ansible task which has stopped the playbook.
+fn parse_failed_task(logs: &str) -> Option<String> {
+    let mut current = None;
+    let mut failed = None;
+    let mut pending = None;
+
+    for line in logs.lines().map(str::trim) {
+        if line.starts_with("TASK [")
+            || line.starts_with("PLAY ")
+            || line.starts_with("RUNNING HANDLER [")
+        {
+            if pending.is_some() {
+                failed = pending.take();
+            }
+            current = line
+                .split_once('[')
+                .and_then(|(_, line)| line.rsplit_once(']'))
+                .map(|(name, _)| name.trim().to_string());
+        } else if line.starts_with("fatal:") || line.starts_with("failed:") {
+            pending = current.clone();
+        } else if line == "...ignoring" {
+            pending = None;
+        }
+    }
+    pending.or(failed)
+}
+
+fn tail_lines(logs: &str, limit: usize) -> Vec<String> {
+    let lines: Vec<_> = logs.lines().collect();
+    lines[lines.len().saturating_sub(limit)..]
+        .iter()
+        .map(|line| line.to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn parse_failed_task() {
+        let logs = r#"
+PLAY [target] ******************************************************************
+
+TASK [Gathering Facts] *********************************************************
+ok: [node1]
+
+TASK [commission : Check the optional packages] ********************************
+fatal: [node1]: FAILED! => {"changed": false, "msg": "not found"}
+...ignoring
+
+TASK [commission : Install the packages] ***************************************
+fatal: [node1]: FAILED! => {"changed": false, "msg": "No package matching"}
+
+PLAY RECAP *********************************************************************
+node1                      : ok=1    changed=0    unreachable=0    failed=1
+"#;
+        assert_eq!(
+            super::parse_failed_task(logs).as_deref(),
+            Some("commission : Install the packages"),
+        );
+    }
+
+    #[test]
+    fn parse_failed_task_succeeded() {
+        let logs = r#"
+TASK [commission : Check the optional packages] ********************************
+fatal: [node1]: FAILED! => {"changed": false, "msg": "not found"}
+...ignoring
+
+PLAY RECAP *********************************************************************
+node1                      : ok=2    changed=0    unreachable=0    failed=0
+"#;
+        assert_eq!(super::parse_failed_task(logs), None);
+    }
+}
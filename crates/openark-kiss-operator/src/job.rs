@@ -16,16 +16,17 @@ use kube::{
 };
 use openark_core::operator::RecorderExt;
 use openark_kiss_ansible::AnsibleClient;
-use openark_kiss_api::r#box::{BoxCrd, BoxState};
+use openark_kiss_api::r#box::{BoxCrd, BoxState, BoxUpgradeState, BoxUpgradeStatus};
 use serde_json::json;
 #[cfg(feature = "tracing")]
 use tracing::{Level, info, instrument, warn};
 
-use crate::status::Reason;
+use crate::{history::HistoryRecorder, status::Reason};
 
 struct Context {
     api_box: Api<BoxCrd>,
     crd_box: ApiResource,
+    history: HistoryRecorder,
     interval: Duration,
     patch_params: PatchParams,
     recorder: Recorder,
@@ -46,6 +47,18 @@ async fn update_box_state(
     // box name is already tested by reconciling
     let box_name = get_box_name(job).unwrap();
 
+    // skip updating if the box is under maintenance
+    if let Some(r#box) = ctx.api_box.get_opt(&box_name).await?
+        && r#box
+            .status
+            .as_ref()
+            .is_some_and(|status| status.state.is_maintenance())
+    {
+        #[cfg(feature = "tracing")]
+        info!("Skipping updating box state (under maintenance): {box_name}");
+        return Ok(Action::await_change());
+    }
+
     // update the box
     {
         let patch = Patch::Apply(json!({
@@ -71,6 +84,76 @@ async fn update_box_state(
     Ok(Action::requeue(ctx.interval))
 }
 
+#[cfg_attr(feature = "tracing", instrument(
+    level = Level::INFO,
+    skip_all,
+    fields(name = %job.name_any(), namespace = job.namespace()),
+    err(Display),
+))]
+async fn update_box_upgrade(
+    job: &Job,
+    ctx: &Context,
+    reference: &ObjectReference,
+    error: Option<String>,
+) -> Result<Action, Error> {
+    // box name is already tested by reconciling
+    let box_name = get_box_name(job).unwrap();
+
+    let upgrade = match ctx.api_box.get_opt(&box_name).await? {
+        Some(r#box) => match r#box.status.and_then(|status| status.upgrade) {
+            Some(upgrade) => upgrade,
+            // not planned by the upgrade controller
+            None => return update_box_state(job, ctx, reference, BoxState::Running).await,
+        },
+        None => return Ok(Action::await_change()),
+    };
+
+    // NOTE: the box keeps running even if failed, pausing the rolling upgrade
+    let state = BoxState::Running;
+    let upgrade = BoxUpgradeStatus {
+        state: if error.is_some() {
+            BoxUpgradeState::Failed
+        } else {
+            BoxUpgradeState::Completed
+        },
+        reason: error,
+        last_updated: Timestamp::now(),
+        ..upgrade
+    };
+
+    // update the box
+    {
+        let mut status = json!({
+            "state": state,
+            "lastUpdated": Timestamp::now(),
+            "upgrade": &upgrade,
+        });
+        if matches!(upgrade.state, BoxUpgradeState::Completed) {
+            status["version"] = json!(&upgrade.target);
+        }
+        let patch = Patch::Apply(json!({
+            "apiVersion": &ctx.crd_box.api_version,
+            "kind": &ctx.crd_box.kind,
+            "metadata": {
+                "name": &box_name,
+            },
+            "status": status,
+        }));
+        ctx.api_box
+            .patch_status(&box_name, &ctx.patch_params, &patch)
+            .await?;
+    }
+
+    {
+        let message = match upgrade.reason.as_deref() {
+            Some(reason) => format!("Upgrade {}: {reason}", upgrade.state),
+            None => format!("Upgrade {}", upgrade.state),
+        };
+        report_update(&ctx.recorder, reference, message).await?;
+    }
+    Ok(Action::requeue(ctx.interval))
+}
+
 fn get_box_name(job: &Job) -> Option<String> {
     get_label(job, AnsibleClient::LABEL_BOX_NAME)
 }
@@ -104,6 +187,12 @@ fn is_critical(job: &Job) -> bool {
     get_label(job, AnsibleClient::LABEL_JOB_IS_CRITICAL).unwrap_or_default()
 }
 
+fn is_upgrade(job: &Job) -> bool {
+    job.labels()
+        .get(AnsibleClient::LABEL_JOB_NAME)
+        .is_some_and(|task| Some(task.as_str()) == BoxState::Upgrading.as_task())
+}
+
 #[cfg_attr(feature = "tracing", instrument(level = Level::INFO, skip_all))]
 async fn reconcile(job: Arc<Job>, ctx: Arc<Context>) -> Result<Action, Error> {
     let name = job.name_any();
@@ -127,6 +216,12 @@ async fn reconcile(job: Arc<Job>, ctx: Arc<Context>) -> Result<Action, Error> {
         uid: None,
     };
 
+    // record the finished job into the box history
+    if ctx.history.reconcile(&job, &box_name).await? {
+        #[cfg(feature = "tracing")]
+        info!("{name} has been recorded into the box history");
+    }
+
     // skip reconciling if critical
     if is_critical(&job) {
         #[cfg(feature = "tracing")]
@@ -149,7 +244,11 @@ async fn reconcile(job: Arc<Job>, ctx: Arc<Context>) -> Result<Action, Error> {
         info!("Job has completed: {name} ({box_name})");
 
         // update the state
-        if let Some(completed_state) = completed_state {
+        if is_upgrade(&job) {
+            #[cfg(feature = "tracing")]
+            info!("Updating box upgrade: {name} ({box_name})");
+            update_box_upgrade(&job, &ctx, &box_reference, None).await
+        } else if let Some(completed_state) = completed_state {
             #[cfg(feature = "tracing")]
             info!("Updating box state: {name} ({box_name} => {completed_state})");
             update_box_state(&job, &ctx, &box_reference, completed_state).await
@@ -161,6 +260,22 @@ async fn reconcile(job: Arc<Job>, ctx: Arc<Context>) -> Result<Action, Error> {
             Ok(Action::requeue(ctx.interval))
         }
     }
+    // when the ansible upgrade job is failed
+    else if has_failed && is_upgrade(&job) {
+        let error = status
+            .and_then(|status| status.conditions.as_ref())
+            .and_then(|conditions| {
+                conditions
+                    .iter()
+                    .find(|condition| condition.type_ == "Failed")
+                    .and_then(|condition| condition.message.clone())
+            })
+            .unwrap_or_else(|| format!("Job has failed: {name}"));
+        #[cfg(feature = "tracing")]
+        warn!("Upgrade has failed: {name} ({box_name}): {error}");
+
+        update_box_upgrade(&job, &ctx, &box_reference, Some(error)).await
+    }
     // when the ansible job is failed
     else if has_failed {
         let failed_state = BoxState::Failed;
@@ -230,8 +345,13 @@ pub async fn loop_forever(args: super::Args, client: Client) -> Result<()> {
     let watcher_config = Config::default();
 
     let context = Arc::new(Context {
-        api_box: Api::all(client),
+        api_box: Api::all(client.clone()),
         crd_box: BoxCrd::api_resource(),
+        history: HistoryRecorder::new(
+            client.clone(),
+            namespace,
+            args.operator.controller_name.clone(),
+        ),
         interval: Duration::from_secs(30),
         patch_params,
         recorder: recorder.clone(),
@@ -1,6 +1,10 @@
 mod r#box;
+mod drain;
+mod history;
 mod job;
+mod power;
 mod status;
+mod upgrade;
 
 use anyhow::Result;
 use clap::Parser;
@@ -15,6 +19,14 @@ struct Args {
     #[arg(long, env = "ENABLE_CRONJOBS")]
     enable_cronjobs: bool,
 
+    /// Whether to recover failed boxes via out-of-band power control (IPMI, Intel AMT)
+    #[arg(long, env = "ENABLE_POWER_RECOVERY")]
+    enable_power_recovery: bool,
+
+    /// Whether to upgrade the outdated boxes one at a time
+    #[arg(long, env = "ENABLE_ROLLING_UPGRADE")]
+    enable_rolling_upgrade: bool,
+
     #[command(flatten)]
     operator: OperatorArgs,
 }
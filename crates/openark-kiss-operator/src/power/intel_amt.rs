@@ -0,0 +1,310 @@
+use std::{net::SocketAddr, time::Duration};
+
+use anyhow::{Error, Result, bail};
+use async_trait::async_trait;
+use md5::{Digest, Md5};
+use reqwest::{
+    Client, StatusCode,
+    header::{AUTHORIZATION, CONTENT_TYPE, WWW_AUTHENTICATE},
+};
+use uuid::Uuid;
+
+use super::{PowerDriver, PowerState, PowerTarget};
+
+/// An Intel AMT (WS-MAN) power driver, talking SOAP over HTTP.
+///
+/// The credentials are only sent as an HTTP digest, so they never appear
+/// on the command line of any process.
+pub(super) struct IntelAmtDriver {
+    client: Client,
+    timeout: Duration,
+}
+
+impl IntelAmtDriver {
+    const PORT: u16 = 16992;
+    const PATH: &'static str = "/wsman";
+
+    const ACTION_GET: &'static str = "http://schemas.xmlsoap.org/ws/2004/09/transfer/Get";
+
+    const RESOURCE_URI_POWER_MANAGEMENT_SERVICE: &'static str =
+        "http://schemas.dmtf.org/wbem/wscim/1/cim-schema/2/CIM_PowerManagementService";
+    const RESOURCE_URI_ASSOCIATED_POWER_MANAGEMENT_SERVICE: &'static str =
+        "http://schemas.dmtf.org/wbem/wscim/1/cim-schema/2/CIM_AssociatedPowerManagementService";
+
+    pub(super) fn new(timeout: Duration) -> Self {
+        Self {
+            client: Client::new(),
+            timeout,
+        }
+    }
+
+    async fn execute(
+        &self,
+        target: &PowerTarget,
+        action: &str,
+        resource_uri: &str,
+        body: &str,
+    ) -> Result<String> {
+        let url = format!(
+            "http://{address}{path}",
+            address = SocketAddr::new(target.address, Self::PORT),
+            path = Self::PATH,
+        );
+        let envelope = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<s:Envelope xmlns:s="http://www.w3.org/2003/05/soap-envelope"
+    xmlns:wsa="http://schemas.xmlsoap.org/ws/2004/08/addressing"
+    xmlns:wsman="http://schemas.dmtf.org/wbem/wsman/1/wsman.xsd">
+    <s:Header>
+        <wsa:Action s:mustUnderstand="true">{action}</wsa:Action>
+        <wsa:To s:mustUnderstand="true">{url}</wsa:To>
+        <wsman:ResourceURI s:mustUnderstand="true">{resource_uri}</wsman:ResourceURI>
+        <wsa:MessageID s:mustUnderstand="true">uuid:{message_id}</wsa:MessageID>
+        <wsa:ReplyTo>
+            <wsa:Address>http://schemas.xmlsoap.org/ws/2004/08/addressing/role/anonymous</wsa:Address>
+        </wsa:ReplyTo>
+    </s:Header>
+    <s:Body>{body}</s:Body>
+</s:Envelope>"#,
+            message_id = Uuid::new_v4(),
+        );
+
+        let send = |authorization: Option<String>| {
+            let mut request = self
+                .client
+                .post(&url)
+                .header(CONTENT_TYPE, "application/soap+xml;charset=UTF-8")
+                .timeout(self.timeout)
+                .body(envelope.clone());
+            if let Some(authorization) = authorization {
+                request = request.header(AUTHORIZATION, authorization);
+            }
+            request.send()
+        };
+
+        // AMT always challenges the first request
+        let mut response = send(None).await?;
+        if response.status() == StatusCode::UNAUTHORIZED {
+            let challenge = response
+                .headers()
+                .get(WWW_AUTHENTICATE)
+                .and_then(|value| value.to_str().ok())
+                .and_then(DigestChallenge::parse);
+            let Some(challenge) = challenge else {
+                bail!("unsupported authentication method: {action}")
+            };
+            let cnonce = Uuid::new_v4().simple().to_string();
+            let authorization = challenge.authorize(
+                "POST",
+                Self::PATH,
+                &target.username,
+                &target.password,
+                &cnonce,
+            );
+            response = send(Some(authorization)).await?;
+        }
+
+        let status = response.status();
+        let output = response.text().await?;
+        if status == StatusCode::UNAUTHORIZED {
+            bail!("unauthorized: {action}")
+        }
+        if !status.is_success() {
+            match parse_tag(&output, "Text") {
+                Some(reason) => bail!("failed to run WS-MAN: {action}: {reason}"),
+                None => bail!("failed to run WS-MAN: {action}: {status}"),
+            }
+        }
+        Ok(output)
+    }
+
+    async fn request_power_state_change(&self, target: &PowerTarget, state: u8) -> Result<()> {
+        let input = format!(
+            r#"<input:RequestPowerStateChange_INPUT
+    xmlns:input="{resource_uri}">
+    <input:PowerState>{state}</input:PowerState>
+    <input:ManagedElement xmlns:wsa="http://schemas.xmlsoap.org/ws/2004/08/addressing"
+        xmlns:wsman="http://schemas.dmtf.org/wbem/wsman/1/wsman.xsd">
+        <wsa:Address>http://schemas.xmlsoap.org/ws/2004/08/addressing/role/anonymous</wsa:Address>
+        <wsa:ReferenceParameters>
+            <wsman:ResourceURI>http://schemas.dmtf.org/wbem/wscim/1/cim-schema/2/CIM_ComputerSystem</wsman:ResourceURI>
+            <wsman:SelectorSet>
+                <wsman:Selector wsman:Name="CreationClassName">CIM_ComputerSystem</wsman:Selector>
+                <wsman:Selector wsman:Name="Name">ManagedSystem</wsman:Selector>
+            </wsman:SelectorSet>
+        </wsa:ReferenceParameters>
+    </input:ManagedElement>
+</input:RequestPowerStateChange_INPUT>"#,
+            resource_uri = Self::RESOURCE_URI_POWER_MANAGEMENT_SERVICE,
+        );
+
+        let action = format!(
+            "{}/RequestPowerStateChange",
+            Self::RESOURCE_URI_POWER_MANAGEMENT_SERVICE,
+        );
+        let output = self
+            .execute(
+                target,
+                &action,
+                Self::RESOURCE_URI_POWER_MANAGEMENT_SERVICE,
+                &input,
+            )
+            .await?;
+
+        // NOTE: AMT reports errors with a successful status code
+        match parse_tag(&output, "ReturnValue") {
+            Some("0") => Ok(()),
+            Some(code) => bail!("failed to change the power state to {state}: {code}"),
+            None => bail!("unexpected WS-MAN output: {output}"),
+        }
+    }
+}
+
+#[async_trait]
+impl PowerDriver for IntelAmtDriver {
+    async fn power_state(&self, target: &PowerTarget) -> Result<PowerState> {
+        let output = self
+            .execute(
+                target,
+                Self::ACTION_GET,
+                Self::RESOURCE_URI_ASSOCIATED_POWER_MANAGEMENT_SERVICE,
+                "",
+            )
+            .await?;
+        parse_power_state(&output)
+    }
+
+    async fn power_on(&self, target: &PowerTarget) -> Result<()> {
+        self.request_power_state_change(target, 2).await
+    }
+
+    async fn power_off(&self, target: &PowerTarget) -> Result<()> {
+        self.request_power_state_change(target, 8).await
+    }
+
+    async fn power_cycle(&self, target: &PowerTarget) -> Result<()> {
+        self.request_power_state_change(target, 10).await
+    }
+}
+
+/// A HTTP digest authentication challenge.
+///
+/// For more details see: RFC 2617 (HTTP Digest Access Authentication)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(super) struct DigestChallenge {
+    realm: String,
+    nonce: String,
+    opaque: Option<String>,
+    qop: Option<String>,
+}
+
+impl DigestChallenge {
+    pub(super) fn parse(header: &str) -> Option<Self> {
+        let mut params = header.trim().strip_prefix("Digest ")?.trim();
+
+        let mut realm = None;
+        let mut nonce = None;
+        let mut opaque = None;
+        let mut qop = None;
+        while !params.is_empty() {
+            let (key, rest) = params.split_once('=')?;
+            let (value, rest) = match rest.strip_prefix('"') {
+                Some(rest) => {
+                    let (value, rest) = rest.split_once('"')?;
+                    (value, rest)
+                }
+                None => rest.split_once(',').unwrap_or((rest, "")),
+            };
+            params = rest.trim_start_matches([',', ' ']);
+
+            let value = Some(value.to_string());
+            match key.trim() {
+                "realm" => realm = value,
+                "nonce" => nonce = value,
+                "opaque" => opaque = value,
+                "qop" => qop = value,
+                _ => continue,
+            }
+        }
+
+        Some(Self {
+            realm: realm?,
+            nonce: nonce?,
+            opaque,
+            qop,
+        })
+    }
+
+    pub(super) fn authorize(
+        &self,
+        method: &str,
+        uri: &str,
+        username: &str,
+        password: &str,
+        cnonce: &str,
+    ) -> String {
+        let Self {
+            realm,
+            nonce,
+            opaque,
+            qop,
+        } = self;
+
+        let ha1 = md5_hex(&format!("{username}:{realm}:{password}"));
+        let ha2 = md5_hex(&format!("{method}:{uri}"));
+
+        let mut header = format!(
+            r#"Digest username="{username}", realm="{realm}", nonce="{nonce}", uri="{uri}""#
+        );
+        let supports_auth = qop
+            .as_deref()
+            .is_some_and(|qop| qop.split(',').any(|qop| qop.trim() == "auth"));
+        if supports_auth {
+            // NOTE: each challenge is used only once
+            let nc = "00000001";
+            let response = md5_hex(&format!("{ha1}:{nonce}:{nc}:{cnonce}:auth:{ha2}"));
+            header.push_str(&format!(
+                r#", qop=auth, nc={nc}, cnonce="{cnonce}", response="{response}""#,
+            ));
+        } else {
+            let response = md5_hex(&format!("{ha1}:{nonce}:{ha2}"));
+            header.push_str(&format!(r#", response="{response}""#));
+        }
+        if let Some(opaque) = opaque {
+            header.push_str(&format!(r#", opaque="{opaque}""#));
+        }
+        header
+    }
+}
+
+fn md5_hex(data: &str) -> String {
+    Md5::digest(data.as_bytes())
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+fn parse_tag<'a>(output: &'a str, name: &str) -> Option<&'a str> {
+    let begin = format!(":{name}>");
+    let offset = output.find(&begin)? + begin.len();
+    let value = &output[offset..];
+    let end = value.find('<')?;
+    Some(value[..end].trim())
+}
+
+/// Parse a `CIM_AssociatedPowerManagementService` instance.
+///
+/// For more details see: DSP1027 (Power State Management Profile)
+pub(super) fn parse_power_state(output: &str) -> Result<PowerState> {
+    let state: u8 = match parse_tag(output, "PowerState") {
+        Some(state) => state.parse().map_err(Error::from)?,
+        None => bail!("unexpected WS-MAN output: {output}"),
+    };
+    match state {
+        // On
+        2 => Ok(PowerState::On),
+        // Sleep, Hibernate and Off
+        3 | 4 | 6 | 7 | 8 | 9 | 12 | 13 => Ok(PowerState::Off),
+        _ => bail!("unknown power state: {state}"),
+    }
+}
@@ -0,0 +1,77 @@
+use std::{process::Stdio, time::Duration};
+
+use anyhow::{Result, bail};
+use async_trait::async_trait;
+use tokio::{process::Command, time::timeout};
+
+use super::{PowerDriver, PowerState, PowerTarget};
+
+/// An IPMI v2.0 (RMCP+) power driver, powered by `ipmitool`.
+pub(super) struct IpmiDriver {
+    timeout: Duration,
+}
+
+impl IpmiDriver {
+    const PROGRAM: &'static str = "ipmitool";
+
+    pub(super) const fn new(timeout: Duration) -> Self {
+        Self { timeout }
+    }
+
+    async fn execute(&self, target: &PowerTarget, command: &str) -> Result<String> {
+        let child = Command::new(Self::PROGRAM)
+            .args(["-I", "lanplus", "-H"])
+            .arg(target.address.to_string())
+            .arg("-U")
+            .arg(&target.username)
+            // read the password from the environment variable
+            .arg("-E")
+            .args(["chassis", "power", command])
+            .env("IPMI_PASSWORD", &target.password)
+            .kill_on_drop(true)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        let output = match timeout(self.timeout, child.wait_with_output()).await {
+            Ok(output) => output?,
+            Err(_) => bail!("timed out running ipmitool: chassis power {command}"),
+        };
+        if !output.status.success() {
+            bail!(
+                "failed to run ipmitool: chassis power {command}: {}",
+                String::from_utf8_lossy(&output.stderr).trim(),
+            )
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+#[async_trait]
+impl PowerDriver for IpmiDriver {
+    async fn power_state(&self, target: &PowerTarget) -> Result<PowerState> {
+        let output = self.execute(target, "status").await?;
+        parse_power_state(&output)
+    }
+
+    async fn power_on(&self, target: &PowerTarget) -> Result<()> {
+        self.execute(target, "on").await.map(|_| ())
+    }
+
+    async fn power_off(&self, target: &PowerTarget) -> Result<()> {
+        self.execute(target, "off").await.map(|_| ())
+    }
+
+    async fn power_cycle(&self, target: &PowerTarget) -> Result<()> {
+        self.execute(target, "cycle").await.map(|_| ())
+    }
+}
+
+pub(super) fn parse_power_state(output: &str) -> Result<PowerState> {
+    match output.trim().strip_prefix("Chassis Power is ") {
+        Some("on") => Ok(PowerState::On),
+        Some("off") => Ok(PowerState::Off),
+        _ => bail!("unexpected ipmitool output: {output}"),
+    }
+}
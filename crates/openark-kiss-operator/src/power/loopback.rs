@@ -0,0 +1,63 @@
+use std::sync::Mutex;
+
+use anyhow::{Result, bail};
+use async_trait::async_trait;
+
+use super::{PowerAction, PowerDriver, PowerState, PowerTarget};
+
+/// An in-memory BMC which accepts only `admin:password`.
+pub(super) struct LoopbackBmc {
+    history: Mutex<Vec<PowerAction>>,
+    state: Mutex<PowerState>,
+}
+
+impl LoopbackBmc {
+    pub(super) fn new(state: PowerState) -> Self {
+        Self {
+            history: Mutex::default(),
+            state: Mutex::new(state),
+        }
+    }
+
+    pub(super) fn history(&self) -> Vec<PowerAction> {
+        self.history.lock().unwrap().clone()
+    }
+
+    fn authenticate(&self, target: &PowerTarget) -> Result<()> {
+        if target.username == "admin" && target.password == "password" {
+            Ok(())
+        } else {
+            bail!("unauthorized: {}", target.address)
+        }
+    }
+
+    fn apply(&self, target: &PowerTarget, action: PowerAction) -> Result<()> {
+        self.authenticate(target)?;
+        self.history.lock().unwrap().push(action);
+        *self.state.lock().unwrap() = match action {
+            PowerAction::PowerOn | PowerAction::PowerCycle => PowerState::On,
+            PowerAction::PowerOff => PowerState::Off,
+        };
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl PowerDriver for LoopbackBmc {
+    async fn power_state(&self, target: &PowerTarget) -> Result<PowerState> {
+        self.authenticate(target)?;
+        Ok(*self.state.lock().unwrap())
+    }
+
+    async fn power_on(&self, target: &PowerTarget) -> Result<()> {
+        self.apply(target, PowerAction::PowerOn)
+    }
+
+    async fn power_off(&self, target: &PowerTarget) -> Result<()> {
+        self.apply(target, PowerAction::PowerOff)
+    }
+
+    async fn power_cycle(&self, target: &PowerTarget) -> Result<()> {
+        self.apply(target, PowerAction::PowerCycle)
+    }
+}
@@ -0,0 +1,250 @@
+mod intel_amt;
+mod ipmi;
+#[cfg(test)]
+mod loopback;
+
+use std::{net::IpAddr, time::Duration};
+
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use k8s_openapi::api::core::v1::Secret;
+use kube::{Api, Client};
+use openark_kiss_api::r#box::{BoxPowerSpec, BoxPowerType};
+use strum::{Display, EnumString};
+#[cfg(feature = "tracing")]
+use tracing::{Level, instrument};
+
+#[derive(Copy, Clone, Debug, Display, EnumString, PartialEq, Eq)]
+pub(crate) enum PowerState {
+    On,
+    Off,
+}
+
+#[derive(Copy, Clone, Debug, Display, EnumString, PartialEq, Eq)]
+pub(crate) enum PowerAction {
+    PowerOn,
+    PowerOff,
+    PowerCycle,
+}
+
+#[derive(Clone, PartialEq, Eq)]
+pub(crate) struct PowerTarget {
+    pub(crate) address: IpAddr,
+    pub(crate) username: String,
+    pub(crate) password: String,
+}
+
+impl ::core::fmt::Debug for PowerTarget {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+        f.debug_struct("PowerTarget")
+            .field("address", &self.address)
+            .field("username", &self.username)
+            .finish_non_exhaustive()
+    }
+}
+
+/// An out-of-band power controller of a box (BMC).
+#[async_trait]
+pub(crate) trait PowerDriver: Send + Sync {
+    async fn power_state(&self, target: &PowerTarget) -> Result<PowerState>;
+
+    async fn power_on(&self, target: &PowerTarget) -> Result<()>;
+
+    async fn power_off(&self, target: &PowerTarget) -> Result<()>;
+
+    async fn power_cycle(&self, target: &PowerTarget) -> Result<()>;
+}
+
+pub(crate) struct PowerClient {
+    api: Api<Secret>,
+    intel_amt: self::intel_amt::IntelAmtDriver,
+    ipmi: self::ipmi::IpmiDriver,
+}
+
+impl PowerClient {
+    pub(crate) const ANNOTATION_POWER_ACTION: &'static str = "kiss.ulagbulag.io/power-action";
+    const SECRET_NAME: &'static str = "kiss-config";
+
+    pub(crate) fn new(kube: &Client, namespace: &str) -> Self {
+        let timeout = Duration::from_secs(30);
+        Self {
+            api: Api::namespaced(kube.clone(), namespace),
+            intel_amt: self::intel_amt::IntelAmtDriver::new(timeout),
+            ipmi: self::ipmi::IpmiDriver::new(timeout),
+        }
+    }
+
+    fn driver(&self, r#type: BoxPowerType) -> &dyn PowerDriver {
+        match r#type {
+            BoxPowerType::IntelAMT => &self.intel_amt,
+            BoxPowerType::Ipmi => &self.ipmi,
+        }
+    }
+
+    async fn load_target(&self, spec: &BoxPowerSpec) -> Result<PowerTarget> {
+        let address = spec
+            .address
+            .ok_or_else(|| anyhow!("power address is not defined"))?;
+
+        let prefix = match spec.r#type {
+            BoxPowerType::IntelAMT => "power_intel_amt",
+            BoxPowerType::Ipmi => "power_ipmi",
+        };
+        let secret = self.api.get(Self::SECRET_NAME).await?;
+        let infer = |key: &str| {
+            let key = format!("{prefix}_{key}");
+            secret
+                .data
+                .as_ref()
+                .and_then(|data| data.get(&key))
+                .ok_or_else(|| anyhow!("failed to find the secret variable: {key}"))
+                .and_then(|value| String::from_utf8(value.0.clone()).map_err(Into::into))
+        };
+
+        Ok(PowerTarget {
+            address,
+            username: infer("username")?,
+            password: infer("password")?,
+        })
+    }
+
+    #[cfg_attr(feature = "tracing", instrument(level = Level::INFO, skip(self), err(Display)))]
+    pub(crate) async fn execute(
+        &self,
+        spec: &BoxPowerSpec,
+        action: PowerAction,
+    ) -> Result<PowerState> {
+        let target = self.load_target(spec).await?;
+        let driver = self.driver(spec.r#type);
+        match action {
+            PowerAction::PowerOn => driver.power_on(&target).await?,
+            PowerAction::PowerOff => driver.power_off(&target).await?,
+            PowerAction::PowerCycle => driver.power_cycle(&target).await?,
+        }
+        driver.power_state(&target).await
+    }
+
+    #[cfg_attr(feature = "tracing", instrument(level = Level::INFO, skip(self), err(Display)))]
+    pub(crate) async fn recover(&self, spec: &BoxPowerSpec) -> Result<PowerAction> {
+        let target = self.load_target(spec).await?;
+        recover(self.driver(spec.r#type), &target).await
+    }
+}
+
+/// Bring a stuck box back to the boot sequence (PXE or the installed OS).
+async fn recover(driver: &dyn PowerDriver, target: &PowerTarget) -> Result<PowerAction> {
+    match driver.power_state(target).await? {
+        PowerState::On => {
+            driver.power_cycle(target).await?;
+            Ok(PowerAction::PowerCycle)
+        }
+        PowerState::Off => {
+            driver.power_on(target).await?;
+            Ok(PowerAction::PowerOn)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{loopback::LoopbackBmc, *};
+
+    fn target() -> PowerTarget {
+        PowerTarget {
+            address: IpAddr::from([127, 0, 0, 1]),
+            username: "admin".into(),
+            password: "password".into(),
+        }
+    }
+
+    #[::tokio::test]
+    async fn test_recover_powered_off_box() {
+        let bmc = LoopbackBmc::new(PowerState::Off);
+        let target = target();
+
+        let action = recover(&bmc, &target).await.unwrap();
+        assert_eq!(action, PowerAction::PowerOn);
+        assert_eq!(bmc.power_state(&target).await.unwrap(), PowerState::On);
+        assert_eq!(bmc.history(), [PowerAction::PowerOn]);
+    }
+
+    #[::tokio::test]
+    async fn test_recover_powered_on_box() {
+        let bmc = LoopbackBmc::new(PowerState::On);
+        let target = target();
+
+        let action = recover(&bmc, &target).await.unwrap();
+        assert_eq!(action, PowerAction::PowerCycle);
+        assert_eq!(bmc.power_state(&target).await.unwrap(), PowerState::On);
+        assert_eq!(bmc.history(), [PowerAction::PowerCycle]);
+    }
+
+    #[::tokio::test]
+    async fn test_recover_unauthorized() {
+        let bmc = LoopbackBmc::new(PowerState::On);
+        let target = PowerTarget {
+            password: "wrong".into(),
+            ..target()
+        };
+
+        assert!(recover(&bmc, &target).await.is_err());
+        assert!(bmc.history().is_empty());
+    }
+
+    #[test]
+    fn test_parse_ipmi_power_state() {
+        use super::ipmi::parse_power_state;
+
+        assert_eq!(
+            parse_power_state("Chassis Power is on\n").unwrap(),
+            PowerState::On,
+        );
+        assert_eq!(
+            parse_power_state("Chassis Power is off\n").unwrap(),
+            PowerState::Off,
+        );
+        assert!(parse_power_state("Error: Unable to establish IPMI v2 / RMCP+ session").is_err());
+    }
+
+    #[test]
+    fn test_parse_intel_amt_power_state() {
+        use super::intel_amt::parse_power_state;
+
+        let response = r#"<a:Body><h:CIM_AssociatedPowerManagementService>
+            <h:AvailableRequestedPowerStates>2</h:AvailableRequestedPowerStates>
+            <h:PowerState>2</h:PowerState>
+        </h:CIM_AssociatedPowerManagementService></a:Body>"#;
+        assert_eq!(parse_power_state(response).unwrap(), PowerState::On);
+
+        let response = "<h:PowerState>8</h:PowerState>";
+        assert_eq!(parse_power_state(response).unwrap(), PowerState::Off);
+
+        assert!(parse_power_state("<h:RequestedPowerState>").is_err());
+    }
+
+    #[test]
+    fn test_authorize_intel_amt_digest() {
+        use super::intel_amt::DigestChallenge;
+
+        // See: RFC 2617, Section 3.5 (Example)
+        let challenge = DigestChallenge::parse(
+            r#"Digest realm="testrealm@host.com", qop="auth,auth-int", nonce="dcd98b7102dd2f0e8b11d0f600bfb0c093", opaque="5ccc069c403ebaf9f0171e9517f40e41""#,
+        )
+        .unwrap();
+        let authorization = challenge.authorize(
+            "GET",
+            "/dir/index.html",
+            "Mufasa",
+            "Circle Of Life",
+            "0a4f113b",
+        );
+        assert!(
+            authorization.starts_with(r#"Digest username="Mufasa", realm="testrealm@host.com""#)
+        );
+        assert!(authorization.contains(r#"response="6629fae49393a05397450978507c4ef1""#));
+        assert!(authorization.contains(r#"opaque="5ccc069c403ebaf9f0171e9517f40e41""#));
+        assert!(!authorization.contains("Circle Of Life"));
+
+        assert!(DigestChallenge::parse(r#"Basic realm="testrealm@host.com""#).is_none());
+    }
+}
@@ -0,0 +1,406 @@
+use itertools::Itertools;
+use kube::{Api, Client, Error, ResourceExt, api::ListParams};
+use openark_kiss_ansible::{AnsibleClient, cluster::ClusterState};
+use openark_kiss_api::r#box::{
+    BoxCrd, BoxGroupRole, BoxState, BoxUpgradeState, BoxUpgradeStatus, BoxVersionSpec,
+};
+#[cfg(feature = "tracing")]
+use tracing::{Level, instrument};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum UpgradeDecision {
+    /// The box is provisioned with the current configuration.
+    UpToDate,
+    /// The box has no recorded version; regard it as up-to-date.
+    Adopt,
+    /// The box cannot be upgraded in place.
+    Blocked(String),
+    /// The box should wait for its turn.
+    Pending(String),
+    /// The box can be upgraded now.
+    Ready,
+}
+
+/// Plans the rolling upgrade of the clusters, one box at a time.
+///
+/// The control planes are upgraded first, and the etcd quorum is respected.
+/// Any failed box pauses the rolling upgrade of its cluster,
+/// until it is retried or the target version is changed.
+pub(crate) struct UpgradePlanner {
+    api: Api<BoxCrd>,
+    kube: Client,
+}
+
+impl UpgradePlanner {
+    pub(crate) const ANNOTATION_RETRY_UPGRADE: &'static str = "kiss.ulagbulag.io/retry-upgrade";
+
+    pub(crate) fn new(kube: Client) -> Self {
+        Self {
+            api: Api::all(kube.clone()),
+            kube,
+        }
+    }
+
+    #[cfg_attr(feature = "tracing", instrument(level = Level::INFO, skip_all, err(Display)))]
+    pub(crate) async fn plan(
+        &self,
+        ansible: &AnsibleClient,
+        r#box: &BoxCrd,
+        target: &BoxVersionSpec,
+    ) -> Result<UpgradeDecision, Error> {
+        if let Some(decision) = check_version(r#box, target) {
+            return Ok(decision);
+        }
+        let Some(bind_group) = r#box
+            .status
+            .as_ref()
+            .and_then(|status| status.bind_group.as_ref())
+        else {
+            return Ok(UpgradeDecision::UpToDate);
+        };
+
+        // load the boxes of the same cluster
+        let lp = ListParams::default();
+        let boxes: Vec<_> = self
+            .api
+            .list(&lp)
+            .await?
+            .items
+            .into_iter()
+            .filter(|object| {
+                object
+                    .status
+                    .as_ref()
+                    .and_then(|status| status.bind_group.as_ref())
+                    .is_some_and(|group| group.cluster_name == bind_group.cluster_name)
+            })
+            .collect();
+        if let Some(decision) = check_turn(r#box, target, &boxes) {
+            return Ok(decision);
+        }
+
+        // respect the etcd quorum
+        if matches!(bind_group.role, BoxGroupRole::ControlPlane) {
+            let cluster_state =
+                ClusterState::load(&self.kube, &ansible.kiss, &r#box.spec, false).await?;
+            if !cluster_state.is_etcd_quorum_safe() {
+                return Ok(UpgradeDecision::Pending(
+                    "Waiting for the etcd members to be healthy".into(),
+                ));
+            }
+        }
+        Ok(UpgradeDecision::Ready)
+    }
+}
+
+/// Decide whether the box's version should be upgraded to the target.
+///
+/// It returns `None` if the box is outdated and can be upgraded in place.
+fn check_version(r#box: &BoxCrd, target: &BoxVersionSpec) -> Option<UpgradeDecision> {
+    let Some(status) = r#box.status.as_ref() else {
+        return Some(UpgradeDecision::UpToDate);
+    };
+    let Some(version) = status.version.as_ref() else {
+        return Some(UpgradeDecision::Adopt);
+    };
+    if version == target {
+        return Some(UpgradeDecision::UpToDate);
+    }
+    is_blocked(version, target).map(UpgradeDecision::Blocked)
+}
+
+/// Decide whether it is the box's turn among the boxes of the same cluster.
+///
+/// It returns `None` if the box can be upgraded now, regardless of the etcd quorum.
+fn check_turn(
+    r#box: &BoxCrd,
+    target: &BoxVersionSpec,
+    boxes: &[BoxCrd],
+) -> Option<UpgradeDecision> {
+    // pause if any box has failed to upgrade to the target
+    if let Some((name, upgrade)) = boxes
+        .iter()
+        .filter_map(|object| Some((object.name_any(), get_upgrade(object)?)))
+        .find(|(_, upgrade)| upgrade.state == BoxUpgradeState::Failed && &upgrade.target == target)
+    {
+        let reason = upgrade.reason.as_deref().unwrap_or("unknown");
+        return Some(UpgradeDecision::Pending(format!(
+            "Paused by the failed box {name}: {reason}"
+        )));
+    }
+
+    // upgrade one box at a time
+    if let Some(object) = boxes.iter().find(|object| {
+        object.uid() != r#box.uid()
+            && object
+                .status
+                .as_ref()
+                .is_some_and(|status| matches!(status.state, BoxState::Upgrading))
+    }) {
+        return Some(UpgradeDecision::Pending(format!(
+            "Waiting for {} to be upgraded",
+            object.name_any(),
+        )));
+    }
+
+    // control planes first
+    let next = boxes
+        .iter()
+        .filter(|object| {
+            object.status.as_ref().is_some_and(|status| {
+                matches!(status.state, BoxState::Running)
+                    && object.spec.maintenance.is_none()
+                    && status
+                        .version
+                        .as_ref()
+                        .is_some_and(|version| version != target)
+                    && status
+                        .version
+                        .as_ref()
+                        .and_then(|version| is_blocked(version, target))
+                        .is_none()
+            })
+        })
+        .sorted_by_key(|object| {
+            (
+                object
+                    .status
+                    .as_ref()
+                    .and_then(|status| status.bind_group.as_ref())
+                    .map(|group| group.role != BoxGroupRole::ControlPlane),
+                object.metadata.creation_timestamp.clone(),
+                object.name_any(),
+            )
+        })
+        .next();
+    match next {
+        Some(next) if next.uid() == r#box.uid() => None,
+        Some(next) => Some(UpgradeDecision::Pending(format!(
+            "Waiting for {} to be upgraded",
+            next.name_any(),
+        ))),
+        None => Some(UpgradeDecision::Pending("Waiting for its turn".into())),
+    }
+}
+
+fn get_upgrade(r#box: &BoxCrd) -> Option<&BoxUpgradeStatus> {
+    r#box.status.as_ref()?.upgrade.as_ref()
+}
+
+/// Return the reason if the box cannot be upgraded in place.
+///
+/// NOTE: The OS packages are upgraded within the recorded release only,
+///       so the OS distribution and release should be changed by resetting the box.
+fn is_blocked(version: &BoxVersionSpec, target: &BoxVersionSpec) -> Option<String> {
+    if version.os_dist != target.os_dist {
+        Some(format!(
+            "Cannot change the OS distribution in place ({} -> {}); reset the box",
+            &version.os_dist, &target.os_dist,
+        ))
+    } else if version.os_version != target.os_version {
+        Some(format!(
+            "Cannot change the OS release in place ({} -> {}); reset the box",
+            &version.os_version, &target.os_version,
+        ))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use jiff::Timestamp;
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+    use openark_kiss_api::r#box::{
+        BoxAccessSpec, BoxGroupSpec, BoxMachineSpec, BoxSpec, BoxStatus,
+    };
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn version(os_version: &str, os_kernel: &str) -> BoxVersionSpec {
+        BoxVersionSpec {
+            kubespray_image: "quay.io/kubespray/kubespray:v2.28.0".into(),
+            os_dist: "ubuntu".into(),
+            os_kernel: os_kernel.into(),
+            os_version: os_version.into(),
+        }
+    }
+
+    fn build_box(
+        id: u128,
+        role: BoxGroupRole,
+        state: BoxState,
+        version: Option<BoxVersionSpec>,
+    ) -> BoxCrd {
+        let uuid = Uuid::from_u128(id);
+        let group = BoxGroupSpec {
+            cluster_name: "default".into(),
+            role,
+        };
+        let mut r#box = BoxCrd::new(
+            &uuid.to_string(),
+            BoxSpec {
+                group: group.clone(),
+                interfaces: Default::default(),
+                machine: BoxMachineSpec { uuid },
+                maintenance: None,
+                power: None,
+            },
+        );
+        r#box.metadata.uid = Some(uuid.to_string());
+        r#box.metadata.creation_timestamp = Some(Time(Timestamp::from_second(id as i64).unwrap()));
+        r#box.status = Some(BoxStatus {
+            state,
+            access: BoxAccessSpec::default(),
+            bind_group: Some(group),
+            hardware: None,
+            history: Vec::default(),
+            last_updated: Timestamp::UNIX_EPOCH,
+            lease: None,
+            upgrade: None,
+            version,
+        });
+        r#box
+    }
+
+    #[test]
+    fn test_block_os_release_changes() {
+        let target = version("24.04", "generic");
+        assert_eq!(is_blocked(&version("24.04", "hwe"), &target), None);
+        assert!(is_blocked(&version("22.04", "generic"), &target).is_some());
+
+        let mut other = target.clone();
+        other.os_dist = "rocky".into();
+        assert!(is_blocked(&other, &target).is_some());
+    }
+
+    #[test]
+    fn test_check_version() {
+        let target = version("24.04", "generic");
+        let r#box = |version| build_box(1, BoxGroupRole::GenericWorker, BoxState::Running, version);
+
+        assert_eq!(
+            check_version(&r#box(None), &target),
+            Some(UpgradeDecision::Adopt),
+        );
+        assert_eq!(
+            check_version(&r#box(Some(target.clone())), &target),
+            Some(UpgradeDecision::UpToDate),
+        );
+        assert_eq!(
+            check_version(&r#box(Some(version("24.04", "hwe"))), &target),
+            None,
+        );
+        assert!(matches!(
+            check_version(&r#box(Some(version("22.04", "generic"))), &target),
+            Some(UpgradeDecision::Blocked(_)),
+        ));
+    }
+
+    #[test]
+    fn test_upgrade_control_planes_first() {
+        let target = version("24.04", "generic");
+        let outdated = Some(version("24.04", "hwe"));
+        let boxes = [
+            build_box(
+                1,
+                BoxGroupRole::GenericWorker,
+                BoxState::Running,
+                outdated.clone(),
+            ),
+            build_box(
+                2,
+                BoxGroupRole::ControlPlane,
+                BoxState::Running,
+                outdated.clone(),
+            ),
+            build_box(3, BoxGroupRole::ControlPlane, BoxState::Running, outdated),
+        ];
+
+        assert_eq!(check_turn(&boxes[1], &target, &boxes), None);
+        assert!(matches!(
+            check_turn(&boxes[0], &target, &boxes),
+            Some(UpgradeDecision::Pending(_)),
+        ));
+        assert!(matches!(
+            check_turn(&boxes[2], &target, &boxes),
+            Some(UpgradeDecision::Pending(_)),
+        ));
+    }
+
+    #[test]
+    fn test_upgrade_one_box_at_a_time() {
+        let target = version("24.04", "generic");
+        let outdated = Some(version("24.04", "hwe"));
+        let boxes = [
+            build_box(
+                1,
+                BoxGroupRole::GenericWorker,
+                BoxState::Running,
+                outdated.clone(),
+            ),
+            build_box(
+                2,
+                BoxGroupRole::GenericWorker,
+                BoxState::Upgrading,
+                outdated,
+            ),
+        ];
+
+        assert_eq!(
+            check_turn(&boxes[0], &target, &boxes),
+            Some(UpgradeDecision::Pending(format!(
+                "Waiting for {} to be upgraded",
+                boxes[1].name_any(),
+            ))),
+        );
+    }
+
+    #[test]
+    fn test_pause_on_failed_boxes() {
+        let target = version("24.04", "generic");
+        let outdated = Some(version("24.04", "hwe"));
+        let mut boxes = [
+            build_box(
+                1,
+                BoxGroupRole::GenericWorker,
+                BoxState::Running,
+                outdated.clone(),
+            ),
+            build_box(2, BoxGroupRole::GenericWorker, BoxState::Failed, outdated),
+        ];
+        boxes[1].status.as_mut().unwrap().upgrade = Some(BoxUpgradeStatus {
+            state: BoxUpgradeState::Failed,
+            target: target.clone(),
+            reason: Some("timeout".into()),
+            last_updated: Timestamp::UNIX_EPOCH,
+        });
+
+        assert!(matches!(
+            check_turn(&boxes[0], &target, &boxes),
+            Some(UpgradeDecision::Pending(reason)) if reason.ends_with("timeout"),
+        ));
+    }
+
+    #[test]
+    fn test_skip_blocked_boxes_in_turn() {
+        let target = version("24.04", "generic");
+        let boxes = [
+            build_box(
+                1,
+                BoxGroupRole::ControlPlane,
+                BoxState::Running,
+                Some(version("22.04", "generic")),
+            ),
+            build_box(
+                2,
+                BoxGroupRole::GenericWorker,
+                BoxState::Running,
+                Some(version("24.04", "hwe")),
+            ),
+        ];
+
+        assert_eq!(check_turn(&boxes[1], &target, &boxes), None);
+    }
+}
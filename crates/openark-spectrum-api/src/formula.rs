@@ -0,0 +1,253 @@
+use std::{collections::BTreeSet, fmt, str::FromStr};
+
+/// An arithmetic expression of the metrics, e.g. `0.7 * gpu_util + 0.3 * queue_len`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Formula {
+    Number(f64),
+    Variable(String),
+    Neg(Box<Formula>),
+    Binary {
+        op: Operator,
+        lhs: Box<Formula>,
+        rhs: Box<Formula>,
+    },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Operator {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl FromStr for Formula {
+    type Err = FormulaError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser { input: s, pos: 0 };
+        let formula = parser.parse_expr()?;
+        parser.skip_whitespace();
+        if parser.pos < s.len() {
+            return Err(parser.error("unexpected token"));
+        }
+        Ok(formula)
+    }
+}
+
+impl Formula {
+    /// Returns the names of the variables referred by the formula.
+    pub fn variables(&self) -> BTreeSet<&str> {
+        let mut names = BTreeSet::default();
+        self.collect_variables(&mut names);
+        names
+    }
+
+    fn collect_variables<'a>(&'a self, names: &mut BTreeSet<&'a str>) {
+        match self {
+            Self::Number(_) => (),
+            Self::Variable(name) => {
+                names.insert(name);
+            }
+            Self::Neg(value) => value.collect_variables(names),
+            Self::Binary { op: _, lhs, rhs } => {
+                lhs.collect_variables(names);
+                rhs.collect_variables(names);
+            }
+        }
+    }
+
+    /// Evaluates the formula, returning `None` if any variable is unknown
+    /// or the result is not finite.
+    pub fn evaluate<F>(&self, get: &F) -> Option<f64>
+    where
+        F: Fn(&str) -> Option<f64>,
+    {
+        let value = match self {
+            Self::Number(value) => *value,
+            Self::Variable(name) => get(name)?,
+            Self::Neg(value) => -value.evaluate(get)?,
+            Self::Binary { op, lhs, rhs } => {
+                let lhs = lhs.evaluate(get)?;
+                let rhs = rhs.evaluate(get)?;
+                match op {
+                    Operator::Add => lhs + rhs,
+                    Operator::Sub => lhs - rhs,
+                    Operator::Mul => lhs * rhs,
+                    Operator::Div => lhs / rhs,
+                }
+            }
+        };
+        Some(value).filter(|value| value.is_finite())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FormulaError {
+    /// The byte offset of the error
+    pub position: usize,
+    pub message: String,
+}
+
+impl fmt::Display for FormulaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at {}", &self.message, self.position)
+    }
+}
+
+impl ::std::error::Error for FormulaError {}
+
+/// A recursive descent parser of the formulas.
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    // expr := term (('+' | '-') term)*
+    fn parse_expr(&mut self) -> Result<Formula, FormulaError> {
+        let mut lhs = self.parse_term()?;
+        loop {
+            let op = match self.peek() {
+                Some('+') => Operator::Add,
+                Some('-') => Operator::Sub,
+                _ => break Ok(lhs),
+            };
+            self.pos += 1;
+            let rhs = self.parse_term()?;
+            lhs = Formula::Binary {
+                op,
+                lhs: Box::new(lhs),
+                rhs: Box::new(rhs),
+            };
+        }
+    }
+
+    // term := factor (('*' | '/') factor)*
+    fn parse_term(&mut self) -> Result<Formula, FormulaError> {
+        let mut lhs = self.parse_factor()?;
+        loop {
+            let op = match self.peek() {
+                Some('*') => Operator::Mul,
+                Some('/') => Operator::Div,
+                _ => break Ok(lhs),
+            };
+            self.pos += 1;
+            let rhs = self.parse_factor()?;
+            lhs = Formula::Binary {
+                op,
+                lhs: Box::new(lhs),
+                rhs: Box::new(rhs),
+            };
+        }
+    }
+
+    // factor := '-' factor | '(' expr ')' | number | variable
+    fn parse_factor(&mut self) -> Result<Formula, FormulaError> {
+        match self.peek() {
+            Some('-') => {
+                self.pos += 1;
+                Ok(Formula::Neg(Box::new(self.parse_factor()?)))
+            }
+            Some('(') => {
+                self.pos += 1;
+                let formula = self.parse_expr()?;
+                if self.peek() != Some(')') {
+                    return Err(self.error("expected ')'"));
+                }
+                self.pos += 1;
+                Ok(formula)
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => {
+                let token = self.take_while(|c| c.is_ascii_digit() || c == '.');
+                token
+                    .parse()
+                    .map(Formula::Number)
+                    .map_err(|_| self.error("invalid number"))
+            }
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+                let token = self.take_while(|c| c.is_ascii_alphanumeric() || c == '_');
+                Ok(Formula::Variable(token.into()))
+            }
+            Some(_) => Err(self.error("unexpected token")),
+            None => Err(self.error("unexpected end of formula")),
+        }
+    }
+
+    /// Skips the whitespaces and returns the next character.
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.input[self.pos..].chars().next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.input[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn take_while(&mut self, f: impl Fn(char) -> bool) -> &'a str {
+        let begin = self.pos;
+        let rest = &self.input[begin..];
+        self.pos += rest.find(|c| !f(c)).unwrap_or(rest.len());
+        &self.input[begin..self.pos]
+    }
+
+    fn error(&self, message: &str) -> FormulaError {
+        FormulaError {
+            position: self.pos,
+            message: message.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate(formula: &str, vars: &[(&str, f64)]) -> Option<f64> {
+        let formula: Formula = formula.parse().unwrap();
+        formula.evaluate(&|name| {
+            vars.iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| *value)
+        })
+    }
+
+    #[test]
+    fn evaluate_formula() {
+        let vars = [("gpu_util", 0.5), ("queue_len", 10.0)];
+        assert_eq!(
+            evaluate("0.5 * gpu_util + 0.25 * queue_len", &vars),
+            Some(2.75),
+        );
+        assert_eq!(evaluate("2 * (gpu_util - 1) / -0.5", &vars), Some(2.0));
+        assert_eq!(evaluate("1 - 2 - 3", &vars), Some(-4.0));
+
+        // unknown variables and non-finite results
+        assert_eq!(evaluate("gpu_util + mem_util", &vars), None);
+        assert_eq!(evaluate("queue_len / 0", &vars), None);
+    }
+
+    #[test]
+    fn parse_invalid_formula() {
+        for formula in [
+            "",
+            "0.7 *",
+            "(gpu_util",
+            "gpu_util queue_len",
+            "1.2.3",
+            "a % b",
+        ] {
+            assert!(formula.parse::<Formula>().is_err(), "{formula:?}");
+        }
+    }
+
+    #[test]
+    fn list_variables() {
+        let formula: Formula = "a * (b + a) - -c".parse().unwrap();
+        assert_eq!(
+            formula.variables().into_iter().collect::<Vec<_>>(),
+            ["a", "b", "c"]
+        );
+    }
+}
@@ -1,5 +1,5 @@
 use jiff::Timestamp;
-use k8s_openapi::apimachinery::pkg::apis::meta::v1::{Condition, Time};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{Condition, LabelSelector, Time};
 #[cfg(feature = "kube")]
 use kube::CustomResource;
 #[cfg(feature = "schemars")]
@@ -79,6 +79,14 @@ pub struct HistogramSpec {
 
     pub target_ref: ObjectReference,
 
+    /// targetSelector selects the resources of the `Node` and `Pod` targets,
+    /// whose `targetRef.name` is just an alias of the selected resources.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub target_selector: Option<LabelSelector>,
+
     pub histogram: HistogramSettings,
 }
 
@@ -102,6 +110,49 @@ pub struct HistogramSettings {
     pub interval: Option<u64>,
 
     pub size: u8,
+
+    /// Boundaries of the buckets, which are `Linear` between the min-max weights by default.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub boundaries: HistogramBoundaries,
+
+    /// Smooth the weights by an exponential moving average with the factor in `(0, 1]`,
+    /// where the larger one follows the latest weights faster.
+    ///
+    /// The factor is applied per `interval` (30 seconds by default),
+    /// being scaled by the elapsed time between the reconciliations.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub ema_alpha: Option<f64>,
+
+    /// Smooth the weights by averaging them within the time window in seconds.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub window: Option<u64>,
+
+    /// Keep the last bucket of an endpoint unless its weight moves beyond the margin,
+    /// which is a fraction of a bucket width, e.g. `0.2`.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub hysteresis: Option<f64>,
+}
+
+/// Boundaries of the histogram buckets.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum HistogramBoundaries {
+    /// Divides the range between the min-max weights evenly.
+    #[default]
+    Linear,
+    /// Divides the weights by their quantiles, so that the skewed weights
+    /// are spread over the buckets.
+    Quantile,
 }
 
 /// Status defines the current state of Histogram.
@@ -1,6 +1,7 @@
 #[cfg(feature = "client")]
 pub mod client;
 pub mod common;
+pub mod formula;
 pub mod histogram;
 pub mod metrics_class;
 pub mod pool;
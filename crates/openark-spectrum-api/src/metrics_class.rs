@@ -1,3 +1,5 @@
+use std::collections::BTreeSet;
+
 use jiff::Timestamp;
 use k8s_openapi::apimachinery::pkg::apis::meta::v1::{Condition, Time};
 #[cfg(feature = "kube")]
@@ -7,7 +9,7 @@ use schemars::JsonSchema;
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
-use crate::common::ServiceReference;
+use crate::{common::ServiceReference, formula::Formula};
 
 #[cfg(feature = "operator")]
 impl ::openark_core::operator::Resource for MetricsClassCrd {
@@ -98,12 +100,174 @@ pub struct MetricsClassSpec {
     pub description: String,
 
     pub backend_ref: ServiceReference,
+
+    /// Source is the metrics source which the backend evaluates the queries with.
+    /// The default source of the backend is used if not given.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub source: Option<MetricsSource>,
+
+    /// Weighting combines several metrics into the weights of the resources.
+    /// The default record of the backend is used if not given.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub weighting: Option<MetricsWeighting>,
 }
 
 impl MetricsClassSpec {
     pub const FIELD_CONTROLLER_NAME: &'static str = "controllerName";
 }
 
+/// A source of the metrics samples.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum MetricsSource {
+    /// Receive the metrics pushed over OTLP/HTTP (JSON)
+    Otlp,
+    /// Query the metrics from Prometheus
+    #[default]
+    Prometheus,
+}
+
+impl MetricsSource {
+    /// Validates the query, which is a PromQL expression for Prometheus,
+    /// or a bare metric name (`[a-zA-Z_:][a-zA-Z0-9_:]*`) for OTLP.
+    pub fn validate_query(&self, query: &str) -> Result<(), String> {
+        match self {
+            Self::Otlp => {
+                let query = query.trim();
+                let is_valid_char = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == ':';
+                let is_valid_name = query
+                    .chars()
+                    .next()
+                    .is_some_and(|c| is_valid_char(c) && !c.is_ascii_digit())
+                    && query.chars().all(is_valid_char);
+                if is_valid_name {
+                    Ok(())
+                } else {
+                    Err(format!("OTLP accepts only metric names: {query:?}"))
+                }
+            }
+            Self::Prometheus => Ok(()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct MetricsWeighting {
+    /// Formula is an arithmetic expression of the metrics,
+    /// e.g. `0.7 * gpu_util + 0.3 * queue_len`.
+    pub formula: String,
+
+    /// Metrics are the queries referred by the formula.
+    pub metrics: Vec<MetricsQuery>,
+}
+
+impl MetricsWeighting {
+    /// Validates the metrics and returns the parsed formula.
+    pub fn validate(&self) -> Result<Formula, String> {
+        let mut names = BTreeSet::default();
+        for MetricsQuery { name, query, .. } in &self.metrics {
+            let is_valid_name = name
+                .chars()
+                .next()
+                .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if !is_valid_name {
+                return Err(format!("invalid metric name: {name:?}"));
+            }
+            if !names.insert(name.as_str()) {
+                return Err(format!("duplicated metric: {name}"));
+            }
+            if query.trim().is_empty() {
+                return Err(format!("empty query: {name}"));
+            }
+        }
+
+        let formula: Formula = self
+            .formula
+            .parse()
+            .map_err(|error| format!("invalid formula: {error}"))?;
+        if let Some(name) = formula
+            .variables()
+            .into_iter()
+            .find(|name| !names.contains(name))
+        {
+            return Err(format!("undefined metric: {name}"));
+        }
+        Ok(formula)
+    }
+
+    /// Validates whether the queries are supported by the given metrics source.
+    pub fn validate_queries(&self, source: MetricsSource) -> Result<(), String> {
+        self.metrics
+            .iter()
+            .try_for_each(|MetricsQuery { name, query, .. }| {
+                source
+                    .validate_query(query)
+                    .map_err(|error| format!("invalid query: {name}: {error}"))
+            })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct MetricsQuery {
+    /// Name is the identifier of the metric in the formula.
+    pub name: String,
+
+    /// Query is a PromQL expression, which returns a sample per `pod`.
+    /// `$namespace` and `$service` are replaced with those of the target service.
+    ///
+    /// The `Otlp` source does not evaluate PromQL, so the query should be
+    /// a bare metric name of the pushed samples there, e.g. `gpu_util`.
+    pub query: String,
+
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub normalization: MetricsNormalization,
+
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub missing: MetricsMissingPolicy,
+}
+
+/// A normalization of the samples across the pods of a service.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum MetricsNormalization {
+    /// Use the samples as-is
+    #[default]
+    None,
+    /// Scale the samples into `[0, 1]` by the minimum and maximum
+    MinMax,
+    /// Divide the samples by the maximum
+    Max,
+}
+
+/// A policy for the pods which have no samples.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum MetricsMissingPolicy {
+    /// Leave the weight of the pod unknown
+    #[default]
+    Unknown,
+    /// Assume the sample is zero
+    Zero,
+    /// Assume the sample is the mean of the others
+    Mean,
+}
+
 /// Status defines the current state of Class.
 ///
 /// Implementations MUST populate status on all Class
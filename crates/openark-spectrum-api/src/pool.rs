@@ -1,5 +1,5 @@
 use jiff::Timestamp;
-use k8s_openapi::apimachinery::pkg::apis::meta::v1::{Condition, Time};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{Condition, LabelSelector, Time};
 #[cfg(feature = "kube")]
 use kube::CustomResource;
 #[cfg(feature = "schemars")]
@@ -78,6 +78,44 @@ pub struct PoolSpec {
     pub metrics_class_name: String,
 
     pub target_ref: ObjectReference,
+
+    /// targetSelector selects the resources of the `Node` and `Pod` targets,
+    /// whose `targetRef.name` is just an alias of the selected resources.
+    ///
+    /// The bound resources are labeled with `<pool claim label>=<claim name>`,
+    /// so the workloads of each claim should select the label, e.g. with node affinity.
+    /// The bound nodes are not tainted, so that the DaemonSets keep running on them,
+    /// and the other workloads should avoid the label to keep out of them.
+    /// The labels are removed once the resources are released or the pool is deleted.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub target_selector: Option<LabelSelector>,
+
+    /// solver is the algorithm which allocates the resources into the claims.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub solver: PoolSolver,
+}
+
+/// An algorithm which allocates the pool resources into the claims.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum PoolSolver {
+    /// Solves the allocation optimally with MILP, falling back to `Greedy`
+    /// if the solver fails or times out.
+    ///
+    /// Infeasible allocations under the topology spread constraints are
+    /// kept infeasible, leaving the claims unsatisfiable.
+    #[default]
+    Milp,
+    /// Allocates the resources deterministically with heuristics,
+    /// which scales to large pools.
+    ///
+    /// The topology spread constraints are enforced by leaving
+    /// the breaking resources unbound.
+    Greedy,
 }
 
 /// Status defines the current state of Pool.
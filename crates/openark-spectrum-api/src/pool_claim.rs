@@ -20,12 +20,19 @@ impl ::openark_core::operator::Resource for PoolClaimCrd {
             .map(|status| status.conditions.as_slice())
     }
 
+    /// Builds the conditions only.
+    ///
+    /// NOTE: the resources are owned by the pool's scheduler, so they are left
+    ///       out of the merge patch not to revert its latest results.
     #[inline]
     fn build_status(
         &self,
         conditions: Vec<Condition>,
     ) -> <Self as ::openark_core::operator::Resource>::Status {
-        PoolClaimStatus { conditions }
+        PoolClaimStatus {
+            conditions,
+            resources: None,
+        }
     }
 }
 
@@ -119,6 +126,70 @@ pub struct PoolResourceSettings {
         serde(default, skip_serializing_if = "Option::is_none")
     )]
     pub weight: Option<u64>,
+
+    /// antiAffinity forbids sharing the given topology domains
+    /// with the other claims of the same pool.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub anti_affinity: Vec<PoolTopologyKey>,
+
+    /// topologySpreadConstraints spread the bound resources
+    /// evenly across the given topology domains.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub topology_spread_constraints: Vec<PoolTopologySpreadConstraint>,
+}
+
+impl PoolResourceSettings {
+    /// Returns `true` if the load meets both of the minimum and maximum weights.
+    pub fn is_satisfied(&self, load: f64) -> bool {
+        self.min.is_none_or(|min| load >= min) && self.max.is_none_or(|max| load <= max)
+    }
+
+    /// Returns `true` if the resources are constrained by any topology domain.
+    pub fn has_topology_constraints(&self) -> bool {
+        !self.anti_affinity.is_empty() || !self.topology_spread_constraints.is_empty()
+    }
+}
+
+/// A kind of topology domains which the pool resources belong to.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum PoolTopologyKey {
+    /// The node, e.g. `kubernetes.io/hostname`
+    Node,
+    /// The rack, e.g. `topology.kubernetes.io/rack`
+    Rack,
+    /// The zone, e.g. `topology.kubernetes.io/zone`
+    Zone,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct PoolTopologySpreadConstraint {
+    pub topology_key: PoolTopologyKey,
+
+    /// maxSkew is the maximum difference of the numbers of bound resources
+    /// between any two topology domains.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default = "PoolTopologySpreadConstraint::default_max_skew")
+    )]
+    pub max_skew: u32,
+}
+
+impl PoolTopologySpreadConstraint {
+    #[cfg(feature = "serde")]
+    const fn default_max_skew() -> u32 {
+        1
+    }
 }
 
 #[derive(Clone, Debug, Default, PartialEq)]
@@ -127,13 +198,26 @@ pub struct PoolResourceSettings {
 #[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
 
 pub struct PoolResourceLifecycle {
+    /// preStart probes are executed in order before the resource is bound,
+    /// where the resource is released if any probe fails.
     #[cfg_attr(
         feature = "serde",
         serde(default, skip_serializing_if = "Vec::is_empty")
     )]
     pub pre_start: Vec<PoolResourceProbe>,
+
+    /// postStop probes are executed in order when the resource is released,
+    /// e.g. to reset the resource before it is bound to the next claim.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub post_stop: Vec<PoolResourceProbe>,
 }
 
+/// A probe of a resource.
+///
+/// The string fields may be templated with `$address`, `$claim` and `$namespace`.
 #[derive(Clone, Debug, PartialEq)]
 #[cfg_attr(feature = "schemars", derive(JsonSchema))]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
@@ -141,6 +225,80 @@ pub struct PoolResourceLifecycle {
 
 pub enum PoolResourceProbe {
     Http(PoolResourceHttpProbe),
+    Tcp(PoolResourceTcpProbe),
+    Grpc(PoolResourceGrpcProbe),
+}
+
+impl PoolResourceProbe {
+    pub const fn policy(&self) -> &PoolResourceProbePolicy {
+        match self {
+            Self::Http(probe) => &probe.policy,
+            Self::Tcp(probe) => &probe.policy,
+            Self::Grpc(probe) => &probe.policy,
+        }
+    }
+}
+
+/// A policy of retrying a probe.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+
+pub struct PoolResourceProbePolicy {
+    /// The number of the retries after the first failure.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    #[cfg_attr(feature = "schemars", schemars(range(max = 10)))]
+    pub retries: Option<u32>,
+
+    /// The initial delay in seconds between the attempts, which doubles per retry.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub backoff_seconds: Option<u64>,
+
+    /// The timeout in seconds of each attempt.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub timeout_seconds: Option<u64>,
+}
+
+impl PoolResourceProbePolicy {
+    pub const DEFAULT_BACKOFF_SECONDS: u64 = 1;
+    pub const DEFAULT_TIMEOUT_SECONDS: u64 = 30;
+    pub const MAX_BACKOFF_SECONDS: u64 = 300;
+    pub const MAX_RETRIES: u32 = 10;
+    /// The maximum total time in seconds of all the attempts, including the backoffs.
+    pub const MAX_TOTAL_SECONDS: u64 = 900;
+
+    /// Returns the delay in seconds before the given retry, starting from 1.
+    pub fn backoff(&self, retry: u32) -> u64 {
+        let initial = self
+            .backoff_seconds
+            .unwrap_or(Self::DEFAULT_BACKOFF_SECONDS);
+        initial
+            .saturating_mul(
+                1u64.checked_shl(retry.saturating_sub(1))
+                    .unwrap_or(u64::MAX),
+            )
+            .min(Self::MAX_BACKOFF_SECONDS)
+    }
+
+    /// Returns the number of the retries, clamped to [`Self::MAX_RETRIES`].
+    pub fn retries(&self) -> u32 {
+        self.retries.unwrap_or(0).min(Self::MAX_RETRIES)
+    }
+
+    pub fn timeout(&self) -> u64 {
+        self.timeout_seconds
+            .unwrap_or(Self::DEFAULT_TIMEOUT_SECONDS)
+    }
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -159,8 +317,54 @@ pub struct PoolResourceHttpProbe {
     #[cfg_attr(feature = "serde", serde(default))]
     pub secure: bool,
 
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "BTreeMap::is_empty")
+    )]
+    pub headers: BTreeMap<String, String>,
+
     #[cfg_attr(feature = "serde", serde(flatten))]
     pub body: Option<PoolResourceHttpBody>,
+
+    #[cfg_attr(feature = "serde", serde(default, flatten))]
+    pub policy: PoolResourceProbePolicy,
+}
+
+/// A probe which succeeds if a TCP connection is established.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+
+pub struct PoolResourceTcpProbe {
+    pub port: u16,
+
+    #[cfg_attr(feature = "serde", serde(default, flatten))]
+    pub policy: PoolResourceProbePolicy,
+}
+
+/// A probe which succeeds if the gRPC health checking protocol
+/// (`grpc.health.v1.Health/Check`) reports `SERVING`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+
+pub struct PoolResourceGrpcProbe {
+    pub port: u16,
+
+    /// The name of the service to check, or the whole server if empty.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "String::is_empty")
+    )]
+    pub service: String,
+
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub secure: bool,
+
+    #[cfg_attr(feature = "serde", serde(default, flatten))]
+    pub policy: PoolResourceProbePolicy,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -192,6 +396,7 @@ impl From<PoolResourceHttpMethod> for ::http::Method {
 
 pub enum PoolResourceHttpBody {
     JsonBody(BTreeMap<String, Value>),
+    TextBody(String),
 }
 
 /// Status defines the current state of PoolClaim.
@@ -211,17 +416,29 @@ pub struct PoolClaimStatus {
     /// of PoolClaimConditionType for the type of each Condition.
     #[serde(default = "PoolClaimStatus::default_conditions")]
     pub conditions: Vec<Condition>,
+
+    /// Resources is the result of the last scheduling of this PoolClaim.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub resources: Option<PoolClaimResourcesStatus>,
 }
 
 impl Default for PoolClaimStatus {
     fn default() -> Self {
         Self {
             conditions: Self::default_conditions(),
+            resources: None,
         }
     }
 }
 
 impl PoolClaimStatus {
+    pub const CONDITION_PREEMPTED: &'static str = "Preempted";
+    pub const CONDITION_SATISFIED: &'static str = "Satisfied";
+    pub const CONDITION_UNSATISFIABLE: &'static str = "Unsatisfiable";
+
     fn default_conditions() -> Vec<Condition> {
         vec![Condition {
             last_transition_time: Time(Timestamp::default()),
@@ -243,3 +460,44 @@ impl PoolClaimStatus {
         })
     }
 }
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct PoolClaimResourcesStatus {
+    /// bound is the number of the bound resources.
+    pub bound: usize,
+
+    /// load is the total weight of the bound resources.
+    pub load: f64,
+
+    /// unsatisfiable is `true` if the pool could not meet the minimum weight.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub unsatisfiable: bool,
+
+    /// preemption is the last preemption of the resources,
+    /// kept until the claim gets satisfied again.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub preemption: Option<PoolClaimPreemption>,
+
+    pub last_updated: Timestamp,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct PoolClaimPreemption {
+    /// evicted is the number of the resources taken from the claim.
+    pub evicted: usize,
+
+    /// preemptors are the names of the claims which have taken the resources.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub preemptors: Vec<String>,
+
+    pub timestamp: Timestamp,
+}
@@ -5,7 +5,10 @@ use ordered_float::OrderedFloat;
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
-use crate::pool_claim::PoolResourceLifecycle;
+use crate::{
+    metrics_class::{MetricsSource, MetricsWeighting},
+    pool_claim::PoolResourceLifecycle,
+};
 
 #[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
@@ -68,6 +71,13 @@ pub struct PoolCommitRequestItem<'a> {
 #[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
 pub struct PoolCommitRequest<'a> {
     pub items: Vec<PoolCommitRequestItem<'a>>,
+    /// All the resources of the pool, whose stale bindings are released,
+    /// e.g. those of the deleted claims.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub pool: Option<PoolRequest<'a>>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -79,6 +89,16 @@ where
 {
     pub metadata: ObjectMeta,
     pub list: Cow<'a, [T]>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub source: Option<MetricsSource>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub weighting: Option<MetricsWeighting>,
 }
 
 #[derive(Clone, Debug, Default, PartialEq)]
@@ -1,7 +1,9 @@
 mod records;
 mod routes;
+mod sources;
+mod weighting;
 
-use std::net::SocketAddr;
+use std::{net::SocketAddr, time::Duration};
 
 use actix_web::{
     App, HttpResponse, HttpServer, Responder, get, middleware,
@@ -13,6 +15,8 @@ use openark_core::client::HealthState;
 use tracing::{Level, instrument};
 use url::Url;
 
+use crate::sources::{Sources, otlp::MetricsStore};
+
 #[cfg_attr(feature = "tracing", instrument(level = Level::INFO))]
 #[get("ping")]
 async fn ping() -> impl Responder {
@@ -41,12 +45,35 @@ struct Args {
     )]
     bind_addr: SocketAddr,
 
-    /// An address of Prometheus
+    /// A duration in seconds until the pushed OTLP samples get stale
+    #[arg(
+        long,
+        env = "OTLP_TTL_SECONDS",
+        value_name = "SECONDS",
+        default_value_t = 300
+    )]
+    otlp_ttl_seconds: u64,
+
+    /// A bearer token of the OTLP senders; the OTLP receiver is disabled if not given
+    #[arg(long, env = "OTLP_TOKEN", value_name = "TOKEN", hide_env_values = true)]
+    otlp_token: Option<String>,
+
+    /// An address of Prometheus, required by the `prometheus` source
     #[arg(long, env = "PROMETHEUS_BASE_URL", value_name = "URL")]
-    prometheus_base_url: Url,
+    prometheus_base_url: Option<Url>,
 
     #[command(flatten)]
     records: self::records::RecordArgs,
+
+    /// A default source of the metrics, used when a `MetricsClass` does not specify one
+    #[arg(
+        long,
+        env = "METRICS_SOURCE",
+        value_name = "KIND",
+        value_enum,
+        default_value_t
+    )]
+    source: self::sources::SourceKind,
 }
 
 async fn try_main(args: Args) -> Result<()> {
@@ -54,8 +81,11 @@ async fn try_main(args: Args) -> Result<()> {
     let Args {
         mut base_url,
         bind_addr: addr,
+        otlp_ttl_seconds,
+        otlp_token,
         prometheus_base_url,
         records,
+        source,
     } = args;
 
     // Remove trailing
@@ -63,16 +93,19 @@ async fn try_main(args: Args) -> Result<()> {
         base_url.pop();
     }
 
-    let client = Data::new(::prometheus_http_query::Client::from(
-        ::reqwest::Client::new(),
-        prometheus_base_url.as_str(),
-    )?);
+    let prometheus = prometheus_base_url
+        .map(|base_url| {
+            ::prometheus_http_query::Client::from(::reqwest::Client::new(), base_url.as_str())
+        })
+        .transpose()?;
+    let otlp = MetricsStore::new(Duration::from_secs(otlp_ttl_seconds), otlp_token);
+    let sources = Data::new(Sources::try_new(source, otlp, prometheus)?);
     let records = Data::new(records);
 
     // Start web server
     HttpServer::new(move || {
         let app = App::new()
-            .app_data(Data::clone(&client))
+            .app_data(Data::clone(&sources))
             .app_data(Data::clone(&records));
 
         let app = app.service(
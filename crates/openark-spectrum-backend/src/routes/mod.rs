@@ -3,5 +3,7 @@ mod service;
 use actix_web::{Scope, web};
 
 pub fn build() -> Scope {
-    web::scope("").service(web::scope("v1/Service").service(self::service::post))
+    web::scope("")
+        .service(web::scope("v1/metrics").service(crate::sources::otlp::post))
+        .service(web::scope("v1/Service").service(self::service::post))
 }
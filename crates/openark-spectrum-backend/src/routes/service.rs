@@ -1,23 +1,24 @@
-use std::collections::HashMap;
-
 use actix_web::{HttpResponse, Responder, post, web};
-use anyhow::{Result, anyhow};
+use anyhow::{Result, anyhow, bail};
 use k8s_openapi::api::discovery::v1::Endpoint;
 use openark_spectrum_api::schema::{WeightRequest, WeightResponse};
-use prometheus_http_query::Client;
 #[cfg(feature = "tracing")]
 use tracing::{Level, instrument, warn};
 
-use crate::records::RecordArgs;
+use crate::{
+    records::RecordArgs,
+    sources::{SourceKind, Sources},
+    weighting::evaluate,
+};
 
 #[cfg_attr(feature = "tracing", instrument(level = Level::INFO, skip_all))]
 #[post("")]
 async fn post(
-    client: web::Data<Client>,
+    sources: web::Data<Sources>,
     records: web::Data<RecordArgs>,
     web::Json(args): web::Json<WeightRequest<'static, Endpoint>>,
 ) -> impl Responder {
-    match try_handle(client, records, args).await {
+    match try_handle(sources, records, args).await {
         Ok(response) => HttpResponse::Ok().json(response),
         Err(error) => {
             #[cfg(feature = "tracing")]
@@ -28,11 +29,16 @@ async fn post(
 }
 
 async fn try_handle(
-    client: web::Data<Client>,
+    sources: web::Data<Sources>,
     records: web::Data<RecordArgs>,
     args: WeightRequest<'static, Endpoint>,
 ) -> Result<WeightResponse> {
-    let WeightRequest { metadata, list } = args;
+    let WeightRequest {
+        metadata,
+        list,
+        source,
+        weighting,
+    } = args;
 
     let name = metadata
         .name
@@ -43,49 +49,61 @@ async fn try_handle(
         .as_ref()
         .ok_or_else(|| anyhow!("Empty service namespace"))?;
 
-    // Build a PromQL query
-    // TODO: validate record by regex
+    // Resolve the metrics source of the class
+    let source = sources.get(source)?;
+
+    // Collect pod (or node) names
+    let pods: Vec<_> = list
+        .as_ref()
+        .iter()
+        .map(|item| {
+            item.target_ref
+                .as_ref()
+                .filter(|target| matches!(target.kind.as_deref(), Some("Pod" | "Node")))
+                .and_then(|target| target.name.as_deref())
+        })
+        .collect();
+
+    // Evaluate the weighting formula if given
+    if let Some(weighting) = weighting {
+        let weights = evaluate(&source, &weighting, namespace, name, &pods).await?;
+        return Ok(WeightResponse {
+            weights: weights
+                .into_iter()
+                .map(|value| value.map(Into::into))
+                .collect(),
+        });
+    }
+
+    // Build a query
     let record = metadata
         .labels
         .as_ref()
         .and_then(|map| map.get(&records.label_custom_histogram_record))
         .unwrap_or(&records.default_record_service);
-    let query = format!(
-        r#"{record}{{
-            namespace = {namespace:?},
-            service_name = {name:?},
-        }}"#
-    )
-    .replace([' ', '\n'], "");
-
-    // Evaluate a PromQL query
-    let response = client.query(query).get().await?;
-
-    // Parse vector data
-    let (data, _stats) = response.into_inner();
-    let data = data
-        .into_vector()
-        .map_err(|_| anyhow!("Invalid PromQL query data"))?;
+    if !is_valid_record(record) {
+        bail!("Invalid record: {record:?}");
+    }
+    let query = match source.kind() {
+        // NOTE: the pushed metrics are already bound to the pods
+        SourceKind::Otlp => record.clone(),
+        SourceKind::Prometheus => format!(
+            r#"{record}{{
+                namespace = {namespace:?},
+                service_name = {name:?},
+            }}"#
+        )
+        .replace([' ', '\n'], ""),
+    };
 
-    // Build a data map
-    let map: HashMap<_, _> = data
-        .iter()
-        .filter_map(|vector| {
-            let pod_name = vector.metric().get("pod")?;
-            let sample = vector.sample().value();
-            Some((pod_name, sample))
-        })
-        .collect();
+    // Evaluate the query
+    let map = source.query_samples(namespace, query).await?;
 
     // Collect samples
-    let samples = list
-        .as_ref()
+    let samples = pods
         .iter()
-        .map(|item| {
-            item.target_ref
-                .as_ref()
-                .filter(|target| target.kind.as_deref() == Some("Pod"))
-                .and_then(|target| target.name.as_ref())
+        .map(|pod_name| {
+            pod_name
                 .and_then(|pod_name| map.get(pod_name).copied())
                 .map(Into::into)
         })
@@ -93,3 +111,13 @@ async fn try_handle(
 
     Ok(WeightResponse { weights: samples })
 }
+
+/// Returns `true` if the record is a valid metric name, i.e. `[a-zA-Z_:][a-zA-Z0-9_:]*`.
+fn is_valid_record(record: &str) -> bool {
+    let is_valid_char = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == ':';
+    record
+        .chars()
+        .next()
+        .is_some_and(|c| is_valid_char(c) && !c.is_ascii_digit())
+        && record.chars().all(is_valid_char)
+}
@@ -0,0 +1,99 @@
+pub(crate) mod otlp;
+pub(crate) mod prometheus;
+
+use std::{collections::HashMap, sync::Arc};
+
+use anyhow::{Result, bail};
+use clap::ValueEnum;
+use openark_spectrum_api::metrics_class::MetricsSource;
+
+use crate::sources::otlp::MetricsStore;
+
+/// A kind of the metrics sources.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub(crate) enum SourceKind {
+    /// Receive the metrics pushed over OTLP/HTTP (JSON)
+    Otlp,
+    /// Query the metrics from Prometheus
+    #[default]
+    Prometheus,
+}
+
+impl From<MetricsSource> for SourceKind {
+    fn from(value: MetricsSource) -> Self {
+        match value {
+            MetricsSource::Otlp => Self::Otlp,
+            MetricsSource::Prometheus => Self::Prometheus,
+        }
+    }
+}
+
+/// All the available metrics sources, resolved per request.
+pub(crate) struct Sources {
+    /// The source used when a request does not specify one
+    default: SourceKind,
+    otlp: Arc<MetricsStore>,
+    prometheus: Option<::prometheus_http_query::Client>,
+}
+
+impl Sources {
+    pub(crate) fn try_new(
+        default: SourceKind,
+        otlp: MetricsStore,
+        prometheus: Option<::prometheus_http_query::Client>,
+    ) -> Result<Self> {
+        let sources = Self {
+            default,
+            otlp: Arc::new(otlp),
+            prometheus,
+        };
+        sources.get(None)?;
+        Ok(sources)
+    }
+
+    pub(crate) fn otlp(&self) -> &MetricsStore {
+        &self.otlp
+    }
+
+    /// Resolves the requested source, falling back to the default one.
+    pub(crate) fn get(&self, kind: Option<MetricsSource>) -> Result<Source> {
+        match kind.map(Into::into).unwrap_or(self.default) {
+            SourceKind::Otlp if self.otlp.is_enabled() => Ok(Source::Otlp(self.otlp.clone())),
+            SourceKind::Otlp => bail!("OTLP token is required by the otlp source"),
+            SourceKind::Prometheus => match &self.prometheus {
+                Some(client) => Ok(Source::Prometheus(client.clone())),
+                None => bail!("Prometheus base URL is required by the prometheus source"),
+            },
+        }
+    }
+}
+
+/// A source of the metrics samples.
+#[derive(Clone)]
+pub(crate) enum Source {
+    Otlp(Arc<MetricsStore>),
+    Prometheus(::prometheus_http_query::Client),
+}
+
+impl Source {
+    pub(crate) const fn kind(&self) -> SourceKind {
+        match self {
+            Self::Otlp(_) => SourceKind::Otlp,
+            Self::Prometheus(_) => SourceKind::Prometheus,
+        }
+    }
+
+    /// Evaluates the query, returning the samples by the pod names.
+    ///
+    /// The query is a PromQL expression for Prometheus, or a metric name for OTLP.
+    pub(crate) async fn query_samples(
+        &self,
+        namespace: &str,
+        query: String,
+    ) -> Result<HashMap<String, f64>> {
+        match self {
+            Self::Otlp(store) => store.query(namespace, &query),
+            Self::Prometheus(client) => self::prometheus::query_samples(client, query).await,
+        }
+    }
+}
@@ -0,0 +1,424 @@
+use std::{
+    collections::HashMap,
+    sync::RwLock,
+    time::{Duration, Instant},
+};
+
+use actix_web::{HttpRequest, HttpResponse, Responder, http::header, post, web};
+use anyhow::{Result, anyhow};
+use openark_spectrum_api::metrics_class::MetricsSource;
+use serde::Deserialize;
+#[cfg(feature = "tracing")]
+use tracing::{Level, instrument};
+
+use crate::sources::Sources;
+
+const ATTRIBUTE_NAMESPACE: &str = "k8s.namespace.name";
+const ATTRIBUTE_NODE: &str = "k8s.node.name";
+const ATTRIBUTE_POD: &str = "k8s.pod.name";
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct Target {
+    namespace: Option<String>,
+    pod: String,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Sample {
+    value: f64,
+    received_at: Instant,
+}
+
+/// Caches the latest per-pod (or per-node) gauges, which are pushed over OTLP.
+pub(crate) struct MetricsStore {
+    metrics: RwLock<HashMap<String, HashMap<Target, Sample>>>,
+    /// The bearer token of the senders; the receiver is disabled if not given
+    token: Option<String>,
+    /// The duration until the samples get stale
+    ttl: Duration,
+}
+
+impl MetricsStore {
+    pub(crate) fn new(ttl: Duration, token: Option<String>) -> Self {
+        Self {
+            metrics: RwLock::default(),
+            token: token.filter(|token| !token.is_empty()),
+            ttl,
+        }
+    }
+
+    /// Returns `true` if the receiver accepts the pushed samples.
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.token.is_some()
+    }
+
+    /// Verifies the `Authorization` header in constant time.
+    fn authenticate(&self, authorization: Option<&str>) -> bool {
+        let (Some(expected), Some(given)) = (
+            self.token.as_deref(),
+            authorization.and_then(|value| value.strip_prefix("Bearer ")),
+        ) else {
+            return false;
+        };
+        expected.len() == given.len()
+            && expected
+                .bytes()
+                .zip(given.bytes())
+                .fold(0, |acc, (a, b)| acc | (a ^ b))
+                == 0
+    }
+
+    /// Returns the fresh samples of the metric by the pod names.
+    ///
+    /// The PromQL expressions are rejected rather than silently yielding nothing.
+    pub(crate) fn query(&self, namespace: &str, name: &str) -> Result<HashMap<String, f64>> {
+        MetricsSource::Otlp
+            .validate_query(name)
+            .map_err(|error| anyhow!("Invalid query: {error}"))?;
+
+        let metrics = self.metrics.read().unwrap();
+        let Some(samples) = metrics.get(name.trim()) else {
+            return Ok(HashMap::default());
+        };
+
+        let now = Instant::now();
+        Ok(samples
+            .iter()
+            .filter(|(target, _)| target.namespace.as_deref().is_none_or(|ns| ns == namespace))
+            .filter(|(_, sample)| now.duration_since(sample.received_at) < self.ttl)
+            .map(|(target, sample)| (target.pod.clone(), sample.value))
+            .collect())
+    }
+
+    /// Records the samples, returning the number of the accepted ones.
+    fn record(&self, request: ExportMetricsServiceRequest) -> usize {
+        let now = Instant::now();
+        let mut metrics = self.metrics.write().unwrap();
+
+        let mut count = 0;
+        for ResourceMetrics {
+            resource,
+            scope_metrics,
+        } in request.resource_metrics
+        {
+            for metric in scope_metrics.into_iter().flat_map(|scope| scope.metrics) {
+                let data_points = metric
+                    .gauge
+                    .into_iter()
+                    .chain(metric.sum)
+                    .flat_map(|data| data.data_points);
+
+                let samples = metrics.entry(metric.name).or_default();
+                for data_point in data_points {
+                    // NOTE: the attributes of the data points precede those of the resources
+                    let get_attribute = |key| {
+                        find_attribute(&data_point.attributes, key)
+                            .or_else(|| find_attribute(&resource.attributes, key))
+                    };
+                    // NOTE: the pod names are unique only within their namespaces,
+                    // while the node-keyed samples are cluster-scoped
+                    let namespace = get_attribute(ATTRIBUTE_NAMESPACE);
+                    let (namespace, pod) = match get_attribute(ATTRIBUTE_POD) {
+                        Some(pod) => match namespace {
+                            Some(namespace) => (Some(namespace), pod),
+                            None => continue,
+                        },
+                        None => match get_attribute(ATTRIBUTE_NODE) {
+                            Some(node) => (namespace, node),
+                            None => continue,
+                        },
+                    };
+                    let Some(value) = data_point.value() else {
+                        continue;
+                    };
+
+                    let target = Target {
+                        namespace: namespace.map(Into::into),
+                        pod: pod.into(),
+                    };
+                    let sample = Sample {
+                        value,
+                        received_at: now,
+                    };
+                    samples.insert(target, sample);
+                    count += 1;
+                }
+            }
+        }
+
+        // Evict the stale samples
+        for samples in metrics.values_mut() {
+            samples.retain(|_, sample| now.duration_since(sample.received_at) < self.ttl);
+        }
+        metrics.retain(|_, samples| !samples.is_empty());
+        count
+    }
+}
+
+fn find_attribute<'a>(attributes: &'a [KeyValue], key: &str) -> Option<&'a str> {
+    attributes
+        .iter()
+        .find(|attribute| attribute.key == key)
+        .and_then(|attribute| attribute.value.string_value.as_deref())
+}
+
+/// Receives the metrics over OTLP/HTTP, encoded in JSON.
+#[cfg_attr(feature = "tracing", instrument(level = Level::INFO, skip_all))]
+#[post("")]
+pub(crate) async fn post(
+    request: HttpRequest,
+    sources: web::Data<Sources>,
+    web::Json(body): web::Json<ExportMetricsServiceRequest>,
+) -> impl Responder {
+    let store = sources.otlp();
+    if !store.is_enabled() {
+        return HttpResponse::NotFound().finish();
+    }
+
+    let authorization = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok());
+    if !store.authenticate(authorization) {
+        return HttpResponse::Unauthorized().finish();
+    }
+
+    store.record(body);
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body("{}")
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ExportMetricsServiceRequest {
+    #[serde(default)]
+    resource_metrics: Vec<ResourceMetrics>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ResourceMetrics {
+    #[serde(default)]
+    resource: Resource,
+    #[serde(default)]
+    scope_metrics: Vec<ScopeMetrics>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Resource {
+    #[serde(default)]
+    attributes: Vec<KeyValue>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ScopeMetrics {
+    #[serde(default)]
+    metrics: Vec<Metric>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Metric {
+    name: String,
+    #[serde(default)]
+    gauge: Option<NumberDataPoints>,
+    #[serde(default)]
+    sum: Option<NumberDataPoints>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NumberDataPoints {
+    #[serde(default)]
+    data_points: Vec<NumberDataPoint>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NumberDataPoint {
+    #[serde(default)]
+    attributes: Vec<KeyValue>,
+    #[serde(default)]
+    as_double: Option<f64>,
+    #[serde(default)]
+    as_int: Option<IntValue>,
+}
+
+impl NumberDataPoint {
+    fn value(&self) -> Option<f64> {
+        self.as_double
+            .or_else(|| match self.as_int.as_ref()? {
+                IntValue::Number(value) => Some(*value as f64),
+                IntValue::String(value) => value.parse::<i64>().ok().map(|value| value as f64),
+            })
+            .filter(|value| value.is_finite())
+    }
+}
+
+/// An int64 value, which is encoded as a string in JSON.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum IntValue {
+    Number(i64),
+    String(String),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct KeyValue {
+    key: String,
+    #[serde(default)]
+    value: AnyValue,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AnyValue {
+    #[serde(default)]
+    string_value: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REQUEST: &str = r#"{
+        "resourceMetrics": [
+            {
+                "resource": {
+                    "attributes": [
+                        {"key": "k8s.namespace.name", "value": {"stringValue": "default"}},
+                        {"key": "k8s.pod.name", "value": {"stringValue": "a"}}
+                    ]
+                },
+                "scopeMetrics": [
+                    {
+                        "metrics": [
+                            {
+                                "name": "gpu_util",
+                                "gauge": {"dataPoints": [{"asDouble": 0.5}]}
+                            },
+                            {
+                                "name": "queue_len",
+                                "sum": {
+                                    "dataPoints": [
+                                        {"asInt": "10"},
+                                        {
+                                            "asInt": "30",
+                                            "attributes": [
+                                                {"key": "k8s.pod.name", "value": {"stringValue": "b"}}
+                                            ]
+                                        }
+                                    ]
+                                }
+                            }
+                        ]
+                    }
+                ]
+            }
+        ]
+    }"#;
+
+    #[test]
+    fn record_samples() {
+        let store = MetricsStore::new(Duration::from_secs(60), None);
+        let request = ::serde_json::from_str(REQUEST).unwrap();
+        assert_eq!(store.record(request), 3);
+
+        let samples = store.query("default", "queue_len").unwrap();
+        assert_eq!(samples.len(), 2);
+        assert_eq!(samples.get("a"), Some(&10.0));
+        assert_eq!(samples.get("b"), Some(&30.0));
+
+        let samples = store.query("default", "gpu_util").unwrap();
+        assert_eq!(samples.get("a"), Some(&0.5));
+        assert!(store.query("kube-system", "gpu_util").unwrap().is_empty());
+        assert!(store.query("default", "mem_util").unwrap().is_empty());
+    }
+
+    #[test]
+    fn evict_stale_samples() {
+        let store = MetricsStore::new(Duration::ZERO, None);
+        let request = ::serde_json::from_str(REQUEST).unwrap();
+        store.record(request);
+        assert!(store.query("default", "gpu_util").unwrap().is_empty());
+    }
+
+    #[test]
+    fn authenticate_senders() {
+        let store = MetricsStore::new(Duration::from_secs(60), Some("secret".into()));
+        assert!(store.is_enabled());
+        assert!(store.authenticate(Some("Bearer secret")));
+        assert!(!store.authenticate(Some("Bearer secreT")));
+        assert!(!store.authenticate(Some("Bearer secret2")));
+        assert!(!store.authenticate(Some("secret")));
+        assert!(!store.authenticate(None));
+
+        let store = MetricsStore::new(Duration::from_secs(60), Some(String::new()));
+        assert!(!store.is_enabled());
+        assert!(!store.authenticate(Some("Bearer ")));
+    }
+
+    #[test]
+    fn drop_samples_without_namespace() {
+        let store = MetricsStore::new(Duration::from_secs(60), None);
+        let request = ::serde_json::from_str(
+            r#"{
+                "resourceMetrics": [
+                    {
+                        "resource": {
+                            "attributes": [
+                                {"key": "k8s.pod.name", "value": {"stringValue": "a"}}
+                            ]
+                        },
+                        "scopeMetrics": [
+                            {
+                                "metrics": [
+                                    {
+                                        "name": "gpu_util",
+                                        "gauge": {
+                                            "dataPoints": [
+                                                {"asDouble": 0.5},
+                                                {
+                                                    "asDouble": 0.25,
+                                                    "attributes": [
+                                                        {"key": "k8s.namespace.name", "value": {"stringValue": "default"}},
+                                                        {"key": "k8s.pod.name", "value": {"stringValue": "b"}}
+                                                    ]
+                                                }
+                                            ]
+                                        }
+                                    }
+                                ]
+                            }
+                        ]
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(store.record(request), 1);
+
+        let samples = store.query("default", "gpu_util").unwrap();
+        assert_eq!(samples.get("a"), None);
+        assert_eq!(samples.get("b"), Some(&0.25));
+    }
+
+    #[test]
+    fn reject_promql_query() {
+        let store = MetricsStore::new(Duration::from_secs(60), None);
+        let request = ::serde_json::from_str(REQUEST).unwrap();
+        store.record(request);
+
+        assert!(store.query("default", " gpu_util ").is_ok());
+        assert!(store.query("default", "avg by (pod) (gpu_util)").is_err());
+        assert!(
+            store
+                .query("default", r#"gpu_util{namespace="default"}"#)
+                .is_err()
+        );
+    }
+}
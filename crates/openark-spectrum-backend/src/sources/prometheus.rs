@@ -0,0 +1,26 @@
+use std::collections::HashMap;
+
+use anyhow::{Result, anyhow};
+use prometheus_http_query::Client;
+
+/// Evaluates a PromQL query, returning the samples by the pod (or node) names.
+pub(crate) async fn query_samples(client: &Client, query: String) -> Result<HashMap<String, f64>> {
+    let response = client.query(query).get().await?;
+
+    // Parse vector data
+    let (data, _stats) = response.into_inner();
+    let data = data
+        .into_vector()
+        .map_err(|_| anyhow!("Invalid PromQL query data"))?;
+
+    // Build a data map
+    Ok(data
+        .iter()
+        .filter_map(|vector| {
+            let metric = vector.metric();
+            let pod_name = metric.get("pod").or_else(|| metric.get("node"))?;
+            let sample = vector.sample().value();
+            Some((pod_name.clone(), sample))
+        })
+        .collect())
+}
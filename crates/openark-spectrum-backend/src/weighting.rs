@@ -0,0 +1,207 @@
+use std::collections::HashMap;
+
+use anyhow::{Result, anyhow};
+use openark_spectrum_api::metrics_class::{
+    MetricsMissingPolicy, MetricsNormalization, MetricsQuery, MetricsWeighting,
+};
+
+use crate::sources::Source;
+
+/// Evaluates the weighting formula for each pod.
+pub(crate) async fn evaluate(
+    source: &Source,
+    weighting: &MetricsWeighting,
+    namespace: &str,
+    service: &str,
+    pods: &[Option<&str>],
+) -> Result<Vec<Option<f64>>> {
+    let formula = weighting
+        .validate()
+        .map_err(|error| anyhow!("Invalid weighting: {error}"))?;
+
+    // Evaluate the queries
+    let mut samples = HashMap::with_capacity(weighting.metrics.len());
+    for metric in &weighting.metrics {
+        let query = metric
+            .query
+            .replace("$namespace", namespace)
+            .replace("$service", service);
+        let map = source.query_samples(namespace, query).await?;
+        samples.insert(metric.name.as_str(), collect_samples(metric, &map, pods));
+    }
+
+    // Combine the samples
+    Ok((0..pods.len())
+        .map(|index| formula.evaluate(&|name| samples.get(name).and_then(|values| values[index])))
+        .collect())
+}
+
+/// Applies the normalization and the missing-data policy of the metric.
+fn collect_samples(
+    metric: &MetricsQuery,
+    map: &HashMap<String, f64>,
+    pods: &[Option<&str>],
+) -> Vec<Option<f64>> {
+    let mut values: Vec<_> = pods
+        .iter()
+        .map(|pod| {
+            pod.and_then(|pod| map.get(pod))
+                .copied()
+                .filter(|value| value.is_finite())
+        })
+        .collect();
+
+    // Normalize the samples
+    let min = values
+        .iter()
+        .flatten()
+        .copied()
+        .fold(f64::INFINITY, f64::min);
+    let max = values
+        .iter()
+        .flatten()
+        .copied()
+        .fold(f64::NEG_INFINITY, f64::max);
+    for value in values.iter_mut().flatten() {
+        *value = match metric.normalization {
+            MetricsNormalization::None => *value,
+            MetricsNormalization::MinMax if max > min => (*value - min) / (max - min),
+            MetricsNormalization::MinMax => 0.0,
+            MetricsNormalization::Max if max > 0.0 => *value / max,
+            MetricsNormalization::Max => *value,
+        };
+    }
+
+    // Fill the missing samples
+    let fill = match metric.missing {
+        MetricsMissingPolicy::Unknown => None,
+        MetricsMissingPolicy::Zero => Some(0.0),
+        MetricsMissingPolicy::Mean => {
+            let known: Vec<_> = values.iter().flatten().copied().collect();
+            (!known.is_empty()).then(|| known.iter().sum::<f64>() / known.len() as f64)
+        }
+    };
+    values.into_iter().map(|value| value.or(fill)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{App, HttpRequest, HttpResponse, HttpServer, web};
+    use prometheus_http_query::Client;
+
+    use super::*;
+
+    /// Mocks the instant query API of Prometheus.
+    async fn query(request: HttpRequest, body: web::Bytes) -> HttpResponse {
+        // NOTE: the query may be given by either URL or form parameters
+        let params = format!(
+            "{}&{}",
+            request.query_string(),
+            String::from_utf8_lossy(&body),
+        );
+        let result = if params.contains("gpu_util") {
+            r#"[
+                {"metric": {"pod": "a"}, "value": [1700000000, "0.5"]},
+                {"metric": {"pod": "b"}, "value": [1700000000, "1.0"]}
+            ]"#
+        } else if params.contains("queue_len") {
+            r#"[
+                {"metric": {"pod": "a"}, "value": [1700000000, "10"]},
+                {"metric": {"pod": "c"}, "value": [1700000000, "30"]}
+            ]"#
+        } else {
+            "[]"
+        };
+        HttpResponse::Ok()
+            .content_type("application/json")
+            .body(format!(
+                r#"{{"status": "success", "data": {{"resultType": "vector", "result": {result}}}}}"#
+            ))
+    }
+
+    fn serve() -> Source {
+        let server = HttpServer::new(|| App::new().route("/api/v1/query", web::to(query)))
+            .workers(1)
+            .bind(("127.0.0.1", 0))
+            .expect("binding a mock server");
+        let addr = server.addrs()[0];
+        ::actix_web::rt::spawn(server.run());
+        Source::Prometheus(
+            Client::from(::reqwest::Client::new(), &format!("http://{addr}")).unwrap(),
+        )
+    }
+
+    fn define_metric(
+        name: &str,
+        normalization: MetricsNormalization,
+        missing: MetricsMissingPolicy,
+    ) -> MetricsQuery {
+        MetricsQuery {
+            name: name.into(),
+            query: format!(r#"{name}{{namespace="$namespace",service_name="$service"}}"#),
+            normalization,
+            missing,
+        }
+    }
+
+    #[::actix_web::test]
+    async fn evaluate_formula() {
+        let source = serve();
+        let weighting = MetricsWeighting {
+            formula: "0.5 * gpu_util + 0.25 * queue_len".into(),
+            metrics: vec![
+                define_metric(
+                    "gpu_util",
+                    MetricsNormalization::Max,
+                    MetricsMissingPolicy::Unknown,
+                ),
+                define_metric(
+                    "queue_len",
+                    MetricsNormalization::MinMax,
+                    MetricsMissingPolicy::Mean,
+                ),
+            ],
+        };
+        let pods = [Some("a"), Some("b"), Some("c"), None];
+
+        let weights = evaluate(&source, &weighting, "default", "test", &pods)
+            .await
+            .unwrap();
+        assert_eq!(weights, &[Some(0.25), Some(0.625), None, None]);
+    }
+
+    #[::actix_web::test]
+    async fn evaluate_missing_samples() {
+        let source = serve();
+        let weighting = MetricsWeighting {
+            formula: "mem_util + 1".into(),
+            metrics: vec![define_metric(
+                "mem_util",
+                MetricsNormalization::None,
+                MetricsMissingPolicy::Zero,
+            )],
+        };
+        let pods = [Some("a"), Some("b")];
+
+        let weights = evaluate(&source, &weighting, "default", "test", &pods)
+            .await
+            .unwrap();
+        assert_eq!(weights, &[Some(1.0), Some(1.0)]);
+    }
+
+    #[::actix_web::test]
+    async fn reject_invalid_weighting() {
+        let source = serve();
+        let weighting = MetricsWeighting {
+            formula: "gpu_util * mem_util".into(),
+            metrics: vec![define_metric(
+                "gpu_util",
+                MetricsNormalization::None,
+                MetricsMissingPolicy::Unknown,
+            )],
+        };
+
+        let result = evaluate(&source, &weighting, "default", "test", &[Some("a")]).await;
+        assert!(result.is_err());
+    }
+}
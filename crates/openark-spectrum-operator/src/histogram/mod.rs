@@ -1,6 +1,10 @@
 mod service;
 
-use std::{sync::Arc, time::Duration};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
 use anyhow::Result;
 use futures::StreamExt;
@@ -25,8 +29,8 @@ use tracing::{Level, info, instrument};
 
 use crate::{
     status::{Reason, Status},
-    targets::{Target, get_target},
-    utils::get_metrics_class,
+    targets::{PoolTarget, get_target},
+    utils::{get_metrics_class, histogram::HistogramState, patch_finalizer},
 };
 
 struct Context {
@@ -36,9 +40,19 @@ struct Context {
     label_parent: String,
     label_weight: String,
     post_params: PostParams,
+    /// The states of the histograms, e.g. the smoothed weights
+    states: Mutex<BTreeMap<String, HistogramState>>,
     status: ::openark_core::operator::Context,
 }
 
+/// Keeps the histograms until their states are forgotten.
+const FINALIZER: &str = "org.ulagbulag.io/spectrum-histogram";
+
+/// Returns the key of the histogram states.
+fn state_key(namespace: &str, name: &str) -> String {
+    format!("{namespace}/{name}")
+}
+
 #[cfg_attr(feature = "tracing", instrument(level = Level::INFO, skip_all))]
 async fn reconcile(hist: Arc<HistogramCrd>, ctx: Arc<Context>) -> Result<Action, Error> {
     let metadata = &hist.metadata;
@@ -47,11 +61,24 @@ async fn reconcile(hist: Arc<HistogramCrd>, ctx: Arc<Context>) -> Result<Action,
     let HistogramSpec {
         metrics_class_name,
         target_ref,
+        target_selector,
         histogram: settings,
     } = &hist.spec;
 
-    // Define status update function
+    // Forget the states of the deleted histogram
     let api = Api::namespaced(ctx.kube.clone(), namespace);
+    let pp = &ctx.status.patch_params;
+    if metadata.deletion_timestamp.is_some() {
+        ctx.states
+            .lock()
+            .unwrap()
+            .remove(&state_key(namespace, &name));
+        patch_finalizer(&api, &*hist, pp, FINALIZER, false).await?;
+        return Ok(Action::await_change());
+    }
+    patch_finalizer(&api, &*hist, pp, FINALIZER, true).await?;
+
+    // Define status update function
     let commit = |status: Status| {
         let api = &api;
         let object = &*hist;
@@ -74,10 +101,11 @@ async fn reconcile(hist: Arc<HistogramCrd>, ctx: Arc<Context>) -> Result<Action,
     };
 
     // Validate target
-    let (target_metadata, target_spec) = match get_target(&ctx.kube, namespace, target_ref).await? {
-        Ok(list) => list,
-        Err(action) => return commit(action).await,
-    };
+    let (target_metadata, target) =
+        match get_target(&ctx.kube, namespace, target_ref, target_selector.as_ref()).await? {
+            Ok(list) => list,
+            Err(action) => return commit(action).await,
+        };
 
     // Build owner references
     let child_owner_references = vec![OwnerReference {
@@ -111,25 +139,24 @@ async fn reconcile(hist: Arc<HistogramCrd>, ctx: Arc<Context>) -> Result<Action,
     };
 
     // Create/Update target resources
-    match match target_spec {
-        Target::Service(target_spec) => {
-            let child_ctx = self::service::Context {
-                child_metadata,
-                class,
-                client: &ctx.client,
-                kube: &ctx.kube,
-                label_parent: &ctx.label_parent,
-                label_weight: &ctx.label_weight,
-                name: &name,
-                namespace,
-                post_params: &ctx.post_params,
-                settings,
-                target_metadata,
-                target_spec,
-            };
-            self::service::update_resources(child_ctx).await?
-        }
-    } {
+    let child_ctx = self::service::Context {
+        child_metadata,
+        class,
+        client: &ctx.client,
+        kube: &ctx.kube,
+        label_parent: &ctx.label_parent,
+        label_weight: &ctx.label_weight,
+        name: &name,
+        namespace,
+        post_params: &ctx.post_params,
+        settings,
+        state_key: state_key(namespace, &name),
+        states: &ctx.states,
+        target: &target,
+        target_metadata,
+        target_spec: target.service_spec(),
+    };
+    match self::service::update_resources(child_ctx).await? {
         // Completed provisioning
         Ok(()) => commit_ok().await,
         Err(action) => commit(action).await,
@@ -186,6 +213,7 @@ pub(crate) async fn loop_forever(
         label_weight: args.label_histogram_weight,
         kube,
         post_params,
+        states: Mutex::default(),
         status: ::openark_core::operator::Context {
             interval: Duration::from_secs(30),
             patch_params,
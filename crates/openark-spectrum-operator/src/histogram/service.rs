@@ -1,8 +1,12 @@
-use std::collections::BTreeSet;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Mutex,
+};
 
+use jiff::Timestamp;
 use k8s_openapi::api::{
     core::v1::{Service, ServiceSpec},
-    discovery::v1::{EndpointPort, EndpointSlice},
+    discovery::v1::{Endpoint, EndpointPort, EndpointSlice},
 };
 use kube::{
     Api, Client, ResourceExt, Result,
@@ -15,11 +19,14 @@ use tracing::{Level, instrument};
 
 use crate::{
     status::Status,
-    targets::service::{
-        Context as TargetContext, LABEL_KEY_SELECTOR, get_weighted_endpoints, infer_address_type,
-        mirror_spec,
+    targets::{
+        Target,
+        service::{
+            Context as TargetContext, LABEL_KEY_SELECTOR, get_weighted_endpoints,
+            infer_address_type, mirror_spec,
+        },
     },
-    utils::histogram::Histogram,
+    utils::histogram::{Histogram, HistogramState},
 };
 
 pub(super) struct Context<'a> {
@@ -33,10 +40,22 @@ pub(super) struct Context<'a> {
     pub(super) namespace: &'a str,
     pub(super) post_params: &'a PostParams,
     pub(super) settings: &'a HistogramSettings,
+    pub(super) state_key: String,
+    pub(super) states: &'a Mutex<BTreeMap<String, HistogramState>>,
+    pub(super) target: &'a Target,
     pub(super) target_metadata: ObjectMeta,
     pub(super) target_spec: Option<ServiceSpec>,
 }
 
+/// Returns the key of the endpoint, which identifies it over the reconciliations.
+fn endpoint_key(endpoint: &Endpoint) -> &str {
+    endpoint
+        .addresses
+        .first()
+        .expect("conciled endpoint")
+        .as_str()
+}
+
 #[cfg_attr(feature = "tracing", instrument(level = Level::INFO, skip_all))]
 pub(super) async fn update_resources(ctx: Context<'_>) -> Result<Result<(), Status>> {
     let Context {
@@ -50,19 +69,24 @@ pub(super) async fn update_resources(ctx: Context<'_>) -> Result<Result<(), Stat
         namespace,
         post_params,
         settings,
+        state_key,
+        states,
+        target,
         target_metadata,
         target_spec,
     } = ctx;
 
     // Fetch items
     let address_type = infer_address_type(target_spec.as_ref());
-    let WeightedItems { items, weights } = match get_weighted_endpoints(TargetContext {
+    let WeightedItems { items, weights, .. } = match get_weighted_endpoints(TargetContext {
         address_type,
         child_metadata: &child_metadata,
         class: &class,
         client,
         kube,
+        target,
         target_metadata: &target_metadata,
+        with_topologies: false,
     })
     .await?
     {
@@ -71,7 +95,13 @@ pub(super) async fn update_resources(ctx: Context<'_>) -> Result<Result<(), Stat
     };
 
     // Calculate histogram
-    let Histogram { data } = match Histogram::build(settings, &items, weights) {
+    let hist = {
+        let mut states = states.lock().unwrap();
+        let state = states.entry(state_key).or_default();
+        let now = Timestamp::now();
+        Histogram::build(settings, state, now, &items, endpoint_key, weights)
+    };
+    let Histogram { data } = match hist {
         Ok(hist) => hist,
         Err(error) => return Ok(Err(error)),
     };
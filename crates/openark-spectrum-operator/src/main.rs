@@ -26,6 +26,9 @@ struct Args {
     #[arg(long, env = "OPENARK_LABEL_SPECTRUM_HISTOGRAM_WEIGHT")]
     label_histogram_weight: String,
 
+    #[arg(long, env = "OPENARK_LABEL_SPECTRUM_POOL_CLAIM_LIFECYCLE_POST_STOP")]
+    label_pool_claim_lifecycle_post_stop: String,
+
     #[arg(long, env = "OPENARK_LABEL_SPECTRUM_POOL_CLAIM_LIFECYCLE_PRE_START")]
     label_pool_claim_lifecycle_pre_start: String,
 
@@ -47,6 +50,9 @@ struct Args {
     #[arg(long, env = "OPENARK_LABEL_SPECTRUM_POOL_CLAIM_WEIGHT_MIN")]
     label_pool_claim_weight_min: String,
 
+    #[arg(long, env = "OPENARK_LABEL_SPECTRUM_POOL_BIND")]
+    label_pool_bind: String,
+
     #[arg(long, env = "OPENARK_LABEL_SPECTRUM_POOL_PARENT")]
     label_pool_parent: String,
 
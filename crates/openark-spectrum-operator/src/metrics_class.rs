@@ -2,7 +2,10 @@ use std::{sync::Arc, time::Duration};
 
 use anyhow::Result;
 use futures::StreamExt;
-use k8s_openapi::{Resource, api::core::v1::Service};
+use k8s_openapi::{
+    Resource,
+    api::core::v1::{ConfigMap, Service},
+};
 use kube::{
     Api, Client, Error,
     api::{PatchParams, ValidationDirective},
@@ -101,6 +104,8 @@ async fn reconcile(class: Arc<MetricsClassCrd>, ctx: Arc<Context>) -> Result<Act
         controller_name,
         description: _,
         backend_ref,
+        source,
+        weighting,
     } = &class.spec;
 
     // Skip if the controller name has mismatched
@@ -133,6 +138,21 @@ async fn reconcile(class: Arc<MetricsClassCrd>, ctx: Arc<Context>) -> Result<Act
         })
     };
 
+    // Validate weighting
+    if let Some(weighting) = weighting
+        && let Err(error) = weighting.validate().and_then(|_| match source {
+            Some(source) => weighting.validate_queries(*source),
+            None => Ok(()),
+        })
+    {
+        return commit(Status {
+            reason: Reason::InvalidMetricsClass,
+            message: format!("Invalid weighting: {error}"),
+            requeue: false,
+        })
+        .await;
+    }
+
     // Validate backend service
     let ServiceReference {
         object:
@@ -160,6 +180,23 @@ async fn reconcile(class: Arc<MetricsClassCrd>, ctx: Arc<Context>) -> Result<Act
                 }
             }
         }
+        (ConfigMap::GROUP, ConfigMap::KIND, _) => {
+            // NOTE: the static weights are in the namespace of each target if not given
+            match namespace.as_deref() {
+                Some(namespace) => {
+                    let api = Api::<ConfigMap>::namespaced(ctx.kube.clone(), namespace);
+                    match api.get_opt(name).await? {
+                        Some(_) => commit_ok().await,
+                        None => {
+                            let message = format!("Missing backend: {kind}/{namespace}/{name}");
+                            let requeue = true;
+                            commit_invalid_backend_ref(message, requeue).await
+                        }
+                    }
+                }
+                None => commit_ok().await,
+            }
+        }
         (Service::GROUP, Service::KIND, None) => {
             let message = format!("Required backend namespace: {kind}/???/{name}");
             let requeue = false;
@@ -26,14 +26,15 @@ use url::Url;
 
 use crate::{
     status::{Reason, Status},
-    targets::{Target, get_target},
-    utils::get_metrics_class,
+    targets::{BindLabels, PoolTarget, get_target},
+    utils::{get_metrics_class, patch_finalizer},
 };
 
 struct Context {
     api_class: Api<MetricsClassCrd>,
     client: ::reqwest::Client,
     kube: Client,
+    label_bind: String,
     label_claim_parent: String,
     label_parent: String,
     pool_base_url: Url,
@@ -41,6 +42,9 @@ struct Context {
     status: ::openark_core::operator::Context,
 }
 
+/// Keeps the pools until their resources are released.
+const FINALIZER: &str = "org.ulagbulag.io/spectrum-pool";
+
 #[cfg_attr(feature = "tracing", instrument(level = Level::INFO, skip_all))]
 async fn reconcile(pool: Arc<PoolCrd>, ctx: Arc<Context>) -> Result<Action, Error> {
     let metadata = &pool.metadata;
@@ -49,10 +53,34 @@ async fn reconcile(pool: Arc<PoolCrd>, ctx: Arc<Context>) -> Result<Action, Erro
     let PoolSpec {
         metrics_class_name,
         target_ref,
+        target_selector,
+        solver,
     } = &pool.spec;
 
-    // Define status update function
+    let pool_uid = pool.uid().unwrap_or_default();
+    let bind_labels = BindLabels {
+        claim: &ctx.label_claim_parent,
+        pool: &ctx.label_bind,
+        pool_uid: &pool_uid,
+    };
+
+    // Release the resources of the deleted pool
     let api = Api::namespaced(ctx.kube.clone(), namespace);
+    let pp = &ctx.status.patch_params;
+    if metadata.deletion_timestamp.is_some() {
+        // NOTE: the resources cannot be found if the target is gone, e.g. a deleted Deployment
+        if let Ok((_, target)) =
+            get_target(&ctx.kube, namespace, target_ref, target_selector.as_ref()).await?
+        {
+            let bindings = Default::default();
+            target.bind(&ctx.kube, pp, &bind_labels, &bindings).await?;
+        }
+        patch_finalizer(&api, &*pool, pp, FINALIZER, false).await?;
+        return Ok(Action::await_change());
+    }
+    patch_finalizer(&api, &*pool, pp, FINALIZER, true).await?;
+
+    // Define status update function
     let commit = |status: Status| {
         let api = &api;
         let object = &*pool;
@@ -75,10 +103,11 @@ async fn reconcile(pool: Arc<PoolCrd>, ctx: Arc<Context>) -> Result<Action, Erro
     };
 
     // Validate target
-    let (target_metadata, target_spec) = match get_target(&ctx.kube, namespace, target_ref).await? {
-        Ok(list) => list,
-        Err(action) => return commit(action).await,
-    };
+    let (target_metadata, target) =
+        match get_target(&ctx.kube, namespace, target_ref, target_selector.as_ref()).await? {
+            Ok(list) => list,
+            Err(action) => return commit(action).await,
+        };
 
     // Build owner references
     let child_owner_references = vec![OwnerReference {
@@ -112,25 +141,24 @@ async fn reconcile(pool: Arc<PoolCrd>, ctx: Arc<Context>) -> Result<Action, Erro
     };
 
     // Create/Update target resources
-    match match target_spec {
-        Target::Service(target_spec) => {
-            let child_ctx = self::service::Context {
-                child_metadata,
-                class,
-                client: &ctx.client,
-                kube: &ctx.kube,
-                label_claim_parent: &ctx.label_claim_parent,
-                label_parent: &ctx.label_parent,
-                name: &name,
-                namespace,
-                pool_url: &ctx.pool_base_url,
-                post_params: &ctx.post_params,
-                target_metadata,
-                target_spec,
-            };
-            self::service::update_resources(child_ctx).await?
-        }
-    } {
+    let child_ctx = self::service::Context {
+        bind_labels: &bind_labels,
+        child_metadata,
+        class,
+        client: &ctx.client,
+        kube: &ctx.kube,
+        label_claim_parent: &ctx.label_claim_parent,
+        label_parent: &ctx.label_parent,
+        name: &name,
+        namespace,
+        pool_url: &ctx.pool_base_url,
+        post_params: &ctx.post_params,
+        solver: *solver,
+        target: &target,
+        target_metadata,
+        target_spec: target.service_spec(),
+    };
+    match self::service::update_resources(child_ctx).await? {
         // Completed provisioning
         Ok(()) => commit_ok().await,
         Err(action) => commit(action).await,
@@ -184,6 +212,7 @@ pub async fn loop_forever(
         api_class,
         client,
         kube,
+        label_bind: args.label_pool_bind,
         label_claim_parent: args.label_pool_claim_parent,
         label_parent: args.label_pool_parent,
         pool_base_url: args.pool_base_url,
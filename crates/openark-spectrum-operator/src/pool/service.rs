@@ -1,35 +1,46 @@
-use std::{borrow::Cow, collections::BTreeSet};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet},
+};
 
+use jiff::Timestamp;
 use k8s_openapi::api::{
     core::v1::{Service, ServiceSpec},
     discovery::v1::{EndpointPort, EndpointSlice},
 };
 use kube::{
     Api, Client, ResourceExt, Result,
-    api::{DeleteParams, ListParams, ObjectMeta, PostParams},
+    api::{DeleteParams, ListParams, ObjectMeta, Patch, PatchParams, PostParams},
 };
 use openark_spectrum_api::{
     client::PoolClient,
     metrics_class::MetricsClassCrd,
-    pool_claim::{PoolClaimCrd, PoolClaimSpec},
+    pool::PoolSolver,
+    pool_claim::{PoolClaimCrd, PoolClaimPreemption, PoolClaimResourcesStatus, PoolClaimSpec},
     schema::{PoolCommitRequest, PoolCommitRequestItem, PoolRequest, PoolResource, PoolResponse},
 };
 use openark_spectrum_scheduler::{
     item::{Item, Resource, ScheduledItem},
     schedule,
 };
+use serde_json::json;
 #[cfg(feature = "tracing")]
 use tracing::{Level, instrument};
 use url::Url;
 
 use crate::{
     status::{Reason, Status},
-    targets::service::{
-        Context as TargetContext, LABEL_KEY_SELECTOR, get_weighted_endpoints, infer_address_type,
+    targets::{
+        BindLabels, PoolTarget, Target,
+        service::{
+            Context as TargetContext, LABEL_KEY_SELECTOR, get_weighted_endpoints,
+            infer_address_type,
+        },
     },
 };
 
 pub(super) struct Context<'a> {
+    pub(super) bind_labels: &'a BindLabels<'a>,
     pub(super) child_metadata: ObjectMeta,
     pub(super) class: MetricsClassCrd,
     pub(super) client: &'a ::reqwest::Client,
@@ -40,6 +51,8 @@ pub(super) struct Context<'a> {
     pub(super) namespace: &'a str,
     pub(super) pool_url: &'a Url,
     pub(super) post_params: &'a PostParams,
+    pub(super) solver: PoolSolver,
+    pub(super) target: &'a Target,
     pub(super) target_metadata: ObjectMeta,
     pub(super) target_spec: Option<ServiceSpec>,
 }
@@ -51,6 +64,7 @@ pub(super) struct Context<'a> {
 ))]
 pub(super) async fn update_resources(ctx: Context<'_>) -> Result<Result<(), Status>> {
     let Context {
+        bind_labels,
         child_metadata,
         class,
         client,
@@ -61,19 +75,37 @@ pub(super) async fn update_resources(ctx: Context<'_>) -> Result<Result<(), Stat
         namespace,
         pool_url,
         post_params,
+        solver,
+        target,
         target_metadata,
         target_spec,
     } = ctx;
 
+    // Fetch all claims
+    let api_claims = Api::<PoolClaimCrd>::namespaced(kube.clone(), namespace);
+    let list_params = ListParams {
+        field_selector: Some(format!(
+            "spec.{key}={name}",
+            key = PoolClaimSpec::FIELD_POOL_NAME,
+        )),
+        ..Default::default()
+    };
+    let claims = api_claims.list(&list_params).await?.items;
+
     // Fetch items
     let address_type = infer_address_type(target_spec.as_ref());
+    let with_topologies = claims
+        .iter()
+        .any(|claim| claim.spec.resources.has_topology_constraints());
     let resources = match get_weighted_endpoints(TargetContext {
         address_type,
         child_metadata: &child_metadata,
         class: &class,
         client,
         kube,
+        target,
         target_metadata: &target_metadata,
+        with_topologies,
     })
     .await?
     {
@@ -81,17 +113,6 @@ pub(super) async fn update_resources(ctx: Context<'_>) -> Result<Result<(), Stat
         Err(error) => return Ok(Err(error)),
     };
 
-    // Fetch all claims
-    let api_claims = Api::<PoolClaimCrd>::namespaced(kube.clone(), namespace);
-    let list_params = ListParams {
-        field_selector: Some(format!(
-            "spec.{key}={name}",
-            key = PoolClaimSpec::FIELD_POOL_NAME,
-        )),
-        ..Default::default()
-    };
-    let claims = api_claims.list(&list_params).await?.items;
-
     // Fetch all claimed resources
     let api_services = Api::<Service>::namespaced(kube.clone(), namespace);
     let list_params = ListParams {
@@ -112,37 +133,32 @@ pub(super) async fn update_resources(ctx: Context<'_>) -> Result<Result<(), Stat
 
             Some(Item {
                 claim: Cow::Borrowed(claim),
-                resource: Resource {
-                    penalty: claim.spec.resources.penalty.unwrap_or(0.0),
-                    priority: claim.spec.resources.priority.unwrap_or(0),
-                    min: claim.spec.resources.min,
-                    max: claim.spec.resources.max,
-                    weight: claim.spec.resources.weight.unwrap_or(1),
-                },
+                resource: Resource::from(&claim.spec.resources),
                 item,
             })
         })
         .collect();
 
     // Fetch binding states
-    let args = PoolRequest {
+    // NOTE: the resources are owned to release the stale bindings after scheduling
+    let pool = PoolRequest {
         resources: resources
             .items
             .iter()
             .map(|endpoint| {
-                Cow::Borrowed(
+                Cow::Owned(
                     endpoint
                         .addresses
                         .first()
                         .expect("conciled endpoint")
-                        .as_str(),
+                        .clone(),
                 )
             })
             .collect(),
         namespace: namespace.into(),
     };
     let PoolResponse { bound } = match client
-        .get_service_binding_states(pool_url.clone(), &args)
+        .get_service_binding_states(pool_url.clone(), &pool)
         .await
     {
         Ok(items) => items,
@@ -171,7 +187,8 @@ pub(super) async fn update_resources(ctx: Context<'_>) -> Result<Result<(), Stat
         .collect();
 
     // Allocate resources into items
-    let items = match schedule(items, bound, resources) {
+    let claim_names: Vec<_> = items.iter().map(|item| item.claim.name_any()).collect();
+    let items = match schedule(items, bound, resources, solver) {
         Ok(items) => items,
         Err(error) => {
             return Ok(Err(Status {
@@ -208,6 +225,7 @@ pub(super) async fn update_resources(ctx: Context<'_>) -> Result<Result<(), Stat
                 priority: item.priority,
             })
             .collect(),
+        pool: Some(pool),
     };
     match client
         .commit_service_binding_states(pool_url.clone(), &args)
@@ -223,6 +241,90 @@ pub(super) async fn update_resources(ctx: Context<'_>) -> Result<Result<(), Stat
         }
     };
 
+    // Mark the resources as bound to the claims
+    let patch_params = PatchParams {
+        field_manager: post_params.field_manager.clone(),
+        ..Default::default()
+    };
+    let bindings: BTreeMap<_, _> = items
+        .iter()
+        .flat_map(|item| {
+            let claim_name = item.item.name_any();
+            item.resources
+                .iter()
+                .filter_map(|endpoint| endpoint.target_ref.as_ref()?.name.clone())
+                .map(move |name| (name, claim_name.clone()))
+        })
+        .collect();
+    // NOTE: the bindings have already been committed to the pool, which is the source of truth.
+    //       The marks are re-applied on every reconcile, so a failure is reported after
+    //       the other resources are in sync, and then retried.
+    let bind_result = target
+        .bind(kube, &patch_params, bind_labels, &bindings)
+        .await;
+
+    // Report the scheduling results to the claims
+    let now = Timestamp::now();
+    for (claim_name, item) in claim_names.iter().zip(&items) {
+        let Some(claim) = claims
+            .iter()
+            .find(|claim| claim.metadata.name.as_deref() == Some(claim_name.as_str()))
+        else {
+            continue;
+        };
+        let last = claim
+            .status
+            .as_ref()
+            .and_then(|status| status.resources.as_ref());
+
+        // NOTE: the last preemption is kept until the claim gets satisfied again
+        let preemption = if !item.evictions.is_empty() {
+            let preemptors: BTreeSet<_> = item
+                .evictions
+                .iter()
+                .filter_map(|eviction| eviction.preemptor)
+                .map(|index| claim_names[index].clone())
+                .collect();
+            Some(PoolClaimPreemption {
+                evicted: item.evictions.len(),
+                preemptors: preemptors.into_iter().collect(),
+                timestamp: now,
+            })
+        } else if !claim.spec.resources.is_satisfied(item.load) {
+            last.and_then(|last| last.preemption.clone())
+        } else {
+            None
+        };
+
+        let status = PoolClaimResourcesStatus {
+            bound: item.resources.len(),
+            load: item.load,
+            unsatisfiable: item.unsatisfiable,
+            preemption,
+            last_updated: now,
+        };
+
+        // Skip updating status if nothing has been changed
+        if let Some(last) = last
+            && *last
+                == (PoolClaimResourcesStatus {
+                    last_updated: last.last_updated,
+                    ..status.clone()
+                })
+        {
+            continue;
+        }
+
+        let patch = Patch::Merge(json!({
+            "status": {
+                "resources": status,
+            },
+        }));
+        api_claims
+            .patch_status(claim_name, &patch_params, &patch)
+            .await?;
+    }
+
     // Poll existing resources
     let api_endpointslices = Api::<EndpointSlice>::namespaced(kube.clone(), namespace);
     let last_endpointslices = api_endpointslices.list(&list_params).await?.items;
@@ -239,10 +341,8 @@ pub(super) async fn update_resources(ctx: Context<'_>) -> Result<Result<(), Stat
     for (
         child_name,
         ScheduledItem {
-            lifecycle: _,
-            item: _,
-            priority: _,
             resources: endpoints,
+            ..
         },
     ) in children_names.iter().zip(items)
     {
@@ -299,5 +399,13 @@ pub(super) async fn update_resources(ctx: Context<'_>) -> Result<Result<(), Stat
             api_endpointslices.delete(&name, &delete_params).await?;
         }
     }
+
+    if let Err(error) = bind_result {
+        return Ok(Err(Status {
+            reason: Reason::InvalidPool,
+            message: format!("Failed to bind resources: {error}"),
+            requeue: true,
+        }));
+    }
     Ok(Ok(()))
 }
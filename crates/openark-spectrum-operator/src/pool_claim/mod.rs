@@ -4,7 +4,8 @@ use std::{sync::Arc, time::Duration};
 
 use anyhow::Result;
 use futures::StreamExt;
-use k8s_openapi::apimachinery::pkg::apis::meta::v1::OwnerReference;
+use jiff::Timestamp;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{Condition, OwnerReference, Time};
 use kube::{
     Api, Client, Error, Resource, ResourceExt,
     api::{ObjectMeta, PatchParams, PostParams, ValidationDirective},
@@ -18,19 +19,23 @@ use kube::{
 use openark_core::operator::RecorderExt;
 use openark_spectrum_api::{
     pool::{PoolCrd, PoolSpec},
-    pool_claim::{PoolClaimCrd, PoolClaimSpec, PoolResourceLifecycle, PoolResourceSettings},
+    pool_claim::{
+        PoolClaimCrd, PoolClaimResourcesStatus, PoolClaimSpec, PoolClaimStatus,
+        PoolResourceLifecycle, PoolResourceSettings,
+    },
 };
 #[cfg(feature = "tracing")]
 use tracing::{Level, info, instrument};
 
 use crate::{
     status::{Reason, Status},
-    targets::{Target, get_target},
+    targets::{PoolTarget, get_target},
     utils::get_pool,
 };
 
 struct Context {
     kube: Client,
+    label_lifecycle_post_stop: String,
     label_lifecycle_pre_start: String,
     label_parent: String,
     label_pool_parent: String,
@@ -50,7 +55,10 @@ async fn reconcile(claim: Arc<PoolClaimCrd>, ctx: Arc<Context>) -> Result<Action
     let namespace = metadata.namespace.as_deref().expect("Namespaced resource");
     let PoolClaimSpec {
         pool_name,
-        lifecycle: PoolResourceLifecycle { pre_start },
+        lifecycle: PoolResourceLifecycle {
+            pre_start,
+            post_stop,
+        },
         resources:
             PoolResourceSettings {
                 penalty,
@@ -58,6 +66,8 @@ async fn reconcile(claim: Arc<PoolClaimCrd>, ctx: Arc<Context>) -> Result<Action
                 weight,
                 max,
                 min,
+                anti_affinity: _,
+                topology_spread_constraints: _,
             },
     } = &claim.spec;
 
@@ -69,23 +79,29 @@ async fn reconcile(claim: Arc<PoolClaimCrd>, ctx: Arc<Context>) -> Result<Action
         ctx.status.commit(api, object, status)
     };
 
-    // Define accept function
+    // Define accept function, publishing the scheduling results
     let commit_ok = || {
-        commit(Status {
+        let status = Status {
             reason: Reason::Accepted,
             message: "Valid PoolClaim".into(),
             requeue: false,
-        })
+        };
+        let conditions = build_resource_conditions(&claim);
+        ctx.status
+            .commit_with_conditions(&api, &*claim, status, conditions)
     };
 
     // Validate pool
     let api_pool = Api::namespaced(ctx.kube.clone(), namespace);
     let PoolCrd {
         metadata: _,
-        spec: PoolSpec {
-            metrics_class_name: _,
-            target_ref,
-        },
+        spec:
+            PoolSpec {
+                metrics_class_name: _,
+                target_ref,
+                target_selector,
+                solver: _,
+            },
         status: _,
     } = match get_pool(&api_pool, pool_name).await? {
         Ok(pool) => pool,
@@ -93,11 +109,11 @@ async fn reconcile(claim: Arc<PoolClaimCrd>, ctx: Arc<Context>) -> Result<Action
     };
 
     // Validate target
-    let (target_metadata, target_spec) = match get_target(&ctx.kube, namespace, &target_ref).await?
-    {
-        Ok(list) => list,
-        Err(action) => return commit(action).await,
-    };
+    let (target_metadata, target) =
+        match get_target(&ctx.kube, namespace, &target_ref, target_selector.as_ref()).await? {
+            Ok(list) => list,
+            Err(action) => return commit(action).await,
+        };
 
     // Build owner references
     let child_owner_references = vec![OwnerReference {
@@ -143,6 +159,10 @@ async fn reconcile(claim: Arc<PoolClaimCrd>, ctx: Arc<Context>) -> Result<Action
             map.insert(ctx.label_pool_parent.clone(), pool_name.clone());
             // lifecycle
             {
+                map.insert(
+                    ctx.label_lifecycle_post_stop.clone(),
+                    post_stop.len().to_string(),
+                );
                 map.insert(
                     ctx.label_lifecycle_pre_start.clone(),
                     pre_start.len().to_string(),
@@ -163,25 +183,136 @@ async fn reconcile(claim: Arc<PoolClaimCrd>, ctx: Arc<Context>) -> Result<Action
     };
 
     // Create/Update a target resource
-    match match target_spec {
-        Target::Service(target_spec) => {
-            let child_ctx = self::service::Context {
-                child_metadata,
-                kube: &ctx.kube,
-                name: &name,
-                namespace,
-                post_params: &ctx.post_params,
-                target_spec,
-            };
-            self::service::update_resources(child_ctx).await?
-        }
-    } {
+    let child_ctx = self::service::Context {
+        child_metadata,
+        kube: &ctx.kube,
+        name: &name,
+        namespace,
+        post_params: &ctx.post_params,
+        target_spec: target.service_spec(),
+    };
+    match self::service::update_resources(child_ctx).await? {
         // Completed provisioning
         Ok(()) => commit_ok().await,
         Err(action) => commit(action).await,
     }
 }
 
+/// Builds the conditions of the last scheduling results.
+fn build_resource_conditions(claim: &PoolClaimCrd) -> Vec<Condition> {
+    let settings = &claim.spec.resources;
+    let PoolResourceSettings { min, max, .. } = *settings;
+    let last_status = claim.status.as_ref();
+    let generation = claim.metadata.generation;
+
+    let build = |type_: &str, status: Option<bool>, reason: &str, message: String| {
+        let status = match status {
+            Some(true) => "True",
+            Some(false) => "False",
+            None => "Unknown",
+        };
+
+        // Keep the transition time if the status has not been changed
+        let last_transition_time = last_status
+            .and_then(|last| {
+                last.conditions
+                    .iter()
+                    .find(|condition| condition.type_ == type_ && condition.status == status)
+            })
+            .map(|condition| condition.last_transition_time.clone())
+            .unwrap_or_else(|| Time(Timestamp::now()));
+
+        Condition {
+            last_transition_time,
+            message,
+            observed_generation: generation,
+            reason: reason.into(),
+            status: status.into(),
+            type_: type_.into(),
+        }
+    };
+
+    let Some(PoolClaimResourcesStatus {
+        bound,
+        load,
+        unsatisfiable,
+        preemption,
+        last_updated: _,
+    }) = last_status.and_then(|status| status.resources.as_ref())
+    else {
+        return vec![build(
+            PoolClaimStatus::CONDITION_SATISFIED,
+            None,
+            "Pending",
+            "Waiting for scheduling".into(),
+        )];
+    };
+
+    let format_limit = |limit: Option<f64>| match limit {
+        Some(limit) => limit.to_string(),
+        None => "-".into(),
+    };
+    let summary = format!(
+        "Bound {bound} resources (load: {load}, min: {min}, max: {max})",
+        min = format_limit(min),
+        max = format_limit(max),
+    );
+
+    let is_satisfied = settings.is_satisfied(*load);
+    let mut conditions = vec![
+        build(
+            PoolClaimStatus::CONDITION_SATISFIED,
+            Some(is_satisfied),
+            if is_satisfied {
+                "Satisfied"
+            } else {
+                "InsufficientResources"
+            },
+            summary.clone(),
+        ),
+        build(
+            PoolClaimStatus::CONDITION_UNSATISFIABLE,
+            Some(*unsatisfiable),
+            if *unsatisfiable {
+                "InsufficientPool"
+            } else {
+                "Satisfiable"
+            },
+            summary,
+        ),
+    ];
+
+    // NOTE: the last preemption is kept until the claim gets satisfied again
+    conditions.push(match preemption.as_ref().filter(|_| !is_satisfied) {
+        Some(preemption) => build(
+            PoolClaimStatus::CONDITION_PREEMPTED,
+            Some(true),
+            "Preempted",
+            if preemption.preemptors.is_empty() {
+                format!(
+                    "Released {evicted} resources at {timestamp}",
+                    evicted = preemption.evicted,
+                    timestamp = preemption.timestamp,
+                )
+            } else {
+                format!(
+                    "Evicted {evicted} resources by {preemptors} at {timestamp}",
+                    evicted = preemption.evicted,
+                    preemptors = preemption.preemptors.join(", "),
+                    timestamp = preemption.timestamp,
+                )
+            },
+        ),
+        None => build(
+            PoolClaimStatus::CONDITION_PREEMPTED,
+            Some(false),
+            "NotPreempted",
+            String::default(),
+        ),
+    });
+    conditions
+}
+
 async fn report_error(
     recorder: &Recorder,
     error: ::kube::runtime::controller::Error<Error, ::kube::runtime::watcher::Error>,
@@ -222,6 +353,7 @@ pub async fn loop_forever(args: super::Args, kube: Client) -> Result<()> {
 
     let context = Arc::new(Context {
         kube,
+        label_lifecycle_post_stop: args.label_pool_claim_lifecycle_post_stop,
         label_lifecycle_pre_start: args.label_pool_claim_lifecycle_pre_start,
         label_parent: args.label_pool_claim_parent,
         label_pool_parent: args.label_pool_parent,
@@ -253,3 +385,105 @@ pub async fn loop_forever(args: super::Args, kube: Client) -> Result<()> {
         .await;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use openark_spectrum_api::pool_claim::PoolClaimPreemption;
+
+    use super::*;
+
+    fn claim(resources: Option<PoolClaimResourcesStatus>) -> PoolClaimCrd {
+        let mut claim = PoolClaimCrd::new(
+            "claim",
+            PoolClaimSpec {
+                pool_name: "pool".into(),
+                lifecycle: PoolResourceLifecycle::default(),
+                resources: PoolResourceSettings {
+                    min: Some(2.0),
+                    max: Some(4.0),
+                    ..Default::default()
+                },
+            },
+        );
+        claim.status = Some(PoolClaimStatus {
+            resources,
+            ..Default::default()
+        });
+        claim
+    }
+
+    fn resources(load: f64, preempted: bool) -> PoolClaimResourcesStatus {
+        PoolClaimResourcesStatus {
+            bound: load as usize,
+            load,
+            unsatisfiable: false,
+            preemption: preempted.then(|| PoolClaimPreemption {
+                evicted: 1,
+                preemptors: vec!["other".into()],
+                timestamp: Timestamp::UNIX_EPOCH,
+            }),
+            last_updated: Timestamp::UNIX_EPOCH,
+        }
+    }
+
+    fn status<'a>(conditions: &'a [Condition], type_: &str) -> Option<&'a str> {
+        conditions
+            .iter()
+            .find(|condition| condition.type_ == type_)
+            .map(|condition| condition.status.as_str())
+    }
+
+    #[test]
+    fn build_pending_conditions() {
+        let conditions = build_resource_conditions(&claim(None));
+        assert_eq!(conditions.len(), 1);
+        assert_eq!(
+            status(&conditions, PoolClaimStatus::CONDITION_SATISFIED),
+            Some("Unknown"),
+        );
+    }
+
+    #[test]
+    fn build_preempted_conditions() {
+        let conditions = build_resource_conditions(&claim(Some(resources(1.0, true))));
+        assert_eq!(
+            status(&conditions, PoolClaimStatus::CONDITION_SATISFIED),
+            Some("False"),
+        );
+        assert_eq!(
+            status(&conditions, PoolClaimStatus::CONDITION_PREEMPTED),
+            Some("True"),
+        );
+
+        // released once the claim gets satisfied again
+        let conditions = build_resource_conditions(&claim(Some(resources(3.0, true))));
+        assert_eq!(
+            status(&conditions, PoolClaimStatus::CONDITION_SATISFIED),
+            Some("True"),
+        );
+        assert_eq!(
+            status(&conditions, PoolClaimStatus::CONDITION_PREEMPTED),
+            Some("False"),
+        );
+    }
+
+    #[test]
+    fn keep_transition_time() {
+        let mut claim = claim(Some(resources(3.0, false)));
+        let conditions = build_resource_conditions(&claim);
+        claim.status.as_mut().unwrap().conditions = conditions
+            .into_iter()
+            .map(|condition| Condition {
+                last_transition_time: Time(Timestamp::UNIX_EPOCH),
+                ..condition
+            })
+            .collect();
+
+        let conditions = build_resource_conditions(&claim);
+        assert!(
+            conditions
+                .iter()
+                .all(|condition| condition.last_transition_time == Time(Timestamp::UNIX_EPOCH))
+        );
+    }
+}
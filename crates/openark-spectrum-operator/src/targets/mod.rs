@@ -1,18 +1,162 @@
+pub(crate) mod node;
 pub(crate) mod service;
+mod static_weights;
+pub(crate) mod workload;
+
+use std::{collections::BTreeMap, net::IpAddr};
 
 use k8s_openapi::{
     Resource,
-    api::core::v1::{Service, ServiceSpec},
+    api::{
+        apps::v1::{Deployment, StatefulSet},
+        core::v1::{Node, Pod, Service, ServiceSpec},
+        discovery::v1::Endpoint,
+    },
+    apimachinery::pkg::apis::meta::v1::LabelSelector,
+};
+use kube::{
+    Api, Client, Result,
+    api::{ObjectMeta, PatchParams},
+    core::Selector,
 };
-use kube::{Api, Client, Result, api::ObjectMeta};
 use openark_spectrum_api::common::ObjectReference;
+use serde_json::{Value, json};
 #[cfg(feature = "tracing")]
 use tracing::{Level, instrument};
 
-use crate::status::{Reason, Status};
+use crate::{
+    status::{Reason, Status},
+    targets::{node::NodeTarget, service::ServiceTarget, workload::WorkloadTarget},
+};
+
+/// A target of the histograms and pools, whose resources are exposed as endpoints.
+pub(crate) trait PoolTarget {
+    /// Returns the spec of the services which expose the resources.
+    fn service_spec(&self) -> Option<ServiceSpec>;
+
+    /// Collects the available resources of the target as endpoints.
+    async fn get_endpoints(&self, kube: &Client, address_type: &str) -> Result<Vec<Endpoint>>;
+
+    /// Marks the resources as bound to the claims with labels.
+    ///
+    /// The bindings map the resource names into the claim names.
+    /// The other resources marked by the pool are released, even if they are not selected anymore,
+    /// so empty bindings release all the resources of the pool.
+    async fn bind(
+        &self,
+        kube: &Client,
+        patch_params: &PatchParams,
+        labels: &BindLabels<'_>,
+        bindings: &BTreeMap<String, String>,
+    ) -> Result<()>;
+}
+
+/// The labels which mark the resources bound to the claims of a pool.
+pub(crate) struct BindLabels<'a> {
+    /// The label key of the bound claim's name
+    pub(crate) claim: &'a str,
+    /// The label key of the owner pool's UID
+    pub(crate) pool: &'a str,
+    /// The owner pool's UID
+    pub(crate) pool_uid: &'a str,
+}
+
+impl BindLabels<'_> {
+    /// Returns the label selector of the resources marked by the pool.
+    fn selector(&self) -> String {
+        format!("{}={}", self.pool, self.pool_uid)
+    }
+
+    /// Returns `true` if the resource is not marked by other pools.
+    fn is_owned(&self, labels: Option<&BTreeMap<String, String>>) -> bool {
+        labels
+            .and_then(|labels| labels.get(self.pool))
+            .is_none_or(|uid| uid == self.pool_uid)
+    }
+
+    /// Builds a merge patch of the labels, returning `None` if nothing has been changed.
+    fn build_patch(
+        &self,
+        labels: Option<&BTreeMap<String, String>>,
+        claim: Option<&String>,
+    ) -> Option<Value> {
+        let pool_uid = claim.map(|_| self.pool_uid);
+        let last_claim = labels.and_then(|labels| labels.get(self.claim));
+        let last_pool_uid = labels
+            .and_then(|labels| labels.get(self.pool))
+            .map(String::as_str);
+        if last_claim == claim && last_pool_uid == pool_uid {
+            return None;
+        }
+
+        let (key_claim, key_pool) = (self.claim, self.pool);
+        Some(json!({
+            "metadata": {
+                "labels": {
+                    key_claim: claim,
+                    key_pool: pool_uid,
+                },
+            },
+        }))
+    }
+}
 
 pub(crate) enum Target {
-    Service(Option<ServiceSpec>),
+    Node(NodeTarget),
+    Service(ServiceTarget),
+    Workload(WorkloadTarget),
+}
+
+impl PoolTarget for Target {
+    fn service_spec(&self) -> Option<ServiceSpec> {
+        match self {
+            Self::Node(target) => target.service_spec(),
+            Self::Service(target) => target.service_spec(),
+            Self::Workload(target) => target.service_spec(),
+        }
+    }
+
+    async fn get_endpoints(&self, kube: &Client, address_type: &str) -> Result<Vec<Endpoint>> {
+        match self {
+            Self::Node(target) => target.get_endpoints(kube, address_type).await,
+            Self::Service(target) => target.get_endpoints(kube, address_type).await,
+            Self::Workload(target) => target.get_endpoints(kube, address_type).await,
+        }
+    }
+
+    async fn bind(
+        &self,
+        kube: &Client,
+        patch_params: &PatchParams,
+        labels: &BindLabels<'_>,
+        bindings: &BTreeMap<String, String>,
+    ) -> Result<()> {
+        match self {
+            Self::Node(target) => target.bind(kube, patch_params, labels, bindings).await,
+            Self::Service(target) => target.bind(kube, patch_params, labels, bindings).await,
+            Self::Workload(target) => target.bind(kube, patch_params, labels, bindings).await,
+        }
+    }
+}
+
+/// Builds a headless service spec, which exposes the resources without ports.
+#[must_use]
+fn headless_spec() -> ServiceSpec {
+    ServiceSpec {
+        cluster_ip: Some("None".into()),
+        type_: Some("ClusterIP".into()),
+        ..Default::default()
+    }
+}
+
+/// Returns `true` if the address belongs to the address type, e.g. `IPv4`.
+#[must_use]
+fn is_address_type(address: &str, address_type: &str) -> bool {
+    match address.parse() {
+        Ok(IpAddr::V4(_)) => address_type == "IPv4",
+        Ok(IpAddr::V6(_)) => address_type == "IPv6",
+        Err(_) => false,
+    }
 }
 
 #[cfg_attr(feature = "tracing", instrument(level = Level::INFO, skip_all))]
@@ -20,6 +164,7 @@ pub(crate) async fn get_target(
     client: &Client,
     namespace: &str,
     target_ref: &ObjectReference,
+    target_selector: Option<&LabelSelector>,
 ) -> Result<Result<(ObjectMeta, Target), Status>> {
     let ObjectReference {
         group,
@@ -29,16 +174,95 @@ pub(crate) async fn get_target(
     } = target_ref;
 
     let namespace = target_namespace.as_deref().unwrap_or(namespace);
+    let not_found = || Status {
+        reason: Reason::InvalidTarget,
+        message: format!("Target not found: {kind}/{namespace}/{name}"),
+        requeue: true,
+    };
+    let parse_selector = |selector: Option<&LabelSelector>| match selector {
+        Some(selector) => Selector::try_from(selector.clone()).map_err(|error| Status {
+            reason: Reason::InvalidTarget,
+            message: format!("Invalid target selector: {error}"),
+            requeue: false,
+        }),
+        None => Err(Status {
+            reason: Reason::InvalidTarget,
+            message: format!("Target selector is required: {kind}/{namespace}/{name}"),
+            requeue: false,
+        }),
+    };
+
+    // NOTE: the selected resources have no metadata, so it is named by the reference
+    let alias_metadata = || ObjectMeta {
+        name: Some(name.clone()),
+        namespace: Some(namespace.into()),
+        ..Default::default()
+    };
+
     match (group.as_str(), kind.as_str()) {
+        (Deployment::GROUP, Deployment::KIND) => {
+            let api = Api::<Deployment>::namespaced(client.clone(), namespace);
+            let Some(item) = api.get_opt(name).await? else {
+                return Ok(Err(not_found()));
+            };
+            let selector = item.spec.as_ref().map(|spec| &spec.selector);
+            match parse_selector(selector) {
+                Ok(selector) => Ok(Ok((
+                    item.metadata,
+                    Target::Workload(WorkloadTarget {
+                        namespace: namespace.into(),
+                        selector,
+                    }),
+                ))),
+                Err(error) => Ok(Err(error)),
+            }
+        }
+        (StatefulSet::GROUP, StatefulSet::KIND) => {
+            let api = Api::<StatefulSet>::namespaced(client.clone(), namespace);
+            let Some(item) = api.get_opt(name).await? else {
+                return Ok(Err(not_found()));
+            };
+            let selector = item.spec.as_ref().map(|spec| &spec.selector);
+            match parse_selector(selector) {
+                Ok(selector) => Ok(Ok((
+                    item.metadata,
+                    Target::Workload(WorkloadTarget {
+                        namespace: namespace.into(),
+                        selector,
+                    }),
+                ))),
+                Err(error) => Ok(Err(error)),
+            }
+        }
+        (Node::GROUP, Node::KIND) => match parse_selector(target_selector) {
+            Ok(selector) => Ok(Ok((
+                alias_metadata(),
+                Target::Node(NodeTarget { selector }),
+            ))),
+            Err(error) => Ok(Err(error)),
+        },
+        (Pod::GROUP, Pod::KIND) => match parse_selector(target_selector) {
+            Ok(selector) => Ok(Ok((
+                alias_metadata(),
+                Target::Workload(WorkloadTarget {
+                    namespace: namespace.into(),
+                    selector,
+                }),
+            ))),
+            Err(error) => Ok(Err(error)),
+        },
         (Service::GROUP, Service::KIND) => {
             let api = Api::<Service>::namespaced(client.clone(), namespace);
             match api.get_opt(name).await? {
-                Some(item) => Ok(Ok((item.metadata, Target::Service(item.spec)))),
-                None => Ok(Err(Status {
-                    reason: Reason::InvalidTarget,
-                    message: format!("Target not found: {kind}/{namespace}/{name}"),
-                    requeue: true,
-                })),
+                Some(item) => Ok(Ok((
+                    item.metadata,
+                    Target::Service(ServiceTarget {
+                        name: name.clone(),
+                        namespace: namespace.into(),
+                        spec: item.spec,
+                    }),
+                ))),
+                None => Ok(Err(not_found())),
             }
         }
         (group, kind) => Ok(Err(Status {
@@ -52,3 +276,60 @@ pub(crate) async fn get_target(
         })),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mark_bound_resources() {
+        let labels = BindLabels {
+            claim: "org.ulagbulag.io/spectrum-pool-claim",
+            pool: "org.ulagbulag.io/spectrum-pool-bind",
+            pool_uid: "uid-a",
+        };
+        let marks = |claim: &str, uid: &str| {
+            BTreeMap::from_iter([
+                (labels.claim.to_string(), claim.to_string()),
+                (labels.pool.to_string(), uid.to_string()),
+            ])
+        };
+        let claim = "team-a".to_string();
+
+        // owned
+        assert!(labels.is_owned(None));
+        assert!(labels.is_owned(Some(&marks("team-a", "uid-a"))));
+        assert!(!labels.is_owned(Some(&marks("team-a", "uid-b"))));
+
+        // unchanged
+        let last = marks("team-a", "uid-a");
+        assert!(labels.build_patch(Some(&last), Some(&claim)).is_none());
+        assert!(labels.build_patch(None, None).is_none());
+
+        // bound
+        assert_eq!(
+            labels.build_patch(None, Some(&claim)).unwrap()["metadata"]["labels"],
+            json!({
+                labels.claim: "team-a",
+                labels.pool: "uid-a",
+            }),
+        );
+
+        // released
+        assert_eq!(
+            labels.build_patch(Some(&last), None).unwrap()["metadata"]["labels"],
+            json!({
+                labels.claim: null,
+                labels.pool: null,
+            }),
+        );
+    }
+
+    #[test]
+    fn match_address_type() {
+        assert!(is_address_type("10.0.0.1", "IPv4"));
+        assert!(!is_address_type("10.0.0.1", "IPv6"));
+        assert!(is_address_type("fd00::1", "IPv6"));
+        assert!(!is_address_type("node-a", "IPv4"));
+    }
+}
@@ -0,0 +1,224 @@
+use std::collections::BTreeMap;
+
+use k8s_openapi::api::{
+    core::v1::{Node, ObjectReference, ServiceSpec, Taint},
+    discovery::v1::{Endpoint, EndpointConditions},
+};
+use kube::{
+    Api, Client, Result,
+    api::{ListParams, Patch, PatchParams},
+    core::Selector,
+};
+use serde_json::json;
+#[cfg(feature = "tracing")]
+use tracing::{Level, instrument, warn};
+
+use crate::targets::{BindLabels, PoolTarget, headless_spec, is_address_type};
+
+const LABEL_TOPOLOGY_ZONE: &str = "topology.kubernetes.io/zone";
+
+/// The nodes selected by a label selector, which are bound to the claims as a whole.
+///
+/// The bound nodes are labeled with `<label claim>=<claim name>`,
+/// so the workloads of a claim should select the label with node affinity.
+/// The nodes are not tainted, so that the DaemonSets (e.g. CNI) keep running on them.
+pub(crate) struct NodeTarget {
+    pub(crate) selector: Selector,
+}
+
+impl NodeTarget {
+    fn list_params(&self) -> ListParams {
+        ListParams::default().labels_from(&self.selector)
+    }
+}
+
+/// Returns `true` if the node is schedulable and ready.
+fn is_ready(node: &Node) -> bool {
+    node.metadata.deletion_timestamp.is_none()
+        && node
+            .spec
+            .as_ref()
+            .is_none_or(|spec| spec.unschedulable != Some(true))
+        && node.status.as_ref().is_some_and(|status| {
+            status
+                .conditions
+                .iter()
+                .flatten()
+                .any(|condition| condition.type_ == "Ready" && condition.status == "True")
+        })
+}
+
+/// Removes the claim taints of the node, returning `None` if there are nothing to remove.
+///
+/// NOTE: the nodes have been tainted by the earlier versions
+fn strip_taints(node: &Node, label_claim: &str) -> Option<Vec<Taint>> {
+    let last_taints = node
+        .spec
+        .as_ref()
+        .and_then(|spec| spec.taints.as_deref())
+        .unwrap_or_default();
+
+    let taints: Vec<_> = last_taints
+        .iter()
+        .filter(|taint| taint.key != label_claim)
+        .cloned()
+        .collect();
+    (taints.len() != last_taints.len()).then_some(taints)
+}
+
+impl PoolTarget for NodeTarget {
+    fn service_spec(&self) -> Option<ServiceSpec> {
+        Some(headless_spec())
+    }
+
+    #[cfg_attr(feature = "tracing", instrument(level = Level::INFO, skip_all))]
+    async fn get_endpoints(&self, kube: &Client, address_type: &str) -> Result<Vec<Endpoint>> {
+        let api = Api::<Node>::all(kube.clone());
+        let nodes = api.list(&self.list_params()).await?.items;
+
+        Ok(nodes
+            .into_iter()
+            .filter(is_ready)
+            .filter_map(|node| {
+                let address = node
+                    .status
+                    .as_ref()?
+                    .addresses
+                    .iter()
+                    .flatten()
+                    .filter(|address| address.type_ == "InternalIP")
+                    .map(|address| address.address.as_str())
+                    .find(|address| is_address_type(address, address_type))?
+                    .to_string();
+                let zone = node
+                    .metadata
+                    .labels
+                    .as_ref()
+                    .and_then(|labels| labels.get(LABEL_TOPOLOGY_ZONE))
+                    .cloned();
+
+                Some(Endpoint {
+                    addresses: vec![address],
+                    conditions: Some(EndpointConditions {
+                        ready: Some(true),
+                        serving: Some(true),
+                        terminating: Some(false),
+                    }),
+                    node_name: node.metadata.name.clone(),
+                    target_ref: Some(ObjectReference {
+                        kind: Some("Node".into()),
+                        name: node.metadata.name,
+                        uid: node.metadata.uid,
+                        ..Default::default()
+                    }),
+                    zone,
+                    ..Default::default()
+                })
+            })
+            .collect())
+    }
+
+    #[cfg_attr(feature = "tracing", instrument(level = Level::INFO, skip_all))]
+    async fn bind(
+        &self,
+        kube: &Client,
+        patch_params: &PatchParams,
+        labels: &BindLabels<'_>,
+        bindings: &BTreeMap<String, String>,
+    ) -> Result<()> {
+        let api = Api::<Node>::all(kube.clone());
+
+        // Collect the selected nodes and the stale ones marked by the pool
+        let mut nodes = BTreeMap::default();
+        let lp = ListParams::default().labels(&labels.selector());
+        for node in api.list(&lp).await?.items {
+            nodes.insert(node.metadata.name.clone(), (node, false));
+        }
+        for node in api.list(&self.list_params()).await?.items {
+            nodes.insert(node.metadata.name.clone(), (node, true));
+        }
+
+        let mut result = Ok(());
+        for (name, (node, is_selected)) in nodes {
+            let Some(name) = name else {
+                continue;
+            };
+            let last_labels = node.metadata.labels.as_ref();
+            if !labels.is_owned(last_labels) {
+                continue;
+            }
+            let claim = if is_selected {
+                bindings.get(&name)
+            } else {
+                None
+            };
+
+            // Skip updating the node if nothing has been changed
+            let taints = strip_taints(&node, labels.claim);
+            let patch = match (labels.build_patch(last_labels, claim), taints) {
+                (None, None) => continue,
+                (patch, taints) => {
+                    let mut patch = patch.unwrap_or_else(|| json!({}));
+                    if let Some(taints) = taints {
+                        // NOTE: the merge patch replaces the whole list of the taints
+                        patch["spec"] = json!({
+                            "taints": taints,
+                        });
+                    }
+                    patch
+                }
+            };
+
+            // NOTE: keep binding the other nodes, so that a node cannot block the whole pool
+            if let Err(error) = api.patch(&name, patch_params, &Patch::Merge(patch)).await {
+                #[cfg(feature = "tracing")]
+                warn!("failed to bind node {name}: {error}");
+                result = Err(error);
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::api::core::v1::NodeSpec;
+
+    use super::*;
+
+    #[test]
+    fn strip_claim_taints() {
+        const LABEL_CLAIM: &str = "org.ulagbulag.io/spectrum-pool-claim";
+
+        let taint = |key: &str, value: &str| Taint {
+            effect: "NoSchedule".into(),
+            key: key.into(),
+            time_added: None,
+            value: Some(value.into()),
+        };
+        let node = Node {
+            spec: Some(NodeSpec {
+                taints: Some(vec![
+                    taint("nvidia.com/gpu", "present"),
+                    taint(LABEL_CLAIM, "team-a"),
+                ]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let taints = strip_taints(&node, LABEL_CLAIM).unwrap();
+        assert_eq!(taints.len(), 1);
+        assert_eq!(taints[0].key, "nvidia.com/gpu");
+
+        // nothing to remove
+        let node = Node {
+            spec: Some(NodeSpec {
+                taints: Some(taints),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert!(strip_taints(&node, LABEL_CLAIM).is_none());
+    }
+}
@@ -1,29 +1,40 @@
-use std::borrow::Cow;
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet},
+};
 
-use k8s_openapi::api::{
-    core::v1::ServiceSpec,
-    discovery::v1::{Endpoint, EndpointSlice},
+use k8s_openapi::{
+    Resource,
+    api::{
+        core::v1::{ConfigMap, Node, ServiceSpec},
+        discovery::v1::{Endpoint, EndpointSlice},
+    },
 };
 use kube::{
     Api, Client, Result,
-    api::{ListParams, ObjectMeta},
+    api::{ListParams, ObjectMeta, PatchParams},
 };
 use openark_spectrum_api::{
     client::BackendClient,
     metrics_class::MetricsClassCrd,
     schema::{WeightRequest, WeightResponse},
 };
-use openark_spectrum_scheduler::item::WeightedItems;
+use openark_spectrum_scheduler::item::{Topology, WeightedItems};
+use ordered_float::OrderedFloat;
 #[cfg(feature = "tracing")]
 use tracing::{Level, instrument};
 
 use crate::{
     status::{Reason, Status},
+    targets::{BindLabels, PoolTarget, Target, static_weights::get_static_weights},
     utils::build_service_reference_url_by_class,
 };
 
 pub(crate) const LABEL_KEY_SELECTOR: &str = "kubernetes.io/service-name";
 
+const LABEL_TOPOLOGY_RACK: &str = "topology.kubernetes.io/rack";
+const LABEL_TOPOLOGY_ZONE: &str = "topology.kubernetes.io/zone";
+
 /// It will first probe IPv4, and only select IPv6
 /// if IPv6 is explicitly declared instead of IPv4.
 #[must_use]
@@ -42,7 +53,8 @@ pub(crate) fn infer_address_type(spec: Option<&ServiceSpec>) -> &str {
 #[must_use]
 pub(crate) fn mirror_spec(spec: Option<ServiceSpec>) -> Option<ServiceSpec> {
     spec.map(|spec| ServiceSpec {
-        cluster_ip: None,
+        // NOTE: headless services are mirrored as headless
+        cluster_ip: spec.cluster_ip.filter(|ip| ip == "None"),
         cluster_ips: None,
         external_ips: None,
         external_name: None,
@@ -70,13 +82,91 @@ pub(crate) async fn get_endpoints(
         .collect())
 }
 
+/// The endpoints of a service.
+pub(crate) struct ServiceTarget {
+    pub(crate) name: String,
+    pub(crate) namespace: String,
+    pub(crate) spec: Option<ServiceSpec>,
+}
+
+impl PoolTarget for ServiceTarget {
+    fn service_spec(&self) -> Option<ServiceSpec> {
+        self.spec.clone()
+    }
+
+    #[cfg_attr(feature = "tracing", instrument(level = Level::INFO, skip_all))]
+    async fn get_endpoints(&self, kube: &Client, address_type: &str) -> Result<Vec<Endpoint>> {
+        let api = Api::<EndpointSlice>::namespaced(kube.clone(), &self.namespace);
+        let list_params = ListParams {
+            label_selector: Some(format!("{LABEL_KEY_SELECTOR}={}", &self.name)),
+            ..Default::default()
+        };
+        get_endpoints(&api, address_type, &list_params).await
+    }
+
+    async fn bind(
+        &self,
+        _kube: &Client,
+        _patch_params: &PatchParams,
+        _labels: &BindLabels<'_>,
+        _bindings: &BTreeMap<String, String>,
+    ) -> Result<()> {
+        // NOTE: the endpoints are bound by the endpointslices of the claims
+        Ok(())
+    }
+}
+
 pub(crate) struct Context<'a> {
     pub(crate) address_type: &'a str,
     pub(crate) child_metadata: &'a ObjectMeta,
     pub(crate) class: &'a MetricsClassCrd,
     pub(crate) client: &'a ::reqwest::Client,
     pub(crate) kube: &'a Client,
+    pub(crate) target: &'a Target,
     pub(crate) target_metadata: &'a ObjectMeta,
+    /// Whether to collect the topology domains, i.e. any claim has the topology constraints
+    pub(crate) with_topologies: bool,
+}
+
+/// Collects the topology domains of the endpoints from their nodes.
+#[cfg_attr(feature = "tracing", instrument(level = Level::INFO, skip_all))]
+async fn get_topologies(kube: &Client, endpoints: &[Endpoint]) -> Result<Vec<Topology>> {
+    let node_names: BTreeSet<_> = endpoints
+        .iter()
+        .filter_map(|endpoint| endpoint.node_name.as_deref())
+        .collect();
+
+    // Fetch node labels
+    let mut labels = BTreeMap::default();
+    if !node_names.is_empty() {
+        let api = Api::<Node>::all(kube.clone());
+        for node in api.list(&ListParams::default()).await?.items {
+            if let Some(name) = node.metadata.name
+                && node_names.contains(name.as_str())
+            {
+                labels.insert(name, node.metadata.labels.unwrap_or_default());
+            }
+        }
+    }
+
+    Ok(endpoints
+        .iter()
+        .map(|endpoint| {
+            let labels = endpoint
+                .node_name
+                .as_ref()
+                .and_then(|name| labels.get(name));
+            let get_label = |key| labels.and_then(|labels| labels.get(key)).cloned();
+            Topology {
+                node: endpoint.node_name.clone(),
+                rack: get_label(LABEL_TOPOLOGY_RACK),
+                zone: endpoint
+                    .zone
+                    .clone()
+                    .or_else(|| get_label(LABEL_TOPOLOGY_ZONE)),
+            }
+        })
+        .collect())
 }
 
 #[cfg_attr(feature = "tracing", instrument(level = Level::INFO, skip_all))]
@@ -89,7 +179,9 @@ pub(crate) async fn get_weighted_endpoints(
         class,
         client,
         kube,
+        target,
         target_metadata,
+        with_topologies,
     } = ctx;
 
     let target_name = target_metadata.name.as_deref().unwrap();
@@ -99,45 +191,29 @@ pub(crate) async fn get_weighted_endpoints(
         .expect("Namespaced resource");
 
     // Validate items
-    let target_api = Api::<EndpointSlice>::namespaced(kube.clone(), target_namespace);
-    let target_list_params = ListParams {
-        label_selector: Some(format!("{LABEL_KEY_SELECTOR}={target_name}")),
-        ..Default::default()
-    };
-    let items = get_endpoints(&target_api, address_type, &target_list_params).await?;
+    let items = target.get_endpoints(kube, address_type).await?;
 
     // Fetch weights
-    let url = match build_service_reference_url_by_class(class) {
-        Ok(url) => url,
-        Err(error) => {
-            return Ok(Err(Status {
-                reason: Reason::InvalidMetricsClass,
-                message: error.to_string(),
-                requeue: true,
-            }));
+    let backend = &class.spec.backend_ref.object;
+    let weights = match (backend.group.as_str(), backend.kind.as_str()) {
+        (ConfigMap::GROUP, ConfigMap::KIND) => {
+            get_static_weights(kube, backend, target_namespace, &items).await?
         }
-    };
-    let args = WeightRequest {
-        metadata: ObjectMeta {
-            name: Some(target_name.into()),
-            namespace: Some(target_namespace.into()),
-            annotations: child_metadata.annotations.clone(),
-            labels: child_metadata.labels.clone(),
-            ..Default::default()
-        },
-        list: Cow::Borrowed(items.as_slice()),
-    };
-
-    let WeightResponse { weights } = match client.get_service_weights(url, &args).await {
-        Ok(response) => response,
-        Err(error) => {
-            return Ok(Err(Status {
-                reason: Reason::ProvisioningError,
-                message: format!("Failed to get service weights: {error}"),
-                requeue: true,
-            }));
+        _ => {
+            let metadata = ObjectMeta {
+                name: Some(target_name.into()),
+                namespace: Some(target_namespace.into()),
+                annotations: child_metadata.annotations.clone(),
+                labels: child_metadata.labels.clone(),
+                ..Default::default()
+            };
+            get_backend_weights(client, class, metadata, &items).await
         }
     };
+    let weights = match weights {
+        Ok(weights) => weights,
+        Err(error) => return Ok(Err(error)),
+    };
 
     // Validate weights
     if items.len() != weights.len() {
@@ -148,5 +224,49 @@ pub(crate) async fn get_weighted_endpoints(
         }));
     }
 
-    Ok(Ok(WeightedItems { items, weights }))
+    // NOTE: the nodes are listed only if required, as the topologies are optional
+    let topologies = if with_topologies {
+        get_topologies(kube, &items).await?
+    } else {
+        Vec::default()
+    };
+    Ok(Ok(WeightedItems {
+        items,
+        weights,
+        topologies,
+    }))
+}
+
+/// Fetches the weights from the backend service of the class.
+async fn get_backend_weights(
+    client: &::reqwest::Client,
+    class: &MetricsClassCrd,
+    metadata: ObjectMeta,
+    items: &[Endpoint],
+) -> Result<Vec<Option<OrderedFloat<f64>>>, Status> {
+    let url = match build_service_reference_url_by_class(class) {
+        Ok(url) => url,
+        Err(error) => {
+            return Err(Status {
+                reason: Reason::InvalidMetricsClass,
+                message: error.to_string(),
+                requeue: true,
+            });
+        }
+    };
+    let args = WeightRequest {
+        metadata,
+        list: Cow::Borrowed(items),
+        source: class.spec.source,
+        weighting: class.spec.weighting.clone(),
+    };
+
+    match client.get_service_weights(url, &args).await {
+        Ok(WeightResponse { weights }) => Ok(weights),
+        Err(error) => Err(Status {
+            reason: Reason::ProvisioningError,
+            message: format!("Failed to get service weights: {error}"),
+            requeue: true,
+        }),
+    }
 }
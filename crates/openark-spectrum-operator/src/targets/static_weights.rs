@@ -0,0 +1,208 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use k8s_openapi::api::{
+    core::v1::{ConfigMap, Node, Pod},
+    discovery::v1::Endpoint,
+};
+use kube::{Api, Client, Result, api::ListParams};
+use openark_spectrum_api::common::ObjectReference;
+use ordered_float::OrderedFloat;
+#[cfg(feature = "tracing")]
+use tracing::{Level, instrument};
+
+use crate::status::{Reason, Status};
+
+/// A weight of the other pods.
+const KEY_DEFAULT: &str = "default";
+/// A pod label whose value is the weight.
+const KEY_LABEL_KEY: &str = "labelKey";
+/// A JSON map of the weights by pod labels, e.g. `{"gpu=a100": 4}`.
+const KEY_LABELS: &str = "labels";
+/// A JSON map of the weights by pod (or node) names.
+const KEY_PODS: &str = "pods";
+
+/// The static weights of the pods, defined in a ConfigMap.
+#[derive(Debug, Default)]
+struct StaticWeights {
+    default: Option<f64>,
+    label_key: Option<String>,
+    labels: BTreeMap<String, f64>,
+    pods: BTreeMap<String, f64>,
+}
+
+impl StaticWeights {
+    fn parse(config_map: &ConfigMap) -> Result<Self, String> {
+        let Some(data) = config_map.data.as_ref() else {
+            return Ok(Self::default());
+        };
+
+        let parse_map = |key: &str| match data.get(key) {
+            Some(value) => ::serde_json::from_str(value)
+                .map_err(|error| format!("Invalid static weights {key:?}: {error}")),
+            None => Ok(BTreeMap::default()),
+        };
+        Ok(Self {
+            default: data
+                .get(KEY_DEFAULT)
+                .map(|value| value.trim().parse())
+                .transpose()
+                .map_err(|error| format!("Invalid static weights {KEY_DEFAULT:?}: {error}"))?,
+            label_key: data.get(KEY_LABEL_KEY).cloned(),
+            labels: parse_map(KEY_LABELS)?,
+            pods: parse_map(KEY_PODS)?,
+        })
+    }
+
+    fn requires_labels(&self) -> bool {
+        self.label_key.is_some() || !self.labels.is_empty()
+    }
+
+    /// Returns the weight of the pod, in order of the pod name,
+    /// the label value, the largest one of the matched labels and the default.
+    fn get(&self, name: &str, labels: Option<&BTreeMap<String, String>>) -> Option<f64> {
+        if let Some(&weight) = self.pods.get(name) {
+            return Some(weight);
+        }
+
+        let labels = labels.into_iter().flatten();
+        if let Some(key) = self.label_key.as_ref()
+            && let Some(weight) = labels
+                .clone()
+                .find(|&(label, _)| label == key)
+                .and_then(|(_, value)| value.parse().ok())
+        {
+            return Some(weight);
+        }
+
+        labels
+            .filter_map(|(key, value)| self.labels.get(&format!("{key}={value}")))
+            .copied()
+            .map(OrderedFloat)
+            .max()
+            .map(OrderedFloat::into_inner)
+            .or(self.default)
+    }
+}
+
+/// Collects the weights of the endpoints from the static weights of a ConfigMap.
+#[cfg_attr(feature = "tracing", instrument(level = Level::INFO, skip_all))]
+pub(super) async fn get_static_weights(
+    kube: &Client,
+    backend: &ObjectReference,
+    target_namespace: &str,
+    endpoints: &[Endpoint],
+) -> Result<Result<Vec<Option<OrderedFloat<f64>>>, Status>> {
+    let namespace = backend.namespace.as_deref().unwrap_or(target_namespace);
+    let api = Api::<ConfigMap>::namespaced(kube.clone(), namespace);
+    let Some(config_map) = api.get_opt(&backend.name).await? else {
+        return Ok(Err(Status {
+            reason: Reason::InvalidMetricsClass,
+            message: format!("Missing static weights: {backend}"),
+            requeue: true,
+        }));
+    };
+    let weights = match StaticWeights::parse(&config_map) {
+        Ok(weights) => weights,
+        Err(message) => {
+            return Ok(Err(Status {
+                reason: Reason::InvalidMetricsClass,
+                message,
+                requeue: false,
+            }));
+        }
+    };
+
+    let pod_names: Vec<_> = endpoints
+        .iter()
+        .map(|endpoint| {
+            endpoint
+                .target_ref
+                .as_ref()
+                .filter(|target| matches!(target.kind.as_deref(), Some("Pod" | "Node")))
+                .and_then(|target| target.name.as_deref())
+        })
+        .collect();
+
+    // Fetch pod (or node) labels
+    let mut labels = BTreeMap::default();
+    if weights.requires_labels() {
+        let names: BTreeSet<_> = pod_names.iter().flatten().copied().collect();
+        let is_node = endpoints.iter().any(|endpoint| {
+            endpoint
+                .target_ref
+                .as_ref()
+                .is_some_and(|target| target.kind.as_deref() == Some("Node"))
+        });
+        let metadata: Vec<_> = if is_node {
+            let api = Api::<Node>::all(kube.clone());
+            let items = api.list(&ListParams::default()).await?.items;
+            items.into_iter().map(|item| item.metadata).collect()
+        } else {
+            let api = Api::<Pod>::namespaced(kube.clone(), target_namespace);
+            let items = api.list(&ListParams::default()).await?.items;
+            items.into_iter().map(|item| item.metadata).collect()
+        };
+        for metadata in metadata {
+            if let Some(name) = metadata.name
+                && names.contains(name.as_str())
+            {
+                labels.insert(name, metadata.labels.unwrap_or_default());
+            }
+        }
+    }
+
+    Ok(Ok(pod_names
+        .into_iter()
+        .map(|name| {
+            let name = name?;
+            weights.get(name, labels.get(name)).map(OrderedFloat)
+        })
+        .collect()))
+}
+
+#[cfg(test)]
+mod tests {
+    use kube::api::ObjectMeta;
+
+    use super::*;
+
+    #[test]
+    fn parse_static_weights() {
+        let config_map = ConfigMap {
+            metadata: ObjectMeta::default(),
+            data: Some(
+                [
+                    (KEY_DEFAULT, "1"),
+                    (KEY_LABEL_KEY, "org.ulagbulag.io/spectrum-weight"),
+                    (KEY_LABELS, r#"{"gpu=a100": 4, "gpu=t4": 2}"#),
+                    (KEY_PODS, r#"{"pod-a": 8}"#),
+                ]
+                .into_iter()
+                .map(|(key, value)| (key.into(), value.into()))
+                .collect(),
+            ),
+            ..Default::default()
+        };
+        let weights = StaticWeights::parse(&config_map).unwrap();
+
+        let labels = |items: &[(&str, &str)]| -> BTreeMap<String, String> {
+            items
+                .iter()
+                .map(|&(key, value)| (key.into(), value.into()))
+                .collect()
+        };
+        assert_eq!(weights.get("pod-a", None), Some(8.0));
+        assert_eq!(
+            weights.get(
+                "pod-b",
+                Some(&labels(&[("org.ulagbulag.io/spectrum-weight", "3")]))
+            ),
+            Some(3.0),
+        );
+        assert_eq!(
+            weights.get("pod-c", Some(&labels(&[("gpu", "t4")]))),
+            Some(2.0)
+        );
+        assert_eq!(weights.get("pod-d", None), Some(1.0));
+    }
+}
@@ -0,0 +1,130 @@
+use std::collections::BTreeMap;
+
+use k8s_openapi::api::{
+    core::v1::{ObjectReference, Pod, ServiceSpec},
+    discovery::v1::{Endpoint, EndpointConditions},
+};
+use kube::{
+    Api, Client, Result,
+    api::{ListParams, Patch, PatchParams},
+    core::Selector,
+};
+#[cfg(feature = "tracing")]
+use tracing::{Level, instrument};
+
+use crate::targets::{BindLabels, PoolTarget, headless_spec, is_address_type};
+
+/// The pods selected by a workload (e.g. Deployment, StatefulSet) or a label selector.
+pub(crate) struct WorkloadTarget {
+    pub(crate) namespace: String,
+    pub(crate) selector: Selector,
+}
+
+impl WorkloadTarget {
+    fn list_params(&self) -> ListParams {
+        ListParams::default().labels_from(&self.selector)
+    }
+}
+
+/// Returns `true` if the pod is running and ready.
+fn is_ready(pod: &Pod) -> bool {
+    pod.metadata.deletion_timestamp.is_none()
+        && pod.status.as_ref().is_some_and(|status| {
+            status.phase.as_deref() == Some("Running")
+                && status
+                    .conditions
+                    .iter()
+                    .flatten()
+                    .any(|condition| condition.type_ == "Ready" && condition.status == "True")
+        })
+}
+
+impl PoolTarget for WorkloadTarget {
+    fn service_spec(&self) -> Option<ServiceSpec> {
+        Some(headless_spec())
+    }
+
+    #[cfg_attr(feature = "tracing", instrument(level = Level::INFO, skip_all))]
+    async fn get_endpoints(&self, kube: &Client, address_type: &str) -> Result<Vec<Endpoint>> {
+        let api = Api::<Pod>::namespaced(kube.clone(), &self.namespace);
+        let pods = api.list(&self.list_params()).await?.items;
+
+        Ok(pods
+            .into_iter()
+            .filter(is_ready)
+            .filter_map(|pod| {
+                let address = pod
+                    .status
+                    .as_ref()?
+                    .pod_ips
+                    .iter()
+                    .flatten()
+                    .map(|pod_ip| pod_ip.ip.as_str())
+                    .find(|ip| is_address_type(ip, address_type))?
+                    .to_string();
+
+                Some(Endpoint {
+                    addresses: vec![address],
+                    conditions: Some(EndpointConditions {
+                        ready: Some(true),
+                        serving: Some(true),
+                        terminating: Some(false),
+                    }),
+                    hostname: pod.spec.as_ref().and_then(|spec| spec.hostname.clone()),
+                    node_name: pod.spec.as_ref().and_then(|spec| spec.node_name.clone()),
+                    target_ref: Some(ObjectReference {
+                        kind: Some("Pod".into()),
+                        name: pod.metadata.name,
+                        namespace: pod.metadata.namespace,
+                        uid: pod.metadata.uid,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                })
+            })
+            .collect())
+    }
+
+    #[cfg_attr(feature = "tracing", instrument(level = Level::INFO, skip_all))]
+    async fn bind(
+        &self,
+        kube: &Client,
+        patch_params: &PatchParams,
+        labels: &BindLabels<'_>,
+        bindings: &BTreeMap<String, String>,
+    ) -> Result<()> {
+        let api = Api::<Pod>::namespaced(kube.clone(), &self.namespace);
+
+        // Collect the selected pods and the stale ones marked by the pool
+        let mut pods = BTreeMap::default();
+        let lp = ListParams::default().labels(&labels.selector());
+        for pod in api.list(&lp).await?.items {
+            pods.insert(pod.metadata.name.clone(), (pod, false));
+        }
+        for pod in api.list(&self.list_params()).await?.items {
+            pods.insert(pod.metadata.name.clone(), (pod, true));
+        }
+
+        for (name, (pod, is_selected)) in pods {
+            let Some(name) = name else {
+                continue;
+            };
+            let last_labels = pod.metadata.labels.as_ref();
+            if !labels.is_owned(last_labels) {
+                continue;
+            }
+            let claim = if is_selected {
+                bindings.get(&name)
+            } else {
+                None
+            };
+
+            // Skip updating labels if nothing has been changed
+            let Some(patch) = labels.build_patch(last_labels, claim) else {
+                continue;
+            };
+            api.patch(&name, patch_params, &Patch::Merge(patch)).await?;
+        }
+        Ok(())
+    }
+}
@@ -1,30 +1,112 @@
-use openark_spectrum_api::histogram::HistogramSettings;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use jiff::{SignedDuration, Timestamp};
+use openark_spectrum_api::histogram::{HistogramBoundaries, HistogramSettings};
 use ordered_float::{Float, OrderedFloat};
 
 use crate::status::{Reason, Status};
 
 const DEFAULT_WEIGHT: u64 = u64::MAX / 2;
 
+/// The default period of the EMA factor in seconds, which follows the reconciliation interval.
+const DEFAULT_INTERVAL: u64 = 30;
+
+/// The smoothed weight and the last bucket level of an endpoint.
+#[derive(Debug, Default)]
+struct EndpointState {
+    ema: Option<(Timestamp, f64)>,
+    samples: VecDeque<(Timestamp, f64)>,
+    level: Option<u64>,
+}
+
+impl EndpointState {
+    fn smooth(
+        &mut self,
+        ema_alpha: Option<f64>,
+        window: Option<u64>,
+        interval: u64,
+        timestamp: Timestamp,
+        weight: Option<f64>,
+    ) -> Option<f64> {
+        if let Some(alpha) = ema_alpha {
+            if let Some(weight) = weight {
+                let value = match self.ema {
+                    // NOTE: the factor is given per interval, so scale it by the elapsed time
+                    // to be independent of how often the histogram is reconciled
+                    Some((updated_at, last)) => {
+                        let elapsed = timestamp.duration_since(updated_at).as_secs_f64().max(0.0);
+                        let alpha = 1.0 - (1.0 - alpha).powf(elapsed / interval.max(1) as f64);
+                        alpha * weight + (1.0 - alpha) * last
+                    }
+                    None => weight,
+                };
+                self.ema = Some((timestamp, value));
+            }
+            self.ema.map(|(_, value)| value)
+        } else if let Some(window) = window {
+            if let Some(weight) = weight {
+                self.samples.push_back((timestamp, weight));
+            }
+
+            // Evict the samples out of the window
+            let since = i64::try_from(window).ok().and_then(|window| {
+                timestamp
+                    .checked_sub(SignedDuration::from_secs(window))
+                    .ok()
+            });
+            if let Some(since) = since {
+                while self
+                    .samples
+                    .front()
+                    .is_some_and(|&(sampled_at, _)| sampled_at < since)
+                {
+                    self.samples.pop_front();
+                }
+            }
+
+            let sum: f64 = self.samples.iter().map(|&(_, weight)| weight).sum();
+            (!self.samples.is_empty()).then(|| sum / self.samples.len() as f64)
+        } else {
+            weight
+        }
+    }
+}
+
+/// The states of a histogram, which are kept over the reconciliations.
+#[derive(Debug, Default)]
+pub(crate) struct HistogramState {
+    endpoints: BTreeMap<String, EndpointState>,
+}
+
 pub(crate) struct Histogram<T> {
     pub(crate) data: Vec<Vec<T>>,
 }
 
 impl<T> Histogram<T> {
-    pub(crate) fn build(
+    pub(crate) fn build<F>(
         settings: &HistogramSettings,
+        state: &mut HistogramState,
+        timestamp: Timestamp,
         data: &[T],
+        key: F,
         weights: Vec<Option<OrderedFloat<f64>>>,
     ) -> Result<Self, Status>
     where
         T: Clone,
+        F: Fn(&T) -> &str,
     {
         let HistogramSettings {
             accumulate,
-            interval: _,
+            interval,
             size,
+            boundaries,
+            ema_alpha,
+            window,
+            hysteresis,
         } = *settings;
 
         let accumulate = accumulate.unwrap_or_default();
+        let interval = interval.unwrap_or(DEFAULT_INTERVAL);
 
         // Validate data
         assert_eq!(data.len(), weights.len());
@@ -44,6 +126,56 @@ impl<T> Histogram<T> {
             });
         }
 
+        // Validate smoothing
+        if ema_alpha.is_some() && window.is_some() {
+            return Err(Status {
+                reason: Reason::InvalidHistogram,
+                message: "Invalid histogram smoothing: Both EMA and time window are given".into(),
+                requeue: false,
+            });
+        }
+        if let Some(alpha) = ema_alpha
+            && !(alpha > 0.0 && alpha <= 1.0)
+        {
+            return Err(Status {
+                reason: Reason::InvalidHistogram,
+                message: format!("Invalid histogram EMA alpha: {alpha}"),
+                requeue: false,
+            });
+        }
+        if let Some(margin) = hysteresis
+            && !(margin >= 0.0 && margin.is_finite())
+        {
+            return Err(Status {
+                reason: Reason::InvalidHistogram,
+                message: format!("Invalid histogram hysteresis: {margin}"),
+                requeue: false,
+            });
+        }
+
+        // Forget the removed endpoints
+        let keys: Vec<_> = data.iter().map(key).collect();
+        {
+            let keys: BTreeSet<_> = keys.iter().copied().collect();
+            state.endpoints.retain(|key, _| keys.contains(key.as_str()));
+        }
+
+        // Smooth weights
+        let weights: Vec<_> = keys
+            .iter()
+            .zip(weights)
+            .map(|(&key, weight)| {
+                state.endpoints.entry(key.into()).or_default().smooth(
+                    ema_alpha,
+                    window,
+                    interval,
+                    timestamp,
+                    weight.map(OrderedFloat::into_inner),
+                )
+            })
+            .map(|weight| weight.map(OrderedFloat))
+            .collect();
+
         // Find min-max
         let (min, max) = weights
             .iter()
@@ -56,20 +188,49 @@ impl<T> Histogram<T> {
 
         // Normalize to [MIN, MAX]
         let scale = OrderedFloat((1u64 << (size - 1)) as _);
-        let weights = if min < max {
-            weights
-                .into_iter()
-                .map(|weight| {
-                    weight
-                        .map(|w| ((w - min) / (max - min) * scale).floor().0 as u64)
-                        .unwrap_or(DEFAULT_WEIGHT)
-                })
-                .collect()
+        let positions: Vec<_> = if min < max {
+            match boundaries {
+                HistogramBoundaries::Linear => weights
+                    .iter()
+                    .map(|weight| weight.map(|w| (w - min) / (max - min) * scale))
+                    .collect(),
+                HistogramBoundaries::Quantile => {
+                    let mut sorted: Vec<_> = weights.iter().copied().flatten().collect();
+                    sorted.sort();
+                    weights
+                        .iter()
+                        .map(|weight| weight.map(|w| quantile(&sorted, w) * scale))
+                        .collect()
+                }
+            }
         } else {
             // uniform dist
-            vec![DEFAULT_WEIGHT; weights.len()]
+            vec![None; weights.len()]
         };
 
+        // Quantize with hysteresis
+        let weights: Vec<_> = keys
+            .iter()
+            .zip(positions)
+            .map(|(&key, position)| {
+                let Some(position) = position else {
+                    return DEFAULT_WEIGHT;
+                };
+                let endpoint = state.endpoints.get_mut(key).expect("smoothed endpoint");
+                let level = match (endpoint.level, hysteresis) {
+                    (Some(last), Some(margin))
+                        if position.0 >= last as f64 - margin
+                            && position.0 < (last + 1) as f64 + margin =>
+                    {
+                        last
+                    }
+                    _ => position.floor().0 as u64,
+                };
+                endpoint.level = Some(level);
+                level
+            })
+            .collect();
+
         // Classify
         let mask = (1u64 << size) - 1;
         let size: usize = size as _;
@@ -105,3 +266,207 @@ impl<T> Histogram<T> {
         Ok(Self { data })
     }
 }
+
+/// Returns the quantile of the weight in `[0, 1]`, taking the middle rank of the ties.
+fn quantile(sorted: &[OrderedFloat<f64>], weight: OrderedFloat<f64>) -> OrderedFloat<f64> {
+    if sorted.len() < 2 {
+        return OrderedFloat(0.5);
+    }
+    let below = sorted.partition_point(|&x| x < weight);
+    let equal = sorted.partition_point(|&x| x <= weight) - below;
+    let rank = below as f64 + equal.saturating_sub(1) as f64 / 2.0;
+    OrderedFloat(rank / (sorted.len() - 1) as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEYS: [&str; 4] = ["a", "b", "c", "d"];
+
+    fn settings(size: u8) -> HistogramSettings {
+        HistogramSettings {
+            accumulate: None,
+            interval: None,
+            size,
+            boundaries: HistogramBoundaries::Linear,
+            ema_alpha: None,
+            window: None,
+            hysteresis: None,
+        }
+    }
+
+    /// Returns the highest bucket of each endpoint.
+    fn levels(
+        settings: &HistogramSettings,
+        state: &mut HistogramState,
+        timestamp: Timestamp,
+        weights: &[f64],
+    ) -> Vec<usize> {
+        let data = &KEYS[..weights.len()];
+        let weights = weights
+            .iter()
+            .copied()
+            .map(OrderedFloat)
+            .map(Some)
+            .collect();
+        let Histogram { data: buckets } =
+            Histogram::build(settings, state, timestamp, data, |key| *key, weights).unwrap();
+
+        data.iter()
+            .map(|key| {
+                (0..buckets.len())
+                    .rev()
+                    .find(|&offset| buckets[offset].contains(key))
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    fn at(seconds: i64) -> Timestamp {
+        Timestamp::from_second(1_700_000_000 + seconds).unwrap()
+    }
+
+    #[test]
+    fn build_linear_buckets() {
+        let settings = settings(3);
+        let mut state = HistogramState::default();
+        assert_eq!(
+            levels(&settings, &mut state, at(0), &[0.0, 1.0, 2.0, 4.0]),
+            [0, 0, 1, 2],
+        );
+    }
+
+    #[test]
+    fn build_quantile_buckets() {
+        // heavily skewed weights are crammed into the lowest bucket linearly
+        let mut settings = settings(3);
+        let mut state = HistogramState::default();
+        let weights = [1.0, 2.0, 3.0, 100.0];
+        assert_eq!(levels(&settings, &mut state, at(0), &weights), [0, 0, 0, 2]);
+
+        settings.boundaries = HistogramBoundaries::Quantile;
+        assert_eq!(levels(&settings, &mut state, at(0), &weights), [0, 0, 1, 2]);
+    }
+
+    #[test]
+    fn smooth_oscillating_trace_with_ema() {
+        // "b" oscillates between the low and high loads
+        let flaps = |settings: &HistogramSettings| {
+            let mut state = HistogramState::default();
+            let trace: Vec<_> = (0..8)
+                .map(|step| {
+                    let b = if step % 2 == 0 { 0.0 } else { 4.0 };
+                    levels(settings, &mut state, at(step), &[0.0, b, 4.0])[1]
+                })
+                .collect();
+            trace.windows(2).filter(|pair| pair[0] != pair[1]).count()
+        };
+
+        let mut settings = settings(3);
+        settings.interval = Some(1);
+        assert_eq!(flaps(&settings), 7);
+
+        settings.ema_alpha = Some(0.25);
+        assert_eq!(flaps(&settings), 1);
+    }
+
+    #[test]
+    fn scale_ema_by_elapsed_time() {
+        // decays from 4 toward 0 over an interval, reconciled in the given steps
+        let smooth = |steps: i64| {
+            let mut state = EndpointState::default();
+            state.smooth(Some(0.5), None, 10, at(0), Some(4.0));
+            (1..=steps)
+                .map(|step| state.smooth(Some(0.5), None, 10, at(10 * step / steps), Some(0.0)))
+                .last()
+                .flatten()
+                .unwrap()
+        };
+
+        // a single interval halves the weight, regardless of the number of reconciliations
+        assert_eq!(smooth(1), 2.0);
+        assert!((smooth(10) - 2.0).abs() < 1e-9);
+
+        // reconciling at once changes nothing
+        let mut state = EndpointState::default();
+        state.smooth(Some(0.5), None, 10, at(0), Some(4.0));
+        assert_eq!(
+            state.smooth(Some(0.5), None, 10, at(0), Some(0.0)),
+            Some(4.0)
+        );
+    }
+
+    #[test]
+    fn smooth_trace_within_time_window() {
+        let mut settings = settings(3);
+        settings.window = Some(10);
+        let mut state = HistogramState::default();
+
+        assert_eq!(
+            levels(&settings, &mut state, at(0), &[0.0, 4.0, 4.0]),
+            [0, 2, 2]
+        );
+        // averaged with the last sample: (4 + 0) / 2
+        assert_eq!(
+            levels(&settings, &mut state, at(5), &[0.0, 0.0, 4.0]),
+            [0, 1, 2]
+        );
+        // the first sample is out of the window
+        assert_eq!(
+            levels(&settings, &mut state, at(12), &[0.0, 0.0, 4.0]),
+            [0, 0, 2]
+        );
+    }
+
+    #[test]
+    fn keep_buckets_with_hysteresis() {
+        let mut settings = settings(3);
+        let mut state = HistogramState::default();
+
+        // "b" wobbles around the boundary of the levels 1 and 2
+        let trace = [1.9, 2.1, 1.95, 2.05];
+        let flaps = |settings: &HistogramSettings, state: &mut HistogramState| {
+            let trace: Vec<_> = trace
+                .iter()
+                .enumerate()
+                .map(|(step, &b)| levels(settings, state, at(step as _), &[0.0, b, 4.0])[1])
+                .collect();
+            trace.windows(2).filter(|pair| pair[0] != pair[1]).count()
+        };
+        assert_eq!(flaps(&settings, &mut state), 3);
+
+        settings.hysteresis = Some(0.2);
+        let mut state = HistogramState::default();
+        assert_eq!(flaps(&settings, &mut state), 0);
+
+        // leaves the bucket beyond the margin
+        assert_eq!(levels(&settings, &mut state, at(9), &[0.0, 3.0, 4.0])[1], 1);
+    }
+
+    #[test]
+    fn reject_invalid_smoothing() {
+        let mut state = HistogramState::default();
+        let weights = vec![Some(OrderedFloat(1.0))];
+
+        let mut settings = settings(3);
+        settings.ema_alpha = Some(1.5);
+        assert!(
+            Histogram::build(
+                &settings,
+                &mut state,
+                at(0),
+                &["a"],
+                |key| *key,
+                weights.clone()
+            )
+            .is_err()
+        );
+
+        settings.ema_alpha = Some(0.5);
+        settings.window = Some(60);
+        assert!(
+            Histogram::build(&settings, &mut state, at(0), &["a"], |key| *key, weights).is_err()
+        );
+    }
+}
@@ -4,14 +4,17 @@ use std::fmt;
 
 use anyhow::{Result, anyhow};
 use k8s_openapi::api::core::v1::Service;
-use kube::{Api, Error, ResourceExt, api::PostParams};
+use kube::{
+    Api, Error, Resource, ResourceExt,
+    api::{Patch, PatchParams, PostParams},
+};
 use openark_spectrum_api::{
     common::{ObjectReference, ServiceReference},
     metrics_class::{MetricsClassCrd, MetricsClassSpec},
     pool::PoolCrd,
 };
-
 use serde::{Serialize, de::DeserializeOwned};
+use serde_json::json;
 #[cfg(feature = "tracing")]
 use tracing::{Level, instrument};
 use url::Url;
@@ -150,3 +153,46 @@ where
         None => api.create(post_params, item).await,
     }
 }
+
+/// Adds or removes the finalizer of the object.
+pub(crate) async fn patch_finalizer<K>(
+    api: &Api<K>,
+    object: &K,
+    pp: &PatchParams,
+    finalizer: &str,
+    add: bool,
+) -> Result<(), Error>
+where
+    K: Clone + fmt::Debug + DeserializeOwned + Resource,
+{
+    let finalizers = object.finalizers();
+    let index = finalizers.iter().position(|item| item == finalizer);
+    let patch = match (index, add) {
+        (None, true) if finalizers.is_empty() => json!([
+            { "op": "add", "path": "/metadata/finalizers", "value": [finalizer] },
+        ]),
+        (None, true) => json!([
+            { "op": "add", "path": "/metadata/finalizers/-", "value": finalizer },
+        ]),
+        // NOTE: remove our finalizer only, keeping the others
+        (Some(index), false) => {
+            let path = format!("/metadata/finalizers/{index}");
+            json!([
+                { "op": "test", "path": &path, "value": finalizer },
+                { "op": "remove", "path": &path },
+            ])
+        }
+        (Some(_), true) | (None, false) => return Ok(()),
+    };
+    let patch: ::json_patch::Patch = ::serde_json::from_value(patch).map_err(Error::SerdeError)?;
+
+    match api
+        .patch(&object.name_any(), pp, &Patch::Json::<()>(patch))
+        .await
+    {
+        Ok(_) => Ok(()),
+        // already released
+        Err(Error::Api(error)) if !add && error.code == 404 => Ok(()),
+        Err(error) => Err(error),
+    }
+}
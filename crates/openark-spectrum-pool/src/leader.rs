@@ -0,0 +1,307 @@
+use std::{
+    collections::BTreeMap,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use actix_web::{
+    rt::{spawn, time::sleep},
+    web::Data,
+};
+use anyhow::{Result, anyhow};
+use clap::Parser;
+use jiff::{SignedDuration, Timestamp};
+use k8s_openapi::{
+    api::{
+        coordination::v1::{Lease, LeaseSpec},
+        core::v1::ConfigMap,
+    },
+    apimachinery::pkg::apis::meta::v1::MicroTime,
+};
+use kube::{
+    Api, Client, Error,
+    api::{ObjectMeta, PostParams},
+};
+#[cfg(feature = "tracing")]
+use tracing::{error, info, warn};
+
+use crate::store::{Snapshot, Store};
+
+/// The key of the snapshot in the ConfigMap.
+const SNAPSHOT_KEY: &str = "snapshot.json";
+
+#[derive(Clone, Debug, Parser)]
+#[command(author, version, about, long_about = None)]
+pub(crate) struct LeaderArgs {
+    /// The name of the Lease to elect a leader among the replicas.
+    /// The replica serves standalone if not given.
+    #[arg(long, env = "OPENARK_SPECTRUM_POOL_LEASE_NAME")]
+    lease_name: Option<String>,
+
+    #[arg(
+        long,
+        env = "OPENARK_SPECTRUM_POOL_LEASE_DURATION_SECONDS",
+        default_value_t = 15
+    )]
+    lease_duration_seconds: u32,
+
+    /// The name of the ConfigMap to persist the snapshots of the bindings,
+    /// which are restored whenever a replica becomes the leader.
+    #[arg(long, env = "OPENARK_SPECTRUM_POOL_SNAPSHOT_NAME")]
+    snapshot_name: Option<String>,
+
+    /// The identity of this replica, e.g. the pod name.
+    #[arg(long, env = "POD_NAME")]
+    pod_name: Option<String>,
+}
+
+impl LeaderArgs {
+    pub(crate) async fn build(self, store: Data<Store>) -> Result<Data<Leader>> {
+        let Self {
+            lease_name,
+            lease_duration_seconds,
+            snapshot_name,
+            pod_name,
+        } = self;
+
+        if lease_name.is_none() && snapshot_name.is_none() {
+            return Ok(Data::new(Leader {
+                is_leader: AtomicBool::new(true),
+            }));
+        }
+
+        let kube = Client::try_default().await?;
+        let namespace = kube.default_namespace().to_string();
+        let lease = match lease_name {
+            Some(name) => Some(LeaseHolder {
+                api: Api::namespaced(kube.clone(), &namespace),
+                duration: lease_duration_seconds.try_into()?,
+                identity: pod_name
+                    .ok_or_else(|| anyhow!("POD_NAME is required to elect a leader"))?,
+                name,
+            }),
+            None => None,
+        };
+        let snapshot = snapshot_name.map(|name| SnapshotHolder {
+            api: Api::namespaced(kube, &namespace),
+            last: None,
+            name,
+        });
+
+        let leader = Data::new(Leader {
+            is_leader: AtomicBool::new(false),
+        });
+        let interval = Duration::from_secs((lease_duration_seconds / 3).max(1).into());
+        spawn(run(Data::clone(&leader), store, lease, snapshot, interval));
+        Ok(leader)
+    }
+}
+
+/// The leadership of this replica, where only the leader may commit the bindings.
+pub(crate) struct Leader {
+    is_leader: AtomicBool,
+}
+
+impl Leader {
+    pub(crate) fn is_leader(&self) -> bool {
+        self.is_leader.load(Ordering::SeqCst)
+    }
+}
+
+struct LeaseHolder {
+    api: Api<Lease>,
+    duration: i32,
+    identity: String,
+    name: String,
+}
+
+impl LeaseHolder {
+    /// Acquires or renews the lease, returning `true` if this replica holds it.
+    async fn try_acquire(&self) -> Result<bool> {
+        let Self {
+            api,
+            duration,
+            identity,
+            name,
+        } = self;
+        let now = Timestamp::now();
+        let pp = PostParams::default();
+
+        let Some(mut lease) = api.get_opt(name).await? else {
+            let lease = Lease {
+                metadata: ObjectMeta {
+                    name: Some(name.clone()),
+                    ..Default::default()
+                },
+                spec: Some(LeaseSpec {
+                    acquire_time: Some(MicroTime(now)),
+                    holder_identity: Some(identity.clone()),
+                    lease_duration_seconds: Some(*duration),
+                    lease_transitions: Some(0),
+                    renew_time: Some(MicroTime(now)),
+                    ..Default::default()
+                }),
+            };
+            return match api.create(&pp, &lease).await {
+                Ok(_) => Ok(true),
+                Err(Error::Api(error)) if error.code == 409 => Ok(false),
+                Err(error) => Err(error.into()),
+            };
+        };
+
+        let spec = lease.spec.get_or_insert_default();
+        let is_holder = spec.holder_identity.as_deref() == Some(identity.as_str());
+        let is_expired = spec
+            .renew_time
+            .as_ref()
+            .is_none_or(|MicroTime(renew_time)| {
+                let duration = spec.lease_duration_seconds.unwrap_or(*duration);
+                *renew_time + SignedDuration::from_secs(duration.into()) < now
+            });
+        if !is_holder && !is_expired {
+            return Ok(false);
+        }
+
+        if !is_holder {
+            spec.acquire_time = Some(MicroTime(now));
+            spec.holder_identity = Some(identity.clone());
+            spec.lease_transitions = Some(spec.lease_transitions.unwrap_or(0) + 1);
+        }
+        spec.lease_duration_seconds = Some(*duration);
+        spec.renew_time = Some(MicroTime(now));
+
+        // NOTE: the resource version of the lease rejects the concurrent updates
+        match api.replace(name, &pp, &lease).await {
+            Ok(_) => Ok(true),
+            Err(Error::Api(error)) if error.code == 409 => Ok(false),
+            Err(error) => Err(error.into()),
+        }
+    }
+}
+
+struct SnapshotHolder {
+    api: Api<ConfigMap>,
+    /// The last persisted snapshot, which skips persisting the unchanged ones
+    last: Option<String>,
+    name: String,
+}
+
+impl SnapshotHolder {
+    async fn restore(&mut self, store: &Store) -> Result<()> {
+        let Some(config_map) = self.api.get_opt(&self.name).await? else {
+            return Ok(());
+        };
+        let Some(data) = config_map
+            .data
+            .as_ref()
+            .and_then(|data| data.get(SNAPSHOT_KEY))
+        else {
+            return Ok(());
+        };
+
+        let snapshot: Snapshot = ::serde_json::from_str(data)?;
+        store.import(&snapshot)?;
+        self.last = Some(data.clone());
+        Ok(())
+    }
+
+    async fn persist(&mut self, store: &Store) -> Result<()> {
+        let data = ::serde_json::to_string(&store.export()?)?;
+        if self.last.as_ref() == Some(&data) {
+            return Ok(());
+        }
+
+        let pp = PostParams::default();
+        let config_map = ConfigMap {
+            metadata: ObjectMeta {
+                name: Some(self.name.clone()),
+                ..Default::default()
+            },
+            data: Some(BTreeMap::from_iter([(SNAPSHOT_KEY.into(), data.clone())])),
+            ..Default::default()
+        };
+        match self.api.get_opt(&self.name).await? {
+            Some(last) => {
+                let config_map = ConfigMap {
+                    metadata: ObjectMeta {
+                        resource_version: last.metadata.resource_version,
+                        ..config_map.metadata
+                    },
+                    ..config_map
+                };
+                self.api.replace(&self.name, &pp, &config_map).await?
+            }
+            None => self.api.create(&pp, &config_map).await?,
+        };
+        self.last = Some(data);
+        Ok(())
+    }
+}
+
+async fn run(
+    leader: Data<Leader>,
+    store: Data<Store>,
+    lease: Option<LeaseHolder>,
+    mut snapshot: Option<SnapshotHolder>,
+    interval: Duration,
+) {
+    loop {
+        let is_leader = match &lease {
+            Some(lease) => match lease.try_acquire().await {
+                Ok(is_leader) => is_leader,
+                Err(error) => {
+                    #[cfg(feature = "tracing")]
+                    error!("Failed to acquire the lease: {error}");
+
+                    #[cfg(not(feature = "tracing"))]
+                    let _ = error;
+                    false
+                }
+            },
+            None => true,
+        };
+
+        match (leader.is_leader(), is_leader) {
+            // Restore the bindings before serving as a leader
+            (false, true) => {
+                let result = match snapshot.as_mut() {
+                    Some(snapshot) => snapshot.restore(&store).await,
+                    None => Ok(()),
+                };
+                match result {
+                    Ok(()) => {
+                        #[cfg(feature = "tracing")]
+                        info!("Became the leader");
+                        leader.is_leader.store(true, Ordering::SeqCst);
+                    }
+                    Err(error) => {
+                        #[cfg(feature = "tracing")]
+                        error!("Failed to restore the snapshot: {error}");
+
+                        #[cfg(not(feature = "tracing"))]
+                        let _ = error;
+                    }
+                }
+            }
+            (true, false) => {
+                #[cfg(feature = "tracing")]
+                warn!("Lost the leadership");
+                leader.is_leader.store(false, Ordering::SeqCst);
+            }
+            (false, false) | (true, true) => (),
+        }
+
+        if leader.is_leader()
+            && let Some(snapshot) = snapshot.as_mut()
+            && let Err(error) = snapshot.persist(&store).await
+        {
+            #[cfg(feature = "tracing")]
+            error!("Failed to persist the snapshot: {error}");
+
+            #[cfg(not(feature = "tracing"))]
+            let _ = error;
+        }
+
+        sleep(interval).await;
+    }
+}
@@ -1,3 +1,4 @@
+mod leader;
 mod pool;
 mod routes;
 mod store;
@@ -19,10 +20,15 @@ async fn ping() -> impl Responder {
     HttpResponse::Ok().finish()
 }
 
-#[cfg_attr(feature = "tracing", instrument(level = Level::INFO))]
+#[cfg_attr(feature = "tracing", instrument(level = Level::INFO, skip_all))]
 #[get("health")]
-async fn health() -> impl Responder {
-    HttpResponse::Ok().json(HealthState::Healthy)
+async fn health(leader: web::Data<self::leader::Leader>) -> impl Responder {
+    // NOTE: only the leader is ready to serve the bindings
+    if leader.is_leader() {
+        HttpResponse::Ok().json(HealthState::Healthy)
+    } else {
+        HttpResponse::ServiceUnavailable().finish()
+    }
 }
 
 #[derive(Clone, Debug, Parser)]
@@ -41,6 +47,9 @@ struct Args {
     )]
     bind_addr: SocketAddr,
 
+    #[command(flatten)]
+    leader: self::leader::LeaderArgs,
+
     #[command(flatten)]
     store: self::store::StoreArgs,
 }
@@ -50,6 +59,7 @@ async fn try_main(args: Args) -> Result<()> {
     let Args {
         mut base_url,
         bind_addr: addr,
+        leader,
         store,
     } = args;
 
@@ -59,10 +69,13 @@ async fn try_main(args: Args) -> Result<()> {
     }
 
     let store = Data::new(store.build()?);
+    let leader = leader.build(Data::clone(&store)).await?;
 
     // Start web server
     HttpServer::new(move || {
-        let app = App::new().app_data(Data::clone(&store));
+        let app = App::new()
+            .app_data(Data::clone(&leader))
+            .app_data(Data::clone(&store));
 
         let app = app.service(
             web::scope(&base_url)
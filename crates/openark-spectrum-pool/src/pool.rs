@@ -1,21 +1,75 @@
-use std::sync::{
-    Arc,
-    atomic::{AtomicUsize, Ordering},
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
 };
 
-use actix_web::rt::spawn;
-use anyhow::Result;
+use actix_web::rt::{
+    net::TcpStream,
+    spawn,
+    time::{sleep, timeout},
+};
+use anyhow::{Result, anyhow, bail};
 use openark_spectrum_api::{
-    pool_claim::{PoolResourceHttpBody, PoolResourceHttpProbe, PoolResourceProbe},
+    pool_claim::{
+        PoolResourceGrpcProbe, PoolResourceHttpBody, PoolResourceHttpProbe, PoolResourceProbe,
+        PoolResourceProbePolicy, PoolResourceTcpProbe,
+    },
     schema::CommitState,
 };
 use reqwest::{Client, Error};
+use serde_json::{Map, Value};
 #[cfg(feature = "tracing")]
-use tracing::{error, info};
+use tracing::{error, info, warn};
+
+/// The variables of a resource, which are templated into the probes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct ProbeContext {
+    pub(crate) address: String,
+    pub(crate) claim: String,
+    pub(crate) namespace: String,
+}
+
+impl ProbeContext {
+    /// Replaces `$address`, `$claim` and `$namespace` in the template.
+    fn render(&self, template: &str) -> String {
+        template
+            .replace("$address", &self.address)
+            .replace("$claim", &self.claim)
+            .replace("$namespace", &self.namespace)
+    }
+
+    fn render_value(&self, value: &Value) -> Value {
+        match value {
+            Value::String(value) => Value::String(self.render(value)),
+            Value::Array(values) => Value::Array(
+                values
+                    .iter()
+                    .map(|value| self.render_value(value))
+                    .collect(),
+            ),
+            Value::Object(map) => Value::Object(
+                map.iter()
+                    .map(|(key, value)| (self.render(key), self.render_value(value)))
+                    .collect(),
+            ),
+            value => value.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Clients {
+    http: Client,
+    /// A client speaking HTTP/2 over cleartext, which is required by the insecure gRPC probes
+    h2c: Client,
+}
 
 async fn execute_probe_http(
     client: &Client,
-    address: &str,
+    ctx: &ProbeContext,
     probe: &PoolResourceHttpProbe,
 ) -> Result<()> {
     let PoolResourceHttpProbe {
@@ -23,26 +77,47 @@ async fn execute_probe_http(
         path,
         port,
         secure,
+        headers,
         body,
+        policy: _,
     } = probe;
 
     let protocol = if *secure { "https" } else { "http" };
     let method = (*method).into();
+    let address = &ctx.address;
+    let path = ctx.render(path);
     let url = format!("{protocol}://{address}:{port}{path}");
-    let builder = client.request(method, &url);
-
-    #[cfg(feature = "tracing")]
-    match body {
-        Some(PoolResourceHttpBody::JsonBody(body)) => info!(
-            "Start probe: {url:?} {body}",
-            body = ::serde_json::to_string(body)?,
-        ),
-        None => info!("Start probe: {url:?}"),
+    let mut builder = client.request(method, &url);
+    for (key, value) in headers {
+        builder = builder.header(key, ctx.render(value));
     }
 
     let builder = match body {
-        Some(PoolResourceHttpBody::JsonBody(body)) => builder.json(body),
-        None => builder,
+        Some(PoolResourceHttpBody::JsonBody(body)) => {
+            let body: Map<_, _> = body
+                .iter()
+                .map(|(key, value)| (ctx.render(key), ctx.render_value(value)))
+                .collect();
+
+            #[cfg(feature = "tracing")]
+            info!(
+                "Start probe: {url:?} {body}",
+                body = ::serde_json::to_string(&body)?,
+            );
+            builder.json(&body)
+        }
+        Some(PoolResourceHttpBody::TextBody(body)) => {
+            let body = ctx.render(body);
+
+            #[cfg(feature = "tracing")]
+            info!("Start probe: {url:?} {body:?}");
+            builder.body(body)
+        }
+        None => {
+            #[cfg(feature = "tracing")]
+            info!("Start probe: {url:?}");
+            builder
+        }
     };
 
     let response = builder.send().await?;
@@ -54,96 +129,457 @@ async fn execute_probe_http(
     #[cfg(feature = "tracing")]
     {
         let status = response.status();
-        info!("Complete commit probe: {url:?} {{{status}}}");
+        info!("Complete probe: {url:?} {{{status}}}");
     }
 
     Ok(())
 }
 
-async fn execute_probe(client: &Client, address: &str, probe: &PoolResourceProbe) -> Result<()> {
-    match probe {
-        PoolResourceProbe::Http(probe) => execute_probe_http(client, address, probe).await,
+async fn execute_probe_tcp(ctx: &ProbeContext, probe: &PoolResourceTcpProbe) -> Result<()> {
+    let PoolResourceTcpProbe { port, policy: _ } = probe;
+
+    #[cfg(feature = "tracing")]
+    info!(
+        "Start probe: tcp://{address}:{port}",
+        address = &ctx.address
+    );
+
+    let _ = TcpStream::connect((ctx.address.as_str(), *port)).await?;
+
+    #[cfg(feature = "tracing")]
+    info!(
+        "Complete probe: tcp://{address}:{port}",
+        address = &ctx.address
+    );
+
+    Ok(())
+}
+
+/// The `SERVING` status of `grpc.health.v1.HealthCheckResponse`.
+const GRPC_HEALTH_SERVING: u64 = 1;
+
+/// Encodes a length-prefixed `grpc.health.v1.HealthCheckRequest` message.
+fn encode_grpc_health_request(service: &str) -> Vec<u8> {
+    let mut message = Vec::with_capacity(service.len() + 8);
+    if !service.is_empty() {
+        // field 1 (service), length-delimited
+        message.push(0x0a);
+        write_varint(&mut message, service.len() as u64);
+        message.extend_from_slice(service.as_bytes());
     }
+
+    let mut frame = Vec::with_capacity(message.len() + 5);
+    frame.push(0); // uncompressed
+    frame.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&message);
+    frame
+}
+
+/// Decodes the status of a length-prefixed `grpc.health.v1.HealthCheckResponse` message.
+fn decode_grpc_health_response(frame: &[u8]) -> Option<u64> {
+    let (&compressed, frame) = frame.split_first()?;
+    if compressed != 0 || frame.len() < 4 {
+        return None;
+    }
+    let (len, frame) = frame.split_at(4);
+    let len = u32::from_be_bytes(len.try_into().ok()?) as usize;
+    let mut message = frame.get(..len)?;
+
+    let mut status = 0; // UNKNOWN
+    while !message.is_empty() {
+        let tag = read_varint(&mut message)?;
+        match (tag >> 3, tag & 0x07) {
+            (1, 0) => status = read_varint(&mut message)?,
+            (_, 0) => {
+                read_varint(&mut message)?;
+            }
+            (_, 1) => message = message.get(8..)?,
+            (_, 2) => {
+                let len = read_varint(&mut message)? as usize;
+                message = message.get(len..)?;
+            }
+            (_, 5) => message = message.get(4..)?,
+            _ => return None,
+        }
+    }
+    Some(status)
+}
+
+fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn read_varint(buf: &mut &[u8]) -> Option<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = buf.split_first()?;
+        *buf = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+async fn execute_probe_grpc(
+    clients: &Clients,
+    ctx: &ProbeContext,
+    probe: &PoolResourceGrpcProbe,
+) -> Result<()> {
+    let PoolResourceGrpcProbe {
+        port,
+        service,
+        secure,
+        policy: _,
+    } = probe;
+
+    let (protocol, client) = if *secure {
+        ("https", &clients.http)
+    } else {
+        ("http", &clients.h2c)
+    };
+    let address = &ctx.address;
+    let url = format!("{protocol}://{address}:{port}/grpc.health.v1.Health/Check");
+    let service = ctx.render(service);
+
+    #[cfg(feature = "tracing")]
+    info!("Start probe: {url:?} {service:?}");
+
+    let response = client
+        .post(&url)
+        .header("content-type", "application/grpc")
+        .header("te", "trailers")
+        .body(encode_grpc_health_request(&service))
+        .send()
+        .await?
+        .error_for_status()?;
+
+    // NOTE: the trailers-only responses carry the status in the headers
+    if let Some(status) = response.headers().get("grpc-status")
+        && status != "0"
+    {
+        bail!("Failed gRPC health check: {url:?} {{grpc-status={status:?}}}")
+    }
+
+    let body = response.bytes().await?;
+    match decode_grpc_health_response(&body) {
+        Some(GRPC_HEALTH_SERVING) => {
+            #[cfg(feature = "tracing")]
+            info!("Complete probe: {url:?} {service:?}");
+            Ok(())
+        }
+        Some(status) => bail!("Not serving: {url:?} {service:?} {{status={status}}}"),
+        None => bail!("Invalid gRPC health check response: {url:?}"),
+    }
+}
+
+async fn execute_probe_once(
+    clients: &Clients,
+    ctx: &ProbeContext,
+    probe: &PoolResourceProbe,
+) -> Result<()> {
+    let duration = Duration::from_secs(probe.policy().timeout());
+    let future = async {
+        match probe {
+            PoolResourceProbe::Http(probe) => execute_probe_http(&clients.http, ctx, probe).await,
+            PoolResourceProbe::Tcp(probe) => execute_probe_tcp(ctx, probe).await,
+            PoolResourceProbe::Grpc(probe) => execute_probe_grpc(clients, ctx, probe).await,
+        }
+    };
+
+    timeout(duration, future)
+        .await
+        .map_err(|_| anyhow!("Timed out after {duration:?}"))?
+}
+
+async fn execute_probe(
+    clients: &Clients,
+    ctx: &ProbeContext,
+    probe: &PoolResourceProbe,
+) -> Result<()> {
+    let policy = probe.policy();
+    let retries = policy.retries();
+
+    // NOTE: the attempts are bounded in total, not to block the claim forever
+    let duration = Duration::from_secs(PoolResourceProbePolicy::MAX_TOTAL_SECONDS);
+    let future = async {
+        let mut retry = 0;
+        loop {
+            match execute_probe_once(clients, ctx, probe).await {
+                Ok(()) => break Ok(()),
+                Err(error) if retry < retries => {
+                    retry += 1;
+                    let backoff = Duration::from_secs(policy.backoff(retry));
+
+                    #[cfg(feature = "tracing")]
+                    {
+                        let address = &ctx.address;
+                        warn!(
+                            "Retrying probe ({address}) in {backoff:?} [{retry}/{retries}]: {error}"
+                        )
+                    }
+
+                    #[cfg(not(feature = "tracing"))]
+                    {
+                        let _ = error;
+                    }
+
+                    sleep(backoff).await;
+                }
+                Err(error) => break Err(error),
+            }
+        }
+    };
+
+    timeout(duration, future)
+        .await
+        .map_err(|_| anyhow!("Timed out after {duration:?} in total"))?
+}
+
+/// Executes the probes in order, returning `true` if all of them have succeeded.
+async fn execute_probes(
+    clients: &Clients,
+    ctx: &ProbeContext,
+    probes: &[PoolResourceProbe],
+    phase: &str,
+) -> bool {
+    for (index, probe) in probes.iter().enumerate() {
+        if let Err(error) = execute_probe(clients, ctx, probe).await {
+            #[cfg(feature = "tracing")]
+            {
+                let address = &ctx.address;
+                error!("Failed to execute {phase} probe ({address})[{index}]: {error}")
+            }
+
+            #[cfg(not(feature = "tracing"))]
+            {
+                let _ = (error, index, phase);
+            }
+            return false;
+        }
+    }
+    true
 }
 
 pub(crate) struct Pool {
-    client: Client,
+    clients: Clients,
     semaphore: Arc<AtomicUsize>,
 }
 
 impl Pool {
     pub fn new(size: usize) -> Result<Self, Error> {
         Ok(Self {
-            client: Client::builder().pool_max_idle_per_host(size).build()?,
+            clients: Clients {
+                http: Client::builder().pool_max_idle_per_host(size).build()?,
+                h2c: Client::builder()
+                    .http2_prior_knowledge()
+                    .pool_max_idle_per_host(size)
+                    .build()?,
+            },
             semaphore: Arc::new(AtomicUsize::new(size)),
         })
     }
 
+    /// Spawns a task running the probes, returning `Pending` if the pool is exhausted.
+    fn spawn<F, Fut>(&self, task: F) -> CommitState
+    where
+        F: 'static + FnOnce(Clients) -> Fut,
+        Fut: 'static + Future<Output = ()>,
+    {
+        let semaphore = self.semaphore.clone();
+        let acquired = semaphore
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |permits| {
+                permits.checked_sub(1)
+            })
+            .is_ok();
+        if !acquired {
+            return CommitState::Pending;
+        }
+
+        let future = task(self.clients.clone());
+        spawn(async move {
+            future.await;
+            semaphore.fetch_add(1, Ordering::SeqCst);
+        });
+        CommitState::Preparing
+    }
+
+    /// Binds a resource, running the `postStop` probes of the last claim if any,
+    /// and then the `preStart` probes of the new claim.
+    ///
+    /// The failures of the `postStop` probes are reported, but do not block the binding.
     pub fn commit<F>(
         &self,
-        address: &str,
-        probes: &[PoolResourceProbe],
+        ctx: ProbeContext,
+        post_stop: Option<(ProbeContext, Vec<PoolResourceProbe>)>,
+        pre_start: &[PoolResourceProbe],
         callback: F,
-    ) -> Result<CommitState>
+    ) -> CommitState
     where
         F: 'static + FnOnce(bool) -> Result<()>,
     {
-        let semaphore = self.semaphore.clone();
-        match semaphore.load(Ordering::SeqCst) {
-            0 => Ok(CommitState::Pending),
-            _ => {
-                semaphore.fetch_sub(1, Ordering::SeqCst);
-                let client = self.client.clone();
-                let address = address.to_string();
-                let probes = probes.to_vec();
-
-                spawn(async move {
-                    let mut is_completed = true;
-                    for (index, probe) in probes.iter().enumerate() {
-                        match execute_probe(&client, &address, probe).await {
-                            Ok(()) => continue,
-                            Err(error) => {
-                                #[cfg(feature = "tracing")]
-                                {
-                                    error!("Failed to commit probe ({address})[{index}]: {error}")
-                                }
-
-                                #[cfg(not(feature = "tracing"))]
-                                {
-                                    let _ = error;
-                                }
-
-                                is_completed = false;
-                                break;
-                            }
-                        }
+        let pre_start = pre_start.to_vec();
+        self.spawn(move |clients| async move {
+            if let Some((last_ctx, probes)) = post_stop {
+                execute_probes(&clients, &last_ctx, &probes, "postStop").await;
+            }
+            let is_completed = execute_probes(&clients, &ctx, &pre_start, "preStart").await;
+
+            let address = &ctx.address;
+            match callback(is_completed) {
+                Ok(()) =>
+                {
+                    #[cfg(feature = "tracing")]
+                    if is_completed {
+                        info!("Completed commit probes ({address})")
+                    } else {
+                        info!("Reverted commit probes ({address})")
+                    }
+                }
+                Err(error) => {
+                    #[cfg(feature = "tracing")]
+                    {
+                        error!("Failed to commit probes ({address}): {error}")
                     }
 
-                    match callback(is_completed) {
-                        Ok(()) =>
-                        {
-                            #[cfg(feature = "tracing")]
-                            if is_completed {
-                                info!("Completed commit probes ({address})")
-                            } else {
-                                info!("Reverted commit probes ({address})")
-                            }
-                        }
-                        Err(error) => {
-                            #[cfg(feature = "tracing")]
-                            {
-                                error!("Failed to commit probes ({address}): {error}")
-                            }
-
-                            #[cfg(not(feature = "tracing"))]
-                            {
-                                let _ = error;
-                            }
-                        }
+                    #[cfg(not(feature = "tracing"))]
+                    {
+                        let _ = (address, error);
                     }
-                });
-                Ok(CommitState::Preparing)
+                }
             }
-        }
+        })
+    }
+
+    /// Releases a resource, running the `postStop` probes of the last claim.
+    pub fn release<F>(
+        &self,
+        ctx: ProbeContext,
+        post_stop: Vec<PoolResourceProbe>,
+        callback: F,
+    ) -> CommitState
+    where
+        F: 'static + FnOnce() -> Result<()>,
+    {
+        self.spawn(move |clients| async move {
+            let is_completed = execute_probes(&clients, &ctx, &post_stop, "postStop").await;
+
+            let address = &ctx.address;
+            match callback() {
+                Ok(()) =>
+                {
+                    #[cfg(feature = "tracing")]
+                    if is_completed {
+                        info!("Completed release probes ({address})")
+                    } else {
+                        info!("Released without reset ({address})")
+                    }
+                }
+                Err(error) => {
+                    #[cfg(feature = "tracing")]
+                    {
+                        error!("Failed to release probes ({address}): {error}")
+                    }
+
+                    #[cfg(not(feature = "tracing"))]
+                    {
+                        let _ = (address, error, is_completed);
+                    }
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_probe_templates() {
+        let ctx = ProbeContext {
+            address: "10.0.0.1".into(),
+            claim: "team-a".into(),
+            namespace: "default".into(),
+        };
+
+        assert_eq!(
+            ctx.render("/reset/$claim?ns=$namespace"),
+            "/reset/team-a?ns=default"
+        );
+
+        let value = ::serde_json::json!({
+            "$claim": ["$address", 1, true],
+            "owner": { "namespace": "$namespace" },
+        });
+        assert_eq!(
+            ctx.render_value(&value),
+            ::serde_json::json!({
+                "team-a": ["10.0.0.1", 1, true],
+                "owner": { "namespace": "default" },
+            }),
+        );
+    }
+
+    #[test]
+    fn encode_grpc_health_messages() {
+        assert_eq!(encode_grpc_health_request(""), [0, 0, 0, 0, 0]);
+        assert_eq!(
+            encode_grpc_health_request("svc"),
+            [0, 0, 0, 0, 5, 0x0a, 3, b's', b'v', b'c'],
+        );
+
+        assert_eq!(
+            decode_grpc_health_response(&[0, 0, 0, 0, 2, 0x08, 1]),
+            Some(1)
+        );
+        assert_eq!(
+            decode_grpc_health_response(&[0, 0, 0, 0, 2, 0x08, 2]),
+            Some(2)
+        );
+        assert_eq!(decode_grpc_health_response(&[0, 0, 0, 0, 0]), Some(0));
+        assert_eq!(decode_grpc_health_response(&[0, 0, 0, 0, 3, 0x08]), None);
+    }
+
+    #[test]
+    fn back_off_exponentially() {
+        let policy = PoolResourceProbePolicy::default();
+        assert_eq!(policy.backoff(1), 1);
+        assert_eq!(policy.backoff(2), 2);
+        assert_eq!(policy.backoff(5), 16);
+        assert_eq!(
+            policy.backoff(10),
+            PoolResourceProbePolicy::MAX_BACKOFF_SECONDS,
+        );
+
+        let policy = PoolResourceProbePolicy {
+            backoff_seconds: Some(5),
+            ..Default::default()
+        };
+        assert_eq!(policy.backoff(0), 5);
+        assert_eq!(policy.backoff(3), 20);
+
+        // never overflows
+        assert_eq!(
+            policy.backoff(u32::MAX),
+            PoolResourceProbePolicy::MAX_BACKOFF_SECONDS,
+        );
+    }
+
+    #[test]
+    fn clamp_retries() {
+        let policy = PoolResourceProbePolicy::default();
+        assert_eq!(policy.retries(), 0);
+
+        let policy = PoolResourceProbePolicy {
+            retries: Some(u32::MAX),
+            ..Default::default()
+        };
+        assert_eq!(policy.retries(), PoolResourceProbePolicy::MAX_RETRIES);
     }
 }
@@ -1,11 +1,18 @@
 mod service;
+mod snapshot;
 
 use actix_web::{Scope, web};
 
 pub fn build() -> Scope {
-    web::scope("").service(
-        web::scope("v1/Service")
-            .service(self::service::post)
-            .service(self::service::post_commit),
-    )
+    web::scope("")
+        .service(
+            web::scope("v1/Service")
+                .service(self::service::post)
+                .service(self::service::post_commit),
+        )
+        .service(
+            web::scope("v1/snapshot")
+                .service(self::snapshot::get)
+                .service(self::snapshot::put),
+        )
 }
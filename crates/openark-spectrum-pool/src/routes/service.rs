@@ -1,4 +1,4 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
 use actix_web::{HttpResponse, Responder, post, web};
 use anyhow::Result;
@@ -10,7 +10,7 @@ use openark_spectrum_api::{
 #[cfg(feature = "tracing")]
 use tracing::{Level, instrument, warn};
 
-use crate::store::Store;
+use crate::{leader::Leader, pool::ProbeContext, store::Store};
 
 #[cfg_attr(feature = "tracing", instrument(level = Level::INFO, skip_all))]
 #[post("")]
@@ -55,9 +55,14 @@ fn try_handle(store: web::Data<Store>, args: PoolRequest<'static>) -> Result<Poo
 #[cfg_attr(feature = "tracing", instrument(level = Level::INFO, skip_all))]
 #[post("commit")]
 async fn post_commit(
+    leader: web::Data<Leader>,
     store: web::Data<Store>,
     web::Json(args): web::Json<PoolCommitRequest<'static>>,
 ) -> impl Responder {
+    if !leader.is_leader() {
+        return HttpResponse::ServiceUnavailable().json("Not a leader");
+    }
+
     match try_handle_commit(store, args) {
         Ok(response) => HttpResponse::Ok().json(response),
         Err(error) => {
@@ -69,27 +74,57 @@ async fn post_commit(
 }
 
 fn try_handle_commit<'a>(store: web::Data<Store>, args: PoolCommitRequest<'a>) -> Result<()> {
-    let PoolCommitRequest { items } = args;
+    let PoolCommitRequest { items, pool } = args;
+
+    let build_resource = |name: &str, namespace: &str| ObjectReference {
+        group: "discovery.k8s.io".into(),
+        kind: "Endpoint".into(),
+        name: name.to_string(),
+        namespace: Some(namespace.into()),
+    };
 
     // Collect last states
-    let last_states = store.read(|txn| {
-        items
+    let (last_states, releases) = store.read(|txn| {
+        let last_states = items
             .iter()
             .map(|item| {
                 item.pool
                     .resources
                     .iter()
-                    .map(|name| {
-                        txn.get(&ObjectReference {
-                            group: "discovery.k8s.io".into(),
-                            kind: "Endpoint".into(),
-                            name: name.to_string(),
-                            namespace: Some(item.pool.namespace.clone()),
-                        })
-                    })
+                    .map(|name| txn.get(&build_resource(name, &item.pool.namespace)))
                     .collect::<Result<Vec<_>, _>>()
             })
-            .collect::<Result<Vec<_>, _>>()
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // Collect the resources which are not bound to any claim anymore,
+        // including those of the claims missing in the request (e.g. deleted ones)
+        let is_bound = |name: &str, namespace: &str| {
+            items.iter().any(|item| {
+                item.pool.namespace == namespace
+                    && item.pool.resources.iter().any(|bound| *bound == name)
+            })
+        };
+        let mut releases = BTreeSet::default();
+        if let Some(pool) = &pool {
+            let namespace = pool.namespace.as_str();
+            for name in &pool.resources {
+                if txn.get(&build_resource(name, namespace))?.claim.is_some()
+                    && !is_bound(name, namespace)
+                {
+                    releases.insert((name.to_string(), namespace));
+                }
+            }
+        }
+        for item in &items {
+            let namespace = item.pool.namespace.as_str();
+            let prefix = build_resource("", namespace).to_string();
+            for name in txn.list_bound(&prefix, &item.name)? {
+                if !is_bound(&name, namespace) {
+                    releases.insert((name, namespace));
+                }
+            }
+        }
+        Ok((last_states, releases))
     })?;
 
     #[derive(Debug)]
@@ -97,6 +132,7 @@ fn try_handle_commit<'a>(store: web::Data<Store>, args: PoolCommitRequest<'a>) -
         address: &'a str,
         claim_name: &'a str,
         lifecycle: &'a PoolResourceLifecycle,
+        namespace: &'a str,
         resource: ObjectReference,
     }
 
@@ -107,6 +143,7 @@ fn try_handle_commit<'a>(store: web::Data<Store>, args: PoolCommitRequest<'a>) -
         .enumerate()
         .flat_map(|(item_index, (item, last_claims))| {
             let claim_name = item.name.as_ref();
+            let namespace = item.pool.namespace.as_str();
             let priority = item.priority;
 
             item.pool
@@ -114,6 +151,8 @@ fn try_handle_commit<'a>(store: web::Data<Store>, args: PoolCommitRequest<'a>) -
                 .iter()
                 .zip(last_claims)
                 .filter(|(_, last)| last.claim.as_deref() != Some(claim_name))
+                // Skip the resources being released until their postStop probes are completed
+                .filter(|(_, last)| last.claim.is_some() || last.state != CommitState::Preparing)
                 .enumerate()
                 .map(move |(resource_index, (name, _))| {
                     let address = name.as_ref(); // endpoint.addresses[0]
@@ -124,12 +163,8 @@ fn try_handle_commit<'a>(store: web::Data<Store>, args: PoolCommitRequest<'a>) -
                         address,
                         claim_name,
                         lifecycle: &item.lifecycle,
-                        resource: ObjectReference {
-                            group: "discovery.k8s.io".into(),
-                            kind: "Endpoint".into(),
-                            name: name.to_string(),
-                            namespace: Some(item.pool.namespace.clone()),
-                        },
+                        namespace,
+                        resource: build_resource(name, namespace),
                     };
                     (key, value)
                 })
@@ -137,14 +172,29 @@ fn try_handle_commit<'a>(store: web::Data<Store>, args: PoolCommitRequest<'a>) -
         .collect();
 
     store.write(|txn| {
+        for (name, namespace) in &releases {
+            let address = name.as_str(); // endpoint.addresses[0]
+            let resource = build_resource(name, namespace);
+            match txn.release(&resource, address, namespace)? {
+                CommitState::Pending => break,
+                CommitState::Preparing | CommitState::Running => continue,
+            }
+        }
+
         for Value {
             address,
             claim_name,
             lifecycle,
+            namespace,
             resource,
         } in orders.values()
         {
-            match txn.put(resource, claim_name, address, lifecycle)? {
+            let ctx = ProbeContext {
+                address: address.to_string(),
+                claim: claim_name.to_string(),
+                namespace: namespace.to_string(),
+            };
+            match txn.put(resource, ctx, lifecycle)? {
                 CommitState::Pending => break,
                 CommitState::Preparing | CommitState::Running => continue,
             }
@@ -152,3 +202,76 @@ fn try_handle_commit<'a>(store: web::Data<Store>, args: PoolCommitRequest<'a>) -
         Ok(())
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use clap::Parser;
+    use openark_spectrum_api::schema::PoolCommitRequestItem;
+
+    use crate::store::StoreArgs;
+
+    use super::*;
+
+    fn commit(store: &web::Data<Store>, items: &[(&str, &[&str])], pool: &[&str]) {
+        let build_pool = |resources: &[&str]| PoolRequest {
+            namespace: "default".into(),
+            resources: resources.iter().copied().map(Cow::Borrowed).collect(),
+        };
+        let args = PoolCommitRequest {
+            items: items
+                .iter()
+                .map(|&(name, resources)| PoolCommitRequestItem {
+                    lifecycle: PoolResourceLifecycle::default(),
+                    name: Cow::Borrowed(name),
+                    pool: build_pool(resources),
+                    priority: 0,
+                })
+                .collect(),
+            pool: Some(build_pool(pool)),
+        };
+        try_handle_commit(store.clone(), args).unwrap();
+    }
+
+    fn claims(store: &web::Data<Store>, resources: &[&str]) -> Vec<Option<String>> {
+        let args = PoolRequest {
+            namespace: "default".into(),
+            resources: resources.iter().copied().map(Cow::Borrowed).collect(),
+        };
+        try_handle(store.clone(), args)
+            .unwrap()
+            .bound
+            .into_iter()
+            .map(|resource| resource.claim)
+            .collect()
+    }
+
+    #[test]
+    fn release_resources_of_deleted_claims() {
+        let args = StoreArgs::parse_from(["openark-spectrum-pool", "--max-pool", "1"]);
+        let store = web::Data::new(args.build().unwrap());
+        let pool = ["10.0.0.1", "10.0.0.2", "10.0.0.3"];
+
+        commit(
+            &store,
+            &[
+                ("team-a", &["10.0.0.1", "10.0.0.2"]),
+                ("team-b", &["10.0.0.3"]),
+            ],
+            &pool,
+        );
+        assert_eq!(
+            claims(&store, &pool),
+            [
+                Some("team-a".into()),
+                Some("team-a".into()),
+                Some("team-b".into()),
+            ],
+        );
+
+        // "team-b" has been deleted, and "team-a" shrinks
+        commit(&store, &[("team-a", &["10.0.0.1"])], &pool);
+        assert_eq!(claims(&store, &pool), [Some("team-a".into()), None, None]);
+    }
+}
@@ -0,0 +1,48 @@
+use actix_web::{HttpResponse, Responder, get, put, web};
+#[cfg(feature = "tracing")]
+use tracing::{Level, instrument, warn};
+
+use crate::{
+    leader::Leader,
+    store::{Snapshot, Store},
+};
+
+#[cfg_attr(feature = "tracing", instrument(level = Level::INFO, skip_all))]
+#[get("")]
+async fn get(store: web::Data<Store>) -> impl Responder {
+    match store.export() {
+        Ok(snapshot) => HttpResponse::Ok().json(snapshot),
+        Err(error) => {
+            #[cfg(feature = "tracing")]
+            warn!("failed to export service binding states: {error}");
+
+            #[cfg(not(feature = "tracing"))]
+            let _ = error;
+            HttpResponse::Forbidden().json("Err")
+        }
+    }
+}
+
+#[cfg_attr(feature = "tracing", instrument(level = Level::INFO, skip_all))]
+#[put("")]
+async fn put(
+    leader: web::Data<Leader>,
+    store: web::Data<Store>,
+    web::Json(snapshot): web::Json<Snapshot>,
+) -> impl Responder {
+    if !leader.is_leader() {
+        return HttpResponse::ServiceUnavailable().json("Not a leader");
+    }
+
+    match store.import(&snapshot) {
+        Ok(()) => HttpResponse::Ok().json("Ok"),
+        Err(error) => {
+            #[cfg(feature = "tracing")]
+            warn!("failed to import service binding states: {error}");
+
+            #[cfg(not(feature = "tracing"))]
+            let _ = error;
+            HttpResponse::Forbidden().json("Err")
+        }
+    }
+}
@@ -1,18 +1,19 @@
-use std::{fs::OpenOptions, path::PathBuf, sync::Arc};
+use std::{collections::BTreeMap, fs::OpenOptions, path::PathBuf, sync::Arc};
 
 use anyhow::Result;
 use clap::Parser;
 use openark_spectrum_api::{
     common::ObjectReference,
-    pool_claim::PoolResourceLifecycle,
+    pool_claim::{PoolResourceLifecycle, PoolResourceProbe},
     schema::{CommitState, PoolResource},
 };
 use redb::{
-    Database, Error, ReadOnlyTable, ReadableDatabase, Table, TableDefinition,
+    Database, Error, ReadOnlyTable, ReadableDatabase, ReadableTable, Table, TableDefinition,
     backends::{FileBackend, InMemoryBackend},
 };
+use serde::{Deserialize, Serialize};
 
-use crate::pool::Pool;
+use crate::pool::{Pool, ProbeContext};
 
 #[derive(Clone, Debug, Parser)]
 #[command(author, version, about, long_about = None)]
@@ -50,6 +51,7 @@ impl StoreArgs {
         {
             let txn = db.begin_write()?;
             let _ = txn.open_table(Store::TABLE_CLAIM)?;
+            let _ = txn.open_table(Store::TABLE_POST_STOP)?;
             let _ = txn.open_table(Store::TABLE_READY)?;
             txn.commit()?
         }
@@ -61,6 +63,18 @@ impl StoreArgs {
     }
 }
 
+/// A snapshot of the bindings, which is exported and imported as a whole.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Snapshot {
+    #[serde(default)]
+    pub claim: BTreeMap<String, String>,
+    #[serde(default)]
+    pub post_stop: BTreeMap<String, Vec<PoolResourceProbe>>,
+    #[serde(default)]
+    pub ready: BTreeMap<String, CommitState>,
+}
+
 type Key = &'static str;
 type Value = String;
 
@@ -71,8 +85,76 @@ pub struct Store {
 
 impl Store {
     const TABLE_CLAIM: TableDefinition<'static, Key, Value> = TableDefinition::new("claim");
+    /// The `postStop` probes of the bound claims, encoded in JSON.
+    const TABLE_POST_STOP: TableDefinition<'static, Key, Value> = TableDefinition::new("post-stop");
     const TABLE_READY: TableDefinition<'static, Key, u8> = TableDefinition::new("ready");
 
+    pub fn export(&self) -> Result<Snapshot> {
+        let txn = self.db.begin_read()?;
+        let mut snapshot = Snapshot::default();
+        for entry in txn.open_table(Self::TABLE_CLAIM)?.iter()? {
+            let (key, value) = entry?;
+            snapshot.claim.insert(key.value().into(), value.value());
+        }
+        for entry in txn.open_table(Self::TABLE_POST_STOP)?.iter()? {
+            let (key, value) = entry?;
+            let probes = ::serde_json::from_str(&value.value())?;
+            snapshot.post_stop.insert(key.value().into(), probes);
+        }
+        for entry in txn.open_table(Self::TABLE_READY)?.iter()? {
+            let (key, value) = entry?;
+            if let Some(state) = CommitState::from_raw(value.value()) {
+                snapshot.ready.insert(key.value().into(), state);
+            }
+        }
+        Ok(snapshot)
+    }
+
+    /// Replaces all the bindings with the snapshot.
+    ///
+    /// The probes in progress cannot be resumed, so such resources are left unbound
+    /// to be committed again.
+    pub fn import(&self, snapshot: &Snapshot) -> Result<()> {
+        let Snapshot {
+            claim,
+            post_stop,
+            ready,
+        } = snapshot;
+        let is_preparing = |key: &str| ready.get(key) == Some(&CommitState::Preparing);
+
+        let txn = self.db.begin_write()?;
+        {
+            let mut table = txn.open_table(Self::TABLE_CLAIM)?;
+            table.retain(|_, _| false)?;
+            for (key, value) in claim {
+                if !is_preparing(key) {
+                    table.insert(key.as_str(), value.clone())?;
+                }
+            }
+        }
+        {
+            let mut table = txn.open_table(Self::TABLE_POST_STOP)?;
+            table.retain(|_, _| false)?;
+            for (key, probes) in post_stop {
+                if !is_preparing(key) {
+                    let value = ::serde_json::to_string(probes)?;
+                    table.insert(key.as_str(), value)?;
+                }
+            }
+        }
+        {
+            let mut table = txn.open_table(Self::TABLE_READY)?;
+            table.retain(|_, _| false)?;
+            for (key, state) in ready {
+                if *state != CommitState::Preparing {
+                    table.insert(key.as_str(), *state as u8)?;
+                }
+            }
+        }
+        txn.commit()?;
+        Ok(())
+    }
+
     pub fn read<F, R>(&self, closure: F) -> Result<R, Box<Error>>
     where
         F: FnOnce(&ReadGuard) -> Result<R, Box<Error>>,
@@ -103,11 +185,13 @@ impl Store {
         let txn = self.db.begin_write()?;
         let output = {
             let table_claim = txn.open_table(Self::TABLE_CLAIM)?;
+            let table_post_stop = txn.open_table(Self::TABLE_POST_STOP)?;
             let table_ready = txn.open_table(Self::TABLE_READY)?;
             let mut guard = WriteGuard {
                 db: &self.db,
                 pool: &self.pool,
                 table_claim,
+                table_post_stop,
                 table_ready,
             };
             closure(&mut guard)?
@@ -141,60 +225,260 @@ impl ReadGuard {
                 .map_err(|error| Box::new(error.into()))?,
         })
     }
+
+    /// Returns the names of the resources bound to the claim, whose keys start with the prefix.
+    pub fn list_bound(&self, prefix: &str, claim: &str) -> Result<Vec<String>, Box<Error>> {
+        let mut names = Vec::default();
+        for entry in self
+            .table_claim
+            .range(prefix..)
+            .map_err(|error| Box::new(error.into()))?
+        {
+            let (key, value) = entry.map_err(|error| Box::new(error.into()))?;
+            let Some(name) = key.value().strip_prefix(prefix) else {
+                break;
+            };
+            if value.value() == claim {
+                names.push(name.to_string());
+            }
+        }
+        Ok(names)
+    }
 }
 
 pub struct WriteGuard<'a> {
     db: &'a Arc<Database>,
     pool: &'a Pool,
     table_claim: Table<'a, Key, Value>,
+    table_post_stop: Table<'a, Key, Value>,
     table_ready: Table<'a, Key, u8>,
 }
 
 impl WriteGuard<'_> {
+    /// Returns the last claim and its `postStop` probes of the resource.
+    fn get_last(&self, key: &str) -> Result<Option<(String, Vec<PoolResourceProbe>)>> {
+        let Some(claim) = self.table_claim.get(key)?.map(|guard| guard.value()) else {
+            return Ok(None);
+        };
+        let post_stop = match self.table_post_stop.get(key)? {
+            Some(guard) => ::serde_json::from_str(&guard.value())?,
+            None => Vec::default(),
+        };
+        Ok(Some((claim, post_stop)))
+    }
+
     pub fn put(
         &mut self,
         key: &ObjectReference,
-        value: &str,
-        address: &str,
+        ctx: ProbeContext,
         lifecycle: &PoolResourceLifecycle,
     ) -> Result<CommitState> {
-        let PoolResourceLifecycle { pre_start } = lifecycle;
-        let state = match pre_start.as_slice() {
-            [] => CommitState::Running,
-            probes => {
-                let on_completed = {
-                    let db = self.db.clone();
-                    let key = key.to_string();
-                    move |is_completed| {
+        let PoolResourceLifecycle {
+            pre_start,
+            post_stop,
+        } = lifecycle;
+        let key = key.to_string();
+
+        // Reset the resource from the last claim
+        let last_post_stop = self
+            .get_last(&key)?
+            .filter(|(claim, probes)| *claim != ctx.claim && !probes.is_empty())
+            .map(|(claim, probes)| {
+                let last_ctx = ProbeContext {
+                    claim,
+                    ..ctx.clone()
+                };
+                (last_ctx, probes)
+            });
+
+        let value = ctx.claim.clone();
+        let state = if pre_start.is_empty() && last_post_stop.is_none() {
+            CommitState::Running
+        } else {
+            let on_completed = {
+                let db = self.db.clone();
+                let key = key.clone();
+                move |is_completed| {
+                    {
+                        let state = if is_completed {
+                            CommitState::Running
+                        } else {
+                            CommitState::Pending
+                        };
+                        let txn = db.begin_write()?;
                         {
-                            let state = if is_completed {
-                                CommitState::Running
-                            } else {
-                                CommitState::Pending
-                            };
-                            let txn = db.begin_write()?;
-                            {
-                                let mut table = txn.open_table(Store::TABLE_READY)?;
-                                table.insert(key.as_str(), state as u8)?;
-                            }
-                            txn.commit()?;
+                            let mut table = txn.open_table(Store::TABLE_READY)?;
+                            table.insert(key.as_str(), state as u8)?;
                         }
-                        Ok(())
+                        txn.commit()?;
                     }
-                };
-                self.pool.commit(address, probes, on_completed)?
-            }
+                    Ok(())
+                }
+            };
+            self.pool
+                .commit(ctx, last_post_stop, pre_start, on_completed)
         };
+        if state == CommitState::Pending {
+            return Ok(state);
+        }
 
-        let key = key.to_string();
         {
-            let value = value.to_string();
             self.table_claim.insert(key.as_str(), value)?;
         }
 
+        if post_stop.is_empty() {
+            self.table_post_stop.remove(key.as_str())?;
+        } else {
+            let value = ::serde_json::to_string(post_stop)?;
+            self.table_post_stop.insert(key.as_str(), value)?;
+        }
+
         {
             self.table_ready.insert(key.as_str(), state as u8)?;
         }
         Ok(state)
     }
+
+    /// Releases the resource from its claim, running the `postStop` probes if any.
+    ///
+    /// The resource is `Preparing` without a claim until the probes are completed.
+    pub fn release(
+        &mut self,
+        key: &ObjectReference,
+        address: &str,
+        namespace: &str,
+    ) -> Result<CommitState> {
+        let key = key.to_string();
+        let Some((claim, post_stop)) = self.get_last(&key)? else {
+            return Ok(CommitState::Running);
+        };
+
+        let state = if post_stop.is_empty() {
+            CommitState::Running
+        } else {
+            let ctx = ProbeContext {
+                address: address.into(),
+                claim,
+                namespace: namespace.into(),
+            };
+            let on_completed = {
+                let db = self.db.clone();
+                let key = key.clone();
+                move || {
+                    let txn = db.begin_write()?;
+                    {
+                        let mut table = txn.open_table(Store::TABLE_READY)?;
+                        table.remove(key.as_str())?;
+                    }
+                    txn.commit()?;
+                    Ok(())
+                }
+            };
+            self.pool.release(ctx, post_stop, on_completed)
+        };
+
+        match state {
+            CommitState::Pending => (),
+            CommitState::Preparing => {
+                self.table_claim.remove(key.as_str())?;
+                self.table_post_stop.remove(key.as_str())?;
+                self.table_ready.insert(key.as_str(), state as u8)?;
+            }
+            CommitState::Running => {
+                self.table_claim.remove(key.as_str())?;
+                self.table_post_stop.remove(key.as_str())?;
+                self.table_ready.remove(key.as_str())?;
+            }
+        }
+        Ok(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build_store() -> Store {
+        StoreArgs {
+            max_pool: 1,
+            path: None,
+        }
+        .build()
+        .unwrap()
+    }
+
+    #[test]
+    fn import_and_export_snapshot() {
+        let store = build_store();
+
+        let key = |name: &str| format!("discovery.k8s.io/Endpoint/default/{name}");
+        let snapshot = Snapshot {
+            claim: BTreeMap::from_iter([
+                (key("10.0.0.1"), "team-a".into()),
+                (key("10.0.0.2"), "team-b".into()),
+            ]),
+            post_stop: BTreeMap::default(),
+            ready: BTreeMap::from_iter([
+                (key("10.0.0.1"), CommitState::Running),
+                (key("10.0.0.2"), CommitState::Preparing),
+            ]),
+        };
+        store.import(&snapshot).unwrap();
+
+        // The resources being prepared are left unbound
+        let exported = store.export().unwrap();
+        assert_eq!(exported.claim.len(), 1);
+        assert_eq!(exported.claim[&key("10.0.0.1")], "team-a");
+        assert_eq!(exported.ready.len(), 1);
+
+        // The last bindings are replaced
+        store.import(&Snapshot::default()).unwrap();
+        assert_eq!(store.export().unwrap(), Snapshot::default());
+    }
+
+    #[test]
+    fn put_and_release_resources() {
+        let store = build_store();
+        let key = ObjectReference {
+            group: "discovery.k8s.io".into(),
+            kind: "Endpoint".into(),
+            name: "10.0.0.1".into(),
+            namespace: Some("default".into()),
+        };
+        let ctx = |claim: &str| ProbeContext {
+            address: "10.0.0.1".into(),
+            claim: claim.into(),
+            namespace: "default".into(),
+        };
+        let lifecycle = PoolResourceLifecycle::default();
+        let get = || store.read(|txn| txn.get(&key)).unwrap();
+
+        // bound without any probes
+        let state = store
+            .write(|txn| txn.put(&key, ctx("team-a"), &lifecycle))
+            .unwrap();
+        assert_eq!(state, CommitState::Running);
+        assert_eq!(get().claim.as_deref(), Some("team-a"));
+        assert_eq!(get().state, CommitState::Running);
+
+        // rebound to another claim
+        store
+            .write(|txn| txn.put(&key, ctx("team-b"), &lifecycle))
+            .unwrap();
+        assert_eq!(get().claim.as_deref(), Some("team-b"));
+
+        // released without any probes
+        let state = store
+            .write(|txn| txn.release(&key, "10.0.0.1", "default"))
+            .unwrap();
+        assert_eq!(state, CommitState::Running);
+        assert_eq!(get(), PoolResource::default());
+        assert!(store.export().unwrap().claim.is_empty());
+
+        // releasing an unbound resource is a no-op
+        let state = store
+            .write(|txn| txn.release(&key, "10.0.0.1", "default"))
+            .unwrap();
+        assert_eq!(state, CommitState::Running);
+    }
 }
@@ -1,6 +1,9 @@
 use std::borrow::Cow;
 
-use openark_spectrum_api::pool_claim::{PoolClaimCrd, PoolResourceLifecycle};
+use openark_spectrum_api::pool_claim::{
+    PoolClaimCrd, PoolResourceLifecycle, PoolResourceSettings, PoolTopologyKey,
+    PoolTopologySpreadConstraint,
+};
 use ordered_float::OrderedFloat;
 
 #[derive(Debug)]
@@ -10,6 +13,8 @@ pub struct Resource {
     pub min: Option<f64>,
     pub max: Option<f64>,
     pub weight: u64,
+    pub anti_affinity: Vec<PoolTopologyKey>,
+    pub spread: Vec<PoolTopologySpreadConstraint>,
 }
 
 impl Default for Resource {
@@ -20,6 +25,22 @@ impl Default for Resource {
             min: Default::default(),
             max: Default::default(),
             weight: 1,
+            anti_affinity: Default::default(),
+            spread: Default::default(),
+        }
+    }
+}
+
+impl From<&PoolResourceSettings> for Resource {
+    fn from(settings: &PoolResourceSettings) -> Self {
+        Self {
+            penalty: settings.penalty.unwrap_or(0.0),
+            priority: settings.priority.unwrap_or(0),
+            min: settings.min,
+            max: settings.max,
+            weight: settings.weight.unwrap_or(1),
+            anti_affinity: settings.anti_affinity.clone(),
+            spread: settings.topology_spread_constraints.clone(),
         }
     }
 }
@@ -31,10 +52,39 @@ pub struct Item<'a, T> {
     pub item: T,
 }
 
+/// The topology domains which a resource belongs to.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Topology {
+    pub node: Option<String>,
+    pub rack: Option<String>,
+    pub zone: Option<String>,
+}
+
+impl Topology {
+    pub fn get(&self, key: PoolTopologyKey) -> Option<&str> {
+        match key {
+            PoolTopologyKey::Node => self.node.as_deref(),
+            PoolTopologyKey::Rack => self.rack.as_deref(),
+            PoolTopologyKey::Zone => self.zone.as_deref(),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct WeightedItems<T> {
     pub items: Vec<T>,
     pub weights: Vec<Option<OrderedFloat<f64>>>,
+    /// The topologies of the items; empty if unknown
+    pub topologies: Vec<Topology>,
+}
+
+/// A resource which has been bound to an item, but is not anymore.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Eviction {
+    /// The index of the resource
+    pub resource: usize,
+    /// The index of the item which has taken the resource, if any
+    pub preemptor: Option<usize>,
 }
 
 #[derive(Debug)]
@@ -43,4 +93,10 @@ pub struct ScheduledItem<S, T> {
     pub lifecycle: PoolResourceLifecycle,
     pub priority: i32,
     pub resources: Vec<S>,
+    /// The resources taken from this item on this scheduling
+    pub evictions: Vec<Eviction>,
+    /// The total weight of the bound resources
+    pub load: f64,
+    /// Whether the minimum weight could not be met
+    pub unsatisfiable: bool,
 }
@@ -5,11 +5,15 @@ mod state;
 use std::collections::{BTreeMap, BTreeSet};
 
 use good_lp::{ResolutionError, solvers::ObjectiveDirection};
-use openark_spectrum_api::schema::{CommitState, PoolResource};
+use openark_spectrum_api::{
+    pool::PoolSolver,
+    schema::{CommitState, PoolResource},
+};
 use ordered_float::OrderedFloat;
 
 use crate::{
-    item::{Item, ScheduledItem, WeightedItems},
+    item::{Item, ScheduledItem, Topology, WeightedItems},
+    solvers::{Problem, solve},
     state::State,
 };
 
@@ -17,6 +21,7 @@ pub fn schedule<'a, S, T>(
     items: Vec<Item<'a, T>>,
     bound: Vec<PoolResource<usize>>,
     resources: WeightedItems<S>,
+    solver: PoolSolver,
 ) -> Result<Vec<ScheduledItem<S, T>>, ResolutionError> {
     // ****************************************
     // Step 0: Validate inputs
@@ -24,6 +29,7 @@ pub fn schedule<'a, S, T>(
 
     // Validate resources
     assert_eq!(resources.items.len(), resources.weights.len());
+    assert!(resources.topologies.is_empty() || resources.items.len() == resources.topologies.len());
 
     // Do nothing if either items or resources are empty
     if items.is_empty() || resources.items.is_empty() {
@@ -61,6 +67,13 @@ pub fn schedule<'a, S, T>(
         .map(|opt| opt.map(OrderedFloat::into_inner).unwrap_or(weights_avg))
         .collect();
 
+    // Fill unknown topologies
+    let topologies = if resources.topologies.is_empty() {
+        vec![Topology::default(); resources.items.len()]
+    } else {
+        resources.topologies.clone()
+    };
+
     // Prefill locked resources
     let mut allocated = vec![Vec::default(); n_j];
     let mut filled = vec![0.0f64; n_j];
@@ -88,6 +101,7 @@ pub fn schedule<'a, S, T>(
         filled,
         items,
         remaining,
+        topologies,
         weights,
     };
 
@@ -126,38 +140,38 @@ pub fn schedule<'a, S, T>(
     };
 
     // ****************************************
-    // Step 2-A: Guaranteed -> MILP
+    // Step 2-A: Guaranteed -> Solver
     // ****************************************
 
     if !guaranteed.is_empty() {
         for items in build_tiers(guaranteed) {
-            let problem = self::solvers::milp::Solver {
+            let problem = Problem {
                 direction: ObjectiveDirection::Maximisation,
                 items,
                 use_all: false,
                 use_max: true,
                 use_min: true,
             };
-            if !problem.solve(&mut state)? {
+            if !solve(solver, &problem, &mut state)? {
                 break;
             }
         }
     }
 
     // ****************************************
-    // Step 2-B: Burstable -> MILP
+    // Step 2-B: Burstable -> Solver
     // ****************************************
 
     if !burstable.is_empty() {
         for items in build_tiers(burstable) {
-            let problem = self::solvers::milp::Solver {
+            let problem = Problem {
                 direction: ObjectiveDirection::Maximisation,
                 items,
                 use_all: false,
                 use_max: true,
                 use_min: true,
             };
-            if !problem.solve(&mut state)? {
+            if !solve(solver, &problem, &mut state)? {
                 break;
             }
         }
@@ -169,14 +183,14 @@ pub fn schedule<'a, S, T>(
 
     drop(priority);
     if !state.remaining.is_empty() && !best_effort.is_empty() {
-        let problem = self::solvers::milp::Solver {
+        let problem = Problem {
             direction: ObjectiveDirection::Minimisation,
             items: best_effort,
             use_all: true,
             use_max: false,
             use_min: false,
         };
-        problem.solve(&mut state)?;
+        solve(solver, &problem, &mut state)?;
     }
 
     // ****************************************
@@ -189,9 +203,12 @@ pub fn schedule<'a, S, T>(
 #[cfg(test)]
 mod tests {
     use kube::api::ObjectMeta;
-    use openark_spectrum_api::pool_claim::PoolClaimSpec;
+    use openark_spectrum_api::pool_claim::{
+        PoolClaimSpec, PoolTopologyKey, PoolTopologySpreadConstraint,
+    };
 
     use super::{super::*, *};
+    use crate::item::Eviction;
 
     fn aggregate_resources<'a, T>(
         items: &[ScheduledItem<usize, &'a T>],
@@ -224,10 +241,31 @@ mod tests {
         let resources = WeightedItems {
             items: (0..weights.len()).collect(),
             weights: weights.into_iter().map(|x| x.map(OrderedFloat)).collect(),
+            topologies: Vec::default(),
         };
         (bound, resources)
     }
 
+    fn build_resources_with_topologies(
+        weights: Vec<f64>,
+        topologies: Vec<Topology>,
+    ) -> (Vec<PoolResource<usize>>, WeightedItems<usize>) {
+        let (bound, resources) = build_resources_unbound(weights.into_iter().map(Some).collect());
+        let resources = WeightedItems {
+            topologies,
+            ..resources
+        };
+        (bound, resources)
+    }
+
+    fn define_topology(node: &str, zone: &str) -> Topology {
+        Topology {
+            node: Some(node.into()),
+            rack: None,
+            zone: Some(zone.into()),
+        }
+    }
+
     #[inline]
     fn define_item<'a>(name: &'a str) -> Item<'a, &'a str> {
         let resource = Resource::default();
@@ -282,7 +320,7 @@ mod tests {
             Some(500.0),
         ]);
 
-        let items = schedule(items, bound, resources.clone()).unwrap();
+        let items = schedule(items, bound, resources.clone(), PoolSolver::Milp).unwrap();
         let (indices, weights) = aggregate_resources(&items, &resources);
         assert_eq!(indices, &[vec![0, 1, 3], vec![2, 4]]);
         assert_eq!(weights, &[700.0, 800.0]);
@@ -302,7 +340,7 @@ mod tests {
             Some(500.0),
         ]);
 
-        let items = schedule(items, bound, resources.clone()).unwrap();
+        let items = schedule(items, bound, resources.clone(), PoolSolver::Milp).unwrap();
         let (indices, weights) = aggregate_resources(&items, &resources);
         assert_eq!(indices, &[vec![0, 1, 3], vec![2, 4]]);
         assert_eq!(weights, &[700.0, 800.0]);
@@ -322,7 +360,7 @@ mod tests {
             Some(500.0),
         ]);
 
-        let items = schedule(items, bound, resources.clone()).unwrap();
+        let items = schedule(items, bound, resources.clone(), PoolSolver::Milp).unwrap();
         let (indices, weights) = aggregate_resources(&items, &resources);
         assert_eq!(indices, &[vec![1, 2], vec![0, 3, 4]]);
         assert_eq!(weights, &[500.0, 1000.0]);
@@ -342,7 +380,7 @@ mod tests {
             Some(500.0),
         ]);
 
-        let items = schedule(items, bound, resources.clone()).unwrap();
+        let items = schedule(items, bound, resources.clone(), PoolSolver::Milp).unwrap();
         let (indices, weights) = aggregate_resources(&items, &resources);
         assert_eq!(indices, &[vec![0, 2], vec![1, 3, 4]]);
         assert_eq!(weights, &[400.0, 1100.0]);
@@ -362,7 +400,7 @@ mod tests {
             Some(500.0),
         ]);
 
-        let items = schedule(items, bound, resources.clone()).unwrap();
+        let items = schedule(items, bound, resources.clone(), PoolSolver::Milp).unwrap();
         let (indices, weights) = aggregate_resources(&items, &resources);
         assert_eq!(indices, &[vec![0, 1], vec![2, 3, 4]]);
         assert_eq!(weights, &[300.0, 1200.0]);
@@ -379,6 +417,7 @@ mod tests {
                     min: Some(800.0),
                     max: None,
                     weight: 1,
+                    ..Default::default()
                 },
             ),
             define_item_with_resource(
@@ -389,6 +428,7 @@ mod tests {
                     min: Some(500.0),
                     max: None,
                     weight: 1,
+                    ..Default::default()
                 },
             ),
         ];
@@ -400,7 +440,7 @@ mod tests {
             Some(500.0),
         ]);
 
-        let items = schedule(items, bound, resources.clone()).unwrap();
+        let items = schedule(items, bound, resources.clone(), PoolSolver::Milp).unwrap();
         let (indices, weights) = aggregate_resources(&items, &resources);
         assert_eq!(indices, &[vec![0, 2, 3], vec![4, 1]]);
         assert_eq!(weights, &[800.0, 700.0]);
@@ -417,6 +457,7 @@ mod tests {
                     min: Some(400.0),
                     max: None,
                     weight: 1,
+                    ..Default::default()
                 },
             ),
             define_item_with_resource(
@@ -427,6 +468,7 @@ mod tests {
                     min: Some(300.0),
                     max: None,
                     weight: 1,
+                    ..Default::default()
                 },
             ),
             define_item_with_resource(
@@ -437,6 +479,7 @@ mod tests {
                     min: Some(300.0),
                     max: None,
                     weight: 1,
+                    ..Default::default()
                 },
             ),
         ];
@@ -448,7 +491,7 @@ mod tests {
             Some(500.0),
         ]);
 
-        let items = schedule(items, bound, resources.clone()).unwrap();
+        let items = schedule(items, bound, resources.clone(), PoolSolver::Milp).unwrap();
         let (indices, weights) = aggregate_resources(&items, &resources);
         assert_eq!(indices, &[vec![3], vec![0, 1, 4], vec![2]]);
         // + Step 2-B: [[3], [0, 1], [2]] -> [400.0, 300.0, 300.0]
@@ -467,6 +510,7 @@ mod tests {
                     min: Some(800.0),
                     max: None,
                     weight: 1,
+                    ..Default::default()
                 },
             ),
             define_item_with_resource(
@@ -477,6 +521,7 @@ mod tests {
                     min: Some(500.0),
                     max: None,
                     weight: 1,
+                    ..Default::default()
                 },
             ),
             define_item_with_resource(
@@ -487,6 +532,7 @@ mod tests {
                     min: Some(500.0),
                     max: None,
                     weight: 1,
+                    ..Default::default()
                 },
             ),
         ];
@@ -498,7 +544,7 @@ mod tests {
             Some(500.0),
         ]);
 
-        let items = schedule(items, bound, resources.clone()).unwrap();
+        let items = schedule(items, bound, resources.clone(), PoolSolver::Milp).unwrap();
         let (indices, weights) = aggregate_resources(&items, &resources);
         assert_eq!(indices, &[vec![4], vec![0, 3], vec![1, 2]]);
         assert_eq!(weights, &[500.0, 500.0, 500.0]);
@@ -515,6 +561,7 @@ mod tests {
                     min: Some(800.0),
                     max: None,
                     weight: 1,
+                    ..Default::default()
                 },
             ),
             define_item_with_resource(
@@ -525,6 +572,7 @@ mod tests {
                     min: Some(500.0),
                     max: None,
                     weight: 1,
+                    ..Default::default()
                 },
             ),
             define_item_with_resource(
@@ -535,6 +583,7 @@ mod tests {
                     min: Some(500.0),
                     max: None,
                     weight: 1,
+                    ..Default::default()
                 },
             ),
         ];
@@ -546,7 +595,7 @@ mod tests {
             Some(500.0),
         ]);
 
-        let items = schedule(items, bound, resources.clone()).unwrap();
+        let items = schedule(items, bound, resources.clone(), PoolSolver::Milp).unwrap();
         let (indices, weights) = aggregate_resources(&items, &resources);
         assert_eq!(indices, &[vec![2, 4], vec![3], vec![0, 1]]);
         assert_eq!(weights, &[800.0, 400.0, 300.0]);
@@ -563,6 +612,7 @@ mod tests {
                     min: Some(1000.0),
                     max: Some(1000.0),
                     weight: 1,
+                    ..Default::default()
                 },
             ),
             define_item_with_resource(
@@ -573,6 +623,7 @@ mod tests {
                     min: Some(500.0),
                     max: Some(500.0),
                     weight: 1,
+                    ..Default::default()
                 },
             ),
         ];
@@ -584,7 +635,7 @@ mod tests {
             Some(500.0),
         ]);
 
-        let items = schedule(items, bound, resources.clone()).unwrap();
+        let items = schedule(items, bound, resources.clone(), PoolSolver::Milp).unwrap();
         let (indices, weights) = aggregate_resources(&items, &resources);
         assert_eq!(indices, &[vec![0, 3, 4], vec![1, 2]]);
         assert_eq!(weights, &[1000.0, 500.0]);
@@ -601,6 +652,7 @@ mod tests {
                     min: Some(1000.0),
                     max: Some(1000.0),
                     weight: 1,
+                    ..Default::default()
                 },
             ),
             define_item_with_resource(
@@ -611,6 +663,7 @@ mod tests {
                     min: Some(500.0),
                     max: Some(500.0),
                     weight: 1,
+                    ..Default::default()
                 },
             ),
         ];
@@ -622,9 +675,353 @@ mod tests {
             Some(500.0),
         ]);
 
-        let items = schedule(items, bound, resources.clone()).unwrap();
+        let items = schedule(items, bound, resources.clone(), PoolSolver::Milp).unwrap();
         let (indices, weights) = aggregate_resources(&items, &resources);
         assert_eq!(indices, &[vec![0, 3, 4], vec![1, 2]]);
         assert_eq!(weights, &[1000.0, 500.0]);
     }
+
+    #[test]
+    fn test_distribute_anti_affinity() {
+        let items = vec![
+            define_item_with_resource(
+                "a",
+                Resource {
+                    anti_affinity: vec![PoolTopologyKey::Node],
+                    ..Default::default()
+                },
+            ),
+            define_item("b"),
+        ];
+        let topologies = vec![
+            define_topology("node1", "zone1"),
+            define_topology("node1", "zone1"),
+            define_topology("node2", "zone1"),
+            define_topology("node2", "zone1"),
+        ];
+        let (bound, resources) =
+            build_resources_with_topologies(vec![100.0; 4], topologies.clone());
+
+        let items = schedule(items, bound, resources.clone(), PoolSolver::Milp).unwrap();
+        let (indices, _) = aggregate_resources(&items, &resources);
+        let nodes = |indices: &[usize]| {
+            indices
+                .iter()
+                .map(|&index| topologies[index].node.clone())
+                .collect::<BTreeSet<_>>()
+        };
+        assert!(!indices[0].is_empty());
+        assert!(nodes(&indices[0]).is_disjoint(&nodes(&indices[1])));
+    }
+
+    #[test]
+    fn test_distribute_spread() {
+        let items = vec![define_item_with_resource(
+            "a",
+            Resource {
+                spread: vec![PoolTopologySpreadConstraint {
+                    topology_key: PoolTopologyKey::Zone,
+                    max_skew: 1,
+                }],
+                ..Default::default()
+            },
+        )];
+        let topologies = vec![
+            define_topology("node1", "zone1"),
+            define_topology("node2", "zone1"),
+            define_topology("node3", "zone1"),
+            define_topology("node4", "zone2"),
+        ];
+        let (bound, resources) =
+            build_resources_with_topologies(vec![100.0; 4], topologies.clone());
+
+        let items = schedule(items, bound, resources.clone(), PoolSolver::Milp).unwrap();
+        let (indices, _) = aggregate_resources(&items, &resources);
+        let count = |zone: &str| {
+            indices[0]
+                .iter()
+                .filter(|&&index| topologies[index].zone.as_deref() == Some(zone))
+                .count()
+        };
+        // zone1: 2, zone2: 1
+        assert_eq!(indices[0].len(), 3);
+        assert_eq!((count("zone1"), count("zone2")), (2, 1));
+    }
+
+    #[test]
+    fn test_distribute_preemption() {
+        let items = vec![
+            define_item_with_resource(
+                "a",
+                Resource {
+                    priority: 1,
+                    min: Some(300.0),
+                    max: Some(300.0),
+                    ..Default::default()
+                },
+            ),
+            define_item("b"),
+        ];
+        let (mut bound, resources) = build_resources_unbound(vec![Some(300.0), Some(100.0)]);
+        bound[0] = PoolResource {
+            claim: Some(1),
+            state: CommitState::Running,
+        };
+
+        let items = schedule(items, bound, resources.clone(), PoolSolver::Milp).unwrap();
+        let (indices, weights) = aggregate_resources(&items, &resources);
+        assert_eq!(indices, &[vec![0], vec![1]]);
+        assert_eq!(weights, &[300.0, 100.0]);
+
+        assert!(items[0].evictions.is_empty());
+        assert!(!items[0].unsatisfiable);
+        assert_eq!(
+            items[1].evictions,
+            &[Eviction {
+                resource: 0,
+                preemptor: Some(0),
+            }],
+        );
+    }
+
+    #[test]
+    fn test_distribute_unsatisfiable() {
+        let items = vec![define_item_with_resource(
+            "a",
+            Resource {
+                min: Some(1000.0),
+                max: Some(1000.0),
+                ..Default::default()
+            },
+        )];
+        let (bound, resources) = build_resources_unbound(vec![Some(100.0), Some(200.0)]);
+
+        let items = schedule(items, bound, resources, PoolSolver::Milp).unwrap();
+        assert!(items[0].unsatisfiable);
+    }
+
+    #[test]
+    fn test_greedy_best_effort() {
+        let items = vec![define_item("a"), define_item("b")];
+        let (bound, resources) = build_resources_unbound(vec![
+            Some(100.0),
+            Some(200.0),
+            Some(300.0),
+            Some(400.0),
+            Some(500.0),
+        ]);
+
+        let items = schedule(items, bound, resources.clone(), PoolSolver::Greedy).unwrap();
+        let (indices, weights) = aggregate_resources(&items, &resources);
+        assert_eq!(indices, &[vec![0, 1, 4], vec![2, 3]]);
+        assert_eq!(weights, &[800.0, 700.0]);
+    }
+
+    #[test]
+    fn test_greedy_anti_affinity() {
+        let items = vec![
+            define_item_with_resource(
+                "a",
+                Resource {
+                    min: Some(200.0),
+                    max: Some(200.0),
+                    anti_affinity: vec![PoolTopologyKey::Zone],
+                    ..Default::default()
+                },
+            ),
+            define_item("b"),
+        ];
+        let (bound, resources) = build_resources_with_topologies(
+            vec![100.0, 100.0, 100.0, 100.0],
+            vec![
+                define_topology("node-0", "zone-a"),
+                define_topology("node-1", "zone-a"),
+                define_topology("node-2", "zone-b"),
+                define_topology("node-3", "zone-b"),
+            ],
+        );
+
+        let items = schedule(items, bound, resources.clone(), PoolSolver::Greedy).unwrap();
+        let (indices, _) = aggregate_resources(&items, &resources);
+        assert_eq!(indices, &[vec![0, 1], vec![2, 3]]);
+    }
+
+    #[test]
+    fn test_greedy_spread() {
+        let items = vec![define_item_with_resource(
+            "a",
+            Resource {
+                spread: vec![PoolTopologySpreadConstraint {
+                    topology_key: PoolTopologyKey::Zone,
+                    max_skew: 1,
+                }],
+                ..Default::default()
+            },
+        )];
+        let topologies = vec![
+            define_topology("node1", "zone1"),
+            define_topology("node2", "zone1"),
+            define_topology("node3", "zone1"),
+            define_topology("node4", "zone2"),
+        ];
+        let (bound, resources) =
+            build_resources_with_topologies(vec![100.0; 4], topologies.clone());
+
+        let items = schedule(items, bound, resources.clone(), PoolSolver::Greedy).unwrap();
+        let (indices, _) = aggregate_resources(&items, &resources);
+        let count = |zone: &str| {
+            indices[0]
+                .iter()
+                .filter(|&&index| topologies[index].zone.as_deref() == Some(zone))
+                .count()
+        };
+        // zone1: 2, zone2: 1
+        assert_eq!(indices[0].len(), 3);
+        assert_eq!((count("zone1"), count("zone2")), (2, 1));
+    }
+
+    #[test]
+    fn test_greedy_spread_zero_skew() {
+        let items = vec![define_item_with_resource(
+            "a",
+            Resource {
+                spread: vec![PoolTopologySpreadConstraint {
+                    topology_key: PoolTopologyKey::Zone,
+                    max_skew: 0,
+                }],
+                ..Default::default()
+            },
+        )];
+        let (bound, resources) = build_resources_with_topologies(
+            vec![100.0; 3],
+            vec![
+                define_topology("node1", "zone1"),
+                define_topology("node2", "zone1"),
+                define_topology("node3", "zone2"),
+            ],
+        );
+
+        let items = schedule(items, bound, resources.clone(), PoolSolver::Greedy).unwrap();
+        let (indices, _) = aggregate_resources(&items, &resources);
+        assert_eq!(indices, &[vec![0, 2]]);
+    }
+
+    #[test]
+    fn test_spread_infeasible() {
+        // Binding 4 resources breaks the zone spread (3:1), so no solver can satisfy the minimum
+        for solver in [PoolSolver::Milp, PoolSolver::Greedy] {
+            let items = vec![define_item_with_resource(
+                "a",
+                Resource {
+                    min: Some(400.0),
+                    max: Some(400.0),
+                    spread: vec![PoolTopologySpreadConstraint {
+                        topology_key: PoolTopologyKey::Zone,
+                        max_skew: 1,
+                    }],
+                    ..Default::default()
+                },
+            )];
+            let topologies = vec![
+                define_topology("node1", "zone1"),
+                define_topology("node2", "zone1"),
+                define_topology("node3", "zone1"),
+                define_topology("node4", "zone2"),
+            ];
+            let (bound, resources) = build_resources_with_topologies(vec![100.0; 4], topologies);
+
+            let items = schedule(items, bound, resources.clone(), solver).unwrap();
+            let (indices, _) = aggregate_resources(&items, &resources);
+            assert_eq!(indices, &[Vec::<usize>::new()], "{solver:?}");
+            assert!(items[0].unsatisfiable, "{solver:?}");
+        }
+    }
+
+    /// A deterministic pseudo-random number generator (xorshift64).
+    struct Random(u64);
+
+    impl Random {
+        fn next(&mut self, bound: u64) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0 % bound
+        }
+    }
+
+    #[test]
+    fn test_solvers_satisfy_bounds() {
+        let names = ["a", "b", "c", "d"];
+        let mut random = Random(0x5EED);
+        for _ in 0..20 {
+            // Define random items
+            let mut demand = 0;
+            let items: Vec<_> = names[..1 + random.next(names.len() as u64) as usize]
+                .iter()
+                .map(|name| {
+                    let min = 1 + random.next(4);
+                    let (min, max) = match random.next(3) {
+                        // Best effort
+                        0 => (None, None),
+                        // Burstable
+                        1 => (Some(min), None),
+                        // Guaranteed
+                        _ => (Some(min), Some(min + random.next(3))),
+                    };
+                    demand += max.or(min).unwrap_or_default();
+                    let resource = Resource {
+                        priority: random.next(2) as i32,
+                        min: min.map(|x| x as f64),
+                        max: max.map(|x| x as f64),
+                        ..Default::default()
+                    };
+                    (*name, resource)
+                })
+                .collect();
+
+            // Define sufficient resources
+            let n_resources = (demand + random.next(4)).max(1) as usize;
+            let weights = vec![Some(1.0); n_resources];
+
+            for solver in [PoolSolver::Milp, PoolSolver::Greedy] {
+                let items: Vec<Item<&str>> = items
+                    .iter()
+                    .map(|(name, resource)| {
+                        let resource = Resource {
+                            priority: resource.priority,
+                            min: resource.min,
+                            max: resource.max,
+                            ..Default::default()
+                        };
+                        define_item_with_resource(name, resource)
+                    })
+                    .collect();
+                let limits: Vec<_> = items
+                    .iter()
+                    .map(|item| (item.resource.min, item.resource.max))
+                    .collect();
+                let (bound, resources) = build_resources_unbound(weights.clone());
+
+                let items = schedule(items, bound, resources.clone(), solver).unwrap();
+                let (indices, weights) = aggregate_resources(&items, &resources);
+
+                // Each resource is bound at most once
+                let mut used: Vec<_> = indices.iter().flatten().collect();
+                used.sort();
+                used.dedup();
+                assert_eq!(used.len(), indices.iter().map(Vec::len).sum::<usize>());
+
+                for ((min, max), (item, load)) in limits.into_iter().zip(items.iter().zip(weights))
+                {
+                    if let Some(min) = min {
+                        assert!(load >= min, "{solver:?}: {load} < min {min}");
+                        assert!(!item.unsatisfiable);
+                    }
+                    if let Some(max) = max {
+                        assert!(load <= max, "{solver:?}: {load} > max {max}");
+                    }
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,369 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet},
+};
+
+use good_lp::{ResolutionError, solvers::ObjectiveDirection};
+use openark_spectrum_api::pool_claim::PoolTopologyKey;
+use ordered_float::OrderedFloat;
+
+use crate::{
+    solvers::{Problem, Solver},
+    state::State,
+};
+
+/// A tolerance of comparing the loads.
+const EPSILON: f64 = 1e-6;
+
+/// Allocates the resources deterministically with heuristics.
+///
+/// It scales to large pools, but the allocation may not be optimal.
+/// The resources which would break the topology spread constraints are left unbound.
+pub(crate) struct GreedySolver;
+
+impl Solver for GreedySolver {
+    fn solve<T>(
+        &self,
+        problem: &Problem,
+        state: &mut State<'_, T>,
+    ) -> Result<bool, ResolutionError> {
+        let Problem {
+            direction,
+            use_max,
+            use_min,
+            ..
+        } = *problem;
+        let targets = &problem.items;
+
+        // Stop if no more items or remaining resources
+        if state.remaining.is_empty() || targets.is_empty() {
+            return Ok(false);
+        }
+
+        let mut plan = Plan::new(state, targets, use_max);
+        match direction {
+            // Meet the minimum weights first, and then use resources as much as possible
+            ObjectiveDirection::Maximisation => {
+                if use_min {
+                    for col in 0..targets.len() {
+                        let min = state.items[targets[col]].resource.min;
+                        plan.fill(state, col, min.unwrap_or_default());
+                    }
+                }
+                for col in 0..targets.len() {
+                    plan.fill(state, col, f64::INFINITY);
+                }
+                if !plan.trim(state) || use_min && !plan.is_satisfied(state) {
+                    return Ok(false);
+                }
+            }
+            // Bind each resource to the least loaded item
+            ObjectiveDirection::Minimisation => {
+                plan.balance(state);
+                if !plan.trim(state) {
+                    return Ok(false);
+                }
+            }
+        }
+        plan.commit(state);
+        Ok(true)
+    }
+}
+
+/// A tentative allocation, which is committed only if feasible.
+struct Plan<'s> {
+    targets: &'s [usize],
+    /// The planned loads of the targets, including the penalties
+    loads: Vec<f64>,
+    /// The maximum loads of the targets
+    caps: Vec<f64>,
+    /// The remaining resources, sorted by their weights in descending order
+    candidates: Vec<usize>,
+    /// The planned bindings of (resource, target)
+    assigned: BTreeMap<usize, usize>,
+    /// The topology keys of the anti-affinity constraints
+    anti_affinity: BTreeSet<PoolTopologyKey>,
+    /// The items which occupy each topology domain
+    occupants: BTreeMap<(PoolTopologyKey, String), BTreeSet<usize>>,
+}
+
+impl<'s> Plan<'s> {
+    fn new<T>(state: &State<'_, T>, targets: &'s [usize], use_max: bool) -> Self {
+        let loads = targets.iter().map(|&j| state.filled[j]).collect();
+        let caps = targets
+            .iter()
+            .map(|&j| {
+                let resource = &state.items[j].resource;
+                match resource.max.or(resource.min) {
+                    Some(max) if use_max => max,
+                    _ => f64::INFINITY,
+                }
+            })
+            .collect();
+
+        let mut candidates: Vec<_> = state.remaining.iter().copied().collect();
+        candidates.sort_by_key(|&i| (Reverse(OrderedFloat(state.weights[i])), i));
+
+        let anti_affinity = state
+            .items
+            .iter()
+            .flat_map(|item| item.resource.anti_affinity.iter().copied())
+            .collect();
+
+        let mut plan = Self {
+            targets,
+            loads,
+            caps,
+            candidates,
+            assigned: BTreeMap::default(),
+            anti_affinity,
+            occupants: BTreeMap::default(),
+        };
+        for (j, allocated) in state.allocated.iter().enumerate() {
+            for &i in allocated {
+                plan.occupy(state, i, j);
+            }
+        }
+        plan
+    }
+
+    /// Binds the resources to the target until its load reaches the goal.
+    fn fill<T>(&mut self, state: &State<'_, T>, col: usize, goal: f64) {
+        let j = self.targets[col];
+        let spread = &state.items[j].resource.spread;
+
+        while self.loads[col] + EPSILON < goal {
+            let Some(i) = self
+                .candidates
+                .iter()
+                .copied()
+                .filter(|i| !self.assigned.contains_key(i))
+                .filter(|&i| self.loads[col] + self.cost(state, i, j) <= self.caps[col] + EPSILON)
+                .filter(|&i| self.is_allowed(state, i, j) && self.is_spread(state, i, j))
+                // Prefer not to preempt, and then the least populated domains
+                .min_by_key(|&i| {
+                    let is_preempting = state.bound[i].claim.is_some_and(|owner| owner != j);
+                    let population: usize = spread
+                        .iter()
+                        .map(|constraint| self.population(state, i, j, constraint.topology_key))
+                        .sum();
+                    (is_preempting, population)
+                })
+            else {
+                break;
+            };
+            self.assign(state, i, col);
+        }
+    }
+
+    /// Binds each resource to the least loaded target, relative to its weight.
+    fn balance<T>(&mut self, state: &State<'_, T>) {
+        // NOTE: The resources blocked by the spread may be allowed once the other domains are filled
+        loop {
+            let mut is_changed = false;
+            for i in self.candidates.clone() {
+                if self.assigned.contains_key(&i) {
+                    continue;
+                }
+                let best = (0..self.targets.len())
+                    .filter(|&col| {
+                        let j = self.targets[col];
+                        self.loads[col] + self.cost(state, i, j) <= self.caps[col] + EPSILON
+                            && self.is_allowed(state, i, j)
+                            && self.is_spread(state, i, j)
+                    })
+                    .min_by_key(|&col| {
+                        let j = self.targets[col];
+                        let weight = state.items[j].resource.weight as f64;
+                        let load = (self.loads[col] + self.cost(state, i, j)) / weight;
+                        let is_preempting = state.bound[i].claim.is_some_and(|owner| owner != j);
+                        (OrderedFloat(load), is_preempting)
+                    });
+
+                // NOTE: The resources blocked by the topology constraints are left unbound
+                if let Some(col) = best {
+                    self.assign(state, i, col);
+                    is_changed = true;
+                }
+            }
+            if !is_changed {
+                break;
+            }
+        }
+    }
+
+    /// Unbinds the planned resources from the most populated domains until
+    /// every topology spread constraint is met.
+    ///
+    /// Returns `false` if a constraint cannot be met, e.g. broken by the locked resources.
+    fn trim<T>(&mut self, state: &State<'_, T>) -> bool {
+        loop {
+            let mut is_changed = false;
+            for col in 0..self.targets.len() {
+                let j = self.targets[col];
+                for constraint in &state.items[j].resource.spread {
+                    let key = constraint.topology_key;
+                    let counts = self.counts(state, j, key);
+                    let (Some(&lo), Some((&domain, &hi))) = (
+                        counts.values().min(),
+                        counts.iter().max_by_key(|&(_, count)| count),
+                    ) else {
+                        continue;
+                    };
+                    if hi - lo <= constraint.max_skew as usize {
+                        continue;
+                    }
+
+                    // Release the lightest planned resource in the domain
+                    let Some(i) = self
+                        .assigned
+                        .iter()
+                        .filter(|&(_, &c)| c == col)
+                        .map(|(&i, _)| i)
+                        .filter(|&i| state.topologies[i].get(key) == Some(domain))
+                        .min_by_key(|&i| (OrderedFloat(state.weights[i]), Reverse(i)))
+                    else {
+                        return false;
+                    };
+                    self.loads[col] -= self.cost(state, i, j);
+                    self.assigned.remove(&i);
+                    is_changed = true;
+                }
+            }
+            if !is_changed {
+                return true;
+            }
+        }
+    }
+
+    fn is_satisfied<T>(&self, state: &State<'_, T>) -> bool {
+        self.targets.iter().zip(&self.loads).all(|(&j, &load)| {
+            state.items[j]
+                .resource
+                .min
+                .is_none_or(|min| load + EPSILON >= min)
+        })
+    }
+
+    fn commit<T>(self, state: &mut State<'_, T>) {
+        for (i, col) in self.assigned {
+            let j = self.targets[col];
+            state.allocated[j].push(i);
+            state.filled[j] += state.weights[i];
+            state.remaining.remove(&i);
+        }
+    }
+
+    fn assign<T>(&mut self, state: &State<'_, T>, i: usize, col: usize) {
+        let j = self.targets[col];
+        self.loads[col] += self.cost(state, i, j);
+        self.assigned.insert(i, col);
+        self.occupy(state, i, j);
+    }
+
+    /// Returns the load of binding the resource to the item, including the penalty of preemption.
+    fn cost<T>(&self, state: &State<'_, T>, i: usize, j: usize) -> f64 {
+        match state.bound[i].claim {
+            Some(owner) if owner != j => state.weights[i] + state.items[j].resource.penalty,
+            _ => state.weights[i],
+        }
+    }
+
+    /// Returns whether the resource can be bound to the item without breaking the anti-affinity.
+    fn is_allowed<T>(&self, state: &State<'_, T>, i: usize, j: usize) -> bool {
+        self.anti_affinity.iter().all(|&key| {
+            let Some(domain) = state.topologies[i].get(key) else {
+                return true;
+            };
+            let Some(occupants) = self.occupants.get(&(key, domain.into())) else {
+                return true;
+            };
+            let is_exclusive = |a: usize| state.items[a].resource.anti_affinity.contains(&key);
+            occupants
+                .iter()
+                .filter(|&&a| a != j)
+                .all(|&a| !is_exclusive(a) && !is_exclusive(j))
+        })
+    }
+
+    /// Returns whether the resource can be bound to the item without breaking the spread.
+    ///
+    /// The domains are filled one by one, so that the skew is kept within 1 while planning.
+    /// A tighter skew is met by trimming the plan afterwards.
+    fn is_spread<T>(&self, state: &State<'_, T>, i: usize, j: usize) -> bool {
+        state.items[j].resource.spread.iter().all(|constraint| {
+            let key = constraint.topology_key;
+            let Some(domain) = state.topologies[i].get(key) else {
+                return true;
+            };
+            let counts = self.counts(state, j, key);
+            let lo = counts.values().min().copied().unwrap_or_default();
+            let count = counts.get(domain).copied().unwrap_or_default();
+            count + 1 - lo <= constraint.max_skew.max(1) as usize
+        })
+    }
+
+    /// Returns the number of the resources of the item in each eligible domain.
+    ///
+    /// As the MILP solver does, only the domains of the remaining or
+    /// the bound resources of the item are eligible.
+    fn counts<'t, T>(
+        &self,
+        state: &'t State<'_, T>,
+        j: usize,
+        key: PoolTopologyKey,
+    ) -> BTreeMap<&'t str, usize> {
+        let mut counts: BTreeMap<_, _> = state
+            .remaining
+            .iter()
+            .filter_map(|&i| state.topologies[i].get(key))
+            .map(|domain| (domain, 0))
+            .collect();
+        let bound = state.allocated[j].iter().chain(
+            self.assigned
+                .iter()
+                .filter(|&(_, &col)| self.targets[col] == j)
+                .map(|(i, _)| i),
+        );
+        for &i in bound {
+            if let Some(domain) = state.topologies[i].get(key) {
+                *counts.entry(domain).or_default() += 1;
+            }
+        }
+        counts
+    }
+
+    /// Returns the number of the resources of the item in the domain of the resource `i`.
+    fn population<T>(
+        &self,
+        state: &State<'_, T>,
+        i: usize,
+        j: usize,
+        key: PoolTopologyKey,
+    ) -> usize {
+        let Some(domain) = state.topologies[i].get(key) else {
+            return 0;
+        };
+        state.allocated[j]
+            .iter()
+            .chain(
+                self.assigned
+                    .iter()
+                    .filter(|&(_, &col)| self.targets[col] == j)
+                    .map(|(i, _)| i),
+            )
+            .filter(|&&index| state.topologies[index].get(key) == Some(domain))
+            .count()
+    }
+
+    fn occupy<T>(&mut self, state: &State<'_, T>, i: usize, j: usize) {
+        for &key in &self.anti_affinity {
+            if let Some(domain) = state.topologies[i].get(key) {
+                self.occupants
+                    .entry((key, domain.into()))
+                    .or_default()
+                    .insert(j);
+            }
+        }
+    }
+}
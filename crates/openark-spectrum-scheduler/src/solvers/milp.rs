@@ -1,6 +1,8 @@
+use std::collections::{BTreeMap, BTreeSet};
+
 use good_lp::{
-    Expression, IntoAffineExpression, LpSolver, ProblemVariables, ResolutionError, Solution,
-    SolverModel, constraint,
+    Constraint, Expression, IntoAffineExpression, LpSolver, ProblemVariables, ResolutionError,
+    Solution, SolverModel, Variable, constraint,
     solvers::{
         ObjectiveDirection,
         lp_solvers::{CbcSolver, WithMaxSeconds, WithMipGap},
@@ -8,25 +10,31 @@ use good_lp::{
     variable,
 };
 
-use crate::state::State;
+use openark_spectrum_api::pool_claim::PoolTopologyKey;
 
-pub(crate) struct Solver {
-    pub(crate) direction: ObjectiveDirection,
-    pub(crate) items: Vec<usize>,
-    pub(crate) use_all: bool,
-    pub(crate) use_max: bool,
-    pub(crate) use_min: bool,
-}
+use crate::{
+    item::{Item, Topology},
+    solvers::{Problem, Solver},
+    state::State,
+};
 
-impl Solver {
-    pub(crate) fn solve<T>(self, state: &mut State<'_, T>) -> Result<bool, ResolutionError> {
-        let Self {
+/// Solves the problem optimally with Mixed-Integer Linear Programming.
+pub(crate) struct MilpSolver;
+
+impl Solver for MilpSolver {
+    fn solve<T>(
+        &self,
+        problem: &Problem,
+        state: &mut State<'_, T>,
+    ) -> Result<bool, ResolutionError> {
+        let Problem {
             direction,
-            items: targets,
             use_all,
             use_max,
             use_min,
-        } = self;
+            ..
+        } = *problem;
+        let targets = &problem.items;
 
         let State {
             allocated,
@@ -34,6 +42,7 @@ impl Solver {
             filled,
             items,
             remaining,
+            topologies,
             weights,
         } = state;
 
@@ -70,12 +79,22 @@ impl Solver {
             }
         }
 
+        // NOTE: The topology constraints may leave some resources unbound,
+        //       so that `use_all` is relaxed into a penalty of the objective.
+        let has_topology = targets
+            .iter()
+            .any(|&j| !items[j].resource.spread.is_empty())
+            || items
+                .iter()
+                .any(|item| !item.resource.anti_affinity.is_empty());
+        let use_penalty = use_all && has_topology;
+
         // (a) Bind each resource to an item
         let mut constraints = Vec::default();
         for row in y.iter().take(n_i) {
             let init = Expression::default();
             let expr = (0..n_j).fold(init, |acc, col| acc + row[col]);
-            let constraint = if use_all {
+            let constraint = if use_all && !use_penalty {
                 // Required
                 constraint!(expr == 1.0)
             } else {
@@ -110,6 +129,19 @@ impl Solver {
             }
         }
 
+        // (d) Topology spread / anti-affinity
+        if has_topology {
+            let problem = TopologyProblem {
+                allocated,
+                items,
+                remaining,
+                targets,
+                topologies,
+                y: &y,
+            };
+            problem.build(&mut vars, &mut constraints);
+        }
+
         let objective = match direction {
             // (c) Just use resources as much as possible
             ObjectiveDirection::Maximisation => {
@@ -129,7 +161,16 @@ impl Solver {
                         constraints.push(constraint!(expr2 - expr1.clone() <= d));
                     }
                 }
-                d.into_expression()
+                if use_penalty {
+                    // Bind as many resources as possible, and then minimise the deviation
+                    let penalty = filled.iter().sum::<f64>() + weights.iter().sum::<f64>() + 1.0;
+                    let init = d.into_expression();
+                    y.iter()
+                        .flatten()
+                        .fold(init, |acc, &var| acc - penalty * var)
+                } else {
+                    d.into_expression()
+                }
             }
         };
 
@@ -165,3 +206,147 @@ impl Solver {
         Ok(true)
     }
 }
+
+/// Encodes the topology constraints of the items into the model.
+struct TopologyProblem<'s, 'a, T> {
+    allocated: &'s [Vec<usize>],
+    items: &'s [Item<'a, T>],
+    remaining: &'s BTreeSet<usize>,
+    targets: &'s [usize],
+    topologies: &'s [Topology],
+    /// The variable matrix [remaining, targets]
+    y: &'s [Vec<Variable>],
+}
+
+impl<T> TopologyProblem<'_, '_, T> {
+    fn build(&self, vars: &mut ProblemVariables, constraints: &mut Vec<Constraint>) {
+        for (col, &j) in self.targets.iter().enumerate() {
+            for spread in &self.items[j].resource.spread {
+                self.build_spread(vars, constraints, col, spread.topology_key, spread.max_skew);
+            }
+        }
+        for (a, item) in self.items.iter().enumerate() {
+            for &key in &item.resource.anti_affinity {
+                self.build_anti_affinity(vars, constraints, a, key);
+            }
+        }
+    }
+
+    /// Bounds the difference of the bound resources between any two domains.
+    fn build_spread(
+        &self,
+        vars: &mut ProblemVariables,
+        constraints: &mut Vec<Constraint>,
+        col: usize,
+        key: PoolTopologyKey,
+        max_skew: u32,
+    ) {
+        let j = self.targets[col];
+
+        // NOTE: Only the domains where the item can be placed are eligible
+        let rows = self.rows_by_domain(key);
+        let prefilled = self.group_by_domain(key, self.allocated[j].iter().copied());
+        let domains: BTreeSet<_> = rows.keys().chain(prefilled.keys()).copied().collect();
+        if domains.len() < 2 {
+            return;
+        }
+
+        let hi = vars.add(variable().min(0.0));
+        let lo = vars.add(variable().min(0.0));
+        for domain in domains {
+            let init = prefilled.get(domain).map(Vec::len).unwrap_or_default() as f64;
+            let count = rows
+                .get(domain)
+                .into_iter()
+                .flatten()
+                .fold(init + Expression::default(), |acc, &row| {
+                    acc + self.y[row][col]
+                });
+            constraints.push(constraint!(count.clone() <= hi));
+            constraints.push(constraint!(count >= lo));
+        }
+        constraints.push(constraint!(hi - lo <= max_skew as f64));
+    }
+
+    /// Forbids the other items to share the domains of the item `a`.
+    fn build_anti_affinity(
+        &self,
+        vars: &mut ProblemVariables,
+        constraints: &mut Vec<Constraint>,
+        a: usize,
+        key: PoolTopologyKey,
+    ) {
+        let rows = self.rows_by_domain(key);
+        let col_a = self.targets.iter().position(|&j| j == a);
+
+        for (domain, rows) in rows {
+            let has_domain = |indices: &[usize]| {
+                indices
+                    .iter()
+                    .any(|&i| self.topologies[i].get(key) == Some(domain))
+            };
+            let occupied_by_a = has_domain(&self.allocated[a]);
+            let occupied_by_others = self
+                .allocated
+                .iter()
+                .enumerate()
+                .any(|(j, indices)| j != a && has_domain(indices));
+
+            match col_a {
+                // The item is being placed: `z` marks whether it takes the domain
+                Some(col_a) => {
+                    let z = vars.add(variable().binary());
+                    if occupied_by_a {
+                        constraints.push(constraint!(z == 1.0));
+                    } else if occupied_by_others {
+                        constraints.push(constraint!(z == 0.0));
+                    }
+                    for &row in &rows {
+                        for (col, &var) in self.y[row].iter().enumerate() {
+                            if col == col_a {
+                                constraints.push(constraint!(var <= z));
+                            } else {
+                                constraints.push(constraint!(var + z <= 1.0));
+                            }
+                        }
+                    }
+                }
+                // The item has been placed: keep the others away from its domains
+                None if occupied_by_a => {
+                    for &row in &rows {
+                        for &var in &self.y[row] {
+                            constraints.push(constraint!(var <= 0.0));
+                        }
+                    }
+                }
+                None => continue,
+            }
+        }
+    }
+
+    /// Groups the rows of the remaining resources by their domains.
+    fn rows_by_domain(&self, key: PoolTopologyKey) -> BTreeMap<&str, Vec<usize>> {
+        let mut map: BTreeMap<_, Vec<_>> = BTreeMap::default();
+        for (row, &i) in self.remaining.iter().enumerate() {
+            if let Some(domain) = self.topologies[i].get(key) {
+                map.entry(domain).or_default().push(row);
+            }
+        }
+        map
+    }
+
+    /// Groups the resources by their domains.
+    fn group_by_domain(
+        &self,
+        key: PoolTopologyKey,
+        indices: impl IntoIterator<Item = usize>,
+    ) -> BTreeMap<&str, Vec<usize>> {
+        let mut map: BTreeMap<_, Vec<_>> = BTreeMap::default();
+        for i in indices {
+            if let Some(domain) = self.topologies[i].get(key) {
+                map.entry(domain).or_default().push(i);
+            }
+        }
+        map
+    }
+}
@@ -1 +1,70 @@
+pub(crate) mod greedy;
 pub(crate) mod milp;
+
+use good_lp::{ResolutionError, solvers::ObjectiveDirection};
+use openark_spectrum_api::pool::PoolSolver;
+#[cfg(feature = "tracing")]
+use tracing::warn;
+
+use crate::{
+    solvers::{greedy::GreedySolver, milp::MilpSolver},
+    state::State,
+};
+
+/// A problem to allocate the remaining resources into a tier of items.
+pub(crate) struct Problem {
+    pub(crate) direction: ObjectiveDirection,
+    pub(crate) items: Vec<usize>,
+    pub(crate) use_all: bool,
+    pub(crate) use_max: bool,
+    pub(crate) use_min: bool,
+}
+
+impl Problem {
+    /// Returns whether any item of the problem has topology spread constraints.
+    fn has_spread<T>(&self, state: &State<'_, T>) -> bool {
+        self.items
+            .iter()
+            .any(|&j| !state.items[j].resource.spread.is_empty())
+    }
+}
+
+pub(crate) trait Solver {
+    /// Allocates the remaining resources into the items of the problem.
+    ///
+    /// Returns `false` if the problem is infeasible, leaving the state as-is.
+    fn solve<T>(
+        &self,
+        problem: &Problem,
+        state: &mut State<'_, T>,
+    ) -> Result<bool, ResolutionError>;
+}
+
+/// Solves the problem, falling back to the greedy solver if the MILP solver fails.
+///
+/// The greedy solver is also tried if the problem is infeasible under the MILP solver,
+/// unless the items have topology spread constraints, which are hard constraints.
+pub(crate) fn solve<T>(
+    solver: PoolSolver,
+    problem: &Problem,
+    state: &mut State<'_, T>,
+) -> Result<bool, ResolutionError> {
+    match solver {
+        PoolSolver::Milp => match MilpSolver.solve(problem, state) {
+            Ok(true) => Ok(true),
+            Ok(false) if problem.has_spread(state) => Ok(false),
+            Ok(false) => {
+                #[cfg(feature = "tracing")]
+                warn!("infeasible with MILP; falling back to greedy");
+                GreedySolver.solve(problem, state)
+            }
+            Err(error) => {
+                #[cfg(feature = "tracing")]
+                warn!("failed to solve with MILP; falling back to greedy: {error}");
+                let _ = error;
+                GreedySolver.solve(problem, state)
+            }
+        },
+        PoolSolver::Greedy => GreedySolver.solve(problem, state),
+    }
+}
@@ -4,7 +4,7 @@ use openark_spectrum_api::schema::PoolResource;
 #[cfg(feature = "tracing")]
 use tracing::debug;
 
-use crate::item::{Item, ScheduledItem, WeightedItems};
+use crate::item::{Eviction, Item, ScheduledItem, Topology, WeightedItems};
 
 pub(crate) struct State<'a, T> {
     pub(crate) allocated: Vec<Vec<usize>>,
@@ -12,6 +12,7 @@ pub(crate) struct State<'a, T> {
     pub(crate) filled: Vec<f64>,
     pub(crate) items: Vec<Item<'a, T>>,
     pub(crate) remaining: BTreeSet<usize>,
+    pub(crate) topologies: Vec<Topology>,
     pub(crate) weights: Vec<f64>,
 }
 
@@ -19,6 +20,7 @@ impl<'a, T> State<'a, T> {
     pub(super) fn collect<S>(self, resources: WeightedItems<S>) -> Vec<ScheduledItem<S, T>> {
         let Self {
             allocated,
+            bound,
             filled,
             items,
             ..
@@ -29,22 +31,46 @@ impl<'a, T> State<'a, T> {
         {
             debug!("Scheduled: {filled:?}");
         }
-        #[cfg(not(feature = "tracing"))]
-        {
-            let _ = filled;
+
+        // Find out the new owners of the resources
+        let mut owners = vec![None; bound.len()];
+        for (j, allocated) in allocated.iter().enumerate() {
+            for &i in allocated {
+                owners[i] = Some(j);
+            }
+        }
+        let mut evictions = vec![Vec::default(); items.len()];
+        for (i, last) in bound.iter().enumerate() {
+            if let Some(j) = last.claim
+                && owners[i] != Some(j)
+                && let Some(evictions) = evictions.get_mut(j)
+            {
+                evictions.push(Eviction {
+                    resource: i,
+                    preemptor: owners[i],
+                });
+            }
         }
 
         items
             .into_iter()
             .zip(allocated)
+            .zip(evictions)
+            .zip(filled)
             .map(
                 |(
-                    Item {
-                        claim,
-                        item,
-                        resource,
-                    },
-                    allocated,
+                    (
+                        (
+                            Item {
+                                claim,
+                                item,
+                                resource,
+                            },
+                            allocated,
+                        ),
+                        evictions,
+                    ),
+                    load,
                 )| ScheduledItem {
                     lifecycle: claim.spec.lifecycle.clone(),
                     item,
@@ -55,6 +81,9 @@ impl<'a, T> State<'a, T> {
                             resources.get_mut(index).and_then(|option| option.take())
                         })
                         .collect(),
+                    evictions,
+                    load,
+                    unsatisfiable: resource.min.is_some_and(|min| load < min),
                 },
             )
             .collect()
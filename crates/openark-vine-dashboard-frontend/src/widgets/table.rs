@@ -662,7 +662,9 @@ fn build_extra_service_tab_content_vnc(
                     command,
                     label_selector: None,
                     namespace: None,
+                    stdin: None,
                     terminal: true,
+                    timeout_seconds: None,
                     wait: true,
                 };
 
@@ -1,5 +1,7 @@
-use std::{collections::BTreeMap, string::String};
+use std::{collections::BTreeMap, string::String, vec::Vec};
 
+use anyhow::{Result, bail};
+use jiff::{SignedDuration, Timestamp, tz::TimeZone};
 #[cfg(feature = "kube")]
 use kube::CustomResource;
 #[cfg(feature = "schemars")]
@@ -7,6 +9,8 @@ use schemars::JsonSchema;
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
+use crate::schedule::CronSchedule;
+
 /// A struct storing user session.
 /// A binding can apply to many sessions.
 ///
@@ -55,10 +59,158 @@ pub struct SessionBindingSpec {
 
     pub profile: String,
 
+    /// Time windows when the binding is active, e.g. the class hours.
+    /// The binding is always active if not given.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub schedule: Option<SessionBindingScheduleSpec>,
+
     #[cfg_attr(feature = "serde", serde(default))]
     pub user: SessionBindingUserSpec,
 }
 
+/// Time windows of a binding, which are evaluated in the timezone of the profile.
+///
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct SessionBindingScheduleSpec {
+    /// The binding is inactive before this time.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub start: Option<Timestamp>,
+
+    /// The binding is inactive since this time.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub end: Option<Timestamp>,
+
+    /// Recurring windows, where the binding is active within any of them.
+    /// The binding is active between `start` and `end` if empty.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub windows: Vec<SessionBindingWindowSpec>,
+
+    /// Warn the users before the binding expires, as seconds.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub warning_seconds: Option<u32>,
+}
+
+impl SessionBindingScheduleSpec {
+    pub const DEFAULT_WARNING_SECONDS: u32 = 300;
+
+    /// Return the duration to warn the users before the binding expires.
+    ///
+    #[must_use]
+    pub fn warning(&self) -> SignedDuration {
+        SignedDuration::from_secs(
+            self.warning_seconds
+                .unwrap_or(Self::DEFAULT_WARNING_SECONDS)
+                .into(),
+        )
+    }
+
+    /// Evaluate whether the binding is active at the timestamp.
+    ///
+    pub fn state(&self, timestamp: Timestamp, tz: &TimeZone) -> Result<ScheduleState> {
+        if let Some(start) = self.start
+            && timestamp < start
+        {
+            return Ok(ScheduleState::Inactive { next: Some(start) });
+        }
+        if let Some(end) = self.end
+            && timestamp >= end
+        {
+            return Ok(ScheduleState::Inactive { next: None });
+        }
+        if self.windows.is_empty() {
+            return Ok(ScheduleState::Active { until: self.end });
+        }
+
+        let mut until = None;
+        let mut next = None;
+        for window in &self.windows {
+            let schedule: CronSchedule = window.cron.parse()?;
+            if window.duration_minutes > SessionBindingWindowSpec::MAX_DURATION_MINUTES {
+                bail!(
+                    "Too long schedule window: {} minutes (max {})",
+                    window.duration_minutes,
+                    SessionBindingWindowSpec::MAX_DURATION_MINUTES,
+                );
+            }
+            let duration = window.duration();
+
+            match schedule.last(timestamp, tz, duration) {
+                Some(opened) if opened + duration > timestamp => {
+                    until = until.max(Some(opened + duration));
+                }
+                Some(_) | None => {
+                    if let Some(opened) = schedule.next(timestamp, tz) {
+                        next = Some(next.map_or(opened, |next: Timestamp| next.min(opened)));
+                    }
+                }
+            }
+        }
+
+        Ok(match until {
+            Some(until) => ScheduleState::Active {
+                until: Some(self.end.map_or(until, |end| end.min(until))),
+            },
+            None => ScheduleState::Inactive {
+                next: next.filter(|&next| self.end.is_none_or(|end| next < end)),
+            },
+        })
+    }
+}
+
+/// A recurring window of a binding.
+///
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct SessionBindingWindowSpec {
+    /// A cron-like schedule opening the window, e.g. `0 9 * * 1-5`.
+    pub cron: String,
+
+    /// How long the window is open, as minutes, up to 7 days.
+    #[cfg_attr(feature = "schemars", schemars(range(min = 1, max = 10080)))]
+    pub duration_minutes: u32,
+}
+
+impl SessionBindingWindowSpec {
+    pub const MAX_DURATION_MINUTES: u32 = 7 * 24 * 60;
+
+    /// Return how long the window is open.
+    ///
+    #[must_use]
+    pub fn duration(&self) -> SignedDuration {
+        SignedDuration::from_mins(self.duration_minutes.into())
+    }
+}
+
+/// A state of the binding's schedule.
+///
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ScheduleState {
+    /// The binding is active until the time, if any.
+    Active { until: Option<Timestamp> },
+    /// The binding is inactive until the time, if any.
+    Inactive { next: Option<Timestamp> },
+}
+
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[cfg_attr(feature = "schemars", derive(JsonSchema))]
@@ -85,3 +237,65 @@ pub enum SessionBindingUserKind {
     Guest,
     User,
 }
+
+#[cfg(test)]
+mod tests {
+    use jiff::civil::date;
+
+    use super::*;
+
+    #[test]
+    fn evaluate_schedule_windows() {
+        let tz = TimeZone::UTC;
+        let at = |day, hour, minute| {
+            tz.to_ambiguous_timestamp(date(2026, 3, day).at(hour, minute, 0, 0))
+                .compatible()
+                .unwrap()
+        };
+
+        // Weekday classes from 09:00 to 10:30, until 2026-03-06 10:00
+        let schedule = SessionBindingScheduleSpec {
+            start: None,
+            end: Some(at(6, 10, 0)),
+            windows: vec![SessionBindingWindowSpec {
+                cron: "0 9 * * 1-5".into(),
+                duration_minutes: 90,
+            }],
+            warning_seconds: None,
+        };
+
+        assert_eq!(
+            schedule.state(at(2, 9, 30), &tz).unwrap(),
+            ScheduleState::Active {
+                until: Some(at(2, 10, 30)),
+            },
+        );
+        assert_eq!(
+            schedule.state(at(2, 10, 30), &tz).unwrap(),
+            ScheduleState::Inactive {
+                next: Some(at(3, 9, 0)),
+            },
+        );
+        // Truncated by the end
+        assert_eq!(
+            schedule.state(at(6, 9, 0), &tz).unwrap(),
+            ScheduleState::Active {
+                until: Some(at(6, 10, 0)),
+            },
+        );
+        assert_eq!(
+            schedule.state(at(6, 10, 0), &tz).unwrap(),
+            ScheduleState::Inactive { next: None },
+        );
+
+        // Too long windows are rejected
+        let schedule = SessionBindingScheduleSpec {
+            windows: vec![SessionBindingWindowSpec {
+                cron: "0 9 * * 1-5".into(),
+                duration_minutes: SessionBindingWindowSpec::MAX_DURATION_MINUTES + 1,
+            }],
+            ..Default::default()
+        };
+        assert!(schedule.state(at(2, 9, 30), &tz).is_err());
+    }
+}
@@ -4,7 +4,10 @@ use http::Method;
 use openark_core::client::{Client, RequestCredentials};
 use url::Url;
 
-use crate::{command::SessionCommandView, exec::ExecArgs};
+use crate::{
+    command::SessionCommandView,
+    exec::{ExecArgs, ExecReport},
+};
 
 #[cfg_attr(feature = "send", async_trait)]
 #[cfg_attr(not(feature = "send"), async_trait(?Send))]
@@ -20,7 +23,7 @@ where
     }
 
     #[inline]
-    async fn vine_session_exec(&self, base_url: Url, args: &ExecArgs) -> Result<()> {
+    async fn vine_session_exec(&self, base_url: Url, args: &ExecArgs) -> Result<ExecReport> {
         let url = base_url.join("exec")?;
         self.request_with_json(RequestCredentials::Include, Method::POST, url, args)
             .await
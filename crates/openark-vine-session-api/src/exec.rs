@@ -1,9 +1,12 @@
+use std::collections::BTreeMap;
+
 #[cfg(feature = "clap")]
 use clap::Parser;
 #[cfg(feature = "schemars")]
 use schemars::JsonSchema;
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
+use strum::{Display, EnumString};
 
 #[cfg_attr(feature = "clap", derive(Parser))]
 #[cfg_attr(feature = "schemars", derive(JsonSchema))]
@@ -33,13 +36,328 @@ pub struct ExecArgs {
     )]
     pub namespace: Option<String>,
 
+    /// Data to be written into the standard input of each command
+    #[cfg_attr(feature = "clap", arg(long, value_name = "DATA"))]
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub stdin: Option<String>,
+
     /// Whether to execute within a GUI terminal.
     #[cfg_attr(feature = "clap", arg(short, long))]
     #[cfg_attr(feature = "serde", serde(default))]
     pub terminal: bool,
 
+    /// Stop waiting the attached processes after this, as seconds.
+    #[cfg_attr(feature = "clap", arg(long, value_name = "SECONDS"))]
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub timeout_seconds: Option<u64>,
+
     /// Whether to wait the attached processes.
     #[cfg_attr(feature = "clap", arg(short, long))]
     #[cfg_attr(feature = "serde", serde(default))]
     pub wait: bool,
 }
+
+#[derive(Copy, Clone, Debug, Default, Display, EnumString, PartialEq, Eq)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+#[strum(serialize_all = "camelCase")]
+pub enum ExecFormat {
+    /// A report of all sessions, once the commands are completed
+    #[default]
+    Json,
+    /// A stream of the outputs and the results, one event per line
+    Ndjson,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", rename_all = "camelCase"))]
+pub enum ExecEvent {
+    Output(ExecOutput),
+    Exit(ExecResult),
+}
+
+/// A line printed by a command.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct ExecOutput {
+    pub pod: String,
+    pub stream: ExecStream,
+    pub line: String,
+}
+
+#[derive(Copy, Clone, Debug, Display, EnumString, PartialEq, Eq)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+#[strum(serialize_all = "camelCase")]
+pub enum ExecStream {
+    Stdout,
+    Stderr,
+}
+
+/// The result of a command executed into a session.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct ExecResult {
+    pub pod: String,
+
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub node: Option<String>,
+
+    pub state: ExecState,
+
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub exit_code: Option<i32>,
+
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub message: Option<String>,
+
+    /// The collected standard output, which is empty on streaming
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "String::is_empty")
+    )]
+    pub stdout: String,
+
+    /// The collected standard error, which is empty on streaming
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "String::is_empty")
+    )]
+    pub stderr: String,
+
+    /// Whether the collected outputs exceeded [`MAX_OUTPUT_BYTES`]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub truncated: bool,
+}
+
+impl ExecResult {
+    /// Append a line to the collected outputs, dropping the lines over [`MAX_OUTPUT_BYTES`].
+    fn push_output(&mut self, stream: ExecStream, line: &str) {
+        if self.truncated {
+            return;
+        }
+        let is_full = self.stdout.len() + self.stderr.len() + line.len() + 1 > MAX_OUTPUT_BYTES;
+        let buf = match stream {
+            ExecStream::Stdout => &mut self.stdout,
+            ExecStream::Stderr => &mut self.stderr,
+        };
+        if is_full {
+            buf.push_str(TRUNCATED_MARKER);
+            self.truncated = true;
+        } else {
+            buf.push_str(line);
+        }
+        buf.push('\n');
+    }
+}
+
+#[derive(Copy, Clone, Debug, Display, EnumString, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
+#[strum(serialize_all = "PascalCase")]
+pub enum ExecState {
+    /// The session is not ready
+    Skipped,
+    /// The command has been spawned without waiting
+    Spawned,
+    Succeeded,
+    Failed,
+    TimedOut,
+    /// The command could not be executed
+    Error,
+}
+
+impl ExecState {
+    /// Return `true` if the command has not failed.
+    #[must_use]
+    pub const fn is_success(&self) -> bool {
+        matches!(self, Self::Skipped | Self::Spawned | Self::Succeeded)
+    }
+}
+
+/// The maximum bytes of the outputs collected from each session.
+pub const MAX_OUTPUT_BYTES: usize = 1 << 20;
+
+/// A line which marks the collected outputs as truncated.
+pub const TRUNCATED_MARKER: &str = "... (truncated)";
+
+/// The results of a command executed into multiple sessions.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct ExecReport {
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub results: Vec<ExecResult>,
+
+    /// The indices of the results by their pods
+    #[cfg_attr(feature = "serde", serde(skip))]
+    index: BTreeMap<String, usize>,
+}
+
+impl PartialEq for ExecReport {
+    fn eq(&self, other: &Self) -> bool {
+        self.results == other.results
+    }
+}
+
+impl Eq for ExecReport {}
+
+impl From<Vec<ExecResult>> for ExecReport {
+    fn from(results: Vec<ExecResult>) -> Self {
+        Self {
+            results,
+            index: BTreeMap::default(),
+        }
+    }
+}
+
+impl ExecReport {
+    /// Apply an event, collecting the outputs into the results.
+    pub fn push(&mut self, event: ExecEvent) {
+        match event {
+            ExecEvent::Output(ExecOutput { pod, stream, line }) => {
+                let index = match self.find(&pod) {
+                    Some(index) => index,
+                    None => self.insert(ExecResult {
+                        pod,
+                        node: None,
+                        state: ExecState::Spawned,
+                        exit_code: None,
+                        message: None,
+                        stdout: String::default(),
+                        stderr: String::default(),
+                        truncated: false,
+                    }),
+                };
+                self.results[index].push_output(stream, &line);
+            }
+            ExecEvent::Exit(next) => match self.find(&next.pod) {
+                Some(index) => {
+                    let result = &mut self.results[index];
+                    result.node = next.node;
+                    result.state = next.state;
+                    result.exit_code = next.exit_code;
+                    result.message = next.message;
+                }
+                None => {
+                    self.insert(next);
+                }
+            },
+        }
+    }
+
+    fn find(&mut self, pod: &str) -> Option<usize> {
+        // NOTE: The results may be given without the index, e.g. deserialized
+        if self.index.len() != self.results.len() {
+            self.index = self
+                .results
+                .iter()
+                .enumerate()
+                .map(|(index, result)| (result.pod.clone(), index))
+                .collect();
+        }
+        self.index.get(pod).copied()
+    }
+
+    fn insert(&mut self, result: ExecResult) -> usize {
+        let index = self.results.len();
+        self.index.insert(result.pod.clone(), index);
+        self.results.push(result);
+        index
+    }
+
+    /// Return the number of the failed commands.
+    #[must_use]
+    pub fn num_failed(&self) -> usize {
+        self.results
+            .iter()
+            .filter(|result| !result.state.is_success())
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collect_exec_report() {
+        let output = |pod: &str, stream, line: &str| {
+            ExecEvent::Output(ExecOutput {
+                pod: pod.into(),
+                stream,
+                line: line.into(),
+            })
+        };
+        let exit = |pod: &str, state, exit_code| {
+            ExecEvent::Exit(ExecResult {
+                pod: pod.into(),
+                node: Some("node".into()),
+                state,
+                exit_code,
+                message: None,
+                stdout: String::default(),
+                stderr: String::default(),
+                truncated: false,
+            })
+        };
+
+        let mut report = ExecReport::default();
+        report.push(output("a", ExecStream::Stdout, "hello"));
+        report.push(output("b", ExecStream::Stderr, "oops"));
+        report.push(output("a", ExecStream::Stdout, "world"));
+        report.push(exit("a", ExecState::Succeeded, Some(0)));
+        report.push(exit("b", ExecState::Failed, Some(1)));
+        report.push(exit("c", ExecState::Skipped, None));
+
+        assert_eq!(report.results.len(), 3);
+        assert_eq!(report.results[0].stdout, "hello\nworld\n");
+        assert_eq!(report.results[1].stderr, "oops\n");
+        assert_eq!(report.results[1].exit_code, Some(1));
+        assert_eq!(report.num_failed(), 1);
+    }
+
+    #[test]
+    fn truncate_exec_outputs() {
+        let line = "x".repeat(1023);
+        let mut report = ExecReport::default();
+        for _ in 0..MAX_OUTPUT_BYTES / 1024 + 8 {
+            report.push(ExecEvent::Output(ExecOutput {
+                pod: "a".into(),
+                stream: ExecStream::Stdout,
+                line: line.clone(),
+            }));
+        }
+
+        let result = &report.results[0];
+        assert_eq!(report.results.len(), 1);
+        assert!(result.truncated);
+        assert!(result.stdout.len() <= MAX_OUTPUT_BYTES + TRUNCATED_MARKER.len() + 1);
+        assert!(result.stdout.ends_with(&format!("{TRUNCATED_MARKER}\n")));
+    }
+}
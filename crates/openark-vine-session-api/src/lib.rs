@@ -10,6 +10,7 @@ pub mod command;
 pub mod exec;
 pub mod owned_profile;
 pub mod profile;
+pub mod schedule;
 pub mod session;
 
 use std::{
@@ -110,6 +111,11 @@ pub struct VineSessionArgs {
     #[cfg_attr(feature = "clap", arg(long, env = "OPENARK_LABEL_BIND_CPU"))]
     label_bind_cpu: String,
 
+    /// A node label storing when the session reservation ends, as milliseconds.
+    /// It is set once the users have been warned of the end.
+    #[cfg_attr(feature = "clap", arg(long, env = "OPENARK_LABEL_BIND_EXPIRES_AT"))]
+    label_bind_expires_at: String,
+
     #[cfg_attr(feature = "clap", arg(long, env = "OPENARK_LABEL_BIND_MEMORY"))]
     label_bind_memory: String,
 
@@ -149,6 +155,11 @@ pub struct VineSessionArgs {
     #[cfg_attr(feature = "clap", arg(long, env = "OPENARK_LABEL_GPU"))]
     label_gpu: String,
 
+    /// A node label storing since when the node has been idle, as milliseconds.
+    /// It is reported by the session handler on each node.
+    #[cfg_attr(feature = "clap", arg(long, env = "OPENARK_LABEL_IDLE_SINCE"))]
+    label_idle_since: String,
+
     #[cfg_attr(feature = "clap", arg(long, env = "OPENARK_LABEL_IS_PRIVATE"))]
     label_is_private: String,
 
@@ -343,6 +354,7 @@ pub struct Metadata<'a> {
     args: &'a VineSessionArgs,
     bind: Option<bool>,
     bind_cpu: Option<Quantity>,
+    bind_expires_at: Option<Timestamp>,
     bind_memory: Option<Quantity>,
     bind_namespace: Option<String>,
     bind_node: Option<String>,
@@ -355,6 +367,7 @@ pub struct Metadata<'a> {
     bind_user: Option<String>,
     compute_mode: Option<ComputeMode>,
     gpu: Option<VineSessionGPU>,
+    idle_since: Option<Timestamp>,
     name: Option<String>,
     signed_out: Option<bool>,
 }
@@ -378,6 +391,10 @@ impl<'a> Metadata<'a> {
                 .filter(|&value| !value.is_empty())
                 .cloned()
                 .map(Quantity),
+            bind_expires_at: labels
+                .and_then(|map| map.get(&args.label_bind_expires_at))
+                .and_then(|value| value.parse::<i64>().ok())
+                .and_then(|millisecond| Timestamp::from_millisecond(millisecond).ok()),
             bind_memory: labels
                 .and_then(|map| map.get(&args.label_bind_memory))
                 .filter(|&value| !value.is_empty())
@@ -425,6 +442,10 @@ impl<'a> Metadata<'a> {
             gpu: labels
                 .and_then(|map| map.get(&args.label_gpu))
                 .and_then(|value| value.parse().ok()),
+            idle_since: labels
+                .and_then(|map| map.get(&args.label_idle_since))
+                .and_then(|value| value.parse::<i64>().ok())
+                .and_then(|millisecond| Timestamp::from_millisecond(millisecond).ok()),
             name: metadata.name.clone(),
             signed_out: labels
                 .and_then(|map| map.get(&args.label_signed_out))
@@ -452,6 +473,12 @@ impl<'a> Metadata<'a> {
             self.args.label_bind_cpu.clone(),
             to_patch_resource(&self.bind_cpu),
         );
+        map.insert(
+            self.args.label_bind_expires_at.clone(),
+            self.bind_expires_at
+                .map(|timestamp| timestamp.as_millisecond().to_string())
+                .unwrap_or_default(),
+        );
         map.insert(
             self.args.label_bind_memory.clone(),
             to_patch_resource(&self.bind_memory),
@@ -583,6 +610,18 @@ impl<'a> NodeSession<'a> {
             .filter(|&s| !s.is_empty())
     }
 
+    /// Get how long the session has been idle, as reported by the node.
+    #[must_use]
+    pub fn idle(&self, timestamp: Timestamp) -> Option<SignedDuration> {
+        let mut since = self.metadata.idle_since?;
+
+        // The node may have been idle before the session is bound
+        if let Some(Time(bound)) = &self.metadata.bind_timestamp {
+            since = since.max(*bound);
+        }
+        Some(timestamp.duration_since(since)).filter(|duration| duration.is_positive())
+    }
+
     /// Get whether the node is not ready.
     #[must_use]
     pub fn not_ready(&self) -> bool {
@@ -757,6 +796,14 @@ impl<'a> NodeSession<'a> {
         }
     }
 
+    /// Set when the session reservation ends, returning `true` if it has been changed.
+    ///
+    pub fn set_expires_at(&mut self, until: Option<Timestamp>) -> bool {
+        let is_changed = self.metadata.bind_expires_at != until;
+        self.metadata.bind_expires_at = until;
+        is_changed
+    }
+
     /// Return the session revision to ensure future updates.
     ///
     pub fn remove_session_revision(&mut self) {
@@ -783,6 +830,7 @@ impl<'a> NodeSession<'a> {
     pub fn set_sign_out(&mut self, timestamp: Timestamp, sign_out: bool) -> Option<SignedDuration> {
         if sign_out {
             self.metadata.bind = Some(false);
+            self.metadata.bind_expires_at = None;
             self.metadata.bind_revision = None;
             self.metadata.bind_namespace = None;
             self.metadata.bind_persistent = Some(false);
@@ -1010,3 +1058,103 @@ impl ProfileState<'_> {
 pub fn filter_taint(taint: &Taint, key: &str) -> bool {
     taint.key == key && taint.effect == "NoExecute"
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build_args() -> VineSessionArgs {
+        let label = |name: &str| ["ark.ulagbulag.io/", name].concat();
+        VineSessionArgs {
+            auth_domain_name: "auth.example.com".into(),
+            duration_sign_out_seconds: 30,
+            feature_gateway: false,
+            feature_ingress: false,
+            feature_vm: false,
+            force_gpu: None,
+            ingress_domain_name: "example.com".into(),
+            label_alias: label("alias"),
+            label_bind: label("bind"),
+            label_bind_cpu: label("bind.cpu"),
+            label_bind_expires_at: label("bind.expires-at"),
+            label_bind_memory: label("bind.memory"),
+            label_bind_mode: label("bind.mode"),
+            label_bind_namespace: label("bind.namespace"),
+            label_bind_node: label("bind.node"),
+            label_bind_persistent: label("bind.persistent"),
+            label_bind_privileged: label("bind.privileged"),
+            label_bind_profile: label("bind.profile"),
+            label_bind_revision: label("bind.revision"),
+            label_bind_storage: label("bind.storage"),
+            label_bind_timestamp: label("bind.timestamp"),
+            label_bind_user: label("bind.user"),
+            label_compute_mode: label("compute-mode"),
+            label_gpu: label("gpu"),
+            label_idle_since: label("idle-since"),
+            label_is_private: label("is-private"),
+            label_signed_out: label("signed-out"),
+            source_path: "templates".into(),
+            source_repo_revision: "main".into(),
+            source_repo_url: "https://example.com/repo.git".parse().unwrap(),
+            timezone: None,
+        }
+    }
+
+    fn build_node(args: &VineSessionArgs, labels: &[(&String, Timestamp)]) -> Node {
+        Node {
+            metadata: ObjectMeta {
+                name: Some("node".into()),
+                labels: Some(
+                    labels
+                        .iter()
+                        .map(|(key, value)| ((*key).clone(), value.as_millisecond().to_string()))
+                        .chain([(args.label_bind.clone(), "true".into())])
+                        .collect(),
+                ),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn measure_idle_since_binding() {
+        let args = build_args();
+        let now = Timestamp::from_second(1_800_000_000).unwrap();
+        let ago = |minutes: i64| now - SignedDuration::from_mins(minutes);
+
+        // Not reported by the node
+        let node = build_node(&args, &[(&args.label_bind_timestamp, ago(60))]);
+        assert_eq!(NodeSession::load(&args, &node).idle(now), None);
+
+        // Idle after the session is bound
+        let node = build_node(
+            &args,
+            &[
+                (&args.label_bind_timestamp, ago(60)),
+                (&args.label_idle_since, ago(10)),
+            ],
+        );
+        assert_eq!(
+            NodeSession::load(&args, &node).idle(now),
+            Some(SignedDuration::from_mins(10)),
+        );
+
+        // Idle before the session is bound
+        let node = build_node(
+            &args,
+            &[
+                (&args.label_bind_timestamp, ago(5)),
+                (&args.label_idle_since, ago(60)),
+            ],
+        );
+        assert_eq!(
+            NodeSession::load(&args, &node).idle(now),
+            Some(SignedDuration::from_mins(5)),
+        );
+
+        // Reported in the future
+        let node = build_node(&args, &[(&args.label_idle_since, ago(-1))]);
+        assert_eq!(NodeSession::load(&args, &node).idle(now), None);
+    }
+}
@@ -1,5 +1,6 @@
 use std::{collections::BTreeMap, path::PathBuf, string::String, vec::Vec};
 
+use jiff::SignedDuration;
 use k8s_openapi::{
     api::core::v1::{
         ContainerPort, EnvVar, PersistentVolumeClaimVolumeSource, ResourceRequirements,
@@ -78,6 +79,12 @@ pub struct SessionProfileSpec {
     )]
     pub greeter: Option<GreeterSpec>,
 
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub idle_timeout: Option<IdleTimeoutSpec>,
+
     #[cfg_attr(
         feature = "serde",
         serde(default, skip_serializing_if = "Option::is_none")
@@ -124,6 +131,25 @@ pub struct SessionProfileSpec {
     pub volumes: Option<VolumesSpec>,
 }
 
+impl SessionProfileSpec {
+    /// Return the idle duration after which the sessions are signed out.
+    ///
+    /// The long-running modes, such as notebooks and model servers,
+    /// are exempted as they may keep working without any user inputs.
+    ///
+    #[must_use]
+    pub fn idle_timeout(&self) -> Option<SignedDuration> {
+        match self.mode.unwrap_or_default() {
+            SessionMode::Notebook | SessionMode::NvidiaTriton | SessionMode::Ollama => None,
+            SessionMode::Desktop | SessionMode::Manual => self
+                .idle_timeout
+                .as_ref()
+                .filter(|spec| spec.enabled.unwrap_or(true))
+                .map(|spec| SignedDuration::from_mins(spec.minutes.into())),
+        }
+    }
+}
+
 pub type DriversSpec = BTreeMap<String, DriverSpec>;
 
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
@@ -292,6 +318,24 @@ pub struct PersistenceSpec {
     pub storage_class_name: Option<String>,
 }
 
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "schemars", derive(JsonSchema))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct IdleTimeoutSpec {
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub enabled: Option<bool>,
+
+    /// Sign out the sessions which have been idle for this long, as minutes.
+    ///
+    /// A session is idle while the node reports no user inputs
+    /// and low CPU/GPU usages.
+    pub minutes: u32,
+}
+
 #[derive(Clone, Debug, Default, PartialEq)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[cfg_attr(feature = "schemars", derive(JsonSchema))]
@@ -715,3 +759,32 @@ pub enum VolumeType {
     #[default]
     Temporary,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exempt_long_running_modes_from_idle_timeout() {
+        let spec = |mode, enabled| SessionProfileSpec {
+            idle_timeout: Some(IdleTimeoutSpec {
+                enabled,
+                minutes: 30,
+            }),
+            mode: Some(mode),
+            ..Default::default()
+        };
+        let timeout = Some(SignedDuration::from_mins(30));
+
+        assert_eq!(spec(SessionMode::Desktop, None).idle_timeout(), timeout);
+        assert_eq!(
+            spec(SessionMode::Manual, Some(true)).idle_timeout(),
+            timeout
+        );
+        assert_eq!(spec(SessionMode::Desktop, Some(false)).idle_timeout(), None);
+        assert_eq!(spec(SessionMode::Notebook, None).idle_timeout(), None);
+        assert_eq!(spec(SessionMode::Ollama, None).idle_timeout(), None);
+        assert_eq!(spec(SessionMode::NvidiaTriton, None).idle_timeout(), None);
+        assert_eq!(SessionProfileSpec::default().idle_timeout(), None);
+    }
+}
@@ -0,0 +1,288 @@
+use core::str::FromStr;
+use std::vec::Vec;
+
+use anyhow::{Error, Result, anyhow, bail};
+use jiff::{
+    SignedDuration, Timestamp, ToSpan,
+    civil::{DateTime, Weekday, time},
+    tz::TimeZone,
+};
+
+/// The maximum lookahead of the next schedule.
+const MAX_LOOKAHEAD_DAYS: i32 = 366;
+
+/// A cron-like schedule of `minute hour day-of-month month day-of-week`.
+///
+/// Each field accepts `*`, a value, a range (`a-b`), a step (`*/n`, `a-b/n`)
+/// and the lists of them (`a,b`), where the day-of-week starts from `0` (Sunday) to `7` (Sunday).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u32,
+    days: u32,
+    months: u16,
+    weekdays: u8,
+    /// Whether the day-of-month field is not `*`
+    days_restricted: bool,
+    /// Whether the day-of-week field is not `*`
+    weekdays_restricted: bool,
+}
+
+impl FromStr for CronSchedule {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<_> = s.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields.as_slice() else {
+            bail!("Expected 5 fields of cron schedule: {s:?}")
+        };
+
+        Ok(Self {
+            minutes: parse_field(minutes, 0, 59)?,
+            hours: parse_field(hours, 0, 23)? as _,
+            days: parse_field(days, 1, 31)? as _,
+            months: parse_field(months, 1, 12)? as _,
+            weekdays: {
+                let weekdays = parse_field(weekdays, 0, 7)?;
+                // Sunday is either `0` or `7`
+                ((weekdays | (weekdays >> 7)) & 0x7f) as _
+            },
+            days_restricted: *days != "*",
+            weekdays_restricted: *weekdays != "*",
+        })
+    }
+}
+
+/// Parses a field into a bitmask of the values.
+fn parse_field(field: &str, min: u8, max: u8) -> Result<u64> {
+    let mut mask = 0;
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => (range, step.parse().map_err(|_| invalid(field))?),
+            None => (item, 1),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            range => match range.split_once('-') {
+                Some((start, end)) => (
+                    start.parse().map_err(|_| invalid(field))?,
+                    end.parse().map_err(|_| invalid(field))?,
+                ),
+                None => {
+                    let value = range.parse().map_err(|_| invalid(field))?;
+                    // `a/n` is a shorthand of `a-max/n`
+                    (value, if step > 1 { max } else { value })
+                }
+            },
+        };
+        if step == 0 || start < min || end > max || start > end {
+            return Err(invalid(field));
+        }
+        for value in (start..=end).step_by(step) {
+            mask |= 1 << value;
+        }
+    }
+    Ok(mask)
+}
+
+fn invalid(field: &str) -> Error {
+    anyhow!("Invalid cron field: {field:?}")
+}
+
+impl CronSchedule {
+    fn matches_day(&self, datetime: DateTime) -> bool {
+        let day = self.days & (1 << datetime.day()) != 0;
+        let weekday = self.weekdays & (1 << weekday_index(datetime.weekday())) != 0;
+        let month = self.months & (1 << datetime.month()) != 0;
+
+        // Like cron, either of day-of-month and day-of-week matches if both are restricted
+        month
+            && match (self.days_restricted, self.weekdays_restricted) {
+                (true, true) => day || weekday,
+                _ => day && weekday,
+            }
+    }
+
+    fn matches_time(&self, datetime: DateTime) -> bool {
+        self.hours & (1 << datetime.hour()) != 0 && self.minutes & (1 << datetime.minute()) != 0
+    }
+
+    /// Returns `true` if the schedule fires at the minute of the local datetime.
+    #[must_use]
+    pub fn matches(&self, datetime: DateTime) -> bool {
+        self.matches_day(datetime) && self.matches_time(datetime)
+    }
+
+    /// Returns the last time the schedule has fired at or before the timestamp,
+    /// looking back no further than the duration.
+    #[must_use]
+    pub fn last(
+        &self,
+        timestamp: Timestamp,
+        tz: &TimeZone,
+        lookbehind: SignedDuration,
+    ) -> Option<Timestamp> {
+        let now = floor_minute(timestamp.to_zoned(tz.clone()).datetime());
+        let limit = now.checked_sub(lookbehind).ok()?;
+
+        let mut datetime = now;
+        while datetime >= limit {
+            if !self.matches_day(datetime) {
+                // Skip to the last minute of the previous day
+                datetime = datetime
+                    .date()
+                    .yesterday()
+                    .ok()?
+                    .to_datetime(time(23, 59, 0, 0));
+            } else if self.hours & (1 << datetime.hour()) == 0 {
+                // Skip to the last minute of the previous hour
+                datetime = datetime
+                    .with()
+                    .minute(59)
+                    .build()
+                    .ok()?
+                    .checked_sub(1.hour())
+                    .ok()?;
+            } else {
+                if self.minutes & (1 << datetime.minute()) != 0 {
+                    let fired = tz.to_ambiguous_timestamp(datetime).compatible().ok()?;
+                    if fired <= timestamp {
+                        return Some(fired);
+                    }
+                }
+                datetime = datetime.checked_sub(1.minute()).ok()?;
+            }
+        }
+        None
+    }
+
+    /// Returns the next time the schedule fires after the timestamp.
+    #[must_use]
+    pub fn next(&self, timestamp: Timestamp, tz: &TimeZone) -> Option<Timestamp> {
+        let now = timestamp.to_zoned(tz.clone()).datetime();
+        let limit = now.checked_add(MAX_LOOKAHEAD_DAYS.days()).ok()?;
+
+        let mut datetime = floor_minute(now).checked_add(1.minute()).ok()?;
+        while datetime <= limit {
+            if !self.matches_day(datetime) {
+                // Skip to the next day
+                datetime = datetime
+                    .date()
+                    .tomorrow()
+                    .ok()?
+                    .to_datetime(Default::default());
+            } else if self.hours & (1 << datetime.hour()) == 0 {
+                // Skip to the next hour
+                datetime = floor_minute(datetime)
+                    .with()
+                    .minute(0)
+                    .build()
+                    .ok()?
+                    .checked_add(1.hour())
+                    .ok()?;
+            } else if self.minutes & (1 << datetime.minute()) == 0 {
+                datetime = datetime.checked_add(1.minute()).ok()?;
+            } else {
+                let fired = tz.to_ambiguous_timestamp(datetime).compatible().ok()?;
+                if fired > timestamp {
+                    return Some(fired);
+                }
+                datetime = datetime.checked_add(1.minute()).ok()?;
+            }
+        }
+        None
+    }
+}
+
+fn weekday_index(weekday: Weekday) -> u8 {
+    weekday.to_sunday_zero_offset() as u8
+}
+
+fn floor_minute(datetime: DateTime) -> DateTime {
+    datetime
+        .with()
+        .second(0)
+        .subsec_nanosecond(0)
+        .build()
+        .unwrap_or(datetime)
+}
+
+#[cfg(test)]
+mod tests {
+    use jiff::civil::date;
+
+    use super::*;
+
+    #[test]
+    fn parse_cron_schedules() {
+        assert!("* * * * *".parse::<CronSchedule>().is_ok());
+        assert!("0 9-18/3 * * 1-5".parse::<CronSchedule>().is_ok());
+        assert!("0,30 9 1 1,7 0".parse::<CronSchedule>().is_ok());
+
+        assert!("* * * *".parse::<CronSchedule>().is_err());
+        assert!("60 * * * *".parse::<CronSchedule>().is_err());
+        assert!("* * 0 * *".parse::<CronSchedule>().is_err());
+        assert!("*/0 * * * *".parse::<CronSchedule>().is_err());
+        assert!("5-1 * * * *".parse::<CronSchedule>().is_err());
+    }
+
+    #[test]
+    fn match_cron_schedules() {
+        // Every weekday at 09:00
+        let schedule: CronSchedule = "0 9 * * 1-5".parse().unwrap();
+        let monday = date(2026, 3, 2).at(9, 0, 0, 0);
+        let sunday = date(2026, 3, 1).at(9, 0, 0, 0);
+        assert!(schedule.matches(monday));
+        assert!(!schedule.matches(sunday));
+        assert!(!schedule.matches(monday.with().minute(1).build().unwrap()));
+
+        // Sunday as `7`
+        let schedule: CronSchedule = "0 9 * * 7".parse().unwrap();
+        assert!(schedule.matches(sunday));
+    }
+
+    #[test]
+    fn find_cron_schedules() {
+        let tz = TimeZone::get("Asia/Seoul").unwrap();
+        let schedule: CronSchedule = "0 9 * * 1-5".parse().unwrap();
+
+        // Friday 2026-03-06 10:30 KST
+        let now = tz
+            .to_ambiguous_timestamp(date(2026, 3, 6).at(10, 30, 0, 0))
+            .compatible()
+            .unwrap();
+
+        let last = schedule
+            .last(now, &tz, SignedDuration::from_hours(2))
+            .unwrap();
+        assert_eq!(
+            last.to_zoned(tz.clone()).datetime(),
+            date(2026, 3, 6).at(9, 0, 0, 0),
+        );
+        assert!(
+            schedule
+                .last(now, &tz, SignedDuration::from_mins(60))
+                .is_none()
+        );
+
+        // Skips the weekend backwards
+        let monday = tz
+            .to_ambiguous_timestamp(date(2026, 3, 9).at(8, 0, 0, 0))
+            .compatible()
+            .unwrap();
+        let last = schedule
+            .last(monday, &tz, SignedDuration::from_hours(7 * 24))
+            .unwrap();
+        assert_eq!(
+            last.to_zoned(tz.clone()).datetime(),
+            date(2026, 3, 6).at(9, 0, 0, 0),
+        );
+
+        // Skips the weekend
+        let next = schedule.next(now, &tz).unwrap();
+        assert_eq!(
+            next.to_zoned(tz.clone()).datetime(),
+            date(2026, 3, 9).at(9, 0, 0, 0),
+        );
+    }
+}
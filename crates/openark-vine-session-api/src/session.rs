@@ -1,5 +1,14 @@
+use std::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
 use jiff::Timestamp;
-use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+use k8s_openapi::{
+    api::core::v1::{ContainerState, ContainerStatus, Pod},
+    apimachinery::pkg::{api::resource::Quantity, apis::meta::v1::Time},
+};
 #[cfg(feature = "schemars")]
 use schemars::JsonSchema;
 #[cfg(feature = "serde")]
@@ -51,6 +60,13 @@ pub struct Session {
     )]
     pub completed_at: Option<Timestamp>,
 
+    /// The end of the reservation, which is known while the users are warned of it.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub expires_at: Option<Timestamp>,
+
     #[cfg_attr(feature = "serde", serde(default))]
     pub resource_annotations: SessionResourceAnnotations,
 
@@ -179,6 +195,134 @@ pub struct SessionStatus {
         serde(default, skip_serializing_if = "Option::is_none")
     )]
     pub state: Option<SessionState>,
+
+    /// A machine-readable reason of the state, e.g. `ImagePullBackOff`
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub reason: Option<String>,
+
+    /// A human-readable message of the state
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub message: Option<String>,
+}
+
+impl SessionStatus {
+    /// Creates a status of the state with its default level.
+    #[must_use]
+    pub fn new(state: SessionState) -> Self {
+        Self {
+            level: Some(state.level()),
+            state: Some(state),
+            reason: None,
+            message: None,
+        }
+    }
+
+    #[must_use]
+    pub fn with_level(mut self, level: SessionStatusLevel) -> Self {
+        self.level = Some(level);
+        self
+    }
+
+    #[must_use]
+    pub fn with_reason(mut self, reason: Option<String>, message: Option<String>) -> Self {
+        self.reason = reason;
+        self.message = message;
+        self
+    }
+
+    /// Computes the lifecycle state of a session from its pod.
+    #[must_use]
+    pub fn from_pod(pod: &Pod) -> Self {
+        if pod.metadata.deletion_timestamp.is_some() {
+            return Self::new(SessionState::SigningOut);
+        }
+
+        let Some(status) = pod.status.as_ref() else {
+            return Self::new(SessionState::Scheduling);
+        };
+        match status.phase.as_deref() {
+            Some("Succeeded") => {
+                return Self::new(SessionState::Terminated)
+                    .with_reason(status.reason.clone(), status.message.clone());
+            }
+            Some("Failed") => {
+                return Self::new(SessionState::Failed)
+                    .with_reason(status.reason.clone(), status.message.clone());
+            }
+            _ => (),
+        }
+
+        let conditions = status.conditions.as_deref().unwrap_or_default();
+        let condition = |type_: &str| conditions.iter().find(|&c| c.type_ == type_);
+
+        // Wait until the pod is bound to a node
+        match condition("PodScheduled") {
+            Some(condition) if condition.status == "True" => (),
+            Some(condition) => {
+                let state = Self::new(SessionState::Scheduling)
+                    .with_reason(condition.reason.clone(), condition.message.clone());
+                return if condition.reason.as_deref() == Some("Unschedulable") {
+                    state.with_level(SessionStatusLevel::Warn)
+                } else {
+                    state
+                };
+            }
+            None => return Self::new(SessionState::Scheduling),
+        }
+
+        // Find the first container which is not ready yet
+        let containers = status
+            .init_container_statuses
+            .iter()
+            .flatten()
+            .chain(status.container_statuses.iter().flatten());
+        for container in containers {
+            if let Some(state) = Self::from_container(container) {
+                return state;
+            }
+        }
+
+        match condition("Ready") {
+            Some(condition) if condition.status == "True" => Self::new(SessionState::Running),
+            Some(condition) => Self::new(SessionState::Starting)
+                .with_reason(condition.reason.clone(), condition.message.clone()),
+            None => Self::new(SessionState::Starting),
+        }
+    }
+
+    fn from_container(container: &ContainerStatus) -> Option<Self> {
+        let waiting = container
+            .state
+            .as_ref()
+            .and_then(|state| state.waiting.as_ref())?;
+        let reason = waiting.reason.clone();
+        let message = waiting.message.clone();
+
+        let state = match reason.as_deref() {
+            // Image pulls may recover by themselves, e.g. on registry outages
+            Some("ErrImagePull" | "ImagePullBackOff") => {
+                Self::new(SessionState::PullingImage).with_level(SessionStatusLevel::Error)
+            }
+            Some(
+                "CrashLoopBackOff"
+                | "CreateContainerConfigError"
+                | "CreateContainerError"
+                | "InvalidImageName"
+                | "RunContainerError",
+            ) => Self::new(SessionState::Failed),
+            // NOTE: A pod does not report a pulling image unless the pull fails,
+            //       so the other reasons, e.g. "ContainerCreating" and "PodInitializing",
+            //       are considered as starting.
+            _ => Self::new(SessionState::Starting),
+        };
+        Some(state.with_reason(reason, message))
+    }
 }
 
 #[derive(Copy, Clone, Debug, Display, EnumString, PartialEq, Eq, PartialOrd, Ord)]
@@ -194,6 +338,10 @@ pub enum SessionStatusLevel {
     Error,
 }
 
+/// The lifecycle of a session.
+///
+/// A session normally goes through `Scheduling`, `PullingImage`, `Starting`
+/// and `Running`, and then `SigningOut` and `Terminated` once it is unbound.
 #[derive(Copy, Clone, Debug, Display, EnumString, PartialEq, Eq, PartialOrd, Ord)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[cfg_attr(feature = "schemars", derive(JsonSchema))]
@@ -201,12 +349,237 @@ pub enum SessionStatusLevel {
 #[strum(serialize_all = "PascalCase")]
 pub enum SessionState {
     Unknown,
+    /// Waiting for the session to be ready.
+    ///
+    /// Deprecated: it is split into `Scheduling`, `PullingImage` and `Starting`,
+    /// and is never computed anymore, but kept for the existing statuses and clients.
     Pending,
+    /// Waiting for the session to be bound to a node
+    Scheduling,
+    /// Waiting for the image to be pulled, which is known only if the pull has failed
+    PullingImage,
+    /// Waiting for the containers to be ready
+    Starting,
     Running,
+    SigningOut,
+    Terminated,
+    Failed,
 }
 
-#[derive(Clone, Debug, Default, PartialEq)]
+impl SessionState {
+    /// Returns the default level of the state.
+    #[must_use]
+    pub const fn level(&self) -> SessionStatusLevel {
+        match self {
+            Self::Unknown => SessionStatusLevel::Warn,
+            Self::Failed => SessionStatusLevel::Error,
+            Self::Pending
+            | Self::Scheduling
+            | Self::PullingImage
+            | Self::Starting
+            | Self::Running
+            | Self::SigningOut
+            | Self::Terminated => SessionStatusLevel::Info,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[cfg_attr(feature = "schemars", derive(JsonSchema))]
 #[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
-pub struct SessionEvent {}
+pub struct SessionEvent {
+    pub level: SessionStatusLevel,
+
+    /// A machine-readable reason of the event, e.g. `Unschedulable`
+    pub reason: String,
+
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub message: Option<String>,
+
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub timestamp: Option<Timestamp>,
+}
+
+impl SessionEvent {
+    /// Collects the events from the conditions and the container states of the pod.
+    #[must_use]
+    pub fn from_pod(pod: &Pod) -> Vec<Self> {
+        let Some(status) = pod.status.as_ref() else {
+            return Vec::new();
+        };
+
+        let conditions = status
+            .conditions
+            .iter()
+            .flatten()
+            .filter(|&condition| condition.status != "True")
+            .filter_map(|condition| {
+                Some(Self {
+                    level: SessionStatusLevel::Warn,
+                    reason: condition.reason.clone()?,
+                    message: condition.message.clone(),
+                    timestamp: condition
+                        .last_transition_time
+                        .as_ref()
+                        .map(|Time(time)| *time),
+                })
+            });
+
+        let containers = status
+            .init_container_statuses
+            .iter()
+            .flatten()
+            .chain(status.container_statuses.iter().flatten())
+            .flat_map(|container| {
+                // The last state tells why the container has been restarted
+                [container.last_state.as_ref(), container.state.as_ref()]
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|state| Self::from_container_state(&container.name, state))
+            });
+
+        let mut events: Vec<_> = conditions.chain(containers).collect();
+        events.sort_by_key(|event| event.timestamp);
+        events
+    }
+
+    fn from_container_state(name: &str, state: &ContainerState) -> Option<Self> {
+        if let Some(waiting) = state.waiting.as_ref() {
+            let reason = waiting.reason.clone()?;
+            Some(Self {
+                level: if reason.ends_with("BackOff") || reason.ends_with("Error") {
+                    SessionStatusLevel::Error
+                } else {
+                    SessionStatusLevel::Info
+                },
+                message: Some(match waiting.message.as_deref() {
+                    Some(message) => format!("{name}: {message}"),
+                    None => name.to_string(),
+                }),
+                reason,
+                timestamp: None,
+            })
+        } else if let Some(terminated) = state.terminated.as_ref() {
+            let exit_code = terminated.exit_code;
+            Some(Self {
+                level: if exit_code == 0 {
+                    SessionStatusLevel::Info
+                } else {
+                    SessionStatusLevel::Error
+                },
+                reason: terminated
+                    .reason
+                    .clone()
+                    .unwrap_or_else(|| "Terminated".into()),
+                message: Some(match terminated.message.as_deref() {
+                    Some(message) => format!("{name}: exited with {exit_code}: {message}"),
+                    None => format!("{name}: exited with {exit_code}"),
+                }),
+                timestamp: terminated.finished_at.as_ref().map(|Time(time)| *time),
+            })
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::api::core::v1::{ContainerStateWaiting, PodCondition, PodStatus};
+
+    use super::*;
+
+    fn build_pod(conditions: &[(&str, &str)], waiting: Option<&str>) -> Pod {
+        Pod {
+            status: Some(PodStatus {
+                phase: Some("Pending".into()),
+                conditions: Some(
+                    conditions
+                        .iter()
+                        .map(|&(type_, status)| PodCondition {
+                            type_: type_.into(),
+                            status: status.into(),
+                            ..Default::default()
+                        })
+                        .collect(),
+                ),
+                container_statuses: waiting.map(|reason| {
+                    vec![ContainerStatus {
+                        name: "desktop".into(),
+                        state: Some(ContainerState {
+                            waiting: Some(ContainerStateWaiting {
+                                reason: Some(reason.into()),
+                                message: None,
+                            }),
+                            ..Default::default()
+                        }),
+                        ..Default::default()
+                    }]
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn compute_session_states_from_pods() {
+        let state = |pod: Pod| SessionStatus::from_pod(&pod).state.unwrap();
+
+        assert_eq!(
+            state(build_pod(&[("PodScheduled", "False")], None)),
+            SessionState::Scheduling,
+        );
+        assert_eq!(
+            state(build_pod(
+                &[("PodScheduled", "True")],
+                Some("ContainerCreating")
+            )),
+            SessionState::Starting,
+        );
+        assert_eq!(
+            state(build_pod(
+                &[("PodScheduled", "True")],
+                Some("PodInitializing")
+            )),
+            SessionState::Starting,
+        );
+        assert_eq!(
+            state(build_pod(
+                &[("PodScheduled", "True")],
+                Some("ImagePullBackOff")
+            )),
+            SessionState::PullingImage,
+        );
+        assert_eq!(
+            state(build_pod(
+                &[("PodScheduled", "True")],
+                Some("CrashLoopBackOff")
+            )),
+            SessionState::Failed,
+        );
+        assert_eq!(
+            state(build_pod(
+                &[("PodScheduled", "True"), ("Ready", "True")],
+                None
+            )),
+            SessionState::Running,
+        );
+    }
+
+    #[test]
+    fn parse_deprecated_session_states() {
+        assert_eq!(
+            "Pending".parse::<SessionState>().unwrap(),
+            SessionState::Pending,
+        );
+        assert_eq!(SessionState::Pending.level(), SessionStatusLevel::Info);
+    }
+}
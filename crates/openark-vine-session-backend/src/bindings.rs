@@ -1,6 +1,12 @@
+use std::collections::{BTreeMap, BTreeSet};
+
 use actix_web::{HttpResponse, Responder, Scope, get, web};
 use itertools::Itertools;
-use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+use jiff::Timestamp;
+use k8s_openapi::{
+    api::core::v1::{Node, Pod},
+    apimachinery::pkg::{api::resource::Quantity, apis::meta::v1::Time},
+};
 use kcr_argoproj_io::v1alpha1::applications::Application;
 use kube::{Api, Client, ResourceExt, api::ListParams};
 use kube_quantity::ParsedQuantity;
@@ -9,8 +15,8 @@ use openark_vine_session_api::{
     owned_profile::OwnedSessionProfileSpec,
     profile::SessionMode,
     session::{
-        Session, SessionLinks, SessionRegion, SessionResourceAnnotations, SessionResourceLabels,
-        SessionState, SessionStatus, SessionStatusLevel, SessionUser,
+        Session, SessionEvent, SessionLinks, SessionRegion, SessionResourceAnnotations,
+        SessionResourceLabels, SessionState, SessionStatus, SessionStatusLevel, SessionUser,
     },
 };
 #[cfg(feature = "tracing")]
@@ -34,6 +40,38 @@ pub async fn list(
     user: User,
 ) -> impl Responder {
     let api = Api::<Application>::default_namespaced(kube.as_ref().clone());
+
+    // NOTE: the nodes are labeled with the reservation ends while warning the users
+    let expires_at = {
+        let api_node = Api::<Node>::all(kube.as_ref().clone());
+        let lp = ListParams {
+            label_selector: Some(labels.label_bind_expires_at.clone()),
+            ..Default::default()
+        };
+        match api_node.list_metadata(&lp).await {
+            Ok(list) => list
+                .items
+                .into_iter()
+                .filter_map(|node| {
+                    let timestamp = node
+                        .labels()
+                        .get(&labels.label_bind_expires_at)
+                        .and_then(|value| value.parse().ok())
+                        .and_then(|millisecond| Timestamp::from_millisecond(millisecond).ok())?;
+                    Some((node.name_any(), timestamp))
+                })
+                .collect(),
+            Err(error) => {
+                #[cfg(feature = "tracing")]
+                warn!("Failed to list session nodes: {error}");
+
+                #[cfg(not(feature = "tracing"))]
+                let _ = error;
+                BTreeMap::default()
+            }
+        }
+    };
+
     let lp = ListParams {
         label_selector: Some(build_label_selector(labels, None, &user)),
         ..Default::default()
@@ -41,10 +79,11 @@ pub async fn list(
 
     match api.list(&lp).await {
         Ok(list) => {
+            let pods = list_pods(kube.as_ref(), &lp, &list.items).await;
             let mut items = list
                 .items
                 .into_iter()
-                .filter_map(|app| convert(app, apiserver_base_url.as_deref()))
+                .filter_map(|app| convert(app, &pods, &expires_at, apiserver_base_url.as_deref()))
                 .collect::<Vec<_>>();
             items.sort_by_key(|s| {
                 (
@@ -63,7 +102,12 @@ pub async fn list(
     }
 }
 
-fn convert(app: Application, apiserver_base_url: Option<&str>) -> Option<Session> {
+fn convert(
+    app: Application,
+    pods: &[Pod],
+    expires_at: &BTreeMap<String, Timestamp>,
+    apiserver_base_url: Option<&str>,
+) -> Option<Session> {
     let values = app
         .spec
         .sources
@@ -78,6 +122,8 @@ fn convert(app: Application, apiserver_base_url: Option<&str>) -> Option<Session
         .and_then(::serde_json::from_value)
         .ok()?;
 
+    let pod = find_pod(pods, &profile);
+
     let limits = profile
         .session
         .resources
@@ -129,6 +175,7 @@ fn convert(app: Application, apiserver_base_url: Option<&str>) -> Option<Session
             .and_then(|state| state.finished_at.as_ref())
             .and_then(|time| time.parse().ok()),
         completed_at: None,
+        expires_at: expires_at.get(&profile.node.name).copied(),
         resource_annotations: SessionResourceAnnotations {
             gpu: if gpus.is_empty() {
                 None
@@ -219,29 +266,127 @@ fn convert(app: Application, apiserver_base_url: Option<&str>) -> Option<Session
                 None
             },
         },
-        status: match app
-            .status
-            .as_ref()
-            .and_then(|status| status.health.as_ref())
-            .and_then(|health| health.status.as_deref())
-        {
-            Some("Healthy") => SessionStatus {
-                level: Some(SessionStatusLevel::Info),
-                state: Some(SessionState::Running),
-            },
-            Some(_) => SessionStatus {
-                level: Some(SessionStatusLevel::Error),
-                state: Some(SessionState::Unknown),
-            },
-            None => SessionStatus {
-                level: Some(SessionStatusLevel::Info),
-                state: Some(SessionState::Pending),
-            },
-        },
-        events: vec![],
+        status: convert_status(&app, pod),
+        events: convert_events(&app, pod),
     })
 }
 
+/// Lists the session pods in the destination namespaces of the applications.
+///
+/// NOTE: the session pods share the labels with their applications
+async fn list_pods(kube: &Client, lp: &ListParams, apps: &[Application]) -> Vec<Pod> {
+    let namespaces: BTreeSet<_> = apps
+        .iter()
+        .map(|app| app.spec.destination.namespace.as_deref())
+        .collect();
+
+    let mut pods = Vec::default();
+    for namespace in namespaces {
+        let api = match namespace {
+            Some(namespace) => Api::<Pod>::namespaced(kube.clone(), namespace),
+            None => Api::<Pod>::default_namespaced(kube.clone()),
+        };
+        match api.list(lp).await {
+            Ok(list) => pods.extend(list.items),
+            Err(error) => {
+                #[cfg(feature = "tracing")]
+                warn!("Failed to list session pods: {error}");
+
+                #[cfg(not(feature = "tracing"))]
+                let _ = error;
+            }
+        }
+    }
+    pods
+}
+
+/// Finds the latest pod of the session, as failed jobs may leave the previous ones.
+fn find_pod<'a>(pods: &'a [Pod], profile: &OwnedSessionProfileSpec) -> Option<&'a Pod> {
+    pods.iter()
+        .filter(|&pod| {
+            pod.labels().get(&profile.openark.labels.bind_node) == Some(&profile.node.name)
+        })
+        .max_by_key(|&pod| {
+            pod.metadata
+                .creation_timestamp
+                .as_ref()
+                .map(|Time(time)| *time)
+        })
+}
+
+fn convert_status(app: &Application, pod: Option<&Pod>) -> SessionStatus {
+    if app.metadata.deletion_timestamp.is_some() {
+        return SessionStatus::new(SessionState::SigningOut);
+    }
+    if let Some(pod) = pod {
+        return SessionStatus::from_pod(pod);
+    }
+
+    // Fallback to the application health, e.g. the VM sessions
+    let health = app
+        .status
+        .as_ref()
+        .and_then(|status| status.health.as_ref());
+    let message = health.and_then(|health| health.message.clone());
+    match health.and_then(|health| health.status.as_deref()) {
+        Some("Healthy") => SessionStatus::new(SessionState::Running),
+        Some("Degraded") => {
+            SessionStatus::new(SessionState::Failed).with_reason(Some("Degraded".into()), message)
+        }
+        Some("Missing" | "Progressing") | None => {
+            let operation = app
+                .status
+                .as_ref()
+                .and_then(|status| status.operation_state.as_ref());
+            SessionStatus::new(SessionState::Scheduling)
+                .with_reason(None, operation.and_then(|state| state.message.clone()))
+        }
+        Some(status) => SessionStatus::new(SessionState::Unknown)
+            .with_level(SessionStatusLevel::Error)
+            .with_reason(Some(status.into()), message),
+    }
+}
+
+fn convert_events(app: &Application, pod: Option<&Pod>) -> Vec<SessionEvent> {
+    let status = app.status.as_ref();
+    let parse_timestamp =
+        |time: Option<&String>| time.and_then(|time| time.parse::<Timestamp>().ok());
+
+    let conditions = status
+        .and_then(|status| status.conditions.as_ref())
+        .into_iter()
+        .flatten()
+        .map(|condition| SessionEvent {
+            level: if condition.r#type.ends_with("Error") {
+                SessionStatusLevel::Error
+            } else if condition.r#type.ends_with("Warning") {
+                SessionStatusLevel::Warn
+            } else {
+                SessionStatusLevel::Info
+            },
+            reason: condition.r#type.clone(),
+            message: Some(condition.message.clone()),
+            timestamp: parse_timestamp(condition.last_transition_time.as_ref()),
+        });
+
+    let operation = status
+        .and_then(|status| status.operation_state.as_ref())
+        .filter(|&state| matches!(state.phase.as_str(), "Error" | "Failed"))
+        .map(|state| SessionEvent {
+            level: SessionStatusLevel::Error,
+            reason: format!("Sync{}", state.phase),
+            message: state.message.clone(),
+            timestamp: parse_timestamp(state.finished_at.as_ref()),
+        });
+
+    let mut events: Vec<_> = conditions
+        .chain(operation)
+        .chain(pod.map(SessionEvent::from_pod).into_iter().flatten())
+        .collect();
+    events.sort_by_key(|event| event.timestamp);
+    events
+}
+
 fn convert_quantity(quantity: &Quantity) -> Option<Quantity> {
     ParsedQuantity::try_from(quantity).ok().map(Into::into)
 }
@@ -1,10 +1,12 @@
 use actix_web::{HttpResponse, Responder, Scope, get, post, web};
+use futures::StreamExt;
 use kube::{Api, Client, ResourceExt, api::ListParams};
 use openark_vine_oauth::User;
 use openark_vine_session_api::{
     command::{SessionCommandCrd, SessionCommandView},
-    exec::ExecArgs,
+    exec::{ExecArgs, ExecFormat},
 };
+use serde::Deserialize;
 #[cfg(feature = "tracing")]
 use tracing::{Level, instrument, warn};
 
@@ -50,6 +52,15 @@ fn convert(cr: SessionCommandCrd) -> SessionCommandView {
     }
 }
 
+/// The default duration of waiting the attached processes, as seconds.
+const DEFAULT_TIMEOUT_SECONDS: u64 = 300;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ExecQuery {
+    format: ExecFormat,
+}
+
 #[cfg_attr(feature = "tracing", instrument(level = Level::INFO, skip_all))]
 #[post("exec")]
 pub async fn exec(
@@ -57,6 +68,7 @@ pub async fn exec(
     // Add support for guest users
     kube: web::Data<Client>,
     user: User,
+    query: web::Query<ExecQuery>,
     args: web::Json<ExecArgs>,
 ) -> impl Responder {
     let web::Json(mut args) = args;
@@ -65,12 +77,23 @@ pub async fn exec(
         args.label_selector.as_deref(),
         &user,
     ));
+    // Do not keep the requests pending forever
+    args.timeout_seconds.get_or_insert(DEFAULT_TIMEOUT_SECONDS);
 
     match ::openark_vine_session_exec::exec(kube.as_ref().clone(), &args).await {
-        Ok(session) => {
-            session.join().await;
-            HttpResponse::Ok().json(())
-        }
+        Ok(session) => match query.format {
+            ExecFormat::Json => HttpResponse::Ok().json(session.join().await),
+            ExecFormat::Ndjson => {
+                let stream = session.stream().map(|event| {
+                    let mut line = ::serde_json::to_vec(&event)?;
+                    line.push(b'\n');
+                    Ok::<_, ::serde_json::Error>(web::Bytes::from(line))
+                });
+                HttpResponse::Ok()
+                    .content_type("application/x-ndjson")
+                    .streaming(stream)
+            }
+        },
         Err(error) => {
             #[cfg(feature = "tracing")]
             warn!("Failed to exec: {error}");
@@ -51,6 +51,9 @@ struct LabelArgs {
     #[arg(long, env = "OPENARK_LABEL_BIND")]
     label_bind: String,
 
+    #[arg(long, env = "OPENARK_LABEL_BIND_EXPIRES_AT")]
+    label_bind_expires_at: String,
+
     #[arg(long, env = "OPENARK_LABEL_BIND_USER")]
     label_bind_user: String,
 }
@@ -3,25 +3,87 @@ pub mod error;
 use std::{borrow::Cow, time::Duration};
 
 use futures::{
-    StreamExt,
-    stream::{FuturesOrdered, FuturesUnordered},
+    Stream, StreamExt,
+    future::{OptionFuture, join3},
+    stream::{self, FuturesOrdered},
 };
-use k8s_openapi::api::core::v1::Pod;
+use k8s_openapi::{api::core::v1::Pod, apimachinery::pkg::apis::meta::v1::Status};
 use kube::{
     Api, Client, ResourceExt,
     api::{AttachParams, AttachedProcess, ListParams},
 };
-use openark_vine_session_api::exec::ExecArgs;
-use tokio::time::sleep;
+use openark_vine_session_api::exec::{
+    ExecArgs, ExecEvent, ExecOutput, ExecReport, ExecResult, ExecState, ExecStream,
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader},
+    sync::mpsc,
+    time::{sleep, timeout},
+};
 
 use self::error::Result;
 
+/// The container of the sessions to be executed into.
+pub const CONTAINER: &str = "desktop";
+
+/// The session pods matching a label selector.
+pub struct SessionPods {
+    pub api: Api<Pod>,
+    /// The pods whose sessions are ready
+    pub pods: Vec<Pod>,
+    /// The results of the pods whose sessions are not ready
+    pub skipped: Vec<ExecResult>,
+}
+
+/// List the session pods, and check whether their sessions are ready.
+pub async fn select(
+    kube: Client,
+    label_selector: Option<&str>,
+    namespace: Option<&str>,
+) -> Result<SessionPods> {
+    let api: Api<Pod> = match namespace {
+        Some(ns) => Api::namespaced(kube, ns),
+        None => Api::default_namespaced(kube),
+    };
+    let lp = ListParams {
+        label_selector: label_selector.map(Into::into),
+        ..Default::default()
+    };
+
+    let (pods, skipped) = api
+        .list(&lp)
+        .await?
+        .items
+        .into_iter()
+        .partition::<Vec<_>, _>(|pod| {
+            pod.status
+                .as_ref()
+                .and_then(|status| status.container_statuses.as_ref())
+                .and_then(|statuses| statuses.iter().find(|&status| status.name == CONTAINER))
+                .is_some_and(|status| status.ready)
+        });
+
+    let skipped = skipped
+        .into_iter()
+        .map(|pod| {
+            let name = pod.name_any();
+            let node = pod.spec.and_then(|spec| spec.node_name);
+            let message = "Session is not ready".into();
+            build_result(name, node, ExecState::Skipped, message)
+        })
+        .collect();
+
+    Ok(SessionPods { api, pods, skipped })
+}
+
 pub async fn exec(kube: Client, args: &ExecArgs) -> Result<ExecSession> {
     let ExecArgs {
         command,
         label_selector,
         namespace,
+        stdin,
         terminal,
+        timeout_seconds,
         wait,
     } = args;
 
@@ -35,49 +97,44 @@ pub async fn exec(kube: Client, args: &ExecArgs) -> Result<ExecSession> {
     };
 
     // List session pods
-    let api: Api<Pod> = match namespace.as_deref() {
-        Some(ns) => Api::namespaced(kube, ns),
-        None => Api::default_namespaced(kube),
-    };
-    let lp = ListParams {
-        label_selector: label_selector.clone(),
-        ..Default::default()
-    };
-    let pods = api.list(&lp).await?.items;
+    let SessionPods { api, pods, skipped } =
+        select(kube, label_selector.as_deref(), namespace.as_deref()).await?;
 
     // Create processes
-    let container = "desktop";
     let wait = *wait;
     let ap = AttachParams {
-        container: Some(container.into()),
-        stdin: false,
+        container: Some(CONTAINER.into()),
+        stdin: stdin.is_some(),
         stdout: true,
-        stderr: !wait,
-        tty: wait,
+        stderr: wait,
+        tty: false,
         max_stdin_buf_size: None,
         max_stdout_buf_size: None,
         max_stderr_buf_size: None,
     };
     let processes: Vec<_> = pods
         .iter()
-        .filter(|&pod| {
-            // Check the session is ready
-            pod.status
-                .as_ref()
-                .and_then(|status| status.container_statuses.as_ref())
-                .and_then(|statuses| statuses.iter().find(|&status| status.name == container))
-                .is_some_and(|status| status.ready)
-        })
         .map(|pod| async {
             let name = pod.name_any();
+            let node = pod.spec.as_ref().and_then(|spec| spec.node_name.clone());
+
             match api.exec(&name, command.as_slice(), &ap).await {
-                Ok(attached) => Some(Process { attached, name }),
+                Ok(attached) => Ok(Process {
+                    attached,
+                    name,
+                    node,
+                }),
                 Err(error) => {
                     #[cfg(feature = "tracing")]
                     {
                         ::tracing::error!("Failed to exec to {name}: {error}");
                     }
-                    None
+                    Err(build_result(
+                        name,
+                        node,
+                        ExecState::Error,
+                        error.to_string(),
+                    ))
                 }
             }
         })
@@ -86,93 +143,306 @@ pub async fn exec(kube: Client, args: &ExecArgs) -> Result<ExecSession> {
         .await;
 
     // Collect processes
+    let mut attached = Vec::default();
+    let mut results = skipped;
+    for process in processes {
+        match process {
+            Ok(process) => attached.push(process),
+            Err(result) => results.push(result),
+        }
+    }
     Ok(ExecSession {
-        processes: processes.into_iter().flatten().collect(),
+        processes: attached,
+        results,
+        stdin: stdin.clone(),
+        timeout: timeout_seconds.map(Duration::from_secs),
         wait,
     })
 }
 
+fn build_result(
+    pod: String,
+    node: Option<String>,
+    state: ExecState,
+    message: String,
+) -> ExecResult {
+    ExecResult {
+        pod,
+        node,
+        state,
+        exit_code: None,
+        message: Some(message),
+        stdout: String::default(),
+        stderr: String::default(),
+        truncated: false,
+    }
+}
+
 struct Process {
     attached: AttachedProcess,
     name: String,
+    node: Option<String>,
 }
 
 impl Process {
-    async fn join(self) {
-        let Self { attached, name } = self;
+    async fn run(
+        self,
+        tx: mpsc::UnboundedSender<ExecEvent>,
+        stdin: Option<String>,
+        duration: Option<Duration>,
+        wait: bool,
+    ) {
+        let Self {
+            mut attached,
+            name,
+            node,
+        } = self;
 
-        match attached.join().await {
-            Ok(()) => (),
-            Err(error) => {
+        if let Some(data) = stdin
+            && let Some(mut writer) = attached.stdin()
+            && let Err(error) = writer.write_all(data.as_bytes()).await
+        {
+            #[cfg(feature = "tracing")]
+            {
+                ::tracing::warn!("Failed to write stdin to {name}: {error}");
+            }
+            let _ = error;
+        }
+
+        if !wait {
+            sleep(Duration::from_secs(1)).await;
+            let result = ExecResult {
+                message: None,
+                ..build_result(name.clone(), node, ExecState::Spawned, String::default())
+            };
+            let _ = tx.send(ExecEvent::Exit(result));
+            drop(tx);
+
+            // Keep the process attached in background
+            if let Err(error) = attached.join().await {
                 #[cfg(feature = "tracing")]
                 {
                     ::tracing::error!("Failed to exec to {name}: {error}");
                 }
+                let _ = error;
+            }
+            return;
+        }
+
+        let stdout: OptionFuture<_> = attached
+            .stdout()
+            .map(|reader| forward(&tx, &name, ExecStream::Stdout, reader))
+            .into();
+        let stderr: OptionFuture<_> = attached
+            .stderr()
+            .map(|reader| forward(&tx, &name, ExecStream::Stderr, reader))
+            .into();
+        let status: OptionFuture<_> = attached.take_status().into();
+        let task = join3(stdout, stderr, status);
+
+        let result = match duration {
+            Some(duration) => match timeout(duration, task).await {
+                Ok((_, _, status)) => Ok(status.flatten()),
+                Err(_) => Err(duration),
+            },
+            None => Ok(task.await.2.flatten()),
+        };
+
+        let result = match result {
+            Ok(status) => match attached.join().await {
+                Ok(()) => convert_status(name, node, status),
+                Err(error) => build_result(name, node, ExecState::Error, error.to_string()),
+            },
+            Err(duration) => {
+                // NOTE: the remote process may keep running after detaching
+                attached.abort();
+                let message = format!("Timed out after {duration:?}");
+                build_result(name, node, ExecState::TimedOut, message)
             }
+        };
+
+        #[cfg(feature = "tracing")]
+        {
+            ::tracing::debug!("Completed: {} ({})", &result.pod, result.state);
+        }
+        let _ = tx.send(ExecEvent::Exit(result));
+    }
+}
+
+async fn forward(
+    tx: &mpsc::UnboundedSender<ExecEvent>,
+    pod: &str,
+    stream: ExecStream,
+    reader: impl AsyncRead + Unpin,
+) {
+    let mut reader = BufReader::new(reader);
+    let mut buf = Vec::default();
+    loop {
+        buf.clear();
+        match reader.read_until(b'\n', &mut buf).await {
+            Ok(0) | Err(_) => break,
+            Ok(_) => (),
         }
+
+        // NOTE: The commands may print non-UTF-8 bytes, e.g. binary files
+        let line = buf.strip_suffix(b"\n").unwrap_or(&buf);
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        let output = ExecOutput {
+            pod: pod.into(),
+            stream,
+            line: String::from_utf8_lossy(line).into_owned(),
+        };
+        if tx.send(ExecEvent::Output(output)).is_err() {
+            break;
+        }
+    }
+}
+
+/// Convert the status of the remote process into a result.
+fn convert_status(pod: String, node: Option<String>, status: Option<Status>) -> ExecResult {
+    let Some(status) = status else {
+        let message = "Missing exit status".into();
+        return build_result(pod, node, ExecState::Error, message);
+    };
+    if status.status.as_deref() == Some("Success") {
+        return ExecResult {
+            exit_code: Some(0),
+            message: None,
+            ..build_result(pod, node, ExecState::Succeeded, String::default())
+        };
+    }
+
+    let exit_code = status
+        .details
+        .as_ref()
+        .and_then(|details| details.causes.as_ref())
+        .and_then(|causes| {
+            causes
+                .iter()
+                .find(|cause| cause.reason.as_deref() == Some("ExitCode"))
+        })
+        .and_then(|cause| cause.message.as_deref())
+        .and_then(|code| code.parse().ok());
+    ExecResult {
+        exit_code,
+        message: status.message,
+        ..build_result(pod, node, ExecState::Failed, String::default())
     }
 }
 
 pub struct ExecSession {
     processes: Vec<Process>,
+    /// The results of the sessions which have not been executed
+    results: Vec<ExecResult>,
+    stdin: Option<String>,
+    timeout: Option<Duration>,
     wait: bool,
 }
 
 impl ExecSession {
-    pub async fn join(self) {
-        let Self { processes, wait } = self;
+    /// Stream the outputs of the processes, followed by their results.
+    pub fn stream(self) -> impl Stream<Item = ExecEvent> + Send + 'static {
+        let Self {
+            processes,
+            results,
+            stdin,
+            timeout,
+            wait,
+        } = self;
 
-        // Spawn processes
-        let processes: Vec<_> = processes
-            .into_iter()
-            .map(|process| {
-                let name = process.name.clone();
-                #[cfg(feature = "tracing")]
-                {
-                    ::tracing::debug!("Executed: {name}");
-                }
-                (name, ::tokio::spawn(process.join()))
-            })
-            .collect();
-
-        let num_processes = processes.len();
-
-        // Join processes
-        if wait {
-            processes
-                .into_iter()
-                .map(|(name, process)| async move {
-                    match process.await {
-                        Ok(()) => {
-                            #[cfg(feature = "tracing")]
-                            {
-                                ::tracing::info!("Completed: {name}");
-                            }
-                            let _ = name;
-                        }
-                        Err(error) => {
-                            #[cfg(feature = "tracing")]
-                            {
-                                ::tracing::error!("Failed to join: {error}");
-                            }
-                        }
-                    }
-                })
-                .collect::<FuturesUnordered<_>>()
-                .collect::<()>()
-                .await;
+        let (tx, rx) = mpsc::unbounded_channel();
+        for result in results {
+            let _ = tx.send(ExecEvent::Exit(result));
+        }
 
+        // Spawn processes
+        for process in processes {
             #[cfg(feature = "tracing")]
             {
-                ::tracing::info!("Completed at {num_processes} sessions");
+                ::tracing::debug!("Executed: {}", &process.name);
             }
-        } else {
-            sleep(Duration::from_secs(1)).await;
+            ::tokio::spawn(process.run(tx.clone(), stdin.clone(), timeout, wait));
+        }
 
-            #[cfg(feature = "tracing")]
-            {
-                ::tracing::info!("Spawned {num_processes} sessions");
-            }
+        stream::unfold(rx, |mut rx| async move {
+            let event = rx.recv().await?;
+            Some((event, rx))
+        })
+    }
+
+    /// Wait for the processes, collecting their outputs and results.
+    pub async fn join(self) -> ExecReport {
+        let report = self
+            .stream()
+            .fold(ExecReport::default(), |mut report, event| async move {
+                report.push(event);
+                report
+            })
+            .await;
+
+        #[cfg(feature = "tracing")]
+        {
+            ::tracing::info!(
+                "Completed at {num_sessions} sessions ({num_failed} failed)",
+                num_sessions = report.results.len(),
+                num_failed = report.num_failed(),
+            );
         }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::{StatusCause, StatusDetails};
+
+    use super::*;
+
+    fn convert(status: Option<Status>) -> ExecResult {
+        convert_status("pod".into(), Some("node".into()), status)
+    }
+
+    #[test]
+    fn convert_success_status() {
+        let result = convert(Some(Status {
+            status: Some("Success".into()),
+            ..Default::default()
+        }));
+        assert_eq!(result.state, ExecState::Succeeded);
+        assert_eq!(result.exit_code, Some(0));
+        assert_eq!(result.message, None);
+        assert_eq!(result.node.as_deref(), Some("node"));
+    }
+
+    #[test]
+    fn convert_non_zero_exit_code() {
+        let result = convert(Some(Status {
+            status: Some("Failure".into()),
+            message: Some("command terminated with non-zero exit code".into()),
+            reason: Some("NonZeroExitCode".into()),
+            details: Some(StatusDetails {
+                causes: Some(vec![StatusCause {
+                    reason: Some("ExitCode".into()),
+                    message: Some("42".into()),
+                    ..Default::default()
+                }]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }));
+        assert_eq!(result.state, ExecState::Failed);
+        assert_eq!(result.exit_code, Some(42));
+        assert_eq!(
+            result.message.as_deref(),
+            Some("command terminated with non-zero exit code"),
+        );
+    }
+
+    #[test]
+    fn convert_missing_status() {
+        let result = convert(None);
+        assert_eq!(result.state, ExecState::Error);
+        assert_eq!(result.exit_code, None);
+        assert_eq!(result.message.as_deref(), Some("Missing exit status"));
     }
 }
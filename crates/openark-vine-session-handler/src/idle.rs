@@ -0,0 +1,393 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{Result, anyhow, bail};
+use clap::Parser;
+use jiff::Timestamp;
+use tokio::{fs, process::Command};
+#[cfg(feature = "tracing")]
+use tracing::{debug, warn};
+
+#[derive(Clone, Debug, Parser)]
+pub(crate) struct IdleArgs {
+    /// Interval of checking whether the node is idle, as seconds.
+    #[arg(long, env = "IDLE_CHECK_INTERVAL_SECONDS", default_value_t = 60)]
+    idle_check_interval_seconds: u64,
+
+    /// The node is idle while the CPU usage of every core is below this, as percent.
+    #[arg(long, env = "IDLE_CPU_THRESHOLD_PERCENT", default_value_t = 10.0)]
+    idle_cpu_threshold_percent: f64,
+
+    /// The node is idle while the GPU usage is below this, as percent.
+    #[arg(long, env = "IDLE_GPU_THRESHOLD_PERCENT", default_value_t = 10.0)]
+    idle_gpu_threshold_percent: f64,
+
+    /// The host's root directory to run `nvidia-smi` in,
+    /// if the handler runs without the NVIDIA container runtime.
+    #[arg(long, env = "IDLE_HOST_ROOT")]
+    idle_host_root: Option<PathBuf>,
+
+    /// The interrupt names of the input devices, such as keyboards and mice.
+    #[arg(
+        long,
+        env = "IDLE_INPUT_INTERRUPTS",
+        value_delimiter = ',',
+        default_value = "i8042,xhci_hcd,ehci_hcd,ohci_hcd,uhci_hcd"
+    )]
+    idle_input_interrupts: Vec<String>,
+
+    /// The directory where the desktop sessions report their idle times, as milliseconds.
+    #[arg(
+        long,
+        env = "IDLE_SESSION_DIR",
+        default_value = "/run/openark-vine/idle"
+    )]
+    idle_session_dir: PathBuf,
+}
+
+impl IdleArgs {
+    pub(crate) fn interval(&self) -> Duration {
+        Duration::from_secs(self.idle_check_interval_seconds.max(1))
+    }
+
+    /// Returns `true` if there are no user inputs and the usages are below the thresholds.
+    ///
+    /// An unknown GPU usage, i.e. a failed probe, counts as busy.
+    fn is_idle(&self, has_inputs: bool, cpu: f64, gpu: Option<f64>) -> bool {
+        !has_inputs
+            && cpu < self.idle_cpu_threshold_percent
+            && gpu.is_some_and(|gpu| gpu < self.idle_gpu_threshold_percent)
+    }
+}
+
+/// Counters sampled from the kernel.
+#[derive(Clone, Debug)]
+struct Sample {
+    /// The busy and total times of each CPU core
+    cpus: Vec<(u64, u64)>,
+    inputs: u64,
+}
+
+/// Tracks since when the node has been idle, i.e. no user inputs and low CPU/GPU usages.
+pub(crate) struct IdleMonitor<'a> {
+    args: &'a IdleArgs,
+    idle_since: Option<Timestamp>,
+    last: Option<(Timestamp, Sample)>,
+}
+
+impl<'a> IdleMonitor<'a> {
+    pub(crate) const fn new(args: &'a IdleArgs) -> Self {
+        Self {
+            args,
+            idle_since: None,
+            last: None,
+        }
+    }
+
+    /// Sample the node's usages, returning since when the node has been idle.
+    ///
+    /// It returns `None` on the first sample, as the usages cannot be measured yet.
+    pub(crate) async fn update(&mut self) -> Result<Option<Option<Timestamp>>> {
+        let timestamp = Timestamp::now();
+        let sample = Sample {
+            cpus: parse_cpu_stat(&fs::read_to_string("/proc/stat").await?)?,
+            inputs: count_interrupts(
+                &fs::read_to_string("/proc/interrupts").await?,
+                &self.args.idle_input_interrupts,
+            ),
+        };
+        let Some((last_timestamp, last)) = self.last.replace((timestamp, sample.clone())) else {
+            return Ok(None);
+        };
+
+        // NOTE: The remote users, e.g. VNC, are only visible to the desktop sessions
+        let has_inputs = sample.inputs != last.inputs
+            || read_session_inputs(&self.args.idle_session_dir, self.args.interval()).await?;
+        let cpu = max_cpu_usage(&last.cpus, &sample.cpus);
+        let gpu = match read_gpu_usage(self.args.idle_host_root.as_deref()).await {
+            Ok(gpu) => Some(gpu),
+            Err(error) => {
+                #[cfg(feature = "tracing")]
+                warn!("failed to read GPU usage: {error}");
+
+                #[cfg(not(feature = "tracing"))]
+                let _ = error;
+                None
+            }
+        };
+
+        #[cfg(feature = "tracing")]
+        debug!("Sampled usages: inputs={has_inputs}, cpu={cpu:.1}%, gpu={gpu:?}");
+
+        self.idle_since = if self.args.is_idle(has_inputs, cpu, gpu) {
+            Some(self.idle_since.unwrap_or(last_timestamp))
+        } else {
+            None
+        };
+        Ok(Some(self.idle_since))
+    }
+}
+
+/// Count the interrupts of the given devices on all CPUs.
+fn count_interrupts(interrupts: &str, names: &[String]) -> u64 {
+    interrupts
+        .lines()
+        .skip(1)
+        .filter(|line| {
+            line.split_whitespace().any(|word| {
+                // e.g. "ehci_hcd:usb1,"
+                let word = word.trim_end_matches(',');
+                let device = word.split_once(':').map_or(word, |(device, _)| device);
+                names.iter().any(|name| word == name || device == name)
+            })
+        })
+        .flat_map(|line| {
+            line.split_whitespace()
+                .skip(1)
+                .map_while(|count| count.parse::<u64>().ok())
+        })
+        .sum()
+}
+
+/// Return `true` if any desktop session has received user inputs within the interval.
+async fn read_session_inputs(dir: &Path, interval: Duration) -> Result<bool> {
+    let mut entries = match fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(error) => return Err(error.into()),
+    };
+    let now = SystemTime::now();
+    while let Some(entry) = entries.next_entry().await? {
+        let (Ok(metadata), Ok(idle)) = (
+            entry.metadata().await,
+            fs::read_to_string(entry.path()).await,
+        ) else {
+            continue;
+        };
+        let Ok(idle) = idle.trim().parse().map(Duration::from_millis) else {
+            continue;
+        };
+        let age = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| now.duration_since(modified).ok())
+            .unwrap_or_default();
+        if has_recent_inputs(age, idle, interval) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Return `true` if the last user input, reported `age` ago, was within the interval.
+///
+/// The reports of the terminated sessions become stale, so they are never recent.
+fn has_recent_inputs(age: Duration, idle: Duration, interval: Duration) -> bool {
+    age + idle < interval
+}
+
+/// Parse the busy and total CPU times of each CPU core.
+fn parse_cpu_stat(stat: &str) -> Result<Vec<(u64, u64)>> {
+    let cpus: Vec<_> = stat
+        .lines()
+        .filter_map(|line| line.strip_prefix("cpu"))
+        // Skip the aggregated "cpu " line
+        .filter(|line| line.starts_with(|c: char| c.is_ascii_digit()))
+        .map(|line| -> Result<_> {
+            let fields: Vec<u64> = line
+                .split_whitespace()
+                .skip(1)
+                .map(|field| field.parse())
+                .collect::<Result<_, _>>()?;
+
+            // user, nice, system, idle, iowait, irq, softirq, steal
+            let total: u64 = fields.iter().take(8).sum();
+            let idle: u64 = fields.iter().skip(3).take(2).sum();
+            Ok((total - idle, total))
+        })
+        .collect::<Result<_>>()?;
+
+    if cpus.is_empty() {
+        bail!("missing cpu stat");
+    }
+    Ok(cpus)
+}
+
+/// Return the maximum usage of the CPU cores between the samples, as percent.
+///
+/// A single busy core, e.g. a single-threaded job, keeps the node busy.
+fn max_cpu_usage(last: &[(u64, u64)], next: &[(u64, u64)]) -> f64 {
+    last.iter()
+        .zip(next)
+        .map(|(&(last_busy, last_total), &(busy, total))| {
+            let busy = busy.saturating_sub(last_busy);
+            let total = total.saturating_sub(last_total);
+            if total > 0 {
+                100.0 * busy as f64 / total as f64
+            } else {
+                0.0
+            }
+        })
+        .fold(0.0, f64::max)
+}
+
+/// Read the maximum usage of the GPUs, as percent.
+///
+/// A node without NVIDIA GPUs has no GPUs to measure, so its usage is zero.
+/// On the other hand, a missing `nvidia-smi` on a node with NVIDIA GPUs is an error,
+/// as their usages are unknown.
+async fn read_gpu_usage(host_root: Option<&Path>) -> Result<f64> {
+    /// The exit code of `chroot` if the command is not found.
+    const CODE_NOT_FOUND: i32 = 127;
+
+    let mut command = match host_root {
+        Some(root) => {
+            let mut command = Command::new("chroot");
+            command.arg(root).arg("nvidia-smi");
+            command
+        }
+        None => Command::new("nvidia-smi"),
+    };
+    let output = match command
+        .args([
+            "--query-gpu=utilization.gpu",
+            "--format=csv,noheader,nounits",
+        ])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .output()
+        .await
+    {
+        Ok(output) if host_root.is_some() && output.status.code() == Some(CODE_NOT_FOUND) => None,
+        Ok(output) => Some(output),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => None,
+        Err(error) => return Err(error.into()),
+    };
+    let Some(output) = output else {
+        if has_nvidia_devices().await? {
+            bail!("nvidia-smi is missing on a node with NVIDIA GPUs");
+        }
+        return Ok(0.0);
+    };
+    if !output.status.success() {
+        bail!("nvidia-smi failed: {}", output.status);
+    }
+    parse_gpu_usage(&String::from_utf8(output.stdout)?)
+}
+
+/// Return `true` if any NVIDIA display controller is attached to the node.
+async fn has_nvidia_devices() -> Result<bool> {
+    let mut entries = fs::read_dir("/sys/bus/pci/devices").await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        let (Ok(vendor), Ok(class)) = (
+            fs::read_to_string(path.join("vendor")).await,
+            fs::read_to_string(path.join("class")).await,
+        ) else {
+            continue;
+        };
+        if is_nvidia_display(&vendor, &class) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Return `true` if the PCI device is an NVIDIA display controller, e.g. VGA or 3D.
+fn is_nvidia_display(vendor: &str, class: &str) -> bool {
+    vendor.trim() == "0x10de" && class.trim().starts_with("0x03")
+}
+
+/// Parse the maximum usage of the GPUs, as percent.
+fn parse_gpu_usage(output: &str) -> Result<f64> {
+    output
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .try_fold(0.0, |usage, line| {
+            line.parse::<f64>()
+                .map(|value| f64::max(usage, value))
+                .map_err(|_| anyhow!("unexpected GPU usage: {line:?}"))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn treat_unknown_gpu_usage_as_busy() {
+        let args = IdleArgs::parse_from(["test"]);
+        assert!(args.is_idle(false, 5.0, Some(0.0)));
+        assert!(!args.is_idle(true, 5.0, Some(0.0)));
+        assert!(!args.is_idle(false, 50.0, Some(0.0)));
+        assert!(!args.is_idle(false, 5.0, Some(50.0)));
+        assert!(!args.is_idle(false, 5.0, None));
+    }
+
+    #[test]
+    fn parse_gpu_usages() {
+        assert_eq!(parse_gpu_usage("").unwrap(), 0.0);
+        assert_eq!(parse_gpu_usage("3\n42\n7\n").unwrap(), 42.0);
+        assert!(parse_gpu_usage("3\n[N/A]\n").is_err());
+    }
+
+    #[test]
+    fn detect_nvidia_displays() {
+        assert!(is_nvidia_display("0x10de\n", "0x030000\n"));
+        assert!(is_nvidia_display("0x10de", "0x030200"));
+        // NVIDIA audio controller
+        assert!(!is_nvidia_display("0x10de", "0x040300"));
+        assert!(!is_nvidia_display("0x8086", "0x030000"));
+    }
+
+    #[test]
+    fn parse_cpu_stats() {
+        let stat = "\
+cpu  20 2 3 170 5 0 0 0 0 0
+cpu0 10 2 3 80 5 0 0 0 0 0
+cpu1 10 0 0 90 0 0 0 0 0 0
+intr 0
+";
+        assert_eq!(parse_cpu_stat(stat).unwrap(), &[(15, 100), (10, 100)]);
+        assert!(parse_cpu_stat("cpu  10 2 3 80 5 0 0 0 0 0\n").is_err());
+        assert!(parse_cpu_stat("intr 0\n").is_err());
+    }
+
+    #[test]
+    fn measure_busiest_cpu_core() {
+        let last = [(0, 100), (0, 100)];
+        // One core is fully busy while the other is idle
+        let next = [(0, 200), (100, 200)];
+        assert_eq!(max_cpu_usage(&last, &next), 100.0);
+        assert_eq!(max_cpu_usage(&last, &last), 0.0);
+    }
+
+    #[test]
+    fn detect_recent_session_inputs() {
+        let interval = Duration::from_secs(60);
+        let secs = Duration::from_secs;
+        assert!(has_recent_inputs(secs(10), secs(5), interval));
+        assert!(!has_recent_inputs(secs(10), secs(120), interval));
+        // Stale report of a terminated session
+        assert!(!has_recent_inputs(secs(3600), secs(0), interval));
+    }
+
+    #[test]
+    fn count_input_interrupts() {
+        let interrupts = "\
+           CPU0       CPU1
+  1:         10          5   IO-APIC    1-edge      i8042
+  9:          0          0   IO-APIC    9-fasteoi   acpi
+ 16:        100        200   IO-APIC   16-fasteoi   ehci_hcd:usb1, snd_hda_intel
+";
+        let names = ["i8042".into(), "ehci_hcd".into()];
+        assert_eq!(count_interrupts(interrupts, &names), 315);
+        assert_eq!(count_interrupts(interrupts, &[]), 0);
+    }
+}
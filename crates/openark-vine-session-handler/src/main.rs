@@ -1,3 +1,5 @@
+mod idle;
+
 use std::{
     path::PathBuf,
     process::{Output, Stdio, exit},
@@ -14,10 +16,12 @@ use kube::{
 };
 use openark_vine_session_api::{VineSessionGPU, filter_taint};
 use serde_json::json;
-use tokio::process::Command;
+use tokio::{process::Command, time::interval};
 #[cfg(feature = "tracing")]
 use tracing::{Level, info, instrument, warn};
 
+use crate::idle::{IdleArgs, IdleMonitor};
+
 #[derive(Clone, Debug, Parser)]
 #[command(author, version, about, long_about = None)]
 struct Args {
@@ -27,12 +31,18 @@ struct Args {
     #[arg(long, env = "DRY_RUN")]
     dry_run: bool,
 
+    #[command(flatten)]
+    idle: IdleArgs,
+
     #[arg(long, env = "OPENARK_LABEL_BIND_STORAGE")]
     label_bind_storage: String,
 
     #[arg(long, env = "OPENARK_LABEL_GPU")]
     label_gpu: String,
 
+    #[arg(long, env = "OPENARK_LABEL_IDLE_SINCE")]
+    label_idle_since: String,
+
     #[arg(long, env = "OPENARK_LABEL_SIGNED_OUT")]
     label_signed_out: String,
 
@@ -49,6 +59,7 @@ struct Args {
 struct Service<'a> {
     api: Api<Node>,
     args: &'a Args,
+    idle: IdleMonitor<'a>,
     patch_params: PatchParams,
     running: Option<bool>,
 }
@@ -238,6 +249,47 @@ exec cat "${primary_dev}/vendor"
         }
         Ok(())
     }
+
+    /// Report since when the current node has been idle
+    ///
+    #[cfg_attr(feature = "tracing", instrument(level = Level::INFO, skip_all))]
+    async fn update_idle(&mut self) -> Result<()> {
+        let Some(idle_since) = self.idle.update().await? else {
+            return Ok(());
+        };
+
+        // Skip unchanged states to avoid waking up the operator
+        let node = self.api.get_metadata(&self.args.node_name).await?;
+        let last = node
+            .metadata
+            .labels
+            .as_ref()
+            .and_then(|map| map.get(&self.args.label_idle_since));
+        let next = idle_since.map(|timestamp| timestamp.as_millisecond().to_string());
+        if last == next.as_ref() {
+            return Ok(());
+        }
+
+        let name = &self.args.node_name;
+        let patch = Patch::Strategic(json!({
+            "apiVersion": Node::API_VERSION,
+            "kind": Node::KIND,
+            "metadata": {
+                "name": name,
+                "labels": {
+                    &self.args.label_idle_since: next,
+                },
+            },
+        }));
+        self.api
+            .patch_metadata(name, &self.patch_params, &patch)
+            .await?;
+        {
+            #[cfg(feature = "tracing")]
+            info!("updated idle state of node/{name}: {next:?}");
+        }
+        Ok(())
+    }
 }
 
 #[cfg_attr(feature = "tracing", instrument(level = Level::INFO))]
@@ -263,6 +315,7 @@ async fn try_main(args: &Args) -> Result<()> {
     let mut service = Service {
         api: Api::all(client),
         args,
+        idle: IdleMonitor::new(&args.idle),
         patch_params: PatchParams {
             dry_run: args.dry_run,
             force: false,
@@ -278,8 +331,26 @@ async fn try_main(args: &Args) -> Result<()> {
         ..Default::default()
     };
 
+    let mut idle_interval = interval(args.idle.interval());
     let mut stream = Box::pin(watcher::watcher(api, watcher_config));
-    while let Some(event) = stream.try_next().await? {
+    loop {
+        let event = ::tokio::select! {
+            event = stream.try_next() => match event? {
+                Some(event) => event,
+                None => break,
+            },
+            _ = idle_interval.tick() => {
+                if let Err(error) = service.update_idle().await {
+                    #[cfg(feature = "tracing")]
+                    warn!("failed to update idle state: {error}");
+
+                    #[cfg(not(feature = "tracing"))]
+                    let _ = error;
+                }
+                continue;
+            }
+        };
+
         match event {
             Event::Apply(node) | Event::InitApply(node) => service.apply(&node).await?,
             Event::Delete(_) => {
@@ -5,7 +5,7 @@ use std::{collections::BTreeMap, iter, sync::Arc, time::Duration};
 use clap::Parser;
 use convert_case::{Case, Casing};
 use futures::StreamExt;
-use jiff::Timestamp;
+use jiff::{Timestamp, tz::TimeZone};
 use k8s_openapi::{
     api::core::v1::{Node, ObjectReference, Pod},
     apimachinery::pkg::apis::meta::v1::{OwnerReference, Time},
@@ -32,7 +32,7 @@ use kube::{
 use openark_core::operator::{OperatorArgs, RecorderExt, install_crd};
 use openark_vine_session_api::{
     NodeSession, ProfileState,
-    binding::{SessionBindingCrd, SessionBindingSpec},
+    binding::{ScheduleState, SessionBindingCrd, SessionBindingSpec},
     command::SessionCommandCrd,
     owned_profile::{
         OwnedFeaturesSpec, OwnedOpenArkSpec, OwnedSessionProfileSpec, OwnedUserSpec,
@@ -93,6 +93,32 @@ struct Context {
 }
 
 impl Context {
+    /// Resolve the timezone of the profile, falling back to the default one.
+    ///
+    fn timezone(&self, profile: Option<&SessionProfileCrd>) -> TimeZone {
+        profile
+            .and_then(|profile| profile.spec.region.as_ref())
+            .and_then(|region| region.timezone.clone())
+            .or_else(|| self.args.api.to_region_timezone())
+            .and_then(|name| TimeZone::get(&name).ok())
+            .unwrap_or(TimeZone::UTC)
+    }
+
+    /// Get the profile, looking it up at most once per reconcile.
+    ///
+    async fn get_profile(
+        &self,
+        profiles: &mut BTreeMap<String, Option<SessionProfileCrd>>,
+        name: &str,
+    ) -> Result<Option<SessionProfileCrd>> {
+        if let Some(profile) = profiles.get(name) {
+            return Ok(profile.clone());
+        }
+        let profile = self.api_profile.get_opt(name).await?;
+        profiles.insert(name.into(), profile.clone());
+        Ok(profile)
+    }
+
     async fn init_nodes(&self) -> Result<()> {
         // List all nodes
         let api = &self.api_node;
@@ -145,6 +171,16 @@ fn is_selected(
     }
 }
 
+/// Return the earlier one of the timestamps.
+///
+#[must_use]
+fn earliest(a: Option<Timestamp>, b: Option<Timestamp>) -> Option<Timestamp> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
 #[must_use]
 fn collect_node_host_devices(node: &Node) -> Vec<OwnedVMHostDeviceSpec> {
     node.status
@@ -197,6 +233,7 @@ fn build_owned_session_profile(
         extra_services,
         features,
         greeter,
+        idle_timeout: _,
         mode,
         persistence,
         region,
@@ -622,11 +659,17 @@ async fn reconcile(node: Arc<Node>, ctx: Arc<Context>) -> Result<Action, Error>
     next.apply_node(&pods);
 
     // Try signing in with a new profile
+    let mut next_schedule = None;
+    let mut expires_at = None;
     let next_profile = {
         // TODO: use pools (reflector?)
+        let mut profiles = BTreeMap::default();
         let lp = ListParams::default();
         let bindings = ctx.api_binding.list(&lp).await?;
-        let binding = bindings
+
+        // Collect the bindings which are active now
+        let mut candidates = Vec::default();
+        for binding in bindings
             .items
             .into_iter()
             .filter(|binding| binding.metadata.deletion_timestamp.is_none())
@@ -637,23 +680,95 @@ async fn reconcile(node: Arc<Node>, ctx: Arc<Context>) -> Result<Action, Error>
                     binding.spec.node_selector.as_ref(),
                 )
             })
-            .min_by_key(|binding| {
+        {
+            let Some(schedule) = binding.spec.schedule.as_ref() else {
+                candidates.push((binding, None));
+                continue;
+            };
+
+            let profile = ctx
+                .get_profile(&mut profiles, &binding.spec.profile)
+                .await?;
+            let tz = ctx.timezone(profile.as_ref());
+            match schedule.state(timestamp, &tz) {
+                Ok(ScheduleState::Active { until }) => {
+                    next_schedule = earliest(next_schedule, until);
+                    candidates.push((binding, until));
+                }
+                Ok(ScheduleState::Inactive { next }) => {
+                    next_schedule = earliest(next_schedule, next);
+                }
+                Err(error) => {
+                    #[cfg(feature = "tracing")]
+                    warn!(
+                        "invalid schedule of binding/{}: {error}",
+                        binding.name_any()
+                    );
+                    let _ = error;
+                }
+            }
+        }
+
+        let binding = candidates
+            .into_iter()
+            .min_by_key(|(binding, _)| {
                 (
                     binding.spec.priority,
                     binding.metadata.creation_timestamp.clone(),
                     binding.metadata.uid.clone(),
                 )
+            })
+            .map(|(binding, until)| {
+                expires_at = until.map(|until| {
+                    let warning = binding
+                        .spec
+                        .schedule
+                        .as_ref()
+                        .map(|schedule| schedule.warning())
+                        .unwrap_or_default();
+                    (until, warning)
+                });
+                binding
             });
         let profile = match &binding {
-            Some(binding) => ctx.api_profile.get_opt(&binding.spec.profile).await?,
+            Some(binding) => {
+                ctx.get_profile(&mut profiles, &binding.spec.profile)
+                    .await?
+            }
             None => None,
         };
         binding.zip(profile)
     };
+
+    // Sign out the sessions which have been idle for too long
+    let mut is_idle = false;
+    let next_profile = match next_profile {
+        Some((binding, profile)) => match (profile.spec.idle_timeout(), next.idle(timestamp)) {
+            (Some(timeout), Some(idle)) => {
+                // A new binding should not be signed out right away
+                let idle = match binding.metadata.creation_timestamp.as_ref() {
+                    Some(Time(created_at)) => idle.min(timestamp.duration_since(*created_at)),
+                    None => idle,
+                };
+                if idle >= timeout {
+                    is_idle = true;
+                    expires_at = None;
+                    None
+                } else {
+                    next_schedule = earliest(next_schedule, Some(timestamp + (timeout - idle)));
+                    Some((binding, profile))
+                }
+            }
+            (_, _) => Some((binding, profile)),
+        },
+        None => None,
+    };
     let profile_state = next.apply_profile(next_profile.as_ref(), timestamp);
     {
         #[cfg(feature = "tracing")]
-        if profile_state.has_changed() {
+        if is_idle {
+            info!("Session has been idle: {name}");
+        } else if profile_state.has_changed() {
             info!("Profile has been changed: {name}");
         }
     }
@@ -722,6 +837,26 @@ async fn reconcile(node: Arc<Node>, ctx: Arc<Context>) -> Result<Action, Error>
         next.remove_session_revision();
     }
 
+    let is_session_changed = current != next;
+
+    // Warn the users before the reservation expires
+    // NOTE: the end is recorded in the session, so that the users are warned only once
+    let mut warn_until = None;
+    if !must_sign_out && !is_app_deleting {
+        let mut expires_at_to_warn = None;
+        if let Some((until, warning)) = expires_at {
+            let warn_at = until - warning;
+            if warn_at > timestamp {
+                next_schedule = earliest(next_schedule, Some(warn_at));
+            } else {
+                expires_at_to_warn = Some(until);
+            }
+        }
+        if next.set_expires_at(expires_at_to_warn) {
+            warn_until = expires_at_to_warn;
+        }
+    }
+
     // Update if changed
     if current != next {
         // Apply patch
@@ -731,10 +866,14 @@ async fn reconcile(node: Arc<Node>, ctx: Arc<Context>) -> Result<Action, Error>
             #[cfg(feature = "tracing")]
             info!("updated node/{name}: {patch:?}");
         }
+    }
 
-        // Report message
+    // Report messages
+    if is_session_changed {
         let message = if is_app_deleting {
             "Signing out".into()
+        } else if must_sign_out && is_idle {
+            "Signed out due to inactivity".into()
         } else if must_sign_out {
             "Signed out".into()
         } else if let Some(user) = next.get_user() {
@@ -745,19 +884,34 @@ async fn reconcile(node: Arc<Node>, ctx: Arc<Context>) -> Result<Action, Error>
         let reference = ObjectRef::from_obj(&*node).into();
         report_update(&ctx.recorder, &reference, message).await?;
     }
+    if let Some(until) = warn_until {
+        let message = format!("Session reservation ends at {until}");
+        let reference = ObjectRef::from_obj(&*node).into();
+        report_warning(&ctx.recorder, &reference, message).await?;
+    }
 
     // Wait some seconds to apply signing out
-    match sign_out_remaining {
+    let requeue = match sign_out_remaining {
         Some(remaining) => {
             {
                 #[cfg(feature = "tracing")]
                 info!("start waiting for signing out: {name} for {remaining:?}");
             }
-            Ok(Action::requeue(remaining.unsigned_abs()))
+            remaining.unsigned_abs()
         }
-        None => Ok(Action::requeue(
-            ctx.args.api.duration_sign_out().unsigned_abs(),
-        )),
+        None => ctx.args.api.duration_sign_out().unsigned_abs(),
+    };
+
+    // Wake up on the next schedule of the bindings
+    match next_schedule {
+        Some(next) => {
+            let remaining = next
+                .duration_since(timestamp)
+                .unsigned_abs()
+                .max(Duration::from_secs(1));
+            Ok(Action::requeue(requeue.min(remaining)))
+        }
+        None => Ok(Action::requeue(requeue)),
     }
 }
 
@@ -776,6 +930,21 @@ async fn report_update(
     RecorderExt::<Reason>::report_update(recorder, &event, reference).await
 }
 
+async fn report_warning(
+    recorder: &Recorder,
+    reference: &ObjectReference,
+    message: String,
+) -> Result<(), ::kube::Error> {
+    let event = ::kube::runtime::events::Event {
+        type_: ::kube::runtime::events::EventType::Warning,
+        reason: Reason::SessionExpiring.to_string(),
+        note: Some(message),
+        action: "Scheduling".into(),
+        secondary: None,
+    };
+    RecorderExt::<Reason>::report_update(recorder, &event, reference).await
+}
+
 async fn report_error(
     recorder: &Recorder,
     error: ::kube::runtime::controller::Error<Error, ::kube::runtime::watcher::Error>,
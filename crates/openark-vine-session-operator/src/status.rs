@@ -3,6 +3,7 @@ use strum::{Display, EnumString};
 #[derive(Copy, Clone, Debug, Display, EnumString, PartialEq, Eq)]
 pub(crate) enum Reason {
     SessionError,
+    SessionExpiring,
     SessionUpdated,
 }
 